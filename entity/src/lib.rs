@@ -3,17 +3,37 @@
 pub mod prelude;
 
 pub mod apub_error_report;
+pub mod domain_policy;
+pub mod emoji;
 pub mod note;
 pub mod note_like;
 pub mod note_mention;
+pub mod note_poll;
+pub mod note_poll_option;
+pub mod note_poll_vote;
 pub mod note_tag;
 pub mod note_upload;
 pub mod notification;
+pub mod oauth_app;
+pub mod oauth_token;
+pub mod pending_upload;
+pub mod personal_access_token;
 pub mod push_notification;
+pub mod remote_media_cache;
 pub mod remote_public_key;
+pub mod report;
+pub mod report_note;
 pub mod sea_orm_active_enums;
 pub mod tag;
 pub mod upload;
 pub mod user;
+pub mod user_alias;
 pub mod user_block;
+pub mod user_export;
 pub mod user_follow;
+pub mod user_import;
+pub mod user_import_row;
+pub mod user_mute;
+pub mod user_recovery_code;
+pub mod user_totp;
+pub mod webauthn_credential;
@@ -26,6 +26,8 @@ pub struct Model {
     pub renote_of_id: Option<Vec<u8>>,
     pub sensitive: i8,
     pub fetched_at: Option<DateTime>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub summary: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
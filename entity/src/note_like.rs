@@ -13,6 +13,7 @@ pub struct Model {
     pub user_id: Vec<u8>,
     pub is_private: i8,
     pub created_at: DateTime,
+    pub reaction: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -13,6 +13,8 @@ pub struct Model {
     pub body: String,
     pub created_at: DateTime,
     pub read_at: Option<DateTime>,
+    #[sea_orm(column_type = "Binary(16)", nullable)]
+    pub actor_id: Option<Vec<u8>>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
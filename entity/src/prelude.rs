@@ -1,16 +1,36 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.7
 
 pub use super::apub_error_report::Entity as ApubErrorReport;
+pub use super::domain_policy::Entity as DomainPolicy;
+pub use super::emoji::Entity as Emoji;
 pub use super::note::Entity as Note;
 pub use super::note_like::Entity as NoteLike;
 pub use super::note_mention::Entity as NoteMention;
+pub use super::note_poll::Entity as NotePoll;
+pub use super::note_poll_option::Entity as NotePollOption;
+pub use super::note_poll_vote::Entity as NotePollVote;
 pub use super::note_tag::Entity as NoteTag;
 pub use super::note_upload::Entity as NoteUpload;
 pub use super::notification::Entity as Notification;
+pub use super::oauth_app::Entity as OauthApp;
+pub use super::oauth_token::Entity as OauthToken;
+pub use super::pending_upload::Entity as PendingUpload;
+pub use super::personal_access_token::Entity as PersonalAccessToken;
 pub use super::push_notification::Entity as PushNotification;
+pub use super::remote_media_cache::Entity as RemoteMediaCache;
 pub use super::remote_public_key::Entity as RemotePublicKey;
+pub use super::report::Entity as Report;
+pub use super::report_note::Entity as ReportNote;
 pub use super::tag::Entity as Tag;
 pub use super::upload::Entity as Upload;
 pub use super::user::Entity as User;
+pub use super::user_alias::Entity as UserAlias;
 pub use super::user_block::Entity as UserBlock;
+pub use super::user_export::Entity as UserExport;
 pub use super::user_follow::Entity as UserFollow;
+pub use super::user_import::Entity as UserImport;
+pub use super::user_import_row::Entity as UserImportRow;
+pub use super::user_mute::Entity as UserMute;
+pub use super::user_recovery_code::Entity as UserRecoveryCode;
+pub use super::user_totp::Entity as UserTotp;
+pub use super::webauthn_credential::Entity as WebauthnCredential;
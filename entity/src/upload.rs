@@ -2,7 +2,7 @@
 
 use sea_orm::entity::prelude::*;
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
 #[sea_orm(table_name = "upload")]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false, column_type = "Binary(16)")]
@@ -10,12 +10,34 @@ pub struct Model {
     pub filename: Option<String>,
     pub url: Option<String>,
     pub mime_type: String,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub description: Option<String>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub blurhash: Option<String>,
+    #[sea_orm(column_type = "Float", nullable)]
+    pub focal_x: Option<f32>,
+    #[sea_orm(column_type = "Float", nullable)]
+    pub focal_y: Option<f32>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub duration: Option<f64>,
+    #[sea_orm(column_type = "Binary(16)", nullable)]
+    pub thumbnail_id: Option<Vec<u8>>,
+    pub has_variants: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(has_many = "super::note_upload::Entity")]
     NoteUpload,
+    #[sea_orm(
+        belongs_to = "Entity",
+        from = "Column::ThumbnailId",
+        to = "Column::Id",
+        on_update = "Cascade",
+        on_delete = "SetNull"
+    )]
+    SelfRef,
     #[sea_orm(has_many = "super::user::Entity")]
     User,
 }
@@ -1,5 +1,6 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.7
 
+use super::sea_orm_active_enums::UserAccountState;
 use sea_orm::entity::prelude::*;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
@@ -35,6 +36,12 @@ pub struct Model {
     pub is_admin: i8,
     pub hide_follows: i8,
     pub preferred_inbox: Option<String>,
+    pub account_state: UserAccountState,
+    pub suspended_at: Option<DateTime>,
+    pub deleted_at: Option<DateTime>,
+    #[sea_orm(column_type = "Binary(16)", nullable)]
+    pub moved_to_id: Option<Vec<u8>>,
+    pub moved_at: Option<DateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -29,6 +29,30 @@ mod m20250225_173949_blocking;
 mod m20250310_160527_apub_error_report;
 mod m20250316_060953_timeline_procedure;
 mod m20250319_015119_push_notification;
+mod m20250322_041527_timeline_block_filter;
+mod m20250323_102044_mute;
+mod m20250324_083112_domain_policy;
+mod m20250325_135204_report;
+mod m20250326_091530_account_state;
+mod m20250327_120318_user_deleted_at;
+mod m20250328_064210_account_move;
+mod m20250329_103512_user_export;
+mod m20250330_054418_user_import;
+mod m20250331_082615_note_poll;
+mod m20250401_031742_custom_emoji;
+mod m20250402_052230_note_reaction;
+mod m20250403_091847_note_summary;
+mod m20250404_140256_upload_metadata;
+mod m20250405_063128_upload_media;
+mod m20250406_021544_upload_variants;
+mod m20250407_013052_remote_media_cache;
+mod m20250408_052917_oauth;
+mod m20250409_041536_personal_access_token;
+mod m20250410_063204_two_factor;
+mod m20250411_084512_webauthn_credential;
+mod m20250412_022731_timeline_after_date;
+mod m20250413_054109_pending_upload;
+mod m20250414_030521_notification_actor;
 
 pub struct Migrator;
 
@@ -64,6 +88,30 @@ impl MigratorTrait for Migrator {
             Box::new(m20250310_160527_apub_error_report::Migration),
             Box::new(m20250316_060953_timeline_procedure::Migration),
             Box::new(m20250319_015119_push_notification::Migration),
+            Box::new(m20250322_041527_timeline_block_filter::Migration),
+            Box::new(m20250323_102044_mute::Migration),
+            Box::new(m20250324_083112_domain_policy::Migration),
+            Box::new(m20250325_135204_report::Migration),
+            Box::new(m20250326_091530_account_state::Migration),
+            Box::new(m20250327_120318_user_deleted_at::Migration),
+            Box::new(m20250328_064210_account_move::Migration),
+            Box::new(m20250329_103512_user_export::Migration),
+            Box::new(m20250330_054418_user_import::Migration),
+            Box::new(m20250331_082615_note_poll::Migration),
+            Box::new(m20250401_031742_custom_emoji::Migration),
+            Box::new(m20250402_052230_note_reaction::Migration),
+            Box::new(m20250403_091847_note_summary::Migration),
+            Box::new(m20250404_140256_upload_metadata::Migration),
+            Box::new(m20250405_063128_upload_media::Migration),
+            Box::new(m20250406_021544_upload_variants::Migration),
+            Box::new(m20250407_013052_remote_media_cache::Migration),
+            Box::new(m20250408_052917_oauth::Migration),
+            Box::new(m20250409_041536_personal_access_token::Migration),
+            Box::new(m20250410_063204_two_factor::Migration),
+            Box::new(m20250411_084512_webauthn_credential::Migration),
+            Box::new(m20250412_022731_timeline_after_date::Migration),
+            Box::new(m20250413_054109_pending_upload::Migration),
+            Box::new(m20250414_030521_notification_actor::Migration),
         ]
     }
 }
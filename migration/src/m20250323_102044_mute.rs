@@ -0,0 +1,241 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::{
+    common::{current_timestamp_6, datetime_6, datetime_6_null},
+    m20220101_000001_create_table::User,
+    m20250322_041527_timeline_block_filter,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                TableCreateStatement::new()
+                    .table(UserMute::Table)
+                    .col(pk_auto(UserMute::Id))
+                    .col(uuid(UserMute::MuterId))
+                    .col(uuid(UserMute::MutedId))
+                    .col(datetime_6(UserMute::MutedAt).default(current_timestamp_6()))
+                    .col(datetime_6_null(UserMute::ExpiresAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_user_mute_muter_id")
+                    .from(UserMute::Table, UserMute::MuterId)
+                    .to(User::Table, User::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_user_mute_muted_id")
+                    .from(UserMute::Table, UserMute::MutedId)
+                    .to(User::Table, User::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                IndexCreateStatement::new()
+                    .name("idx_user_mute_unique")
+                    .table(UserMute::Table)
+                    .col(UserMute::MuterId)
+                    .col(UserMute::MutedId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        create_timeline_procedure(manager).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // restore the procedure without mute filtering
+        m20250322_041527_timeline_block_filter::Migration
+            .up(manager)
+            .await?;
+
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(UserMute::Table)
+                    .cascade()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// ミュートを考慮したタイムライン取得プロシージャを作成する。
+pub(crate) async fn create_timeline_procedure(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    let db = manager.get_connection();
+
+    db.execute_unprepared(
+        r#"
+CREATE OR REPLACE PROCEDURE get_note_ids_generalized(
+  IN viewer_id BINARY(16), 
+  IN include_self BOOLEAN, 
+  IN include_public BOOLEAN, 
+  IN include_unlisted BOOLEAN, 
+  IN limit_reply_to_id BINARY(16), 
+  IN lim BIGINT, 
+  IN before_date DATETIME
+) 
+BEGIN
+  SELECT n.id
+  FROM note n
+  WHERE
+  -- visibility check
+  (
+    -- self notes
+    (
+      CASE
+        WHEN include_self AND (viewer_id IS NOT NULL) THEN n.author_id = viewer_id
+        ELSE FALSE
+      END
+    )
+    -- public notes
+    OR (
+      CASE
+        WHEN include_public THEN n.visibility = 'public'
+        ELSE FALSE
+      END
+    )
+    -- unlisted notes
+    OR (
+      CASE
+        WHEN include_unlisted THEN n.visibility = 'unlisted'
+        ELSE FALSE
+      END
+    )
+    -- follower notes
+    OR (
+      CASE
+        WHEN viewer_id IS NULL THEN FALSE
+        ELSE (
+          (n.visibility IN ('public', 'unlisted', 'follower'))
+          AND (
+            EXISTS (
+              SELECT f.id
+              FROM user_follow f
+              WHERE f.follower_id = viewer_id
+                AND f.followed_id = n.author_id
+                AND f.pending = FALSE
+            )
+          )
+        )
+      END
+    )
+    -- mentioned notes
+    OR (
+      CASE
+        WHEN viewer_id IS NULL THEN FALSE
+        ELSE (
+          EXISTS (
+            SELECT m.id
+            FROM note_mention m
+            WHERE m.target_user_id = viewer_id
+              AND m.note_id = n.id
+          )
+        )
+      END
+    )
+  )
+  -- deleted_at
+  AND (
+    n.deleted_at IS NULL
+  )
+  -- blocked users
+  AND (
+    CASE
+      WHEN viewer_id IS NULL THEN TRUE
+      ELSE NOT EXISTS (
+        SELECT b.id
+        FROM user_block b
+        WHERE (b.blocker_id = viewer_id AND b.blocked_id = n.author_id)
+          OR (b.blocker_id = n.author_id AND b.blocked_id = viewer_id)
+      )
+    END
+  )
+  -- renotes of blocked users' notes
+  AND (
+    CASE
+      WHEN viewer_id IS NULL OR n.renote_of_id IS NULL THEN TRUE
+      ELSE NOT EXISTS (
+        SELECT b.id
+        FROM note r
+        INNER JOIN user_block b
+          ON (b.blocker_id = viewer_id AND b.blocked_id = r.author_id)
+          OR (b.blocker_id = r.author_id AND b.blocked_id = viewer_id)
+        WHERE r.id = n.renote_of_id
+      )
+    END
+  )
+  -- muted users
+  AND (
+    CASE
+      WHEN viewer_id IS NULL THEN TRUE
+      ELSE NOT EXISTS (
+        SELECT mu.id
+        FROM user_mute mu
+        WHERE mu.muter_id = viewer_id
+          AND (
+            mu.muted_id = n.author_id
+            OR mu.muted_id = (SELECT r.author_id FROM note r WHERE r.id = n.renote_of_id)
+          )
+          AND (mu.expires_at IS NULL OR mu.expires_at > UTC_TIMESTAMP(6))
+      )
+    END
+  )
+  -- limit to replies
+  AND (
+    CASE
+      WHEN limit_reply_to_id IS NULL THEN TRUE
+      ELSE n.reply_to_id = limit_reply_to_id
+    END
+  )
+  -- limit before_date
+  AND (
+    CASE
+      WHEN before_date IS NULL THEN TRUE
+      ELSE n.created_at <= before_date
+    END
+  )
+  ORDER BY n.created_at DESC
+  LIMIT lim;
+END
+    "#,
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[derive(DeriveIden)]
+enum UserMute {
+    Table,
+    Id,
+    MuterId,
+    MutedId,
+    MutedAt,
+    ExpiresAt,
+}
@@ -0,0 +1,194 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::{
+    common::{current_timestamp_6, datetime_6, datetime_6_null, URL_LENGTH},
+    m20220101_000001_create_table::User,
+    m20250202_050205_notes::Note,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                TableCreateStatement::new()
+                    .table(Report::Table)
+                    .col(pk_auto(Report::Id))
+                    .col(string_len_null(Report::Url, URL_LENGTH).unique_key())
+                    .col(uuid(Report::ReporterId))
+                    .col(uuid(Report::TargetUserId))
+                    .col(text(Report::Comment))
+                    .col(enumeration(
+                        Report::Status,
+                        ReportStatus::Enum,
+                        [
+                            ReportStatus::Open,
+                            ReportStatus::Resolved,
+                            ReportStatus::Dismissed,
+                        ],
+                    ))
+                    .col(boolean(Report::Forwarded).default(Expr::value(false)))
+                    .col(datetime_6(Report::CreatedAt).default(current_timestamp_6()))
+                    .col(datetime_6_null(Report::ClosedAt))
+                    .col(uuid_null(Report::ClosedBy))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_report_reporter_id")
+                    .from(Report::Table, Report::ReporterId)
+                    .to(User::Table, User::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_report_target_user_id")
+                    .from(Report::Table, Report::TargetUserId)
+                    .to(User::Table, User::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_report_closed_by")
+                    .from(Report::Table, Report::ClosedBy)
+                    .to(User::Table, User::Id)
+                    .on_delete(ForeignKeyAction::SetNull)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                IndexCreateStatement::new()
+                    .name("idx_report_status_created_at")
+                    .table(Report::Table)
+                    .col(Report::Status)
+                    .col(Report::CreatedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                TableCreateStatement::new()
+                    .table(ReportNote::Table)
+                    .col(pk_auto(ReportNote::Id))
+                    .col(integer(ReportNote::ReportId))
+                    .col(uuid(ReportNote::NoteId))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_report_note_report_id")
+                    .from(ReportNote::Table, ReportNote::ReportId)
+                    .to(Report::Table, Report::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_report_note_note_id")
+                    .from(ReportNote::Table, ReportNote::NoteId)
+                    .to(Note::Table, Note::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                IndexCreateStatement::new()
+                    .name("idx_report_note_unique")
+                    .table(ReportNote::Table)
+                    .col(ReportNote::ReportId)
+                    .col(ReportNote::NoteId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(ReportNote::Table)
+                    .cascade()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(Report::Table)
+                    .cascade()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Report {
+    Table,
+    Id,
+    Url,
+    ReporterId,
+    TargetUserId,
+    Comment,
+    Status,
+    Forwarded,
+    CreatedAt,
+    ClosedAt,
+    ClosedBy,
+}
+
+#[derive(DeriveIden)]
+enum ReportNote {
+    Table,
+    Id,
+    ReportId,
+    NoteId,
+}
+
+#[derive(DeriveIden)]
+pub enum ReportStatus {
+    #[sea_orm(iden = "report_status")]
+    Enum,
+    #[sea_orm(iden = "open")]
+    Open,
+    #[sea_orm(iden = "resolved")]
+    Resolved,
+    #[sea_orm(iden = "dismissed")]
+    Dismissed,
+}
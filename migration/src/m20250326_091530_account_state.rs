@@ -0,0 +1,245 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::{
+    common::datetime_6_null, m20220101_000001_create_table::User, m20250324_083112_domain_policy,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(User::Table)
+                    .add_column(
+                        enumeration(
+                            UserState::AccountState,
+                            UserAccountState::Enum,
+                            [
+                                UserAccountState::Active,
+                                UserAccountState::Limited,
+                                UserAccountState::Suspended,
+                            ],
+                        )
+                        .default("active"),
+                    )
+                    .add_column(datetime_6_null(UserState::SuspendedAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        create_timeline_procedure(manager).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // restore the procedure without account state filtering
+        m20250324_083112_domain_policy::create_timeline_procedure(manager).await?;
+
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(User::Table)
+                    .drop_column(UserState::AccountState)
+                    .drop_column(UserState::SuspendedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// 凍結・制限されたアカウントを考慮したタイムライン取得プロシージャを作成する。
+pub(crate) async fn create_timeline_procedure(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    let db = manager.get_connection();
+
+    db.execute_unprepared(
+        r#"
+CREATE OR REPLACE PROCEDURE get_note_ids_generalized(
+  IN viewer_id BINARY(16), 
+  IN include_self BOOLEAN, 
+  IN include_public BOOLEAN, 
+  IN include_unlisted BOOLEAN, 
+  IN limit_reply_to_id BINARY(16), 
+  IN lim BIGINT, 
+  IN before_date DATETIME
+) 
+BEGIN
+  SELECT n.id
+  FROM note n
+  WHERE
+  -- visibility check
+  (
+    -- self notes
+    (
+      CASE
+        WHEN include_self AND (viewer_id IS NOT NULL) THEN n.author_id = viewer_id
+        ELSE FALSE
+      END
+    )
+    -- public notes (excluding silenced domains and limited users)
+    OR (
+      CASE
+        WHEN include_public THEN (
+          n.visibility = 'public'
+          AND NOT EXISTS (
+            SELECT dp.id
+            FROM domain_policy dp
+            INNER JOIN `user` u ON u.domain = dp.domain
+              OR RIGHT(u.domain, CHAR_LENGTH(dp.domain) + 1) = CONCAT('.', dp.domain)
+            WHERE u.id = n.author_id
+              AND dp.action = 'silence'
+          )
+          AND NOT EXISTS (
+            SELECT u.id
+            FROM `user` u
+            WHERE u.id = n.author_id
+              AND u.account_state = 'limited'
+          )
+        )
+        ELSE FALSE
+      END
+    )
+    -- unlisted notes
+    OR (
+      CASE
+        WHEN include_unlisted THEN n.visibility = 'unlisted'
+        ELSE FALSE
+      END
+    )
+    -- follower notes
+    OR (
+      CASE
+        WHEN viewer_id IS NULL THEN FALSE
+        ELSE (
+          (n.visibility IN ('public', 'unlisted', 'follower'))
+          AND (
+            EXISTS (
+              SELECT f.id
+              FROM user_follow f
+              WHERE f.follower_id = viewer_id
+                AND f.followed_id = n.author_id
+                AND f.pending = FALSE
+            )
+          )
+        )
+      END
+    )
+    -- mentioned notes
+    OR (
+      CASE
+        WHEN viewer_id IS NULL THEN FALSE
+        ELSE (
+          EXISTS (
+            SELECT m.id
+            FROM note_mention m
+            WHERE m.target_user_id = viewer_id
+              AND m.note_id = n.id
+          )
+        )
+      END
+    )
+  )
+  -- deleted_at
+  AND (
+    n.deleted_at IS NULL
+  )
+  -- suspended users
+  AND NOT EXISTS (
+    SELECT u.id
+    FROM `user` u
+    WHERE u.account_state = 'suspended'
+      AND (
+        u.id = n.author_id
+        OR u.id = (SELECT r.author_id FROM note r WHERE r.id = n.renote_of_id)
+      )
+  )
+  -- blocked users
+  AND (
+    CASE
+      WHEN viewer_id IS NULL THEN TRUE
+      ELSE NOT EXISTS (
+        SELECT b.id
+        FROM user_block b
+        WHERE (b.blocker_id = viewer_id AND b.blocked_id = n.author_id)
+          OR (b.blocker_id = n.author_id AND b.blocked_id = viewer_id)
+      )
+    END
+  )
+  -- renotes of blocked users' notes
+  AND (
+    CASE
+      WHEN viewer_id IS NULL OR n.renote_of_id IS NULL THEN TRUE
+      ELSE NOT EXISTS (
+        SELECT b.id
+        FROM note r
+        INNER JOIN user_block b
+          ON (b.blocker_id = viewer_id AND b.blocked_id = r.author_id)
+          OR (b.blocker_id = r.author_id AND b.blocked_id = viewer_id)
+        WHERE r.id = n.renote_of_id
+      )
+    END
+  )
+  -- muted users
+  AND (
+    CASE
+      WHEN viewer_id IS NULL THEN TRUE
+      ELSE NOT EXISTS (
+        SELECT mu.id
+        FROM user_mute mu
+        WHERE mu.muter_id = viewer_id
+          AND (
+            mu.muted_id = n.author_id
+            OR mu.muted_id = (SELECT r.author_id FROM note r WHERE r.id = n.renote_of_id)
+          )
+          AND (mu.expires_at IS NULL OR mu.expires_at > UTC_TIMESTAMP(6))
+      )
+    END
+  )
+  -- limit to replies
+  AND (
+    CASE
+      WHEN limit_reply_to_id IS NULL THEN TRUE
+      ELSE n.reply_to_id = limit_reply_to_id
+    END
+  )
+  -- limit before_date
+  AND (
+    CASE
+      WHEN before_date IS NULL THEN TRUE
+      ELSE n.created_at <= before_date
+    END
+  )
+  ORDER BY n.created_at DESC
+  LIMIT lim;
+END
+
+        "#,
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[derive(DeriveIden)]
+enum UserState {
+    AccountState,
+    SuspendedAt,
+}
+
+#[derive(DeriveIden)]
+pub enum UserAccountState {
+    #[sea_orm(iden = "user_account_state")]
+    Enum,
+    #[sea_orm(iden = "active")]
+    Active,
+    #[sea_orm(iden = "limited")]
+    Limited,
+    #[sea_orm(iden = "suspended")]
+    Suspended,
+}
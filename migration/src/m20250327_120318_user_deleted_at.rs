@@ -0,0 +1,40 @@
+use sea_orm_migration::prelude::*;
+
+use crate::{common::datetime_6_null, m20220101_000001_create_table::User};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(User::Table)
+                    .add_column(datetime_6_null(UserDeletedAt::DeletedAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(User::Table)
+                    .drop_column(UserDeletedAt::DeletedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum UserDeletedAt {
+    DeletedAt,
+}
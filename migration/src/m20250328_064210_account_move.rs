@@ -0,0 +1,119 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::{
+    common::{datetime_6_null, URL_LENGTH},
+    m20220101_000001_create_table::User,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(User::Table)
+                    .add_column(uuid_null(UserMove::MovedToId))
+                    .add_column(datetime_6_null(UserMove::MovedAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_user_moved_to_id")
+                    .from(User::Table, UserMove::MovedToId)
+                    .to(User::Table, User::Id)
+                    .on_delete(ForeignKeyAction::SetNull)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                TableCreateStatement::new()
+                    .table(UserAlias::Table)
+                    .col(pk_auto(UserAlias::Id))
+                    .col(uuid(UserAlias::UserId))
+                    .col(string_len(UserAlias::Url, URL_LENGTH))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_user_alias_user_id")
+                    .from(UserAlias::Table, UserAlias::UserId)
+                    .to(User::Table, User::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                IndexCreateStatement::new()
+                    .name("idx_user_alias_unique")
+                    .table(UserAlias::Table)
+                    .col(UserAlias::UserId)
+                    .col(UserAlias::Url)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(UserAlias::Table)
+                    .cascade()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_foreign_key(
+                ForeignKeyDropStatement::new()
+                    .name("fk_user_moved_to_id")
+                    .table(User::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(User::Table)
+                    .drop_column(UserMove::MovedToId)
+                    .drop_column(UserMove::MovedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum UserMove {
+    MovedToId,
+    MovedAt,
+}
+
+#[derive(DeriveIden)]
+enum UserAlias {
+    Table,
+    Id,
+    UserId,
+    Url,
+}
@@ -0,0 +1,100 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::{
+    common::{current_timestamp_6, datetime_6, datetime_6_null},
+    m20220101_000001_create_table::User,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                TableCreateStatement::new()
+                    .table(UserExport::Table)
+                    .col(pk_auto(UserExport::Id))
+                    .col(uuid(UserExport::UserId))
+                    .col(enumeration(
+                        UserExport::Status,
+                        UserExportStatus::Enum,
+                        [
+                            UserExportStatus::Pending,
+                            UserExportStatus::Processing,
+                            UserExportStatus::Completed,
+                            UserExportStatus::Failed,
+                        ],
+                    ))
+                    .col(string_len_null(UserExport::Filename, 128))
+                    .col(datetime_6(UserExport::CreatedAt).default(current_timestamp_6()))
+                    .col(datetime_6_null(UserExport::CompletedAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_user_export_user_id")
+                    .from(UserExport::Table, UserExport::UserId)
+                    .to(User::Table, User::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                IndexCreateStatement::new()
+                    .name("idx_user_export_user_id_created_at")
+                    .table(UserExport::Table)
+                    .col(UserExport::UserId)
+                    .col(UserExport::CreatedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(UserExport::Table)
+                    .cascade()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum UserExport {
+    Table,
+    Id,
+    UserId,
+    Status,
+    Filename,
+    CreatedAt,
+    CompletedAt,
+}
+
+#[derive(DeriveIden)]
+pub enum UserExportStatus {
+    #[sea_orm(iden = "user_export_status")]
+    Enum,
+    #[sea_orm(iden = "pending")]
+    Pending,
+    #[sea_orm(iden = "processing")]
+    Processing,
+    #[sea_orm(iden = "completed")]
+    Completed,
+    #[sea_orm(iden = "failed")]
+    Failed,
+}
@@ -0,0 +1,175 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::{
+    common::{current_timestamp_6, datetime_6, datetime_6_null},
+    m20220101_000001_create_table::User,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                TableCreateStatement::new()
+                    .table(UserImport::Table)
+                    .col(pk_auto(UserImport::Id))
+                    .col(uuid(UserImport::UserId))
+                    .col(enumeration(
+                        UserImport::Kind,
+                        UserImportKind::Enum,
+                        [UserImportKind::Follows, UserImportKind::Blocks],
+                    ))
+                    .col(enumeration(
+                        UserImport::Status,
+                        UserImportStatus::Enum,
+                        [
+                            UserImportStatus::Pending,
+                            UserImportStatus::Processing,
+                            UserImportStatus::Completed,
+                        ],
+                    ))
+                    .col(datetime_6(UserImport::CreatedAt).default(current_timestamp_6()))
+                    .col(datetime_6_null(UserImport::CompletedAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_user_import_user_id")
+                    .from(UserImport::Table, UserImport::UserId)
+                    .to(User::Table, User::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                IndexCreateStatement::new()
+                    .name("idx_user_import_user_id_created_at")
+                    .table(UserImport::Table)
+                    .col(UserImport::UserId)
+                    .col(UserImport::CreatedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                TableCreateStatement::new()
+                    .table(UserImportRow::Table)
+                    .col(pk_auto(UserImportRow::Id))
+                    .col(integer(UserImportRow::ImportId))
+                    .col(string_len(UserImportRow::Account, 512))
+                    .col(enumeration(
+                        UserImportRow::Status,
+                        UserImportRowStatus::Enum,
+                        [
+                            UserImportRowStatus::Pending,
+                            UserImportRowStatus::Succeeded,
+                            UserImportRowStatus::Failed,
+                        ],
+                    ))
+                    .col(string_len_null(UserImportRow::Message, 512))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_user_import_row_import_id")
+                    .from(UserImportRow::Table, UserImportRow::ImportId)
+                    .to(UserImport::Table, UserImport::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(UserImportRow::Table)
+                    .cascade()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(UserImport::Table)
+                    .cascade()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum UserImport {
+    Table,
+    Id,
+    UserId,
+    Kind,
+    Status,
+    CreatedAt,
+    CompletedAt,
+}
+
+#[derive(DeriveIden)]
+enum UserImportRow {
+    Table,
+    Id,
+    ImportId,
+    Account,
+    Status,
+    Message,
+}
+
+#[derive(DeriveIden)]
+pub enum UserImportKind {
+    #[sea_orm(iden = "user_import_kind")]
+    Enum,
+    #[sea_orm(iden = "follows")]
+    Follows,
+    #[sea_orm(iden = "blocks")]
+    Blocks,
+}
+
+#[derive(DeriveIden)]
+pub enum UserImportStatus {
+    #[sea_orm(iden = "user_import_status")]
+    Enum,
+    #[sea_orm(iden = "pending")]
+    Pending,
+    #[sea_orm(iden = "processing")]
+    Processing,
+    #[sea_orm(iden = "completed")]
+    Completed,
+}
+
+#[derive(DeriveIden)]
+pub enum UserImportRowStatus {
+    #[sea_orm(iden = "user_import_row_status")]
+    Enum,
+    #[sea_orm(iden = "pending")]
+    Pending,
+    #[sea_orm(iden = "succeeded")]
+    Succeeded,
+    #[sea_orm(iden = "failed")]
+    Failed,
+}
@@ -0,0 +1,198 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::{
+    common::{current_timestamp_6, datetime_6, datetime_6_null},
+    m20220101_000001_create_table::User,
+    m20250202_050205_notes::Note,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                TableCreateStatement::new()
+                    .table(NotePoll::Table)
+                    .col(uuid(NotePoll::NoteId).primary_key())
+                    .col(boolean(NotePoll::Multiple).default(Expr::value(false)))
+                    .col(datetime_6_null(NotePoll::ExpiresAt))
+                    .col(datetime_6_null(NotePoll::ClosedAt))
+                    .col(integer(NotePoll::VotersCount).default(Expr::value(0)))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_note_poll_note_id")
+                    .from(NotePoll::Table, NotePoll::NoteId)
+                    .to(Note::Table, Note::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                IndexCreateStatement::new()
+                    .name("idx_note_poll_expires_at_closed_at")
+                    .table(NotePoll::Table)
+                    .col(NotePoll::ExpiresAt)
+                    .col(NotePoll::ClosedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                TableCreateStatement::new()
+                    .table(NotePollOption::Table)
+                    .col(pk_auto(NotePollOption::Id))
+                    .col(uuid(NotePollOption::NoteId))
+                    .col(integer(NotePollOption::Position))
+                    .col(string_len(NotePollOption::Title, 256))
+                    .col(integer(NotePollOption::VotesCount).default(Expr::value(0)))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_note_poll_option_note_id")
+                    .from(NotePollOption::Table, NotePollOption::NoteId)
+                    .to(NotePoll::Table, NotePoll::NoteId)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                IndexCreateStatement::new()
+                    .name("idx_note_poll_option_unique")
+                    .table(NotePollOption::Table)
+                    .col(NotePollOption::NoteId)
+                    .col(NotePollOption::Position)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                TableCreateStatement::new()
+                    .table(NotePollVote::Table)
+                    .col(pk_auto(NotePollVote::Id))
+                    .col(integer(NotePollVote::OptionId))
+                    .col(uuid(NotePollVote::UserId))
+                    .col(datetime_6(NotePollVote::CreatedAt).default(current_timestamp_6()))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_note_poll_vote_option_id")
+                    .from(NotePollVote::Table, NotePollVote::OptionId)
+                    .to(NotePollOption::Table, NotePollOption::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_note_poll_vote_user_id")
+                    .from(NotePollVote::Table, NotePollVote::UserId)
+                    .to(User::Table, User::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                IndexCreateStatement::new()
+                    .name("idx_note_poll_vote_unique")
+                    .table(NotePollVote::Table)
+                    .col(NotePollVote::OptionId)
+                    .col(NotePollVote::UserId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(NotePollVote::Table)
+                    .cascade()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(NotePollOption::Table)
+                    .cascade()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(NotePoll::Table)
+                    .cascade()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum NotePoll {
+    Table,
+    NoteId,
+    Multiple,
+    ExpiresAt,
+    ClosedAt,
+    VotersCount,
+}
+
+#[derive(DeriveIden)]
+enum NotePollOption {
+    Table,
+    Id,
+    NoteId,
+    Position,
+    Title,
+    VotesCount,
+}
+
+#[derive(DeriveIden)]
+enum NotePollVote {
+    Table,
+    Id,
+    OptionId,
+    UserId,
+    CreatedAt,
+}
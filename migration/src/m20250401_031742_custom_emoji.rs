@@ -0,0 +1,81 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::{
+    common::{current_timestamp_6, datetime_6},
+    m20250211_132721_uploads::Upload,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                TableCreateStatement::new()
+                    .table(Emoji::Table)
+                    .col(pk_auto(Emoji::Id))
+                    .col(string_len(Emoji::Shortcode, 64))
+                    // ローカルの絵文字は空文字列
+                    .col(string_len(Emoji::Domain, 255).default(""))
+                    .col(uuid(Emoji::UploadId))
+                    .col(string_len_null(Emoji::Url, 512))
+                    .col(datetime_6(Emoji::CreatedAt).default(current_timestamp_6()))
+                    .col(datetime_6(Emoji::UpdatedAt).default(current_timestamp_6()))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_emoji_upload_id")
+                    .from(Emoji::Table, Emoji::UploadId)
+                    .to(Upload::Table, Upload::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                IndexCreateStatement::new()
+                    .name("idx_emoji_shortcode_domain_unique")
+                    .table(Emoji::Table)
+                    .col(Emoji::Shortcode)
+                    .col(Emoji::Domain)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(Emoji::Table)
+                    .cascade()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Emoji {
+    Table,
+    Id,
+    Shortcode,
+    Domain,
+    UploadId,
+    Url,
+    CreatedAt,
+    UpdatedAt,
+}
@@ -0,0 +1,65 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // NULL の場合は通常のお気に入り
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(NoteLike::Table)
+                    .add_column(string_len_null(NoteReaction::Reaction, 256))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                IndexCreateStatement::new()
+                    .name("idx_note_like_note_id_reaction")
+                    .table(NoteLike::Table)
+                    .col(NoteLike::NoteId)
+                    .col(NoteReaction::Reaction)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                IndexDropStatement::new()
+                    .name("idx_note_like_note_id_reaction")
+                    .table(NoteLike::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(NoteLike::Table)
+                    .drop_column(NoteReaction::Reaction)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum NoteReaction {
+    Reaction,
+}
+
+#[derive(DeriveIden)]
+enum NoteLike {
+    Table,
+    NoteId,
+}
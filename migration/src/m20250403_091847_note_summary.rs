@@ -0,0 +1,41 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::m20250202_050205_notes::Note;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // コンテンツ警告 (CW) の文言
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Note::Table)
+                    .add_column(text_null(NoteSummary::Summary))
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Note::Table)
+                    .drop_column(NoteSummary::Summary)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum NoteSummary {
+    Summary,
+}
@@ -0,0 +1,56 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::m20250211_132721_uploads::Upload;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Upload::Table)
+                    .add_column(text_null(UploadMetadata::Description))
+                    .add_column(integer_null(UploadMetadata::Width))
+                    .add_column(integer_null(UploadMetadata::Height))
+                    .add_column(string_len_null(UploadMetadata::Blurhash, 128))
+                    // フォーカルポイント (-1.0 ~ 1.0)
+                    .add_column(float_null(UploadMetadata::FocalX))
+                    .add_column(float_null(UploadMetadata::FocalY))
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Upload::Table)
+                    .drop_column(UploadMetadata::Description)
+                    .drop_column(UploadMetadata::Width)
+                    .drop_column(UploadMetadata::Height)
+                    .drop_column(UploadMetadata::Blurhash)
+                    .drop_column(UploadMetadata::FocalX)
+                    .drop_column(UploadMetadata::FocalY)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum UploadMetadata {
+    Description,
+    Width,
+    Height,
+    Blurhash,
+    FocalX,
+    FocalY,
+}
@@ -0,0 +1,66 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::m20250211_132721_uploads::Upload;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Upload::Table)
+                    // 動画・音声の長さ (秒)
+                    .add_column(double_null(UploadMedia::Duration))
+                    // 動画のサムネイル
+                    .add_column(uuid_null(UploadMedia::ThumbnailId))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_upload_thumbnail_id")
+                    .from(Upload::Table, UploadMedia::ThumbnailId)
+                    .to(Upload::Table, Upload::Id)
+                    .on_delete(ForeignKeyAction::SetNull)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_foreign_key(
+                ForeignKeyDropStatement::new()
+                    .name("fk_upload_thumbnail_id")
+                    .table(Upload::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Upload::Table)
+                    .drop_column(UploadMedia::Duration)
+                    .drop_column(UploadMedia::ThumbnailId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum UploadMedia {
+    Duration,
+    ThumbnailId,
+}
@@ -0,0 +1,41 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::m20250211_132721_uploads::Upload;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Upload::Table)
+                    // サムネイル・プレビュー画像が生成済みかどうか
+                    .add_column(boolean(UploadVariants::HasVariants).default(Expr::value(false)))
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Upload::Table)
+                    .drop_column(UploadVariants::HasVariants)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum UploadVariants {
+    HasVariants,
+}
@@ -0,0 +1,79 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::{
+    common::{current_timestamp_6, datetime_6},
+    m20250211_132721_uploads::Upload,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                TableCreateStatement::new()
+                    .table(RemoteMediaCache::Table)
+                    .col(uuid(RemoteMediaCache::UploadId).primary_key())
+                    .col(string_len(RemoteMediaCache::Filename, 64))
+                    // バリアントを含めた合計の大きさ (バイト)
+                    .col(big_unsigned(RemoteMediaCache::Size))
+                    .col(boolean(RemoteMediaCache::HasVariants).default(false))
+                    .col(datetime_6(RemoteMediaCache::CachedAt).default(current_timestamp_6()))
+                    .col(
+                        datetime_6(RemoteMediaCache::LastAccessedAt).default(current_timestamp_6()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_remote_media_cache_upload_id")
+                    .from(RemoteMediaCache::Table, RemoteMediaCache::UploadId)
+                    .to(Upload::Table, Upload::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                IndexCreateStatement::new()
+                    .name("idx_remote_media_cache_last_accessed_at")
+                    .table(RemoteMediaCache::Table)
+                    .col(RemoteMediaCache::LastAccessedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(RemoteMediaCache::Table)
+                    .cascade()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum RemoteMediaCache {
+    Table,
+    UploadId,
+    Filename,
+    Size,
+    HasVariants,
+    CachedAt,
+    LastAccessedAt,
+}
@@ -0,0 +1,123 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::{
+    common::{current_timestamp_6, datetime_6, URL_LENGTH},
+    m20220101_000001_create_table::User,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                TableCreateStatement::new()
+                    .table(OauthApp::Table)
+                    .col(pk_auto(OauthApp::Id))
+                    .col(string_len_uniq(OauthApp::ClientId, 64))
+                    .col(string_len(OauthApp::ClientSecret, 64))
+                    .col(string_len(OauthApp::Name, 255))
+                    .col(string_len_null(OauthApp::Website, URL_LENGTH))
+                    // 改行区切り
+                    .col(text(OauthApp::RedirectUris))
+                    // スペース区切り
+                    .col(string_len(OauthApp::Scopes, 255))
+                    .col(datetime_6(OauthApp::CreatedAt).default(current_timestamp_6()))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                TableCreateStatement::new()
+                    .table(OauthToken::Table)
+                    .col(pk_auto(OauthToken::Id))
+                    .col(integer(OauthToken::AppId))
+                    .col(uuid(OauthToken::UserId))
+                    // トークンそのものではなく SHA-256 ハッシュを保存する
+                    .col(string_len_uniq(OauthToken::AccessToken, 64))
+                    .col(string_len_null(OauthToken::RefreshToken, 64).unique_key())
+                    .col(string_len(OauthToken::Scopes, 255))
+                    .col(datetime_6(OauthToken::CreatedAt).default(current_timestamp_6()))
+                    .col(datetime_6(OauthToken::ExpiresAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_oauth_token_app_id")
+                    .from(OauthToken::Table, OauthToken::AppId)
+                    .to(OauthApp::Table, OauthApp::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_oauth_token_user_id")
+                    .from(OauthToken::Table, OauthToken::UserId)
+                    .to(User::Table, User::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(OauthToken::Table)
+                    .cascade()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(OauthApp::Table)
+                    .cascade()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum OauthApp {
+    Table,
+    Id,
+    ClientId,
+    ClientSecret,
+    Name,
+    Website,
+    RedirectUris,
+    Scopes,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum OauthToken {
+    Table,
+    Id,
+    AppId,
+    UserId,
+    AccessToken,
+    RefreshToken,
+    Scopes,
+    CreatedAt,
+    ExpiresAt,
+}
@@ -0,0 +1,70 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::{
+    common::{current_timestamp_6, datetime_6, datetime_6_null},
+    m20220101_000001_create_table::User,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                TableCreateStatement::new()
+                    .table(PersonalAccessToken::Table)
+                    .col(pk_auto(PersonalAccessToken::Id))
+                    .col(uuid(PersonalAccessToken::UserId))
+                    .col(string_len(PersonalAccessToken::Name, 64))
+                    // トークンそのものではなく SHA-256 ハッシュを保存する
+                    .col(string_len_uniq(PersonalAccessToken::Token, 64))
+                    // スペース区切り
+                    .col(string_len(PersonalAccessToken::Scopes, 255))
+                    .col(datetime_6(PersonalAccessToken::CreatedAt).default(current_timestamp_6()))
+                    .col(datetime_6_null(PersonalAccessToken::LastUsedAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_personal_access_token_user_id")
+                    .from(PersonalAccessToken::Table, PersonalAccessToken::UserId)
+                    .to(User::Table, User::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(PersonalAccessToken::Table)
+                    .cascade()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum PersonalAccessToken {
+    Table,
+    Id,
+    UserId,
+    Name,
+    Token,
+    Scopes,
+    CreatedAt,
+    LastUsedAt,
+}
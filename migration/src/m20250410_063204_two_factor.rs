@@ -0,0 +1,110 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::{
+    common::{current_timestamp_6, datetime_6, datetime_6_null},
+    m20220101_000001_create_table::User,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                TableCreateStatement::new()
+                    .table(UserTotp::Table)
+                    .col(uuid(UserTotp::UserId).primary_key())
+                    // Base32 でエンコードした共有鍵
+                    .col(string_len(UserTotp::Secret, 64))
+                    // 確認用のコードを入力するまでは無効
+                    .col(boolean(UserTotp::Enabled).default(false))
+                    // 同じコードを再利用できないように、最後に使用したタイムステップを記録する
+                    .col(big_integer_null(UserTotp::LastUsedStep))
+                    .col(datetime_6(UserTotp::CreatedAt).default(current_timestamp_6()))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_user_totp_user_id")
+                    .from(UserTotp::Table, UserTotp::UserId)
+                    .to(User::Table, User::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                TableCreateStatement::new()
+                    .table(UserRecoveryCode::Table)
+                    .col(pk_auto(UserRecoveryCode::Id))
+                    .col(uuid(UserRecoveryCode::UserId))
+                    // リカバリーコードの SHA-256 ハッシュ
+                    .col(string_len(UserRecoveryCode::Code, 64))
+                    .col(datetime_6_null(UserRecoveryCode::UsedAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_user_recovery_code_user_id")
+                    .from(UserRecoveryCode::Table, UserRecoveryCode::UserId)
+                    .to(User::Table, User::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(UserRecoveryCode::Table)
+                    .cascade()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(UserTotp::Table)
+                    .cascade()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum UserTotp {
+    Table,
+    UserId,
+    Secret,
+    Enabled,
+    LastUsedStep,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum UserRecoveryCode {
+    Table,
+    Id,
+    UserId,
+    Code,
+    UsedAt,
+}
@@ -0,0 +1,72 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::{
+    common::{current_timestamp_6, datetime_6, datetime_6_null},
+    m20220101_000001_create_table::User,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                TableCreateStatement::new()
+                    .table(WebauthnCredential::Table)
+                    .col(pk_auto(WebauthnCredential::Id))
+                    .col(uuid(WebauthnCredential::UserId))
+                    .col(string_len(WebauthnCredential::Name, 64))
+                    // Base64URL でエンコードしたクレデンシャル ID
+                    .col(string_len_uniq(WebauthnCredential::CredentialId, 512))
+                    // Base64URL でエンコードした P-256 公開鍵 (SEC1 非圧縮形式)
+                    .col(string_len(WebauthnCredential::PublicKey, 128))
+                    .col(big_integer(WebauthnCredential::SignCount).default(0))
+                    .col(datetime_6(WebauthnCredential::CreatedAt).default(current_timestamp_6()))
+                    .col(datetime_6_null(WebauthnCredential::LastUsedAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_webauthn_credential_user_id")
+                    .from(WebauthnCredential::Table, WebauthnCredential::UserId)
+                    .to(User::Table, User::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(WebauthnCredential::Table)
+                    .cascade()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum WebauthnCredential {
+    Table,
+    Id,
+    UserId,
+    Name,
+    CredentialId,
+    PublicKey,
+    SignCount,
+    CreatedAt,
+    LastUsedAt,
+}
@@ -0,0 +1,84 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::{
+    common::{current_timestamp_6, datetime_6},
+    m20220101_000001_create_table::User,
+    m20250211_132721_uploads::Upload,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // ノートに添付される前のアップロード。添付されないまま期限を過ぎたものは削除される。
+        manager
+            .create_table(
+                TableCreateStatement::new()
+                    .table(PendingUpload::Table)
+                    .col(uuid(PendingUpload::UploadId).primary_key())
+                    .col(uuid(PendingUpload::UserId))
+                    .col(datetime_6(PendingUpload::CreatedAt).default(current_timestamp_6()))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_pending_upload_upload_id")
+                    .from(PendingUpload::Table, PendingUpload::UploadId)
+                    .to(Upload::Table, Upload::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKeyCreateStatement::new()
+                    .name("fk_pending_upload_user_id")
+                    .from(PendingUpload::Table, PendingUpload::UserId)
+                    .to(User::Table, User::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                IndexCreateStatement::new()
+                    .name("idx_pending_upload_created_at")
+                    .table(PendingUpload::Table)
+                    .col(PendingUpload::CreatedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(PendingUpload::Table)
+                    .cascade()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum PendingUpload {
+    Table,
+    UploadId,
+    UserId,
+    CreatedAt,
+}
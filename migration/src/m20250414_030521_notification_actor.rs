@@ -0,0 +1,107 @@
+use sea_orm_migration::{
+    prelude::*,
+    schema::*,
+    sea_orm::{ConnectionTrait, Statement},
+};
+
+use crate::m20250210_064038_notification::Notification;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// 既存の通知を埋める際に、一度に読み込む件数
+const BACKFILL_BATCH_SIZE: i64 = 1000;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 通知のきっかけとなったユーザー。ミュートの絞り込みに使う。
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Notification::Table)
+                    .add_column(uuid_null(NotificationActor::ActorId))
+                    .to_owned(),
+            )
+            .await?;
+
+        backfill_actor_id(manager).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Notification::Table)
+                    .drop_column(NotificationActor::ActorId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// 既存の通知の actor_id を本文の JSON から埋める。
+/// 本文は `{"Followed":"<ULID>"}` や `{"Replied":["<ULID>",...]}` の形式で、最初の ID がきっかけとなったユーザー。
+async fn backfill_actor_id(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    let db = manager.get_connection();
+    let backend = manager.get_database_backend();
+
+    let mut last_id = 0;
+    loop {
+        let rows = db
+            .query_all(Statement::from_sql_and_values(
+                backend,
+                "SELECT id, body FROM notification WHERE id > ? ORDER BY id LIMIT ?",
+                [last_id.into(), BACKFILL_BATCH_SIZE.into()],
+            ))
+            .await?;
+        if rows.is_empty() {
+            break;
+        }
+
+        for row in &rows {
+            let id: i32 = row.try_get("", "id")?;
+            let body: String = row.try_get("", "body")?;
+            last_id = id;
+
+            let Some(actor_id) = body.split('"').nth(3).and_then(decode_ulid) else {
+                continue;
+            };
+            db.execute(Statement::from_sql_and_values(
+                backend,
+                "UPDATE notification SET actor_id = ? WHERE id = ?",
+                [actor_id.to_vec().into(), id.into()],
+            ))
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Crockford's Base32 で表された ULID をバイト列に変換する。
+fn decode_ulid(s: &str) -> Option<[u8; 16]> {
+    const ALPHABET: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+    if s.len() != 26 {
+        return None;
+    }
+    let mut value: u128 = 0;
+    for (i, c) in s.bytes().enumerate() {
+        let digit = ALPHABET.iter().position(|a| *a == c.to_ascii_uppercase())?;
+        // 先頭の文字は 3 ビットまでしか使えない
+        if i == 0 && digit > 7 {
+            return None;
+        }
+        value = (value << 5) | digit as u128;
+    }
+    Some(value.to_be_bytes())
+}
+
+#[derive(DeriveIden)]
+enum NotificationActor {
+    ActorId,
+}
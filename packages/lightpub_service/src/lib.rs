@@ -21,7 +21,8 @@ use std::{borrow::Cow, fmt::Debug};
 use activitypub_federation::config::FederationConfig;
 use derive_more::Constructor;
 use services::{
-    db::MaybeTxConn, fulltext::FTClient, kv::KVObject, notification::push::WPClient, queue::QConn,
+    db::MaybeTxConn, fulltext::FTClient, kv::KVObject, media_cache::MediaCacheConfig,
+    notification::push::WPClient, queue::QConn, storage::StorageObject,
 };
 use url::Url;
 
@@ -91,6 +92,14 @@ impl ServiceState {
     pub fn wp(&self) -> Option<&WPClient> {
         self.base.wp()
     }
+
+    pub fn storage(&self) -> &StorageObject {
+        self.base.storage()
+    }
+
+    pub fn media_cache(&self) -> &MediaCacheConfig {
+        self.base.media_cache()
+    }
 }
 
 #[derive(Clone, Constructor)]
@@ -104,6 +113,8 @@ pub struct ServiceStateBase {
     proxy_client: reqwest_middleware::ClientWithMiddleware,
     ft: Option<FTClient>,
     webpush: Option<WPClient>,
+    storage: StorageObject,
+    media_cache: MediaCacheConfig,
 }
 
 impl std::fmt::Debug for ServiceStateBase {
@@ -157,4 +168,12 @@ impl ServiceStateBase {
     pub fn wp(&self) -> Option<&WPClient> {
         self.webpush.as_ref()
     }
+
+    pub fn storage(&self) -> &StorageObject {
+        &self.storage
+    }
+
+    pub fn media_cache(&self) -> &MediaCacheConfig {
+        &self.media_cache
+    }
 }
@@ -0,0 +1,235 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! パーソナルアクセストークン
+//!
+//! Bot やスクリプトから API を使うために、ユーザーが自分で発行するトークン。
+//! OAuth のトークンと同じスコープを持ち、有効期限はないが個別に無効にできる。
+//! すべての端末からのログアウト (パスワードの変更を含む) やアカウントの削除でも無効になる。
+
+use actix_web::http::StatusCode;
+use chrono::{DateTime, Utc};
+use expected_error_derive::ExpectedError;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, EntityTrait, PaginatorTrait,
+    QueryFilter, QueryOrder, Set,
+};
+use serde::Serialize;
+use thiserror::Error;
+
+use super::{
+    MapToUnknown, ServiceError, ServiceResult,
+    db::{Conn, MaybeTxConn},
+    id::{Identifier, UserID},
+    oauth::{OAuthScopes, generate_token, hash_token},
+};
+
+/// パーソナルアクセストークンの接頭辞。
+/// ログイン時の JWT や OAuth のトークンと区別するために使う。
+pub const PERSONAL_ACCESS_TOKEN_PREFIX: &str = "lpat_";
+
+/// 1 ユーザーが発行できるトークンの数
+const MAX_TOKENS_PER_USER: u64 = 50;
+
+const TOKEN_NAME_MAX_LENGTH: usize = 64;
+
+/// 最終使用日時を更新する間隔 (秒)。
+/// リクエストのたびにデータベースを更新しないようにする。
+const LAST_USED_UPDATE_INTERVAL_SECS: i64 = 60;
+
+#[derive(Debug, Clone, Error, ExpectedError)]
+pub enum PersonalAccessTokenError {
+    #[error("invalid token name")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    InvalidName,
+    #[error("too many tokens")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    TooManyTokens,
+    #[error("token not found")]
+    #[ee(status(StatusCode::NOT_FOUND))]
+    NotFound,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PersonalAccessTokenModel {
+    pub id: i32,
+    pub name: String,
+    pub scopes: OAuthScopes,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl PersonalAccessTokenModel {
+    fn from_db(model: entity::personal_access_token::Model) -> ServiceResult<Self> {
+        Ok(Self {
+            id: model.id,
+            name: model.name,
+            scopes: OAuthScopes::parse(&model.scopes)?,
+            created_at: model.created_at.and_utc(),
+            last_used_at: model.last_used_at.map(|t| t.and_utc()),
+        })
+    }
+}
+
+/// トークンを発行する。
+/// 発行したトークンはここでしか取得できない。
+pub async fn create_personal_access_token(
+    conn: &Conn,
+    user_id: UserID,
+    name: &str,
+    scopes: &OAuthScopes,
+) -> ServiceResult<(PersonalAccessTokenModel, String)> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > TOKEN_NAME_MAX_LENGTH {
+        return Err(ServiceError::known(PersonalAccessTokenError::InvalidName));
+    }
+
+    let count = entity::personal_access_token::Entity::find()
+        .filter(entity::personal_access_token::Column::UserId.eq(user_id.as_db()))
+        .count(conn.db())
+        .await
+        .map_err_unknown()?;
+    if count >= MAX_TOKENS_PER_USER {
+        return Err(ServiceError::known(PersonalAccessTokenError::TooManyTokens));
+    }
+
+    let token = format!("{}{}", PERSONAL_ACCESS_TOKEN_PREFIX, generate_token());
+    let model = entity::personal_access_token::ActiveModel {
+        user_id: Set(user_id.as_db()),
+        name: Set(name.to_string()),
+        token: Set(hash_token(&token)),
+        scopes: Set(scopes.to_string()),
+        created_at: Set(Utc::now().naive_utc()),
+        ..Default::default()
+    };
+    let model = model.insert(conn.db()).await.map_err_unknown()?;
+
+    Ok((PersonalAccessTokenModel::from_db(model)?, token))
+}
+
+pub async fn get_personal_access_tokens(
+    tx: &MaybeTxConn,
+    user_id: UserID,
+) -> ServiceResult<Vec<PersonalAccessTokenModel>> {
+    entity::personal_access_token::Entity::find()
+        .filter(entity::personal_access_token::Column::UserId.eq(user_id.as_db()))
+        .order_by_desc(entity::personal_access_token::Column::CreatedAt)
+        .all(tx)
+        .await
+        .map_err_unknown()?
+        .into_iter()
+        .map(PersonalAccessTokenModel::from_db)
+        .collect()
+}
+
+/// ユーザーのすべてのトークンを無効にする。
+pub(crate) async fn revoke_all_personal_access_tokens(
+    tx: &impl ConnectionTrait,
+    user_id: UserID,
+) -> ServiceResult<()> {
+    entity::personal_access_token::Entity::delete_many()
+        .filter(entity::personal_access_token::Column::UserId.eq(user_id.as_db()))
+        .exec(tx)
+        .await
+        .map_err_unknown()?;
+    Ok(())
+}
+
+pub async fn revoke_personal_access_token(
+    conn: &Conn,
+    user_id: UserID,
+    token_id: i32,
+) -> ServiceResult<()> {
+    let result = entity::personal_access_token::Entity::delete_many()
+        .filter(
+            Condition::all()
+                .add(entity::personal_access_token::Column::Id.eq(token_id))
+                .add(entity::personal_access_token::Column::UserId.eq(user_id.as_db())),
+        )
+        .exec(conn.db())
+        .await
+        .map_err_unknown()?;
+
+    if result.rows_affected == 0 {
+        return Err(ServiceError::known(PersonalAccessTokenError::NotFound));
+    }
+    Ok(())
+}
+
+/// パーソナルアクセストークンで認証されたユーザー
+#[derive(Debug, Clone)]
+pub struct PersonalAccessTokenUser {
+    pub user_id: UserID,
+    pub token_id: i32,
+    pub scopes: OAuthScopes,
+}
+
+/// トークンを検証して、最終使用日時を更新する。
+/// 無効なトークンの場合は None を返す。
+pub async fn authenticate_personal_access_token(
+    conn: &Conn,
+    token: &str,
+) -> ServiceResult<Option<PersonalAccessTokenUser>> {
+    let model = entity::personal_access_token::Entity::find()
+        .filter(entity::personal_access_token::Column::Token.eq(hash_token(token)))
+        .one(conn.db())
+        .await
+        .map_err_unknown()?;
+    let Some(model) = model else {
+        return Ok(None);
+    };
+
+    // 削除されたユーザーや、発行後にすべての端末からログアウトしたユーザーのトークンは使えない
+    let user = entity::user::Entity::find_by_id(model.user_id.clone())
+        .one(conn.db())
+        .await
+        .map_err_unknown()?;
+    let Some(user) = user else {
+        return Ok(None);
+    };
+    if user.deleted_at.is_some()
+        || user
+            .auth_expired_at
+            .is_some_and(|expired_at| expired_at >= model.created_at)
+    {
+        return Ok(None);
+    }
+
+    let now = Utc::now().naive_utc();
+    let needs_update = model
+        .last_used_at
+        .is_none_or(|t| (now - t).num_seconds() >= LAST_USED_UPDATE_INTERVAL_SECS);
+    if needs_update {
+        entity::personal_access_token::Entity::update_many()
+            .col_expr(
+                entity::personal_access_token::Column::LastUsedAt,
+                sea_orm::sea_query::Expr::value(now),
+            )
+            .filter(entity::personal_access_token::Column::Id.eq(model.id))
+            .exec(conn.db())
+            .await
+            .map_err_unknown()?;
+    }
+
+    Ok(Some(PersonalAccessTokenUser {
+        user_id: UserID::from_db_trusted(model.user_id),
+        token_id: model.id,
+        scopes: OAuthScopes::parse(&model.scopes)?,
+    }))
+}
@@ -0,0 +1,74 @@
+use activitypub_federation::{
+    config::Data, fetch::object_id::ObjectId, kinds::activity::BlockType, traits::ActivityHandler,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    MyFederationData,
+    services::{
+        FederationServiceError,
+        user::{UserWithApubModel, block_user},
+    },
+};
+
+use super::local_object_check;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockActivity {
+    pub actor: ObjectId<UserWithApubModel>,
+    pub object: ObjectId<UserWithApubModel>,
+    #[serde(rename = "type")]
+    pub kind: BlockType,
+    pub id: Url,
+}
+
+impl BlockActivity {
+    pub fn new(id: i32, blocker: Url, blocked: Url, base_url: &Url) -> Self {
+        let id = base_url.join(format!("block/{}", id).as_str()).unwrap();
+        BlockActivity {
+            id,
+            kind: BlockType::Block,
+            actor: ObjectId::from(blocker),
+            object: ObjectId::from(blocked),
+        }
+    }
+}
+
+#[async_trait]
+impl ActivityHandler for BlockActivity {
+    type DataType = MyFederationData;
+    type Error = FederationServiceError;
+
+    fn id(&self) -> &Url {
+        &self.id
+    }
+
+    fn actor(&self) -> &Url {
+        self.actor.inner()
+    }
+
+    async fn verify(&self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        // ブロックされるのはこのサーバーのユーザーだけ
+        local_object_check(self.object.inner(), &data.my_domain())?;
+        self.object.dereference_local(data).await?;
+        Ok(())
+    }
+
+    async fn receive(self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        let actor = self.actor.dereference(data).await?;
+        let blocked = self.object.dereference(data).await?;
+        block_user(
+            data.conn(),
+            &data.rconn(),
+            data.qconn(),
+            actor.basic.id,
+            blocked.basic.id,
+            data.base_url(),
+        )
+        .await?;
+        Ok(())
+    }
+}
@@ -12,7 +12,7 @@ use crate::{
     MyFederationData,
     services::{
         FederationServiceError,
-        note::{ApubNoteModel, NoteWithApubModel},
+        note::{ApubNoteModel, NoteWithApubModel, receive_poll_vote},
         user::UserWithApubModel,
     },
 };
@@ -84,6 +84,22 @@ impl ActivityHandler for CreateActivity {
     async fn receive(self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
         match self.object {
             CreatableObject::Note(note) => {
+                // 投票は通常のノートとして保存しない
+                if let Some((poll_url, name)) = note.as_poll_vote() {
+                    let voter = self.actor.dereference(data).await?;
+                    if receive_poll_vote(
+                        data.conn(),
+                        voter.basic.id,
+                        poll_url,
+                        name,
+                        &data.my_domain(),
+                    )
+                    .await?
+                    {
+                        return Ok(());
+                    }
+                }
+
                 // save note
                 let _ = NoteWithApubModel::from_json(note, data).await?;
             }
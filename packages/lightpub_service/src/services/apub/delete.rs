@@ -6,7 +6,8 @@ use activitypub_federation::{
 };
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use tracing::warn;
 use url::Url;
 
 use crate::{
@@ -22,6 +23,7 @@ pub struct DeleteActivity {
     pub kind: DeleteType,
     pub actor: ObjectId<UserWithApubModel>,
     pub published: Option<DateTime<Utc>>,
+    #[serde(deserialize_with = "deserialize_delete_object")]
     pub object: Tombstone,
 }
 
@@ -32,6 +34,28 @@ pub struct Tombstone {
     pub kind: TombstoneType,
 }
 
+/// object には Tombstone の他に、URL の文字列や削除されたアクターのオブジェクトそのものが送られてくることがある
+fn deserialize_delete_object<'de, D>(deserializer: D) -> Result<Tombstone, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum ObjectOrId {
+        Id(Url),
+        Object { id: Url },
+    }
+
+    let id = match ObjectOrId::deserialize(deserializer)? {
+        ObjectOrId::Id(id) => id,
+        ObjectOrId::Object { id } => id,
+    };
+    Ok(Tombstone {
+        id,
+        kind: TombstoneType::Tombstone,
+    })
+}
+
 impl DeleteActivity {
     pub fn from_note(
         target_note: ObjectId<NoteWithApubModel>,
@@ -50,6 +74,20 @@ impl DeleteActivity {
             published: Some(deleted_at),
         }
     }
+
+    pub fn from_user(actor: ObjectId<UserWithApubModel>, deleted_at: DateTime<Utc>) -> Self {
+        let delete_id = Url::parse(&format!("{}#delete", actor.inner())).unwrap();
+        Self {
+            id: delete_id,
+            kind: DeleteType::Delete,
+            object: Tombstone {
+                id: actor.inner().clone(),
+                kind: TombstoneType::Tombstone,
+            },
+            actor,
+            published: Some(deleted_at),
+        }
+    }
 }
 
 #[async_trait]
@@ -70,15 +108,31 @@ impl ActivityHandler for DeleteActivity {
     }
 
     async fn receive(self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        use activitypub_federation::error::Error;
+
         // try to get object as note
         let note_id = ObjectId::<NoteWithApubModel>::from(self.object.id.clone());
         let note = note_id.dereference_local(data).await;
-
-        use activitypub_federation::error::Error;
         match note {
-            Ok(n) => n.delete(data).await,
+            Ok(n) => return n.delete(data).await,
+            Err(FederationServiceError::FederationError(Error::NotFound)) => {}
+            Err(e) => return Err(e),
+        }
+
+        // try to get object as user
+        let user_id = ObjectId::<UserWithApubModel>::from(self.object.id.clone());
+        let user = user_id.dereference_local(data).await;
+        match user {
+            Ok(u) => {
+                // アクターは自分自身しか削除できない
+                if self.actor.inner() != &self.object.id {
+                    warn!("actor {} tried to delete {}", self.actor, self.object.id);
+                    return Ok(());
+                }
+                u.delete(data).await
+            }
             Err(FederationServiceError::FederationError(Error::NotFound)) => Ok(()),
-            Err(e) => Err(e.into()),
+            Err(e) => Err(e),
         }
     }
 }
@@ -0,0 +1,144 @@
+use activitypub_federation::{
+    config::Data, fetch::object_id::ObjectId, kinds::activity::FlagType,
+    protocol::helpers::deserialize_one_or_many, traits::ActivityHandler,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use url::Url;
+
+use crate::{
+    MyFederationData,
+    services::{
+        FederationServiceError, ServiceError, note::NoteWithApubModel,
+        report::receive_remote_report, user::UserWithApubModel,
+    },
+};
+
+use super::{ApubError, local_object_check};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlagActivity {
+    pub id: Url,
+    #[serde(rename = "type")]
+    pub kind: FlagType,
+    pub actor: ObjectId<UserWithApubModel>,
+    /// 通報対象のユーザーとノートの URL
+    #[serde(deserialize_with = "deserialize_one_or_many")]
+    pub object: Vec<Url>,
+    #[serde(default)]
+    pub content: Option<String>,
+}
+
+impl FlagActivity {
+    pub fn new(
+        id: i32,
+        actor: Url,
+        target_user: Url,
+        notes: impl IntoIterator<Item = Url>,
+        content: String,
+        base_url: &Url,
+    ) -> Self {
+        let flag_id = base_url.join(&format!("report/{}", id)).unwrap();
+        let mut object = vec![target_user];
+        object.extend(notes);
+        Self {
+            id: flag_id,
+            kind: FlagType::Flag,
+            actor: ObjectId::from(actor),
+            object,
+            content: Some(content),
+        }
+    }
+}
+
+#[async_trait]
+impl ActivityHandler for FlagActivity {
+    type DataType = MyFederationData;
+    type Error = FederationServiceError;
+
+    fn id(&self) -> &Url {
+        &self.id
+    }
+
+    fn actor(&self) -> &Url {
+        self.actor.inner()
+    }
+
+    async fn verify(&self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        // 通報できるのはこのサーバーのユーザーとノートだけ
+        for url in &self.object {
+            local_object_check(url, &data.my_domain())?;
+        }
+
+        let mut known = false;
+        for url in &self.object {
+            let user = ObjectId::<UserWithApubModel>::from(url.clone())
+                .dereference_local(data)
+                .await;
+            let note = ObjectId::<NoteWithApubModel>::from(url.clone())
+                .dereference_local(data)
+                .await;
+            if user.is_ok() || note.is_ok() {
+                known = true;
+                break;
+            }
+        }
+        if !known {
+            return Err(ServiceError::known(ApubError::NonLocalObject).into());
+        }
+
+        Ok(())
+    }
+
+    async fn receive(self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        let actor = self.actor.dereference(data).await?;
+
+        // object にはユーザーとノートが混在するので、ローカルに存在するものだけを拾う
+        let mut target_user = None;
+        let mut notes = vec![];
+        for url in self.object {
+            let user = ObjectId::<UserWithApubModel>::from(url.clone())
+                .dereference_local(data)
+                .await;
+            if let Ok(user) = user {
+                target_user.get_or_insert(user.basic.id);
+                continue;
+            }
+            let note = ObjectId::<NoteWithApubModel>::from(url.clone())
+                .dereference_local(data)
+                .await;
+            match note {
+                Ok(note) => notes.push(note),
+                Err(_) => warn!("unknown object in flag (skipped): {url}"),
+            }
+        }
+
+        // ユーザーが明示されていない場合はノートの作成者を対象とする
+        let target_user = match target_user.or_else(|| notes.first().map(|n| n.basic.author.id)) {
+            Some(u) => u,
+            None => {
+                warn!("flag {} has no known target, ignored", self.id);
+                return Ok(());
+            }
+        };
+        let note_ids = notes
+            .into_iter()
+            .filter(|n| n.basic.author.id == target_user && n.basic.content.is_some())
+            .map(|n| n.basic.id)
+            .collect::<Vec<_>>();
+
+        receive_remote_report(
+            &data.maybe_conn(),
+            actor.basic.id,
+            target_user,
+            &note_ids,
+            self.content.as_deref().unwrap_or(""),
+            &self.id,
+        )
+        .await?;
+
+        Ok(())
+    }
+}
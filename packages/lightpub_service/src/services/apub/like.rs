@@ -1,5 +1,8 @@
 use activitypub_federation::{
-    config::Data, fetch::object_id::ObjectId, kinds::activity::LikeType, traits::ActivityHandler,
+    config::Data,
+    fetch::object_id::ObjectId,
+    kinds::{activity::LikeType, kind},
+    traits::ActivityHandler,
 };
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
@@ -9,18 +12,41 @@ use crate::{
     MyFederationData,
     services::{
         FederationServiceError,
-        note::{NoteWithApubModel, note_like_add},
+        emoji::{ApubEmojiTag, ApubMaybeEmojiTag},
+        note::{NoteWithApubModel, normalize_remote_reaction, note_like_add},
         user::UserWithApubModel,
     },
 };
 
+kind!(EmojiReactType, EmojiReact);
+
+/// Pleroma は絵文字リアクションに `EmojiReact` を使う
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LikeActivityKind {
+    Like(LikeType),
+    EmojiReact(EmojiReactType),
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LikeActivity {
     pub id: Url,
     #[serde(rename = "type")]
-    pub kind: LikeType,
+    pub kind: LikeActivityKind,
     pub actor: ObjectId<UserWithApubModel>,
     pub object: LikeableObject,
+    /// 絵文字リアクション
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// Misskey の絵文字リアクション
+    #[serde(
+        default,
+        rename = "_misskey_reaction",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub misskey_reaction: Option<String>,
+    #[serde(default, rename = "tag", skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<ApubMaybeEmojiTag>,
 }
 
 impl LikeActivity {
@@ -29,11 +55,35 @@ impl LikeActivity {
         let like_id = base_url.join(&format!("like/{}", id)).unwrap();
         Self {
             id: like_id,
-            kind: LikeType::Like,
+            kind: LikeActivityKind::Like(Default::default()),
             actor: ObjectId::from(actor),
             object,
+            content: None,
+            misskey_reaction: None,
+            tags: vec![],
         }
     }
+
+    /// 絵文字リアクションとして送信する。
+    /// Misskey と Pleroma の両方が解釈できるように `content` と `_misskey_reaction` の両方を設定する。
+    pub fn with_reaction(mut self, reaction: String, emoji: Option<ApubEmojiTag>) -> Self {
+        self.content = Some(reaction.clone());
+        self.misskey_reaction = Some(reaction);
+        self.tags = emoji
+            .into_iter()
+            .map(|e| ApubMaybeEmojiTag::Emoji(Box::new(e)))
+            .collect();
+        self
+    }
+
+    /// 受信したアクティビティに含まれる絵文字リアクション
+    pub fn reaction(&self) -> Option<&str> {
+        self.misskey_reaction
+            .as_deref()
+            .or(self.content.as_deref())
+            .map(|r| r.trim())
+            .filter(|r| !r.is_empty())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -78,16 +128,30 @@ impl ActivityHandler for LikeActivity {
     }
 
     async fn receive(self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
-        match self.object {
+        match &self.object {
             LikeableObject::Note(note) => {
                 let note = note.dereference(data).await?;
                 let actor = self.actor.dereference(data).await?;
+                let reaction = match self.reaction() {
+                    None => None,
+                    Some(reaction) => {
+                        normalize_remote_reaction(
+                            &data.maybe_conn(),
+                            data.proxy_client(),
+                            self.actor.inner().domain().expect("domain must be set"),
+                            reaction,
+                            &ApubMaybeEmojiTag::emojis(&self.tags),
+                        )
+                        .await?
+                    }
+                };
                 note_like_add(
                     data.conn(),
                     data.qconn(),
                     actor.basic.id,
                     note.basic.id,
                     false,
+                    reaction,
                     &data.my_domain(),
                     data.base_url(),
                 )
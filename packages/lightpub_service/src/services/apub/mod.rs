@@ -25,10 +25,13 @@ use url::Url;
 
 mod accept;
 mod announce;
+mod block;
 mod create;
 mod delete;
+mod flag;
 mod follow;
 mod like;
+mod move_account;
 mod reject;
 mod reporter;
 mod undo;
@@ -38,10 +41,13 @@ use super::{ServiceError, ServiceResult};
 
 pub use accept::{AcceptActivity, AcceptableObject};
 pub use announce::{AnnounceActivity, AnnounceableObject};
+pub use block::BlockActivity;
 pub use create::{CreatableObject, CreateActivity};
 pub use delete::DeleteActivity;
+pub use flag::FlagActivity;
 pub use follow::FollowActivity;
 pub use like::{LikeActivity, LikeableObject};
+pub use move_account::MoveActivity;
 pub use reject::{RejectActivity, RejectableObject};
 pub use reporter::report_apub_error;
 pub use undo::{UndoActivity, UndoableObject};
@@ -52,6 +58,9 @@ pub enum ApubError {
     #[error("activity actor and object actor do not match")]
     #[ee(status(StatusCode::FORBIDDEN))]
     ActorMismatch,
+    #[error("activity object is not a local object")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    NonLocalObject,
 }
 
 fn actor_check(a1: &Url, a2: &Url) -> ServiceResult<()> {
@@ -61,6 +70,13 @@ fn actor_check(a1: &Url, a2: &Url) -> ServiceResult<()> {
     Ok(())
 }
 
+fn local_object_check(object: &Url, my_domain: &str) -> ServiceResult<()> {
+    if object.domain() != Some(my_domain) {
+        return Err(ServiceError::known(ApubError::NonLocalObject));
+    }
+    Ok(())
+}
+
 const PUBLIC_URL_: &'static str = "https://www.w3.org/ns/activitystreams#Public";
 
 pub static PUBLIC_URL: Lazy<Url> = Lazy::new(|| Url::parse(PUBLIC_URL_).unwrap());
@@ -0,0 +1,103 @@
+use activitypub_federation::{
+    config::Data, fetch::object_id::ObjectId, kinds::activity::MoveType, traits::ActivityHandler,
+};
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use url::Url;
+
+use crate::{
+    MyFederationData,
+    services::{
+        FederationServiceError,
+        user::{UserWithApubModel, receive_user_move},
+    },
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveActivity {
+    pub id: Url,
+    #[serde(rename = "type")]
+    pub kind: MoveType,
+    pub actor: ObjectId<UserWithApubModel>,
+    /// 移行元のアカウント
+    pub object: ObjectId<UserWithApubModel>,
+    /// 移行先のアカウント
+    pub target: ObjectId<UserWithApubModel>,
+}
+
+impl MoveActivity {
+    pub fn new(actor: Url, target: Url) -> Self {
+        let id = Url::parse(&format!("{}#move/{}", actor, Utc::now().timestamp_millis())).unwrap();
+        Self {
+            id,
+            kind: MoveType::Move,
+            actor: ObjectId::from(actor.clone()),
+            object: ObjectId::from(actor),
+            target: ObjectId::from(target),
+        }
+    }
+}
+
+#[async_trait]
+impl ActivityHandler for MoveActivity {
+    type DataType = MyFederationData;
+    type Error = FederationServiceError;
+
+    fn id(&self) -> &Url {
+        &self.id
+    }
+
+    fn actor(&self) -> &Url {
+        self.actor.inner()
+    }
+
+    async fn verify(&self, _data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn receive(self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        // 他人のアカウントを移行させることはできない
+        if self.actor.inner() != self.object.inner() {
+            warn!("move {} has a different actor and object, ignored", self.id);
+            return Ok(());
+        }
+
+        let old_user = self.object.dereference(data).await?;
+        if old_user.is_local() {
+            warn!("move of local user {} ignored", old_user.basic.id);
+            return Ok(());
+        }
+
+        // 移行先の alsoKnownAs を確認するため、最新の情報を取得する
+        let new_user = if self.target.inner().domain() == Some(data.my_domain().as_ref()) {
+            self.target.dereference_local(data).await?
+        } else {
+            self.target.dereference_forced(data).await?
+        };
+        if new_user.basic.id == old_user.basic.id
+            || !new_user.apub.also_known_as.contains(&old_user.apub.url)
+        {
+            warn!(
+                "move target {} does not list {} in alsoKnownAs, ignored",
+                new_user.apub.url, old_user.apub.url
+            );
+            return Ok(());
+        }
+
+        receive_user_move(
+            data.conn(),
+            &data.rconn(),
+            data.qconn(),
+            data.wp(),
+            old_user.basic.id,
+            new_user.basic.id,
+            data.base_url(),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
@@ -12,12 +12,12 @@ use crate::{
         FederationServiceError,
         follow::unfollow_user,
         note::{delete_renote_by_id, note_like_remove},
-        user::UserWithApubModel,
+        user::{UserWithApubModel, unblock_user},
     },
 };
 
 use super::{
-    AnnounceActivity, AnnounceableObject, LikeActivity, LikeableObject, actor_check,
+    AnnounceActivity, AnnounceableObject, BlockActivity, LikeActivity, LikeableObject, actor_check,
     follow::FollowActivity,
 };
 
@@ -49,6 +49,7 @@ pub enum UndoableObject {
     Follow(FollowActivity),
     Like(LikeActivity),
     Announce(AnnounceActivity),
+    Block(BlockActivity),
 }
 
 impl UndoableObject {
@@ -57,6 +58,7 @@ impl UndoableObject {
             UndoableObject::Follow(follow) => follow.id(),
             UndoableObject::Like(like) => like.id(),
             UndoableObject::Announce(announce) => announce.id(),
+            UndoableObject::Block(block) => block.id(),
         }
     }
 
@@ -65,6 +67,7 @@ impl UndoableObject {
             UndoableObject::Follow(follow) => &follow.actor,
             UndoableObject::Like(like) => &like.actor,
             UndoableObject::Announce(announce) => &announce.actor,
+            UndoableObject::Block(block) => &block.actor,
         }
     }
 }
@@ -88,6 +91,7 @@ impl ActivityHandler for UndoActivity {
             UndoableObject::Follow(follow) => actor_check(self.actor(), follow.actor())?, // actor == follower
             UndoableObject::Like(like) => actor_check(self.actor(), like.actor())?, // actor == liker
             UndoableObject::Announce(an) => actor_check(self.actor(), an.actor())?, // actor = announcer
+            UndoableObject::Block(block) => actor_check(self.actor(), block.actor())?, // actor == blocker
         }
 
         Ok(())
@@ -141,6 +145,19 @@ impl ActivityHandler for UndoActivity {
                     }
                 }
             }
+            UndoableObject::Block(block) => {
+                let actor = block.actor.dereference(data).await?;
+                let blocked = block.object.dereference(data).await?;
+                unblock_user(
+                    data.conn(),
+                    &data.rconn(),
+                    data.qconn(),
+                    actor.basic.id,
+                    blocked.basic.id,
+                    data.base_url(),
+                )
+                .await?;
+            }
         }
 
         Ok(())
@@ -19,13 +19,18 @@ along with this program.  If not, see <https://www.gnu.org/licenses/>.
 use crate::try_opt_res;
 
 use super::{
-    MapToUnknown, ServiceError, ServiceResult, create_error_simple,
+    MapToUnknown, ServiceError, ServiceResult,
+    access_token::revoke_all_personal_access_tokens,
+    create_error_simple,
     db::Conn,
     id::{Identifier, UserID},
+    oauth::revoke_all_oauth_tokens,
+    user::UserSuspendError,
 };
 use activitypub_federation::http_signatures::generate_actor_keypair;
 use actix_web::http::StatusCode;
 use chrono::Utc;
+use entity::sea_orm_active_enums::UserAccountState;
 use expected_error_derive::ExpectedError;
 use regex::Regex;
 use sea_orm::{ActiveModelTrait, SqlErr};
@@ -182,6 +187,10 @@ pub async fn check_password_user(
     };
 
     if bcrypt::verify(password, &hashed_password).map_err(ServiceError::unknown)? {
+        // 凍結されたアカウントはパスワードが正しくてもログインできない
+        if user.account_state == UserAccountState::Suspended {
+            return Err(ServiceError::known(UserSuspendError::Suspended));
+        }
         Ok(Some(LoginResult {
             user_id: UserID::from_db_trusted(user.id),
         }))
@@ -204,6 +213,10 @@ pub async fn logout_all(conn: &Conn, user_id: UserID) -> ServiceResult<()> {
         user.update(&txn).await.map_err_unknown()?;
     }
 
+    // OAuth で発行したトークンや個人用アクセストークンも無効にする
+    revoke_all_oauth_tokens(&txn, user_id).await?;
+    revoke_all_personal_access_tokens(&txn, user_id).await?;
+
     txn.commit().await.map_err_unknown()?;
 
     Ok(())
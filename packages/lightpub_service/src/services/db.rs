@@ -252,6 +252,25 @@ impl KV for RedisConn {
         let mut c = self.cm.clone();
         c.del(key).await.map_err_unknown()
     }
+
+    async fn incr_(&self, key: &str, ttl: std::time::Duration) -> ServiceResult<u64> {
+        let mut c = self.cm.clone();
+        // SET NX で期限付きのキーを作ってから INCR するので、期限は最初の 1 回だけ設定される
+        let (count,): (u64,) = redis::pipe()
+            .atomic()
+            .cmd("SET")
+            .arg(key)
+            .arg(0)
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl.as_secs())
+            .ignore()
+            .incr(key, 1)
+            .query_async(&mut c)
+            .await
+            .map_err_unknown()?;
+        Ok(count)
+    }
 }
 
 /// Dummy KV store that does nothing.
@@ -277,6 +296,10 @@ impl KV for DummyKV {
     async fn delete_(&self, _: &str) -> ServiceResult<()> {
         Ok(())
     }
+
+    async fn incr_(&self, _: &str, _: std::time::Duration) -> ServiceResult<u64> {
+        Ok(1)
+    }
 }
 
 pub fn is_unique_constraint_error(err: &DbErr) -> bool {
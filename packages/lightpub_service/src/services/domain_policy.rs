@@ -0,0 +1,286 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use actix_web::http::StatusCode;
+use chrono::{DateTime, Utc};
+use entity::sea_orm_active_enums::DomainPolicyAction;
+use expected_error_derive::ExpectedError;
+use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, Set};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use url::Url;
+
+use super::{
+    MapToUnknown, ServiceError, ServiceResult,
+    db::{MaybeTxConn, is_unique_constraint_error},
+};
+
+#[derive(Debug, Clone, Error, ExpectedError)]
+pub enum DomainPolicyError {
+    #[error("Invalid domain")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    InvalidDomain,
+    #[error("Domain policy not found")]
+    #[ee(status(StatusCode::NOT_FOUND))]
+    NotFound,
+    #[error("Domain {0} is rejected")]
+    #[ee(status(StatusCode::FORBIDDEN))]
+    DomainRejected(String),
+    #[error("Media from domain {0} is rejected")]
+    #[ee(status(StatusCode::FORBIDDEN))]
+    MediaRejected(String),
+}
+
+/// リモートインスタンスに対してとる措置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DomainPolicyActionModel {
+    /// 全てのアクティビティを拒否する
+    Reject,
+    /// 公開タイムラインから隠す
+    Silence,
+    /// メディアを取り込まない
+    RejectMedia,
+}
+
+impl DomainPolicyActionModel {
+    pub fn from_db(action: DomainPolicyAction) -> Self {
+        match action {
+            DomainPolicyAction::Reject => DomainPolicyActionModel::Reject,
+            DomainPolicyAction::Silence => DomainPolicyActionModel::Silence,
+            DomainPolicyAction::RejectMedia => DomainPolicyActionModel::RejectMedia,
+        }
+    }
+
+    pub fn as_db(self) -> DomainPolicyAction {
+        match self {
+            DomainPolicyActionModel::Reject => DomainPolicyAction::Reject,
+            DomainPolicyActionModel::Silence => DomainPolicyAction::Silence,
+            DomainPolicyActionModel::RejectMedia => DomainPolicyAction::RejectMedia,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DomainPolicyModel {
+    pub id: i32,
+    pub domain: String,
+    pub action: DomainPolicyActionModel,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl DomainPolicyModel {
+    fn from_db(model: entity::domain_policy::Model) -> Self {
+        Self {
+            id: model.id,
+            domain: model.domain,
+            action: DomainPolicyActionModel::from_db(model.action),
+            reason: model.reason,
+            created_at: model.created_at.and_utc(),
+        }
+    }
+}
+
+/// あるドメインに対して実際に適用される措置をまとめたもの
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EffectiveDomainPolicy {
+    pub reject: bool,
+    pub silence: bool,
+    pub reject_media: bool,
+}
+
+impl EffectiveDomainPolicy {
+    pub fn rejects_activities(&self) -> bool {
+        self.reject
+    }
+
+    pub fn rejects_media(&self) -> bool {
+        self.reject || self.reject_media
+    }
+}
+
+/// ドメイン名を小文字に正規化する。ドメイン名として不正な場合は None を返す。
+fn normalize_domain(domain: &str) -> Option<String> {
+    let domain = domain.trim().trim_end_matches('.').to_ascii_lowercase();
+    if domain.is_empty() {
+        return None;
+    }
+
+    let url = Url::parse(&format!("https://{domain}/")).ok()?;
+    match url.host_str() {
+        Some(host) if host == domain => Some(domain),
+        _ => None,
+    }
+}
+
+/// ドメインとその親ドメインを列挙する。
+/// 例: `a.example.com` -> `["a.example.com", "example.com", "com"]`
+fn domain_and_parents(domain: &str) -> Vec<String> {
+    let mut result = vec![domain.to_string()];
+    let mut rest = domain;
+    while let Some((_, parent)) = rest.split_once('.') {
+        result.push(parent.to_string());
+        rest = parent;
+    }
+    result
+}
+
+/// ドメインに適用されるポリシーを取得する。
+/// 親ドメインに設定されたポリシーはサブドメインにも適用される。
+pub async fn get_domain_policy(
+    conn: &MaybeTxConn,
+    domain: &str,
+) -> ServiceResult<EffectiveDomainPolicy> {
+    let domain = match normalize_domain(domain) {
+        Some(d) => d,
+        None => return Ok(EffectiveDomainPolicy::default()),
+    };
+
+    let policies = entity::domain_policy::Entity::find()
+        .filter(entity::domain_policy::Column::Domain.is_in(domain_and_parents(&domain)))
+        .all(conn)
+        .await
+        .map_err_unknown()?;
+
+    let mut result = EffectiveDomainPolicy::default();
+    for policy in policies {
+        match policy.action {
+            DomainPolicyAction::Reject => result.reject = true,
+            DomainPolicyAction::Silence => result.silence = true,
+            DomainPolicyAction::RejectMedia => result.reject_media = true,
+        }
+    }
+    Ok(result)
+}
+
+/// URL のホストに適用されるポリシーを取得する。
+pub async fn get_domain_policy_for_url(
+    conn: &MaybeTxConn,
+    url: &Url,
+) -> ServiceResult<EffectiveDomainPolicy> {
+    match url.host_str() {
+        Some(host) => get_domain_policy(conn, host).await,
+        None => Ok(EffectiveDomainPolicy::default()),
+    }
+}
+
+/// URL のホストからのアクティビティが拒否されている場合にエラーを返す。
+pub async fn check_domain_not_rejected(conn: &MaybeTxConn, url: &Url) -> ServiceResult<()> {
+    let policy = get_domain_policy_for_url(conn, url).await?;
+    if policy.rejects_activities() {
+        return Err(ServiceError::known(DomainPolicyError::DomainRejected(
+            url.host_str().unwrap_or_default().to_string(),
+        )));
+    }
+    Ok(())
+}
+
+pub async fn list_domain_policies(conn: &MaybeTxConn) -> ServiceResult<Vec<DomainPolicyModel>> {
+    let policies = entity::domain_policy::Entity::find()
+        .order_by_asc(entity::domain_policy::Column::Domain)
+        .order_by_asc(entity::domain_policy::Column::Id)
+        .all(conn)
+        .await
+        .map_err_unknown()?;
+
+    Ok(policies
+        .into_iter()
+        .map(DomainPolicyModel::from_db)
+        .collect())
+}
+
+/// ドメインにポリシーを追加する。既に同じポリシーが存在する場合は理由のみ更新する。
+pub async fn add_domain_policy(
+    conn: &MaybeTxConn,
+    domain: &str,
+    action: DomainPolicyActionModel,
+    reason: Option<String>,
+) -> ServiceResult<DomainPolicyModel> {
+    let domain =
+        normalize_domain(domain).ok_or(ServiceError::known(DomainPolicyError::InvalidDomain))?;
+
+    let existing = entity::domain_policy::Entity::find()
+        .filter(entity::domain_policy::Column::Domain.eq(&domain))
+        .filter(entity::domain_policy::Column::Action.eq(action.as_db()))
+        .one(conn)
+        .await
+        .map_err_unknown()?;
+
+    if let Some(existing) = existing {
+        let mut model: entity::domain_policy::ActiveModel = existing.into();
+        model.reason = Set(reason);
+        let model = model.update(conn).await.map_err_unknown()?;
+        return Ok(DomainPolicyModel::from_db(model));
+    }
+
+    let model = entity::domain_policy::ActiveModel {
+        domain: Set(domain),
+        action: Set(action.as_db()),
+        reason: Set(reason),
+        created_at: Set(Utc::now().naive_utc()),
+        ..Default::default()
+    };
+    let model = match model.insert(conn).await {
+        Ok(m) => m,
+        Err(e) if is_unique_constraint_error(&e) => {
+            return Err(ServiceError::ise("domain policy was created concurrently"));
+        }
+        Err(e) => return Err(ServiceError::unknown(e)),
+    };
+
+    Ok(DomainPolicyModel::from_db(model))
+}
+
+pub async fn remove_domain_policy(conn: &MaybeTxConn, policy_id: i32) -> ServiceResult<()> {
+    let result = entity::domain_policy::Entity::delete_by_id(policy_id)
+        .exec(conn)
+        .await
+        .map_err_unknown()?;
+
+    if result.rows_affected == 0 {
+        return Err(ServiceError::known(DomainPolicyError::NotFound));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_normalize_domain() {
+    assert_eq!(
+        normalize_domain("Example.COM"),
+        Some("example.com".to_string())
+    );
+    assert_eq!(
+        normalize_domain(" example.com. "),
+        Some("example.com".to_string())
+    );
+    assert_eq!(normalize_domain(""), None);
+    assert_eq!(normalize_domain("example.com/path"), None);
+    assert_eq!(normalize_domain("user@example.com"), None);
+}
+
+#[test]
+fn test_domain_and_parents() {
+    assert_eq!(
+        domain_and_parents("a.example.com"),
+        vec!["a.example.com", "example.com", "com"]
+    );
+    assert_eq!(domain_and_parents("localhost"), vec!["localhost"]);
+}
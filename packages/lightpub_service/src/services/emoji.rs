@@ -0,0 +1,458 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::{path::Path, str::FromStr};
+
+use actix_web::http::StatusCode;
+use chrono::{DateTime, Utc};
+use expected_error_derive::ExpectedError;
+use itertools::Itertools;
+use nestify::nest;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder, Set,
+};
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+use thiserror::Error;
+use tracing::warn;
+use url::Url;
+
+use super::{
+    MapToUnknown, ServiceError, ServiceResult,
+    db::{Conn, MaybeTxConn, is_unique_constraint_error},
+    id::{Identifier, UploadID},
+    storage::StorageObject,
+    upload::{
+        UploadKind, UploadMetadata, delete_uploads, register_remote_upload, remove_upload_files,
+        save_upload_file, save_upload_file_info, stored_files,
+    },
+};
+
+const MAX_SHORTCODE_LENGTH: usize = 64;
+
+#[derive(Debug, Clone, Error, ExpectedError)]
+pub enum EmojiError {
+    #[error("invalid shortcode")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    InvalidShortcode,
+    #[error("emoji already exists")]
+    #[ee(status(StatusCode::CONFLICT))]
+    AlreadyExists,
+    #[error("emoji not found")]
+    #[ee(status(StatusCode::NOT_FOUND))]
+    NotFound,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EmojiModel {
+    pub shortcode: String,
+    /// ローカルの絵文字の場合は None
+    pub domain: Option<String>,
+    pub upload_id: UploadID,
+    pub mime_type: String,
+    /// リモートの絵文字の場合は Emoji オブジェクトの ID
+    pub url: Option<Url>,
+}
+
+impl EmojiModel {
+    /// 表示用の画像 URL
+    pub fn image_url(&self, base_url: &Url) -> Url {
+        self.upload_id.as_local_url(base_url)
+    }
+
+    /// Emoji オブジェクトの ID
+    pub fn apub_id(&self, base_url: &Url) -> Url {
+        match &self.url {
+            Some(url) => url.clone(),
+            None => base_url
+                .join(&format!("/emoji/{}", self.shortcode))
+                .unwrap(),
+        }
+    }
+
+    pub fn to_apub(&self, base_url: &Url) -> ApubEmojiTag {
+        ApubEmojiTag {
+            id: Some(self.apub_id(base_url)),
+            name: format!(":{}:", self.shortcode),
+            updated: None,
+            icon: ApubEmojiIcon {
+                kind: "Image".to_string(),
+                media_type: Some(self.mime_type.clone()),
+                url: self.image_url(base_url),
+            },
+        }
+    }
+
+    fn from_db(emoji: entity::emoji::Model, upload: entity::upload::Model) -> Self {
+        Self {
+            shortcode: emoji.shortcode,
+            domain: Some(emoji.domain).filter(|d| !d.is_empty()),
+            upload_id: UploadID::from_db_trusted(emoji.upload_id),
+            mime_type: upload.mime_type,
+            url: emoji.url.and_then(|u| Url::from_str(&u).ok()),
+        }
+    }
+}
+
+nest! {
+    /// `tag` に含まれる Emoji
+    #[derive(Debug, Clone, Serialize, Deserialize)]*
+    #[serde(rename_all = "camelCase")]*
+    pub struct ApubEmojiTag {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub(crate) id: Option<Url>,
+        /// `:shortcode:` 形式
+        pub(crate) name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub(crate) updated: Option<DateTime<Utc>>,
+        pub(crate) icon: pub struct ApubEmojiIcon {
+            #[serde(rename = "type")]
+            pub(crate) kind: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub(crate) media_type: Option<String>,
+            pub(crate) url: Url,
+        },
+    }
+}
+
+impl ApubEmojiTag {
+    pub fn shortcode(&self) -> &str {
+        self.name.trim_matches(':')
+    }
+}
+
+/// `tag` のうち、絵文字以外は無視する。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ApubMaybeEmojiTag {
+    #[serde(rename = "Emoji")]
+    Emoji(Box<ApubEmojiTag>),
+    #[serde(other)]
+    Other,
+}
+
+impl ApubMaybeEmojiTag {
+    pub fn emojis(tags: &[Self]) -> Vec<&ApubEmojiTag> {
+        tags.iter()
+            .filter_map(|t| match t {
+                Self::Emoji(e) => Some(e.as_ref()),
+                Self::Other => None,
+            })
+            .collect()
+    }
+}
+
+fn is_shortcode_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+pub fn validate_shortcode(shortcode: &str) -> bool {
+    !shortcode.is_empty()
+        && shortcode.len() <= MAX_SHORTCODE_LENGTH
+        && shortcode.chars().all(is_shortcode_char)
+}
+
+/// テキスト中の `:shortcode:` を重複なく抽出する。
+pub fn extract_shortcodes(content: &str) -> Vec<String> {
+    let mut result = vec![];
+    let mut rest = content;
+    while let Some(start) = rest.find(':') {
+        let after = &rest[start + 1..];
+        let len = after
+            .find(|c: char| !is_shortcode_char(c))
+            .unwrap_or(after.len());
+        if len > 0 && after[len..].starts_with(':') {
+            let shortcode = &after[..len];
+            if validate_shortcode(shortcode) {
+                result.push(shortcode.to_string());
+            }
+            // 閉じコロンは次の絵文字の開始にはならない
+            rest = &after[len + 1..];
+        } else {
+            rest = &after[len..];
+        }
+    }
+    result.into_iter().unique().collect()
+}
+
+/// HTML のテキスト部分に含まれる `:shortcode:` を画像に置き換える。
+/// タグの内側 (属性値など) は置き換えない。
+pub fn replace_emoji_shortcodes(html: &str, emojis: &[EmojiModel], base_url: &Url) -> String {
+    if emojis.is_empty() {
+        return html.to_string();
+    }
+
+    let mut result = String::with_capacity(html.len());
+    let mut i = 0;
+    while i < html.len() {
+        let rest = &html[i..];
+        if rest.starts_with('<') {
+            let end = rest.find('>').map(|e| e + 1).unwrap_or(rest.len());
+            result.push_str(&rest[..end]);
+            i += end;
+            continue;
+        }
+
+        if let Some(after) = rest.strip_prefix(':') {
+            let len = after
+                .find(|c: char| !is_shortcode_char(c))
+                .unwrap_or(after.len());
+            if len > 0 && after[len..].starts_with(':') {
+                let shortcode = &after[..len];
+                if let Some(emoji) = emojis.iter().find(|e| e.shortcode == shortcode) {
+                    result.push_str(&format!(
+                        "<img src=\"{}\" alt=\":{shortcode}:\" title=\":{shortcode}:\" class=\"emoji\" />",
+                        emoji.image_url(base_url)
+                    ));
+                    i += len + 2;
+                    continue;
+                }
+            }
+        }
+
+        let c = rest.chars().next().unwrap();
+        result.push(c);
+        i += c.len_utf8();
+    }
+    result
+}
+
+fn domain_as_db(domain: Option<&str>) -> String {
+    domain.unwrap_or("").to_string()
+}
+
+/// ローカルの絵文字を登録する。
+pub async fn create_local_emoji(
+    conn: &Conn,
+    storage: &StorageObject,
+    shortcode: &str,
+    file: NamedTempFile,
+) -> ServiceResult<EmojiModel> {
+    let shortcode = shortcode.trim_matches(':');
+    if !validate_shortcode(shortcode) {
+        return Err(ServiceError::known(EmojiError::InvalidShortcode));
+    }
+
+    let (upload_id, filename, mime_type, metadata) =
+        save_upload_file(storage, file, &[UploadKind::Image]).await?;
+
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+    let result =
+        insert_local_emoji(&tx, shortcode, &upload_id, &filename, &mime_type, &metadata).await;
+    match result {
+        Ok(emoji) => {
+            tx.commit().await?;
+            Ok(emoji)
+        }
+        Err(e) => {
+            let keys = stored_files(
+                &upload_id,
+                filename.to_str().expect("bad filename"),
+                mime_type.mime_type(),
+                metadata.has_variants,
+            );
+            remove_upload_files(storage, keys.into_iter().map(|(key, _)| key).collect()).await;
+            Err(e)
+        }
+    }
+}
+
+async fn insert_local_emoji(
+    tx: &MaybeTxConn,
+    shortcode: &str,
+    upload_id: &UploadID,
+    filename: &Path,
+    mime_type: &infer::Type,
+    metadata: &UploadMetadata,
+) -> ServiceResult<EmojiModel> {
+    save_upload_file_info(tx, upload_id, filename, mime_type.mime_type(), metadata).await?;
+
+    let model = entity::emoji::ActiveModel {
+        shortcode: Set(shortcode.to_string()),
+        domain: Set(domain_as_db(None)),
+        upload_id: Set(upload_id.as_db()),
+        ..Default::default()
+    };
+    match model.insert(tx).await {
+        Ok(_) => {}
+        Err(e) if is_unique_constraint_error(&e) => {
+            return Err(ServiceError::known(EmojiError::AlreadyExists));
+        }
+        Err(e) => return Err(ServiceError::unknown(e)),
+    }
+
+    get_emojis_by_shortcodes(tx, None, &[shortcode.to_string()])
+        .await
+        .map(|mut e| e.pop().expect("emoji should exist"))
+}
+
+/// ローカルの絵文字を一覧する。
+pub async fn list_local_emojis(conn: &MaybeTxConn) -> ServiceResult<Vec<EmojiModel>> {
+    let emojis = entity::emoji::Entity::find()
+        .find_also_related(entity::upload::Entity)
+        .filter(entity::emoji::Column::Domain.eq(domain_as_db(None)))
+        .order_by_asc(entity::emoji::Column::Shortcode)
+        .all(conn)
+        .await
+        .map_err_unknown()?;
+
+    Ok(emojis
+        .into_iter()
+        .filter_map(|(e, u)| u.map(|u| EmojiModel::from_db(e, u)))
+        .collect())
+}
+
+/// ローカルの絵文字を削除する。画像ファイルも削除される。
+pub async fn delete_local_emoji(
+    conn: &Conn,
+    storage: &StorageObject,
+    shortcode: &str,
+) -> ServiceResult<()> {
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+
+    let emoji = entity::emoji::Entity::find()
+        .filter(
+            Condition::all()
+                .add(entity::emoji::Column::Shortcode.eq(shortcode))
+                .add(entity::emoji::Column::Domain.eq(domain_as_db(None))),
+        )
+        .one(&tx)
+        .await
+        .map_err_unknown()?
+        .ok_or(ServiceError::known(EmojiError::NotFound))?;
+
+    // upload を削除すると emoji もカスケード削除される
+    let keys = delete_uploads(&tx, &[UploadID::from_db_trusted(emoji.upload_id)]).await?;
+
+    tx.commit().await?;
+
+    remove_upload_files(storage, keys).await;
+
+    Ok(())
+}
+
+/// 指定したドメインの絵文字をショートコードで取得する。
+/// `domain` が None の場合はローカルの絵文字を取得する。
+pub async fn get_emojis_by_shortcodes(
+    conn: &MaybeTxConn,
+    domain: Option<&str>,
+    shortcodes: &[String],
+) -> ServiceResult<Vec<EmojiModel>> {
+    if shortcodes.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let emojis = entity::emoji::Entity::find()
+        .find_also_related(entity::upload::Entity)
+        .filter(
+            Condition::all()
+                .add(entity::emoji::Column::Domain.eq(domain_as_db(domain)))
+                .add(entity::emoji::Column::Shortcode.is_in(shortcodes.iter().cloned())),
+        )
+        .all(conn)
+        .await
+        .map_err_unknown()?;
+
+    Ok(emojis
+        .into_iter()
+        .filter_map(|(e, u)| u.map(|u| EmojiModel::from_db(e, u)))
+        .collect())
+}
+
+/// テキスト中で使われている絵文字を取得する。
+pub async fn get_emojis_in_content(
+    conn: &MaybeTxConn,
+    domain: Option<&str>,
+    content: &str,
+) -> ServiceResult<Vec<EmojiModel>> {
+    get_emojis_by_shortcodes(conn, domain, &extract_shortcodes(content)).await
+}
+
+/// リモートの絵文字を登録または更新する。
+async fn upsert_remote_emoji(
+    conn: &MaybeTxConn,
+    client: &reqwest_middleware::ClientWithMiddleware,
+    domain: &str,
+    tag: &ApubEmojiTag,
+) -> ServiceResult<()> {
+    let shortcode = tag.shortcode();
+    if !validate_shortcode(shortcode) {
+        return Err(ServiceError::known(EmojiError::InvalidShortcode));
+    }
+
+    let existing = entity::emoji::Entity::find()
+        .find_also_related(entity::upload::Entity)
+        .filter(
+            Condition::all()
+                .add(entity::emoji::Column::Shortcode.eq(shortcode))
+                .add(entity::emoji::Column::Domain.eq(domain)),
+        )
+        .one(conn)
+        .await
+        .map_err_unknown()?;
+
+    let icon_url = tag.icon.url.as_str();
+    match existing {
+        Some((_, Some(upload))) if upload.url.as_deref() == Some(icon_url) => Ok(()),
+        Some((emoji, _)) => {
+            let old_upload_id = UploadID::from_db_trusted(emoji.upload_id.clone());
+            let upload_id = register_remote_upload(conn, &tag.icon.url, client).await?;
+
+            let mut model = emoji.into_active_model();
+            model.upload_id = Set(upload_id.as_db());
+            model.url = Set(tag.id.as_ref().map(|u| u.to_string()));
+            model.updated_at = Set(Utc::now().naive_utc());
+            model.update(conn).await.map_err_unknown()?;
+
+            // リモートの upload はファイルを持たない
+            delete_uploads(conn, &[old_upload_id]).await?;
+            Ok(())
+        }
+        None => {
+            let upload_id = register_remote_upload(conn, &tag.icon.url, client).await?;
+            let model = entity::emoji::ActiveModel {
+                shortcode: Set(shortcode.to_string()),
+                domain: Set(domain.to_string()),
+                upload_id: Set(upload_id.as_db()),
+                url: Set(tag.id.as_ref().map(|u| u.to_string())),
+                ..Default::default()
+            };
+            model.insert(conn).await.map_err_unknown()?;
+            Ok(())
+        }
+    }
+}
+
+/// 受信したノートやユーザーの `tag` に含まれる絵文字をキャッシュする。失敗しても処理は継続する。
+pub async fn register_remote_emojis(
+    conn: &MaybeTxConn,
+    client: &reqwest_middleware::ClientWithMiddleware,
+    domain: &str,
+    tags: &[&ApubEmojiTag],
+) {
+    for tag in tags {
+        if let Err(e) = upsert_remote_emoji(conn, client, domain, tag).await {
+            warn!(
+                "failed to register remote emoji {} (skipped): {:?}",
+                tag.name, e
+            );
+        }
+    }
+}
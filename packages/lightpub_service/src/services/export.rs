@@ -0,0 +1,607 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::{
+    collections::HashSet,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use activitypub_federation::{config::Data, traits::Object};
+use actix_web::http::StatusCode;
+use chrono::{DateTime, Utc};
+use entity::sea_orm_active_enums::UserExportStatus;
+use expected_error_derive::ExpectedError;
+use flate2::{Compression, write::GzEncoder};
+use migration::Query;
+use sea_orm::{Condition, IntoActiveModel, QueryOrder, Set};
+use serde::Serialize;
+use serde_json::json;
+use thiserror::Error;
+use tokio_util::io::SyncIoBridge;
+use tracing::{error, info, warn};
+use url::Url;
+
+use crate::MyFederationData;
+
+use super::{
+    MapToUnknown, ServiceError, ServiceResult,
+    db::{Conn, MaybeTxConn},
+    id::{Identifier, UserID},
+    kv::KVObject,
+    note::{OutboxActivity, get_user_apub_outbox},
+    queue::{BackgroundJob, QConn},
+    user::{
+        SimpleUserModel, get_apubuser_by_id, get_user_by_id, get_user_followers,
+        get_user_followings,
+    },
+};
+use sea_orm::prelude::*;
+
+/// 一度に取得するノート数
+const EXPORT_NOTE_BATCH_SIZE: u64 = 100;
+
+#[derive(Debug, Error, ExpectedError)]
+pub enum UserExportError {
+    #[error("User {0} does not exist")]
+    #[ee(status(StatusCode::NOT_FOUND))]
+    UserDoesNotExist(UserID),
+    #[error("User is not a local user")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    NotLocalUser,
+    #[error("Another export is in progress")]
+    #[ee(status(StatusCode::CONFLICT))]
+    ExportInProgress,
+    #[error("Export not found")]
+    #[ee(status(StatusCode::NOT_FOUND))]
+    ExportNotFound,
+    #[error("Export is not ready")]
+    #[ee(status(StatusCode::CONFLICT))]
+    ExportNotReady,
+}
+
+/// エクスポートの状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UserExportStatusModel {
+    Pending,
+    Processing,
+    Completed,
+    Failed,
+}
+
+impl UserExportStatusModel {
+    pub fn from_db(status: UserExportStatus) -> Self {
+        match status {
+            UserExportStatus::Pending => UserExportStatusModel::Pending,
+            UserExportStatus::Processing => UserExportStatusModel::Processing,
+            UserExportStatus::Completed => UserExportStatusModel::Completed,
+            UserExportStatus::Failed => UserExportStatusModel::Failed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserExportModel {
+    pub id: i32,
+    pub status: UserExportStatusModel,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+impl UserExportModel {
+    fn from_db(model: entity::user_export::Model) -> Self {
+        Self {
+            id: model.id,
+            status: UserExportStatusModel::from_db(model.status),
+            created_at: model.created_at.and_utc(),
+            completed_at: model.completed_at.map(|d| d.and_utc()),
+        }
+    }
+}
+
+pub fn get_exports_dir() -> PathBuf {
+    let export_dir = std::env::var("EXPORT_DIR").unwrap_or_else(|_| "./exports".to_string());
+    PathBuf::from(export_dir)
+}
+
+/// ユーザーデータのエクスポートを要求する。
+/// アーカイブはバックグラウンドで作成され、完了後にダウンロードできるようになる。
+pub async fn request_user_export(
+    conn: &Conn,
+    qconn: &QConn,
+    user_id: UserID,
+) -> ServiceResult<i32> {
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+
+    let user = entity::user::Entity::find_by_id(user_id.as_db())
+        .one(&tx)
+        .await
+        .map_err_unknown()?
+        .ok_or(ServiceError::known(UserExportError::UserDoesNotExist(
+            user_id,
+        )))?;
+    if !user.domain.is_empty() {
+        return Err(ServiceError::known(UserExportError::NotLocalUser));
+    }
+
+    let in_progress = entity::user_export::Entity::find()
+        .filter(
+            Condition::all()
+                .add(entity::user_export::Column::UserId.eq(user_id.as_db()))
+                .add(
+                    entity::user_export::Column::Status
+                        .is_in([UserExportStatus::Pending, UserExportStatus::Processing]),
+                ),
+        )
+        .count(&tx)
+        .await
+        .map_err_unknown()?;
+    if in_progress > 0 {
+        return Err(ServiceError::known(UserExportError::ExportInProgress));
+    }
+
+    let model = entity::user_export::ActiveModel {
+        user_id: Set(user_id.as_db()),
+        status: Set(UserExportStatus::Pending),
+        ..Default::default()
+    };
+    let export = model.insert(&tx).await.map_err_unknown()?;
+    let export_id = export.id;
+
+    tx.commit().await?;
+
+    // ワーカーが行を参照できるよう、コミット後にキューに入れる
+    if let Err(e) = qconn
+        .queue_job(BackgroundJob::UserExport { export_id })
+        .await
+    {
+        let mut model = export.into_active_model();
+        model.status = Set(UserExportStatus::Failed);
+        model
+            .update(&MaybeTxConn::Conn(conn.clone()))
+            .await
+            .map_err_unknown()?;
+        return Err(e);
+    }
+
+    Ok(export_id)
+}
+
+/// ユーザーのエクスポート一覧を新しい順に取得する。
+pub async fn get_user_exports(
+    conn: &MaybeTxConn,
+    user_id: UserID,
+) -> ServiceResult<Vec<UserExportModel>> {
+    let exports = entity::user_export::Entity::find()
+        .filter(entity::user_export::Column::UserId.eq(user_id.as_db()))
+        .order_by_desc(entity::user_export::Column::CreatedAt)
+        .all(conn)
+        .await
+        .map_err_unknown()?;
+
+    Ok(exports.into_iter().map(UserExportModel::from_db).collect())
+}
+
+/// ダウンロード可能なエクスポートアーカイブのパスを取得する。
+pub async fn get_user_export_file(
+    conn: &MaybeTxConn,
+    user_id: UserID,
+    export_id: i32,
+) -> ServiceResult<PathBuf> {
+    let export = entity::user_export::Entity::find_by_id(export_id)
+        .filter(entity::user_export::Column::UserId.eq(user_id.as_db()))
+        .one(conn)
+        .await
+        .map_err_unknown()?
+        .ok_or(ServiceError::known(UserExportError::ExportNotFound))?;
+
+    match (export.status, export.filename) {
+        (UserExportStatus::Completed, Some(filename)) => Ok(get_exports_dir().join(filename)),
+        _ => Err(ServiceError::known(UserExportError::ExportNotReady)),
+    }
+}
+
+/// キューから受け取ったエクスポートを処理する。
+/// 失敗した場合はエクスポートを失敗状態にし、再試行はしない。
+pub async fn process_user_export(
+    conn: &Conn,
+    rconn: &KVObject,
+    export_id: i32,
+    my_domain: &str,
+    base_url: &Url,
+    data: &Data<MyFederationData>,
+) -> ServiceResult<()> {
+    let maybe_conn = MaybeTxConn::Conn(conn.clone());
+
+    let export = match entity::user_export::Entity::find_by_id(export_id)
+        .one(&maybe_conn)
+        .await
+        .map_err_unknown()?
+    {
+        None => {
+            warn!("export {export_id} not found (skipped)");
+            return Ok(());
+        }
+        Some(e) => e,
+    };
+    match export.status {
+        UserExportStatus::Pending | UserExportStatus::Processing => {}
+        _ => return Ok(()),
+    }
+    let user_id = UserID::from_db_trusted(export.user_id.clone());
+
+    let mut model = export.into_active_model();
+    model.status = Set(UserExportStatus::Processing);
+    let export = model.update(&maybe_conn).await.map_err_unknown()?;
+
+    let filename = format!("export-{}-{}.tar.gz", user_id, export_id);
+    let result = build_user_export(
+        &maybe_conn,
+        rconn,
+        user_id,
+        &filename,
+        my_domain,
+        base_url,
+        data,
+    )
+    .await;
+
+    let mut model = export.into_active_model();
+    match result {
+        Ok(()) => {
+            model.status = Set(UserExportStatus::Completed);
+            model.filename = Set(Some(filename));
+            model.completed_at = Set(Some(Utc::now().naive_utc()));
+            model.update(&maybe_conn).await.map_err_unknown()?;
+            info!("export {export_id} completed");
+
+            remove_old_exports(conn, user_id, export_id).await?;
+        }
+        Err(e) => {
+            error!("export {export_id} failed: {e:?}");
+            model.status = Set(UserExportStatus::Failed);
+            model.update(&maybe_conn).await.map_err_unknown()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// 完了済みの古いエクスポートとそのアーカイブを削除する。
+async fn remove_old_exports(conn: &Conn, user_id: UserID, keep_id: i32) -> ServiceResult<()> {
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+
+    let old = entity::user_export::Entity::find()
+        .filter(
+            Condition::all()
+                .add(entity::user_export::Column::UserId.eq(user_id.as_db()))
+                .add(entity::user_export::Column::Id.ne(keep_id))
+                .add(
+                    entity::user_export::Column::Status
+                        .is_in([UserExportStatus::Completed, UserExportStatus::Failed]),
+                ),
+        )
+        .all(&tx)
+        .await
+        .map_err_unknown()?;
+
+    let ids = old.iter().map(|e| e.id).collect::<Vec<_>>();
+    entity::user_export::Entity::delete_many()
+        .filter(entity::user_export::Column::Id.is_in(ids))
+        .exec(&tx)
+        .await
+        .map_err_unknown()?;
+
+    tx.commit().await?;
+
+    for filename in old.into_iter().filter_map(|e| e.filename) {
+        let path = get_exports_dir().join(filename);
+        if let Err(e) = tokio::fs::remove_file(&path).await {
+            warn!(
+                "failed to remove old export {} (skipped): {e}",
+                path.display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+async fn build_user_export(
+    conn: &MaybeTxConn,
+    rconn: &KVObject,
+    user_id: UserID,
+    filename: &str,
+    my_domain: &str,
+    base_url: &Url,
+    data: &Data<MyFederationData>,
+) -> ServiceResult<()> {
+    // JSON と CSV は小さいので、まとめて作ってから書き込む
+    let mut entries: Vec<(String, Vec<u8>)> = vec![];
+
+    // アクター
+    let actor = get_apubuser_by_id(conn, user_id, base_url)
+        .await?
+        .ok_or(ServiceError::known(UserExportError::UserDoesNotExist(
+            user_id,
+        )))?;
+    let actor_url = actor.apub.url.clone();
+    let mut actor_json = serde_json::to_value(actor.into_json(data).await?).map_err_unknown()?;
+    actor_json["@context"] = json!("https://www.w3.org/ns/activitystreams");
+    entries.push((
+        "actor.json".to_string(),
+        serde_json::to_vec_pretty(&actor_json).map_err_unknown()?,
+    ));
+
+    // ノート (outbox 互換)
+    let items = collect_outbox_items(conn, user_id, base_url, data).await?;
+    let outbox = json!({
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "id": format!("{}/outbox", actor_url),
+        "type": "OrderedCollection",
+        "totalItems": items.len(),
+        "orderedItems": items,
+    });
+    entries.push((
+        "outbox.json".to_string(),
+        serde_json::to_vec_pretty(&outbox).map_err_unknown()?,
+    ));
+
+    // フォロー・フォロワー・ブロック (Mastodon 互換の CSV)
+    let followings = get_user_followings(conn, rconn, &user_id, u64::MAX, None).await?;
+    let mut csv = "Account address,Show boosts,Notify on new posts,Languages\n".to_string();
+    for f in followings {
+        csv.push_str(&format!(
+            "{},true,false,\n",
+            account_address(&f.user, my_domain)
+        ));
+    }
+    entries.push(("following_accounts.csv".to_string(), csv.into_bytes()));
+
+    let followers = get_user_followers(conn, rconn, &user_id, u64::MAX, None).await?;
+    let mut csv = "Account address\n".to_string();
+    for f in followers {
+        csv.push_str(&format!("{}\n", account_address(&f.user, my_domain)));
+    }
+    entries.push(("followers.csv".to_string(), csv.into_bytes()));
+
+    let blocks = entity::user_block::Entity::find()
+        .filter(entity::user_block::Column::BlockerId.eq(user_id.as_db()))
+        .all(conn)
+        .await
+        .map_err_unknown()?;
+    let mut csv = String::new();
+    for b in blocks {
+        let blocked = get_user_by_id(conn, rconn, UserID::from_db_trusted(b.blocked_id)).await?;
+        if let Some(blocked) = blocked {
+            csv.push_str(&format!("{}\n", account_address(&blocked, my_domain)));
+        }
+    }
+    entries.push(("blocked_accounts.csv".to_string(), csv.into_bytes()));
+
+    let export_dir = get_exports_dir();
+    let mut archive = run_blocking(move || {
+        let mut archive = ArchiveWriter::new(&export_dir)?;
+        for (name, bytes) in entries {
+            archive.append(&name, bytes.len() as u64, bytes.as_slice())?;
+        }
+        Ok(archive)
+    })
+    .await?;
+
+    // アップロードしたファイルは、1 つずつストレージから読み込みながら書き込む
+    for upload_filename in collect_upload_filenames(conn, user_id).await? {
+        let file = match data.storage().open(&upload_filename).await {
+            Ok(Some(file)) => file,
+            Ok(None) => {
+                warn!("upload {} not found in storage (skipped)", upload_filename);
+                continue;
+            }
+            Err(e) => {
+                warn!(
+                    "failed to read upload {} (skipped): {:?}",
+                    upload_filename, e
+                );
+                continue;
+            }
+        };
+        let reader = SyncIoBridge::new(file.reader);
+        archive = run_blocking(move || {
+            archive.append(&format!("media/{}", upload_filename), file.size, reader)?;
+            Ok(archive)
+        })
+        .await?;
+    }
+
+    let path = get_exports_dir().join(filename);
+    run_blocking(move || archive.finish(&path)).await
+}
+
+/// ファイルの読み書きをする処理を、非同期ランタイムをブロックしないように別スレッドで実行する。
+async fn run_blocking<T: Send + 'static>(
+    f: impl FnOnce() -> ServiceResult<T> + Send + 'static,
+) -> ServiceResult<T> {
+    tokio::task::spawn_blocking(f).await.map_err_unknown()?
+}
+
+/// ユーザーの全ノートを outbox の orderedItems として取得する。
+async fn collect_outbox_items(
+    conn: &MaybeTxConn,
+    user_id: UserID,
+    base_url: &Url,
+    data: &Data<MyFederationData>,
+) -> ServiceResult<Vec<serde_json::Value>> {
+    let mut items = vec![];
+    let mut seen = HashSet::new();
+    let mut before_date = None;
+    loop {
+        let activities = get_user_apub_outbox(
+            conn,
+            Some(user_id),
+            user_id,
+            EXPORT_NOTE_BATCH_SIZE,
+            before_date,
+            base_url,
+            data,
+        )
+        .await?;
+        let fetched = activities.len() as u64;
+
+        // before_date は境界を含むので、重複したものは読み飛ばす
+        let mut added = false;
+        for activity in activities {
+            before_date = Some(activity.published());
+            let value = match activity {
+                OutboxActivity::Create(c) => serde_json::to_value(c),
+                OutboxActivity::Announce(a) => serde_json::to_value(a),
+            }
+            .map_err_unknown()?;
+            let id = value["id"].as_str().unwrap_or_default().to_string();
+            if seen.insert(id) {
+                items.push(value);
+                added = true;
+            }
+        }
+
+        if fetched < EXPORT_NOTE_BATCH_SIZE || !added {
+            break;
+        }
+    }
+
+    Ok(items)
+}
+
+/// ユーザーがアップロードしたローカルファイル名を取得する。
+async fn collect_upload_filenames(
+    conn: &MaybeTxConn,
+    user_id: UserID,
+) -> ServiceResult<Vec<String>> {
+    let mut upload_ids = entity::note_upload::Entity::find()
+        .filter(
+            entity::note_upload::Column::NoteId.in_subquery(
+                Query::select()
+                    .column(entity::note::Column::Id)
+                    .from(entity::note::Entity)
+                    .and_where(entity::note::Column::AuthorId.eq(user_id.as_db()))
+                    .and_where(entity::note::Column::DeletedAt.is_null())
+                    .to_owned(),
+            ),
+        )
+        .all(conn)
+        .await
+        .map_err_unknown()?
+        .into_iter()
+        .map(|u| u.upload_id)
+        .collect::<Vec<_>>();
+
+    let avatar = entity::user::Entity::find_by_id(user_id.as_db())
+        .one(conn)
+        .await
+        .map_err_unknown()?
+        .and_then(|u| u.avatar);
+    upload_ids.extend(avatar);
+
+    let uploads = entity::upload::Entity::find()
+        .filter(entity::upload::Column::Id.is_in(upload_ids))
+        .all(conn)
+        .await
+        .map_err_unknown()?;
+
+    Ok(uploads.into_iter().filter_map(|u| u.filename).collect())
+}
+
+/// `user@domain` 形式のアカウント名を返す。
+fn account_address(user: &SimpleUserModel, my_domain: &str) -> String {
+    format!(
+        "{}@{}",
+        user.username,
+        user.domain.as_deref().unwrap_or(my_domain)
+    )
+}
+
+/// tar.gz アーカイブを書き出す。書き込み途中のファイルが見えないよう、一時ファイルに書いてから移動する。
+struct ArchiveWriter {
+    tmp: tempfile::NamedTempFile,
+    builder: tar::Builder<GzEncoder<std::fs::File>>,
+}
+
+impl ArchiveWriter {
+    fn new(export_dir: &Path) -> ServiceResult<Self> {
+        std::fs::create_dir_all(export_dir).map_err_unknown()?;
+
+        let tmp = tempfile::NamedTempFile::new_in(export_dir).map_err_unknown()?;
+        let file = tmp.reopen().map_err_unknown()?;
+        let builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        Ok(Self { tmp, builder })
+    }
+
+    /// `size` バイトのファイルを追加する。`data` の長さが `size` と異なる場合はエラーになる。
+    fn append(&mut self, name: &str, size: u64, data: impl Read) -> ServiceResult<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(size);
+        header.set_mode(0o644);
+        header.set_mtime(Utc::now().timestamp() as u64);
+        header.set_cksum();
+        self.builder
+            .append_data(&mut header, name, ExactReader::new(data, size))
+            .map_err_unknown()
+    }
+
+    fn finish(self, path: &Path) -> ServiceResult<()> {
+        self.builder
+            .into_inner()
+            .map_err_unknown()?
+            .finish()
+            .map_err_unknown()?;
+        self.tmp.persist(path).map_err_unknown()?;
+        Ok(())
+    }
+}
+
+/// ちょうど `remaining` バイトを読み込むリーダー。
+/// tar のヘッダーに書いた大きさと中身がずれないように、途中で終わった場合はエラーにする。
+struct ExactReader<R> {
+    inner: R,
+    remaining: u64,
+}
+
+impl<R: Read> ExactReader<R> {
+    fn new(inner: R, size: u64) -> Self {
+        Self {
+            inner,
+            remaining: size,
+        }
+    }
+}
+
+impl<R: Read> Read for ExactReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.remaining == 0 {
+            return Ok(0);
+        }
+        let max = buf.len().min(self.remaining as usize);
+        let n = self.inner.read(&mut buf[..max])?;
+        if n == 0 && max > 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
@@ -192,14 +192,26 @@ pub async fn unfollow_user(
     follower_id: UserID,
     followee_id: UserID,
     base_url: &Url,
+) -> ServiceResult<()> {
+    let tx = conn.as_tx().await?.into();
+    unfollow_user_tx(&tx, rconn, qconn, follower_id, followee_id, base_url).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn unfollow_user_tx(
+    tx: &MaybeTxConn,
+    rconn: &KVObject,
+    qconn: &QConn,
+    follower_id: UserID,
+    followee_id: UserID,
+    base_url: &Url,
 ) -> ServiceResult<()> {
     if follower_id == followee_id {
         return Err(ServiceError::known(UserFollowError::SameUser));
     }
 
-    let tx = conn.as_tx().await?.into();
-
-    let follower = get_user_by_id(&tx, rconn, follower_id).await?;
+    let follower = get_user_by_id(tx, rconn, follower_id).await?;
     let follower = match follower {
         Some(f) => f,
         None => {
@@ -209,7 +221,7 @@ pub async fn unfollow_user(
         }
     };
 
-    let followee = get_user_by_id(&tx, rconn, followee_id).await?;
+    let followee = get_user_by_id(tx, rconn, followee_id).await?;
     let followee = match followee {
         Some(f) => f,
         None => {
@@ -225,15 +237,12 @@ pub async fn unfollow_user(
                 .add(entity::user_follow::Column::FollowerId.eq(follower.id().as_db()))
                 .add(entity::user_follow::Column::FollowedId.eq(followee.id().as_db())),
         )
-        .one(&tx)
+        .one(tx)
         .await
         .map_err_unknown()?;
 
     let model = match model {
-        None => {
-            tx.commit().await?;
-            return Ok(());
-        }
+        None => return Ok(()),
         Some(m) => m,
     };
 
@@ -244,14 +253,14 @@ pub async fn unfollow_user(
 
     // Unfollow
     let model = model.into_active_model();
-    model.delete(&tx).await.map_err_unknown()?;
+    model.delete(tx).await.map_err_unknown()?;
 
     // send activitypub undo if follower is local and followee is remote
     if follower.is_local() && followee.is_remote() {
-        let follower_model = get_apubuser_by_id(&tx, follower_id, base_url)
+        let follower_model = get_apubuser_by_id(tx, follower_id, base_url)
             .await?
             .expect("follower should exist");
-        let followed_model = get_apubuser_by_id(&tx, followee_id, base_url)
+        let followed_model = get_apubuser_by_id(tx, followee_id, base_url)
             .await?
             .expect("followed should exist");
 
@@ -267,8 +276,6 @@ pub async fn unfollow_user(
             .await?;
     }
 
-    tx.commit().await?;
-
     Ok(())
 }
 
@@ -374,8 +381,20 @@ pub async fn reject_pending_follow(
     base_url: &Url,
 ) -> ServiceResult<()> {
     let tx = conn.as_tx().await?.into();
+    reject_pending_follow_tx(&tx, rconn, qconn, follower_id, followee_id, base_url).await?;
+    tx.commit().await?;
+    Ok(())
+}
 
-    let follower = match get_user_by_id(&tx, rconn, follower_id).await? {
+pub async fn reject_pending_follow_tx(
+    tx: &MaybeTxConn,
+    rconn: &KVObject,
+    qconn: &QConn,
+    follower_id: UserID,
+    followee_id: UserID,
+    base_url: &Url,
+) -> ServiceResult<()> {
+    let follower = match get_user_by_id(tx, rconn, follower_id).await? {
         Some(f) => f,
         None => {
             return Err(ServiceError::known(UserFollowError::UserDoesNotExist(
@@ -383,7 +402,7 @@ pub async fn reject_pending_follow(
             )));
         }
     };
-    let followee = match get_user_by_id(&tx, rconn, followee_id).await? {
+    let followee = match get_user_by_id(tx, rconn, followee_id).await? {
         Some(f) => f,
         None => {
             return Err(ServiceError::known(UserFollowError::UserDoesNotExist(
@@ -398,7 +417,7 @@ pub async fn reject_pending_follow(
                 .add(entity::user_follow::Column::FollowerId.eq(follower_id.as_db()))
                 .add(entity::user_follow::Column::FollowedId.eq(followee_id.as_db())),
         )
-        .one(&tx)
+        .one(tx)
         .await
         .map_err_unknown()?;
 
@@ -411,14 +430,14 @@ pub async fn reject_pending_follow(
     let follow_url = follow.url.clone();
 
     let follow = follow.into_active_model();
-    follow.delete(&tx).await.map_err_unknown()?;
+    follow.delete(tx).await.map_err_unknown()?;
 
     // if follower is remote and followee is local, send Reject
     if follower.is_remote() && followee.is_local() {
-        let follower_model = get_apubuser_by_id(&tx, follower_id, base_url)
+        let follower_model = get_apubuser_by_id(tx, follower_id, base_url)
             .await?
             .expect("follower should exist");
-        let followed_model = get_apubuser_by_id(&tx, followee_id, base_url)
+        let followed_model = get_apubuser_by_id(tx, followee_id, base_url)
             .await?
             .expect("followed should exist");
 
@@ -441,8 +460,6 @@ pub async fn reject_pending_follow(
             .await?;
     }
 
-    tx.commit().await?;
-
     Ok(())
 }
 
@@ -0,0 +1,504 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use activitypub_federation::config::Data;
+use actix_web::http::StatusCode;
+use chrono::{DateTime, Utc};
+use entity::sea_orm_active_enums::{UserImportKind, UserImportRowStatus, UserImportStatus};
+use expected_error_derive::ExpectedError;
+use sea_orm::{Condition, IntoActiveModel, QueryOrder, QuerySelect, Set};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{info, warn};
+use url::Url;
+
+use crate::MyFederationData;
+
+use super::{
+    MapToUnknown, ServiceError, ServiceResult, create_error_simple_err,
+    db::{Conn, MaybeTxConn},
+    follow::follow_user,
+    id::{Identifier, UserID},
+    kv::KVObject,
+    notification::push::WPClient,
+    queue::{BackgroundJob, QConn},
+    user::{UserGetError, UserSpecifier, block_user, get_user_by_spec_with_remote},
+};
+use sea_orm::prelude::*;
+
+/// 1回のインポートで扱える最大行数
+const MAX_IMPORT_ROWS: usize = 5000;
+
+/// 一覧に表示するインポートの最大数
+const MAX_LISTED_IMPORTS: u64 = 10;
+
+#[derive(Debug, Error, ExpectedError)]
+pub enum UserImportError {
+    #[error("User {0} does not exist")]
+    #[ee(status(StatusCode::NOT_FOUND))]
+    UserDoesNotExist(UserID),
+    #[error("User is not a local user")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    NotLocalUser,
+    #[error("Another import is in progress")]
+    #[ee(status(StatusCode::CONFLICT))]
+    ImportInProgress,
+    #[error("Import not found")]
+    #[ee(status(StatusCode::NOT_FOUND))]
+    ImportNotFound,
+    #[error("No accounts found in the file")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    EmptyImport,
+    #[error("Too many accounts in the file")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    TooManyRows,
+}
+
+/// インポートの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UserImportKindModel {
+    /// following_accounts.csv
+    Follows,
+    /// blocked_accounts.csv
+    Blocks,
+}
+
+impl UserImportKindModel {
+    pub fn from_db(kind: UserImportKind) -> Self {
+        match kind {
+            UserImportKind::Follows => UserImportKindModel::Follows,
+            UserImportKind::Blocks => UserImportKindModel::Blocks,
+        }
+    }
+
+    pub fn as_db(self) -> UserImportKind {
+        match self {
+            UserImportKindModel::Follows => UserImportKind::Follows,
+            UserImportKindModel::Blocks => UserImportKind::Blocks,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UserImportStatusModel {
+    Pending,
+    Processing,
+    Completed,
+}
+
+impl UserImportStatusModel {
+    pub fn from_db(status: UserImportStatus) -> Self {
+        match status {
+            UserImportStatus::Pending => UserImportStatusModel::Pending,
+            UserImportStatus::Processing => UserImportStatusModel::Processing,
+            UserImportStatus::Completed => UserImportStatusModel::Completed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UserImportRowStatusModel {
+    Pending,
+    Succeeded,
+    Failed,
+}
+
+impl UserImportRowStatusModel {
+    pub fn from_db(status: UserImportRowStatus) -> Self {
+        match status {
+            UserImportRowStatus::Pending => UserImportRowStatusModel::Pending,
+            UserImportRowStatus::Succeeded => UserImportRowStatusModel::Succeeded,
+            UserImportRowStatus::Failed => UserImportRowStatusModel::Failed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserImportRowModel {
+    pub account: String,
+    pub status: UserImportRowStatusModel,
+    /// 失敗した場合の理由
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserImportModel {
+    pub id: i32,
+    pub kind: UserImportKindModel,
+    pub status: UserImportStatusModel,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub total_count: u64,
+    pub succeeded_count: u64,
+    pub failed_count: u64,
+}
+
+/// 行ごとの結果を含むインポート
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserImportDetailModel {
+    #[serde(flatten)]
+    pub import: UserImportModel,
+    pub rows: Vec<UserImportRowModel>,
+}
+
+/// Mastodon 互換の CSV からアカウント名を取り出す。
+///
+/// 各行の先頭の列をアカウント名とみなす。ヘッダー行・空行は無視し、
+/// `user@domain` 形式は `@user@domain` に正規化する。重複は取り除く。
+pub fn parse_account_csv(content: &str) -> Vec<String> {
+    let mut accounts: Vec<String> = vec![];
+    for line in content.lines() {
+        let account = line.split(',').next().unwrap_or("").trim();
+        let account = account.trim_matches('"').trim();
+        if account.is_empty() || account.eq_ignore_ascii_case("Account address") {
+            continue;
+        }
+        let account = if account.starts_with('@') {
+            account.to_string()
+        } else {
+            format!("@{}", account)
+        };
+        if !accounts.contains(&account) {
+            accounts.push(account);
+        }
+    }
+    accounts
+}
+
+/// CSV からフォロー・ブロックのインポートを要求する。
+/// 各行はバックグラウンドで処理され、結果は `get_user_import` で確認できる。
+pub async fn request_user_import(
+    conn: &Conn,
+    qconn: &QConn,
+    user_id: UserID,
+    kind: UserImportKindModel,
+    content: &str,
+) -> ServiceResult<i32> {
+    let accounts = parse_account_csv(content);
+    if accounts.is_empty() {
+        return Err(ServiceError::known(UserImportError::EmptyImport));
+    }
+    if accounts.len() > MAX_IMPORT_ROWS {
+        return Err(ServiceError::known(UserImportError::TooManyRows));
+    }
+
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+
+    let user = entity::user::Entity::find_by_id(user_id.as_db())
+        .one(&tx)
+        .await
+        .map_err_unknown()?
+        .ok_or(ServiceError::known(UserImportError::UserDoesNotExist(
+            user_id,
+        )))?;
+    if !user.domain.is_empty() {
+        return Err(ServiceError::known(UserImportError::NotLocalUser));
+    }
+
+    let in_progress = entity::user_import::Entity::find()
+        .filter(
+            Condition::all()
+                .add(entity::user_import::Column::UserId.eq(user_id.as_db()))
+                .add(
+                    entity::user_import::Column::Status
+                        .is_in([UserImportStatus::Pending, UserImportStatus::Processing]),
+                ),
+        )
+        .count(&tx)
+        .await
+        .map_err_unknown()?;
+    if in_progress > 0 {
+        return Err(ServiceError::known(UserImportError::ImportInProgress));
+    }
+
+    let model = entity::user_import::ActiveModel {
+        user_id: Set(user_id.as_db()),
+        kind: Set(kind.as_db()),
+        status: Set(UserImportStatus::Pending),
+        ..Default::default()
+    };
+    let import = model.insert(&tx).await.map_err_unknown()?;
+    let import_id = import.id;
+
+    let rows = accounts
+        .into_iter()
+        .map(|account| entity::user_import_row::ActiveModel {
+            import_id: Set(import_id),
+            account: Set(account),
+            status: Set(UserImportRowStatus::Pending),
+            ..Default::default()
+        });
+    entity::user_import_row::Entity::insert_many(rows)
+        .exec(&tx)
+        .await
+        .map_err_unknown()?;
+
+    tx.commit().await?;
+
+    // ワーカーが行を参照できるよう、コミット後にキューに入れる
+    if let Err(e) = qconn
+        .queue_job(BackgroundJob::UserImport { import_id })
+        .await
+    {
+        // 再度インポートできるよう、残りの行を失敗として完了させる
+        warn!("failed to queue import {import_id}: {e:?}");
+        finish_import(&MaybeTxConn::Conn(conn.clone()), import, "queue error").await?;
+        return Err(e);
+    }
+
+    Ok(import_id)
+}
+
+async fn count_import_rows(
+    conn: &MaybeTxConn,
+    import_id: i32,
+    status: Option<UserImportRowStatus>,
+) -> ServiceResult<u64> {
+    let mut cond = Condition::all().add(entity::user_import_row::Column::ImportId.eq(import_id));
+    if let Some(status) = status {
+        cond = cond.add(entity::user_import_row::Column::Status.eq(status));
+    }
+    entity::user_import_row::Entity::find()
+        .filter(cond)
+        .count(conn)
+        .await
+        .map_err_unknown()
+}
+
+async fn import_model_from_db(
+    conn: &MaybeTxConn,
+    import: entity::user_import::Model,
+) -> ServiceResult<UserImportModel> {
+    Ok(UserImportModel {
+        id: import.id,
+        kind: UserImportKindModel::from_db(import.kind),
+        status: UserImportStatusModel::from_db(import.status),
+        created_at: import.created_at.and_utc(),
+        completed_at: import.completed_at.map(|d| d.and_utc()),
+        total_count: count_import_rows(conn, import.id, None).await?,
+        succeeded_count: count_import_rows(conn, import.id, Some(UserImportRowStatus::Succeeded))
+            .await?,
+        failed_count: count_import_rows(conn, import.id, Some(UserImportRowStatus::Failed)).await?,
+    })
+}
+
+/// ユーザーの最近のインポートを新しい順に取得する。
+pub async fn get_user_imports(
+    conn: &MaybeTxConn,
+    user_id: UserID,
+) -> ServiceResult<Vec<UserImportModel>> {
+    let imports = entity::user_import::Entity::find()
+        .filter(entity::user_import::Column::UserId.eq(user_id.as_db()))
+        .order_by_desc(entity::user_import::Column::CreatedAt)
+        .limit(MAX_LISTED_IMPORTS)
+        .all(conn)
+        .await
+        .map_err_unknown()?;
+
+    let mut result = vec![];
+    for import in imports {
+        result.push(import_model_from_db(conn, import).await?);
+    }
+    Ok(result)
+}
+
+/// インポートの結果を行ごとに取得する。
+pub async fn get_user_import(
+    conn: &MaybeTxConn,
+    user_id: UserID,
+    import_id: i32,
+) -> ServiceResult<UserImportDetailModel> {
+    let import = entity::user_import::Entity::find_by_id(import_id)
+        .filter(entity::user_import::Column::UserId.eq(user_id.as_db()))
+        .one(conn)
+        .await
+        .map_err_unknown()?
+        .ok_or(ServiceError::known(UserImportError::ImportNotFound))?;
+
+    let rows = entity::user_import_row::Entity::find()
+        .filter(entity::user_import_row::Column::ImportId.eq(import_id))
+        .order_by_asc(entity::user_import_row::Column::Id)
+        .all(conn)
+        .await
+        .map_err_unknown()?
+        .into_iter()
+        .map(|r| UserImportRowModel {
+            account: r.account,
+            status: UserImportRowStatusModel::from_db(r.status),
+            message: r.message,
+        })
+        .collect();
+
+    Ok(UserImportDetailModel {
+        import: import_model_from_db(conn, import).await?,
+        rows,
+    })
+}
+
+/// キューから受け取ったインポートを処理する。
+/// 行ごとにアカウントを解決し、フォローまたはブロックする。失敗した行は理由を記録して続行する。
+pub async fn process_user_import(
+    conn: &Conn,
+    rconn: &KVObject,
+    qconn: &QConn,
+    wp: Option<&WPClient>,
+    import_id: i32,
+    my_domain: &str,
+    base_url: &Url,
+    data: &Data<MyFederationData>,
+) -> ServiceResult<()> {
+    let maybe_conn = MaybeTxConn::Conn(conn.clone());
+
+    let import = match entity::user_import::Entity::find_by_id(import_id)
+        .one(&maybe_conn)
+        .await
+        .map_err_unknown()?
+    {
+        None => {
+            warn!("import {import_id} not found (skipped)");
+            return Ok(());
+        }
+        Some(i) => i,
+    };
+    if import.status == UserImportStatus::Completed {
+        return Ok(());
+    }
+    let user_id = UserID::from_db_trusted(import.user_id.clone());
+    let kind = UserImportKindModel::from_db(import.kind.clone());
+
+    let mut model = import.into_active_model();
+    model.status = Set(UserImportStatus::Processing);
+    let import = model.update(&maybe_conn).await.map_err_unknown()?;
+
+    // 再配送された場合は未処理の行だけを処理する
+    let rows = entity::user_import_row::Entity::find()
+        .filter(
+            Condition::all()
+                .add(entity::user_import_row::Column::ImportId.eq(import_id))
+                .add(entity::user_import_row::Column::Status.eq(UserImportRowStatus::Pending)),
+        )
+        .order_by_asc(entity::user_import_row::Column::Id)
+        .all(&maybe_conn)
+        .await
+        .map_err_unknown()?;
+
+    for row in rows {
+        let result = import_account(
+            conn,
+            rconn,
+            qconn,
+            wp,
+            user_id,
+            kind,
+            &row.account,
+            my_domain,
+            base_url,
+            data,
+        )
+        .await;
+
+        let mut row = row.into_active_model();
+        match result {
+            Ok(()) => {
+                row.status = Set(UserImportRowStatus::Succeeded);
+            }
+            Err(e) => {
+                row.status = Set(UserImportRowStatus::Failed);
+                row.message = Set(Some(e.get_error_code().1.into_owned()));
+            }
+        }
+        row.update(&maybe_conn).await.map_err_unknown()?;
+    }
+
+    finish_import(&maybe_conn, import, "not processed").await?;
+    info!("import {import_id} completed");
+
+    Ok(())
+}
+
+async fn import_account(
+    conn: &Conn,
+    rconn: &KVObject,
+    qconn: &QConn,
+    wp: Option<&WPClient>,
+    user_id: UserID,
+    kind: UserImportKindModel,
+    account: &str,
+    my_domain: &str,
+    base_url: &Url,
+    data: &Data<MyFederationData>,
+) -> ServiceResult<()> {
+    let spec = UserSpecifier::from_str(account, my_domain)
+        .ok_or(ServiceError::known(UserGetError::BadSpecifier))?;
+    let target = get_user_by_spec_with_remote(
+        &MaybeTxConn::Conn(conn.clone()),
+        rconn,
+        &spec,
+        my_domain,
+        data,
+    )
+    .await?
+    .ok_or_else(|| create_error_simple_err(StatusCode::NOT_FOUND, "user not found"))?;
+
+    match kind {
+        UserImportKindModel::Follows => {
+            follow_user(conn, rconn, qconn, wp, user_id, target.id, base_url).await
+        }
+        UserImportKindModel::Blocks => {
+            block_user(conn, rconn, qconn, user_id, target.id, base_url).await
+        }
+    }
+}
+
+/// インポートを完了状態にする。未処理の行は `message` を理由として失敗にする。
+async fn finish_import(
+    tx: &MaybeTxConn,
+    import: entity::user_import::Model,
+    message: &str,
+) -> ServiceResult<()> {
+    entity::user_import_row::Entity::update_many()
+        .col_expr(
+            entity::user_import_row::Column::Status,
+            Expr::value(UserImportRowStatus::Failed),
+        )
+        .col_expr(
+            entity::user_import_row::Column::Message,
+            Expr::value(message),
+        )
+        .filter(
+            Condition::all()
+                .add(entity::user_import_row::Column::ImportId.eq(import.id))
+                .add(entity::user_import_row::Column::Status.eq(UserImportRowStatus::Pending)),
+        )
+        .exec(tx)
+        .await
+        .map_err_unknown()?;
+
+    let mut model = import.into_active_model();
+    model.status = Set(UserImportStatus::Completed);
+    model.completed_at = Set(Some(Utc::now().naive_utc()));
+    model.update(tx).await.map_err_unknown()?;
+
+    Ok(())
+}
@@ -17,6 +17,9 @@ pub trait KV {
         ttl: Option<std::time::Duration>,
     ) -> ServiceResult<()>;
     async fn delete_(&self, key: &str) -> ServiceResult<()>;
+    /// 整数の値を原子的に 1 増やして、増やした後の値を返す。
+    /// キーが存在しなかった場合は `ttl` 後に期限切れになる。既存のキーの期限は延長しない。
+    async fn incr_(&self, key: &str, ttl: std::time::Duration) -> ServiceResult<u64>;
 }
 
 impl dyn KV + Send + Sync {
@@ -60,4 +63,12 @@ impl dyn KV + Send + Sync {
     pub async fn delete(&self, key: impl AsRef<str>) -> ServiceResult<()> {
         self.delete_(key.as_ref()).await
     }
+
+    pub async fn incr_ttl(
+        &self,
+        key: impl AsRef<str>,
+        ttl: std::time::Duration,
+    ) -> ServiceResult<u64> {
+        self.incr_(key.as_ref(), ttl).await
+    }
 }
@@ -0,0 +1,326 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! リモートのメディアのキャッシュ
+//!
+//! リモートのアップロードを初回アクセス時 (または受信時) にダウンロードしてストレージに保存し、
+//! 以降はリモートにプロキシせずにストレージから配信する。
+//! 最後にアクセスされた日時を記録し、期限切れのキャッシュや容量の上限を超えた分は
+//! [`evict_remote_media_cache`] で古いものから削除する。
+
+use chrono::{TimeDelta, Utc};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder, Set,
+    TryIntoModel, sea_query::OnConflict,
+};
+use tracing::{debug, info, warn};
+use url::Url;
+
+use crate::ServiceStateBase;
+
+use super::{
+    MapToUnknown, ServiceResult,
+    db::MaybeTxConn,
+    id::{Identifier, UploadID},
+    storage::StorageObject,
+    upload::{UploadKind, generate_image_variants, remove_upload_files, stored_files},
+};
+
+/// 最終アクセス日時を更新する間隔。アクセスのたびに書き込まないようにする。
+const ACCESS_UPDATE_INTERVAL: TimeDelta = TimeDelta::minutes(1);
+
+#[derive(Debug, Clone)]
+pub struct MediaCacheConfig {
+    /// false の場合はキャッシュせずにリモートにプロキシする
+    pub enabled: bool,
+    /// キャッシュ全体の大きさの上限 (バイト)
+    pub quota: u64,
+    /// 最後にアクセスされてからこの期間が経過したキャッシュは削除する
+    pub ttl: Option<TimeDelta>,
+    /// リモートのノートを受信したときに添付ファイルをキャッシュするかどうか
+    pub eager: bool,
+}
+
+impl Default for MediaCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            quota: 1024 * 1024 * 1024,
+            ttl: Some(TimeDelta::days(30)),
+            eager: false,
+        }
+    }
+}
+
+/// キャッシュとしてストレージに保存されているファイルのキーを返す。
+pub(crate) fn cached_media_keys(cache: &entity::remote_media_cache::Model) -> Vec<String> {
+    let upload_id = UploadID::from_db_trusted(cache.upload_id.clone());
+    stored_files(&upload_id, &cache.filename, "", cache.has_variants)
+        .into_iter()
+        .map(|(key, _)| key)
+        .collect()
+}
+
+/// キャッシュを取得し、最終アクセス日時を更新する。
+pub async fn get_remote_media_cache(
+    conn: &MaybeTxConn,
+    upload_id: &UploadID,
+) -> ServiceResult<Option<entity::remote_media_cache::Model>> {
+    let cache = entity::remote_media_cache::Entity::find_by_id(upload_id.as_db())
+        .one(conn)
+        .await
+        .map_err_unknown()?;
+
+    let Some(cache) = cache else {
+        return Ok(None);
+    };
+
+    let now = Utc::now().naive_utc();
+    if now - cache.last_accessed_at >= ACCESS_UPDATE_INTERVAL {
+        let mut model: entity::remote_media_cache::ActiveModel = cache.clone().into();
+        model.last_accessed_at = Set(now);
+        model.update(conn).await.map_err_unknown()?;
+    }
+
+    Ok(Some(cache))
+}
+
+/// リモートのアップロードをダウンロードしてキャッシュする。
+/// 既にキャッシュされている場合はそれを返す。
+/// 大きすぎるファイルや対応していない種類のファイルなど、キャッシュできない場合は None を返す。
+pub async fn cache_remote_media(
+    conn: &MaybeTxConn,
+    storage: &StorageObject,
+    client: &reqwest_middleware::ClientWithMiddleware,
+    config: &MediaCacheConfig,
+    upload_id: &UploadID,
+    url: &Url,
+    mime_type: &str,
+) -> ServiceResult<Option<entity::remote_media_cache::Model>> {
+    if let Some(cache) = get_remote_media_cache(conn, upload_id).await? {
+        return Ok(Some(cache));
+    }
+
+    let Some(kind) = UploadKind::from_mime_type(mime_type) else {
+        debug!("{} is not cached: unsupported type {}", url, mime_type);
+        return Ok(None);
+    };
+    let max_size = kind.max_size().min(config.quota);
+
+    let Some(data) = download_remote_media(client, url, mime_type, max_size).await? else {
+        return Ok(None);
+    };
+
+    save_remote_media_cache(conn, storage, upload_id, mime_type, data)
+        .await
+        .map(Some)
+}
+
+async fn download_remote_media(
+    client: &reqwest_middleware::ClientWithMiddleware,
+    url: &Url,
+    mime_type: &str,
+    max_size: u64,
+) -> ServiceResult<Option<Vec<u8>>> {
+    let mut res = client.get(url.as_str()).send().await.map_err_unknown()?;
+
+    if res.status() != reqwest::StatusCode::OK {
+        warn!("failed to download {}: status {}", url, res.status());
+        return Ok(None);
+    }
+
+    let content_type = res
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|c| c.to_str().ok())
+        .and_then(|c| c.split(';').next())
+        .map(|c| c.trim().to_string());
+    if content_type.as_deref() != Some(mime_type) {
+        warn!(
+            "{} is not cached: content type mismatch ({:?} != {})",
+            url, content_type, mime_type
+        );
+        return Ok(None);
+    }
+
+    if res.content_length().is_some_and(|len| len > max_size) {
+        debug!("{} is not cached: too large", url);
+        return Ok(None);
+    }
+
+    let mut data = vec![];
+    while let Some(chunk) = res.chunk().await.map_err_unknown()? {
+        if (data.len() + chunk.len()) as u64 > max_size {
+            debug!("{} is not cached: too large", url);
+            return Ok(None);
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    Ok(Some(data))
+}
+
+/// ダウンロードしたファイルをストレージに保存し、キャッシュとして登録する。
+/// 画像の場合はバリアントも生成する。
+pub(crate) async fn save_remote_media_cache(
+    conn: &MaybeTxConn,
+    storage: &StorageObject,
+    upload_id: &UploadID,
+    mime_type: &str,
+    data: Vec<u8>,
+) -> ServiceResult<entity::remote_media_cache::Model> {
+    let extension = infer::get(&data)
+        .map(|kind| kind.extension())
+        .unwrap_or("bin");
+    let filename = format!("{}.{}", upload_id, extension);
+
+    // バリアントは作業ディレクトリに生成してからストレージに保存する
+    let work_dir = tempfile::tempdir().map_err_unknown()?;
+    let path = work_dir.path().join(&filename);
+    let mut size = data.len() as u64;
+    tokio::fs::write(&path, &data).await.map_err_unknown()?;
+    storage.put(&filename, data, mime_type).await?;
+
+    let has_variants = if UploadKind::from_mime_type(mime_type) == Some(UploadKind::Image) {
+        // 画像の縮小は CPU を使うので、非同期ランタイムをブロックしないように別スレッドで行う
+        let out_dir = work_dir.path().to_path_buf();
+        let upload_id = *upload_id;
+        tokio::task::spawn_blocking(move || generate_image_variants(&path, &out_dir, &upload_id))
+            .await
+            .map_err_unknown()?
+    } else {
+        false
+    };
+
+    let mut saved = vec![filename.clone()];
+    for (key, content_type) in stored_files(upload_id, &filename, mime_type, has_variants)
+        .into_iter()
+        .skip(1)
+    {
+        let variant_path = work_dir.path().join(&key);
+        size += tokio::fs::metadata(&variant_path)
+            .await
+            .map_err_unknown()?
+            .len();
+        if let Err(e) = storage.put_file(&key, &variant_path, &content_type).await {
+            remove_upload_files(storage, saved).await;
+            return Err(e);
+        }
+        saved.push(key);
+    }
+
+    let now = Utc::now().naive_utc();
+    let model = entity::remote_media_cache::ActiveModel {
+        upload_id: Set(upload_id.as_db()),
+        filename: Set(filename),
+        size: Set(size),
+        has_variants: Set(has_variants),
+        cached_at: Set(now),
+        last_accessed_at: Set(now),
+    };
+    // 同時にキャッシュされた場合は、同じキーに保存されているので後から来た方を無視する
+    entity::remote_media_cache::Entity::insert(model.clone())
+        .on_conflict(
+            OnConflict::column(entity::remote_media_cache::Column::UploadId)
+                .do_nothing()
+                .to_owned(),
+        )
+        .do_nothing()
+        .exec(conn)
+        .await
+        .map_err_unknown()?;
+
+    model.try_into_model().map_err_unknown()
+}
+
+/// リモートのアップロードをバックグラウンドでキャッシュする。
+/// キャッシュが無効な場合や、ノート受信時にキャッシュしない設定の場合は何もしない。
+pub fn spawn_remote_media_cache(data: &ServiceStateBase, uploads: Vec<(UploadID, Url, String)>) {
+    let config = data.media_cache();
+    if !config.enabled || !config.eager || uploads.is_empty() {
+        return;
+    }
+
+    let conn = data.maybe_conn();
+    let storage = data.storage().clone();
+    let client = data.proxy_client().clone();
+    let config = config.clone();
+    tokio::spawn(async move {
+        for (upload_id, url, mime_type) in uploads {
+            if let Err(e) = cache_remote_media(
+                &conn, &storage, &client, &config, &upload_id, &url, &mime_type,
+            )
+            .await
+            {
+                warn!("failed to cache {}: {:?}", url, e);
+            }
+        }
+    });
+}
+
+/// キャッシュを削除する。
+/// 最後にアクセスされてから `ttl` が経過したものと、合計が `quota` を超えた分を
+/// 最後にアクセスされた日時が古いものから削除する。
+/// 削除したキャッシュの数を返す。
+pub async fn evict_remote_media_cache(
+    conn: &MaybeTxConn,
+    storage: &StorageObject,
+    config: &MediaCacheConfig,
+) -> ServiceResult<usize> {
+    let expires_before = config.ttl.map(|ttl| Utc::now().naive_utc() - ttl);
+
+    // 新しいものから順に合計を数え、上限を超えたところより古いものを削除対象にする
+    let mut pages = entity::remote_media_cache::Entity::find()
+        .order_by_desc(entity::remote_media_cache::Column::LastAccessedAt)
+        .order_by_desc(entity::remote_media_cache::Column::UploadId)
+        .paginate(conn, 500);
+
+    let mut total = 0u64;
+    let mut evicted = vec![];
+    while let Some(caches) = pages.fetch_and_next().await.map_err_unknown()? {
+        for cache in caches {
+            total += cache.size;
+            let expired = expires_before.is_some_and(|e| cache.last_accessed_at < e);
+            if expired || total > config.quota {
+                evicted.push(cache);
+            }
+        }
+    }
+
+    if evicted.is_empty() {
+        return Ok(0);
+    }
+
+    // 先にデータベースから削除し、配信中のキャッシュが参照されないようにする
+    let mut keys = vec![];
+    for chunk in evicted.chunks(500) {
+        entity::remote_media_cache::Entity::delete_many()
+            .filter(
+                entity::remote_media_cache::Column::UploadId
+                    .is_in(chunk.iter().map(|c| c.upload_id.clone())),
+            )
+            .exec(conn)
+            .await
+            .map_err_unknown()?;
+        keys.extend(chunk.iter().flat_map(cached_media_keys));
+    }
+    remove_upload_files(storage, keys).await;
+
+    info!("evicted {} remote media caches", evicted.len());
+    Ok(evicted.len())
+}
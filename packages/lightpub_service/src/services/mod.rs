@@ -24,22 +24,34 @@ use serde_json::json;
 use thiserror::Error;
 use validator::ValidationErrors;
 
+pub mod access_token;
 pub mod apub;
 pub mod auth;
 pub mod db;
+pub mod domain_policy;
+pub mod emoji;
+pub mod export;
 pub mod follow;
 pub mod fulltext;
 pub mod id;
+pub mod import;
 pub mod kv;
+pub mod media_cache;
 pub mod note;
 pub mod notification;
+pub mod oauth;
+pub mod pending_upload;
 pub mod queue;
+pub mod report;
 pub mod search;
+pub mod storage;
 #[cfg(test)]
 pub mod tests;
 pub mod timeline;
+pub mod two_factor;
 pub mod upload;
 pub mod user;
+pub mod webauthn;
 
 pub use expected_error::ExpectedError;
 
@@ -28,12 +28,16 @@ use super::VisibilityModel;
 use super::create::ExistingNote;
 use super::create::upsert_note;
 use super::create::validate_note_content;
+use super::create::validate_note_summary;
 use super::delete;
 use super::get::get_apubnote_by_id;
 use super::get::get_apubnote_by_spec;
+use super::poll::NotePollModel;
+use super::poll::upsert_remote_note_poll;
 use super::upload::NoteUploadApubModel;
 use activitypub_federation::config::Data;
 use activitypub_federation::fetch::object_id::ObjectId;
+use activitypub_federation::kinds::collection::CollectionType;
 use activitypub_federation::kinds::object::NoteType;
 use activitypub_federation::traits::Object;
 use actix_web::http::StatusCode;
@@ -46,7 +50,6 @@ use nestify::nest;
 use serde::Deserialize;
 use serde::Serialize;
 use thiserror::Error;
-use tracing::info;
 use tracing::warn;
 use url::Url;
 
@@ -59,11 +62,14 @@ use crate::services::apub::CreateActivity;
 use crate::services::apub::contains_public_url;
 use crate::services::db::Conn;
 use crate::services::db::MaybeTxConn;
+use crate::services::domain_policy::get_domain_policy_for_url;
+use crate::services::emoji::{ApubEmojiTag, EmojiModel, register_remote_emojis};
 use crate::services::fulltext::FTClient;
 use crate::services::id::UploadID;
 use crate::services::kv::KVObject;
 use crate::services::notification::push::WPClient;
 use crate::services::queue::QConn;
+use crate::services::upload::UploadMetadata;
 use crate::services::user::get_apubuser_by_id;
 
 use super::super::FederationServiceError;
@@ -84,6 +90,7 @@ nest! {
             pub(crate) view_url: Option<Url>,
             pub(crate) author_url: Url,
             pub(crate) in_reply_to_url: Option<Url>,
+            pub(crate) quote_url: Option<Url>,
             pub(crate) fetched_at: Option<DateTime<Utc>>,
             pub(crate) to: Vec<Url>,
             pub(crate) cc: Vec<Url>,
@@ -96,6 +103,8 @@ nest! {
                 pub(crate) url: Url,
             }>,
             pub(crate) attachments: Vec<NoteUploadApubModel>,
+            pub(crate) poll: Option<NotePollModel>,
+            pub(crate) emojis: Vec<EmojiModel>,
         }
     }
 }
@@ -154,6 +163,17 @@ impl Object for NoteWithApubModel {
                 name: Some(mention.name),
             })
         }
+        for emoji in self.apub.emojis {
+            tags.push(ApubNoteTag::Emoji(emoji.to_apub(data.base_url())))
+        }
+        if let Some(quote_url) = &self.apub.quote_url {
+            // FEP-e232
+            tags.push(ApubNoteTag::Link {
+                href: quote_url.clone(),
+                media_type: Some(ACTIVITYSTREAMS_LINK_MEDIA_TYPE.to_string()),
+                name: Some(format!("RE: {quote_url}")),
+            })
+        }
 
         let attachment = self
             .apub
@@ -163,6 +183,11 @@ impl Object for NoteWithApubModel {
                 kind: a.kind,
                 media_type: a.mime_type,
                 url: a.url,
+                name: a.metadata.description,
+                blurhash: a.metadata.blurhash,
+                width: a.metadata.width,
+                height: a.metadata.height,
+                focal_point: a.metadata.focal_point.map(|(x, y)| [x, y]),
             })
             .collect();
 
@@ -172,15 +197,53 @@ impl Object for NoteWithApubModel {
             media_type: note_content.mime_type().to_string(),
         };
 
+        let (kind, one_of, any_of, end_time, closed, voters_count) = match self.apub.poll {
+            None => (ApubNoteKind::Note, None, None, None, None, None),
+            Some(poll) => {
+                let options = poll
+                    .options
+                    .into_iter()
+                    .map(|o| ApubPollOption {
+                        kind: NoteType::Note,
+                        name: o.title,
+                        replies: Some(ApubPollReplies {
+                            kind: CollectionType::Collection,
+                            total_items: o.votes_count,
+                        }),
+                    })
+                    .collect::<Vec<_>>();
+                let (one_of, any_of) = if poll.multiple {
+                    (None, Some(options))
+                } else {
+                    (Some(options), None)
+                };
+                let closed = if poll.closed {
+                    Some(poll.expires_at.unwrap_or_else(Utc::now).min(Utc::now()))
+                } else {
+                    None
+                };
+                (
+                    ApubNoteKind::Question,
+                    one_of,
+                    any_of,
+                    poll.expires_at,
+                    closed,
+                    Some(poll.voters_count),
+                )
+            }
+        };
+
         let qconn = data.qconn();
         Ok(ApubNoteModel {
             id: ObjectId::parse(&self.apub.url.to_string()).unwrap(),
             attributed_to: ObjectId::parse(&self.apub.author_url.to_string()).unwrap(),
             content: note_content.render_to_html(qconn).await?.into_inner(),
-            kind: NoteType::Note,
+            kind,
             published: self.basic.created_at,
             updated: self.basic.updated_at,
-            sensitive: Some(self.basic.sensitive),
+            // Mastodon に合わせて、CW 付きのノートはセンシティブとして扱う
+            sensitive: Some(self.basic.sensitive || self.basic.summary.is_some()),
+            summary: self.basic.summary,
             in_reply_to: self
                 .apub
                 .in_reply_to_url
@@ -191,6 +254,14 @@ impl Object for NoteWithApubModel {
             to: self.apub.to,
             cc: self.apub.cc,
             attachment: Some(attachment),
+            name: None,
+            one_of,
+            any_of,
+            end_time,
+            closed,
+            voters_count,
+            misskey_quote: self.apub.quote_url.clone(),
+            quote_url: self.apub.quote_url,
         })
     }
 
@@ -250,9 +321,12 @@ nest! {
     pub struct ApubNoteModel {
         pub(crate) id: ObjectId<NoteWithApubModel>,
         #[serde(rename = "type")]
-        pub(crate) kind: NoteType,
+        pub(crate) kind: ApubNoteKind,
         pub(crate) attributed_to: ObjectId<UserWithApubModel>,
+        // 投票の Note は content を持たない
+        #[serde(default)]
         pub(crate) content: String,
+        #[serde(default = "Utc::now")]
         pub(crate) published: DateTime<Utc>,
         #[serde(skip_serializing_if = "Option::is_none")]
         pub(crate) updated: Option<DateTime<Utc>>,
@@ -270,6 +344,9 @@ nest! {
         #[serde(skip_serializing_if = "Option::is_none")]
         pub(crate) in_reply_to: Option<ObjectId<UserWithApubModel>>,
         pub(crate) sensitive: Option<bool>,
+        /// コンテンツ警告 (CW) の文言
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub(crate) summary: Option<String>,
         #[serde(skip_serializing_if = "Option::is_none")]
         #[serde(rename = "tag")]
         pub(crate) tags: Option<Vec<ApubNoteTag>>,
@@ -279,7 +356,64 @@ nest! {
             pub(crate) kind: String,
             pub(crate) url: Url,
             pub(crate) media_type: String,
-        }>>
+            /// 説明文 (代替テキスト)
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub(crate) name: Option<String>,
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub(crate) blurhash: Option<String>,
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub(crate) width: Option<u32>,
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub(crate) height: Option<u32>,
+            /// フォーカルポイント (Mastodon 拡張)
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub(crate) focal_point: Option<[f32; 2]>,
+        }>>,
+        /// 投票の Note の場合、選んだ選択肢
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub(crate) name: Option<String>,
+        /// 単一選択の投票の選択肢
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub(crate) one_of: Option<Vec<ApubPollOption>>,
+        /// 複数選択の投票の選択肢
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub(crate) any_of: Option<Vec<ApubPollOption>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub(crate) end_time: Option<DateTime<Utc>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub(crate) closed: Option<DateTime<Utc>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub(crate) voters_count: Option<u64>,
+        /// 引用先ノート (Misskey, Fedibird)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub(crate) quote_url: Option<Url>,
+        /// 引用先ノート (Misskey)
+        #[serde(default, rename = "_misskey_quote", skip_serializing_if = "Option::is_none")]
+        pub(crate) misskey_quote: Option<Url>,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApubNoteKind {
+    Note,
+    /// 投票を含むノート
+    Question,
+}
+
+nest! {
+    #[derive(Debug, Clone, Serialize, Deserialize)]*
+    #[serde(rename_all = "camelCase")]*
+    pub struct ApubPollOption {
+        #[serde(rename = "type")]
+        pub(crate) kind: NoteType,
+        pub(crate) name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub(crate) replies: Option<pub struct ApubPollReplies {
+            #[serde(rename = "type")]
+            pub(crate) kind: CollectionType,
+            #[serde(default)]
+            pub(crate) total_items: u64,
+        }>,
     }
 }
 
@@ -291,14 +425,52 @@ pub enum ApubNoteTag {
     #[serde(rename = "Mention")]
     Mention { name: Option<String>, href: Url },
     #[serde(rename = "Emoji")]
-    Emoji {
-        // TODO: ignored for now
+    Emoji(ApubEmojiTag),
+    /// FEP-e232 のオブジェクトリンク
+    #[serde(rename = "Link")]
+    Link {
+        href: Url,
+        #[serde(rename = "mediaType")]
+        media_type: Option<String>,
+        name: Option<String>,
     },
 }
 
+/// FEP-e232 でオブジェクトへのリンクを表す mediaType
+const ACTIVITYSTREAMS_LINK_MEDIA_TYPE: &str =
+    "application/ld+json; profile=\"https://www.w3.org/ns/activitystreams\"";
+
+fn is_activitystreams_media_type(media_type: &str) -> bool {
+    let media_type = media_type.trim();
+    media_type == "application/activity+json"
+        || (media_type.starts_with("application/ld+json")
+            && media_type.contains("https://www.w3.org/ns/activitystreams"))
+}
+
 impl ApubNoteModel {
+    /// 引用先ノートの URL を返す。
+    /// `quoteUrl`, `_misskey_quote`, FEP-e232 の Link タグの順に探す。
+    pub fn quote_target(&self) -> Option<&Url> {
+        self.quote_url
+            .as_ref()
+            .or(self.misskey_quote.as_ref())
+            .or_else(|| {
+                self.tags.iter().flatten().find_map(|t| match t {
+                    ApubNoteTag::Link {
+                        href,
+                        media_type: Some(media_type),
+                        ..
+                    } if is_activitystreams_media_type(media_type) => Some(href),
+                    _ => None,
+                })
+            })
+    }
+
     pub fn validate(&self) -> ServiceResult<()> {
         validate_note_content(&self.content)?;
+        if let Some(summary) = &self.summary {
+            validate_note_summary(summary)?;
+        }
 
         if let Some(attachments) = self.attachment.as_ref() {
             let domain = self.id.inner().domain().expect("domain must be set");
@@ -312,6 +484,19 @@ impl ApubNoteModel {
 }
 
 impl ApubNoteAttachment {
+    /// 添付ファイルのメタデータを取り出す。
+    pub fn metadata(&self) -> UploadMetadata {
+        UploadMetadata {
+            description: self.name.clone(),
+            width: self.width,
+            height: self.height,
+            blurhash: self.blurhash.clone(),
+            focal_point: self.focal_point.map(|[x, y]| (x, y)),
+            ..Default::default()
+        }
+        .sanitize_remote()
+    }
+
     pub fn validate(&self, domain: &str) -> ServiceResult<()> {
         if self.url.domain() != Some(domain) {
             return create_error_simple(
@@ -346,6 +531,7 @@ async fn upsert_apub_note(
 
     let mut hashtags = vec![];
     let mut mentions = vec![];
+    let mut emojis = vec![];
     if let Some(tags) = &json.tags {
         for tag in tags {
             match tag {
@@ -361,20 +547,47 @@ async fn upsert_apub_note(
                         warn!("empty hashtag");
                     }
                 }
-                ApubNoteTag::Emoji {} => {
-                    // TODO: handle emoji tag
-                    info!("ignoring emoji tag");
+                ApubNoteTag::Emoji(emoji) => {
+                    emojis.push(emoji);
                 }
+                ApubNoteTag::Link { .. } => {}
             }
         }
     }
 
+    let media_rejected = get_domain_policy_for_url(&conn.clone().into(), json.id.inner())
+        .await?
+        .rejects_media();
+
+    if !media_rejected {
+        let domain = json.id.inner().domain().expect("domain must be set");
+        register_remote_emojis(&conn.clone().into(), data.proxy_client(), domain, &emojis).await;
+    }
+
+    // 引用先のノートを取得する。取得できない場合は通常のノートとして扱う。
+    let quote_of_id = match json.quote_target() {
+        None => None,
+        Some(quote_url) => {
+            match ObjectId::<NoteWithApubModel>::from(quote_url.clone())
+                .dereference(data)
+                .await
+            {
+                Ok(n) if n.basic.content.is_some() && n.basic.is_renotable() => Some(n.basic.id),
+                Ok(_) => None,
+                Err(e) => {
+                    warn!("failed to fetch quoted note {quote_url}: {e:?}");
+                    None
+                }
+            }
+        }
+    };
+
     let mut uploads = vec![];
-    if let Some(attachments) = &json.attachment {
+    if let Some(attachments) = json.attachment.as_ref().filter(|_| !media_rejected) {
         for at in attachments {
             let mime_type = match Mime::from_str(&at.media_type) {
                 Ok(m) => match m.type_() {
-                    mime::IMAGE => m,
+                    mime::IMAGE | mime::VIDEO | mime::AUDIO => m,
                     ty => {
                         warn!("unsupported media type (skip): {}", ty);
                         continue;
@@ -390,6 +603,7 @@ async fn upsert_apub_note(
                 upload_id,
                 at.url.clone(),
                 mime_type.to_string(),
+                at.metadata(),
             ))
         }
     }
@@ -407,10 +621,12 @@ async fn upsert_apub_note(
         Some(viz),
         &PostCreateOptionsBuilder::default()
             .sensitive(UpsertOperation::Set(json.sensitive.unwrap_or(false)))
+            .summary(UpsertOperation::Set(json.summary.clone()))
             .mentions_override(Some(mentions))
             .hashtags_override(Some(hashtags))
             .created_at(Some(json.published))
             .uploads(UpsertOperation::Set(uploads))
+            .quote_of_id(quote_of_id)
             .build()
             .unwrap(),
         my_domain,
@@ -419,6 +635,8 @@ async fn upsert_apub_note(
     )
     .await?;
 
+    upsert_remote_note_poll(conn, note_id, json.remote_poll_data().as_ref()).await?;
+
     let conn = conn.clone().into();
     let note =
         get_apubnote_by_spec(&conn, &NoteSpecifier::ID(note_id), my_domain, base_url).await?;
@@ -10,7 +10,7 @@ use crate::{
         db::MaybeTxConn,
         id::{Identifier, NoteID, UserID},
         note::VisibilityModel,
-        user::get_apubuser_by_id,
+        user::{get_apubuser_by_id, get_blocking_or_blocked_user_ids},
     },
 };
 use sea_orm::{Condition, prelude::*};
@@ -29,11 +29,15 @@ pub async fn calculate_to_and_cc_of_renote(
     visibility: VisibilityModel,
     base_url: &Url,
 ) -> ServiceResult<CalculateToAndCcResult> {
+    let blocks = get_blocking_or_blocked_user_ids(tx, renote_author_id).await?;
+
     let (renote_followers_url, renote_followers_inboxes) =
-        calculate_follower_recipients(tx, renote_author_id, base_url).await?;
+        calculate_follower_recipients(tx, renote_author_id, &blocks, base_url).await?;
 
     let (renote_target_url, renote_target_inbox) =
         calculate_user_addresses(tx, renote_target_author_id, base_url).await?;
+    let renote_target_inbox =
+        renote_target_inbox.filter(|_| !blocks.contains(&renote_target_author_id));
 
     let mut to = vec![];
     let mut cc = vec![];
@@ -66,6 +70,7 @@ pub async fn calculate_to_and_cc_of_renote(
 async fn calculate_mention_recipients(
     tx: &MaybeTxConn,
     note_id: NoteID,
+    excluded_users: &HashSet<UserID>,
     base_url: &Url,
 ) -> ServiceResult<(Vec<Url>, Vec<Url>)> {
     let mentions = entity::note_mention::Entity::find()
@@ -77,14 +82,14 @@ async fn calculate_mention_recipients(
     let mut mention_urls = vec![];
     let mut mention_inboxes = vec![];
     for mention in mentions {
-        let user = get_apubuser_by_id(
-            tx,
-            UserID::from_db_trusted(mention.target_user_id),
-            base_url,
-        )
-        .await
-        .map_err_unknown()?
-        .expect("mentioned user should exist");
+        let user_id = UserID::from_db_trusted(mention.target_user_id);
+        if excluded_users.contains(&user_id) {
+            continue;
+        }
+        let user = get_apubuser_by_id(tx, user_id, base_url)
+            .await
+            .map_err_unknown()?
+            .expect("mentioned user should exist");
         mention_urls.push(user.apub.url.clone());
         mention_inboxes.push(user.shared_inbox_or_inbox().clone());
     }
@@ -95,6 +100,7 @@ async fn calculate_mention_recipients(
 async fn calculate_follower_recipients(
     tx: &MaybeTxConn,
     author_id: UserID,
+    excluded_users: &HashSet<UserID>,
     base_url: &Url,
 ) -> ServiceResult<(Option<Url>, Vec<Url>)> {
     let author_model = get_apubuser_by_id(tx, author_id, base_url)
@@ -114,11 +120,14 @@ async fn calculate_follower_recipients(
 
     let mut followers_inboxes = vec![];
     for follower in followers {
-        let follower =
-            get_apubuser_by_id(tx, UserID::from_db_trusted(follower.follower_id), base_url)
-                .await
-                .map_err_unknown()?
-                .expect("follower should exist");
+        let follower_id = UserID::from_db_trusted(follower.follower_id);
+        if excluded_users.contains(&follower_id) {
+            continue;
+        }
+        let follower = get_apubuser_by_id(tx, follower_id, base_url)
+            .await
+            .map_err_unknown()?
+            .expect("follower should exist");
         followers_inboxes.push(follower.shared_inbox_or_inbox().clone());
     }
 
@@ -154,13 +163,16 @@ pub async fn calculate_to_and_cc(
     include_author: bool,
     base_url: &Url,
 ) -> ServiceResult<CalculateToAndCcResult> {
+    // Users in a block relation with the author never receive the note
+    let blocks = get_blocking_or_blocked_user_ids(tx, author_id).await?;
+
     // Calculate mentions
     let (mention_urls, mention_inboxes) =
-        calculate_mention_recipients(tx, note_id, base_url).await?;
+        calculate_mention_recipients(tx, note_id, &blocks, base_url).await?;
 
     // Calculate followers
     let (author_followers_url, followers_inboxes) =
-        calculate_follower_recipients(tx, author_id, base_url).await?;
+        calculate_follower_recipients(tx, author_id, &blocks, base_url).await?;
 
     // Calculate author addresses if needed
     let (_, author_inbox) = if include_author {
@@ -10,7 +10,12 @@ pub async fn count_local_notes(tx: &MaybeTxConn) -> ServiceResult<u64> {
         .filter(
             Condition::all()
                 .add(entity::note::Column::DeletedAt.is_null())
-                .add(entity::note::Column::RenoteOfId.is_null())
+                // 引用ノートは数えるが、リノートは数えない
+                .add(
+                    Condition::any()
+                        .add(entity::note::Column::RenoteOfId.is_null())
+                        .add(entity::note::Column::Content.is_not_null()),
+                )
                 .add(entity::user::Column::Domain.eq("")),
         )
         .count(tx)
@@ -27,8 +27,11 @@ use crate::{
         },
         id::{Identifier, NoteID, UploadID, UserID},
         kv::KVObject,
+        media_cache::spawn_remote_media_cache,
         notification::{NotificationBody, add_notification, push::WPClient},
+        pending_upload::attach_pending_upload,
         queue::QConn,
+        upload::{UploadMetadata, save_upload_file_info},
         user::{
             UserSpecifier, get_apubuser_by_id, get_user_by_id, get_user_by_spec_with_remote,
             is_blocking_or_blocked,
@@ -38,9 +41,14 @@ use crate::{
 };
 
 use super::{
-    ContentType, Mention, VisibilityModel, get::get_apubnote_by_id_with_inboxes, get_note_by_id,
-    hashtag::find_hashtags, invalidate_note_basic_cache, mention::find_mentions,
+    ContentType, Mention, VisibilityModel,
+    get::get_apubnote_by_id_with_inboxes,
+    get_note_by_id,
+    hashtag::find_hashtags,
+    invalidate_note_basic_cache,
+    mention::find_mentions,
     note_visibility_check,
+    poll::{NotePollCreate, insert_note_poll},
 };
 
 pub async fn upsert_note(
@@ -65,6 +73,12 @@ pub async fn upsert_note(
         .into();
 
     validate_note_content(content)?;
+    if let UpsertOperation::Set(Some(summary)) = &options.summary {
+        validate_note_summary(summary)?;
+    }
+    if let Some(poll) = &options.poll {
+        poll.validate()?;
+    }
 
     // find author
     let author = get_user_by_spec_with_remote(
@@ -195,6 +209,27 @@ pub async fn upsert_note(
         }
     };
 
+    // Get quoted note
+    // 編集時には引用先を変更しない
+    let quoted_note = match (is_update, options.quote_of_id) {
+        (false, Some(q)) => {
+            let qn = get_note_by_id(&tx, q, false).await?;
+            match qn {
+                Some(qn)
+                    if qn.content.is_some()
+                        && note_visibility_check(&tx, qn.id, Some(author_id), false).await? =>
+                {
+                    if !qn.is_renotable() {
+                        return Err(ServiceError::known(NoteCreateError::RenoteNotAllowed));
+                    }
+                    Some(qn)
+                }
+                _ => return Err(ServiceError::known(NoteCreateError::QuoteTargetNotFound)),
+            }
+        }
+        _ => None,
+    };
+
     let hashtags = note_create_find_hashtags(content, content_type, &options.hashtags_override);
     let mentions: Vec<UserID> = note_create_find_mentions(
         &tx,
@@ -226,9 +261,13 @@ pub async fn upsert_note(
         UpsertOperation::Set(url.clone()),
         UpsertOperation::Set(options.view_url.clone()),
         options.sensitive,
+        options.summary.clone(),
         is_update,
         now_time,
     );
+    if let Some(quoted_note) = &quoted_note {
+        model.renote_of_id = Set(Some(quoted_note.id.as_db()));
+    }
     if is_update {
         model.update(&tx).await.map_err_unknown()?;
     } else {
@@ -259,6 +298,14 @@ pub async fn upsert_note(
         add_notification(&tx, rconn, wp, replied_note.author.id, &body, base_url).await?;
     }
 
+    // quote notification
+    if let Some(quoted_note) = &quoted_note {
+        if quoted_note.author.is_local() && quoted_note.author.id != author_id {
+            let body = NotificationBody::Quoted(author_id, note_id, quoted_note.id);
+            add_notification(&tx, rconn, wp, quoted_note.author.id, &body, base_url).await?;
+        }
+    }
+
     // mentions
     // delete all mentions first
     entity::note_mention::Entity::delete_many()
@@ -292,6 +339,12 @@ pub async fn upsert_note(
             .await?;
     }
 
+    // poll
+    // 編集時には投票を変更しない
+    if let (false, Some(poll)) = (is_update, &options.poll) {
+        insert_note_poll(&tx, note_id, poll).await?;
+    }
+
     // uploads
     if let UpsertOperation::Set(uploads) = &options.uploads {
         // delete all uploads first
@@ -303,26 +356,25 @@ pub async fn upsert_note(
         // then insert new uploads
         for upload in uploads {
             let upload_id = match upload {
-                NoteUpload::File(upload_id, filename, mime_type) => {
-                    let model = entity::upload::ActiveModel {
-                        id: Set(upload_id.as_db()),
-                        filename: Set(Some(filename.to_str().expect("bad filename").to_string())),
-                        mime_type: Set(mime_type.to_string()),
-                        ..Default::default()
-                    };
-                    model.insert(&tx).await.map_err_unknown()?;
+                NoteUpload::File(upload_id, filename, mime_type, metadata) => {
+                    save_upload_file_info(&tx, upload_id, filename, mime_type, metadata).await?;
                     upload_id.clone()
                 }
-                NoteUpload::URL(upload_id, url, mime_type) => {
-                    let model = entity::upload::ActiveModel {
+                NoteUpload::URL(upload_id, url, mime_type, metadata) => {
+                    let mut model = entity::upload::ActiveModel {
                         id: Set(upload_id.as_db()),
                         url: Set(Some(url.to_string())),
                         mime_type: Set(mime_type.to_string()),
                         ..Default::default()
                     };
+                    metadata.set_to_model(&mut model);
                     model.insert(&tx).await.map_err_unknown()?;
                     upload_id.clone()
                 }
+                NoteUpload::Pending(upload_id) => {
+                    attach_pending_upload(&tx, author_id, upload_id).await?;
+                    *upload_id
+                }
             };
             let model = entity::note_upload::ActiveModel {
                 note_id: Set(note_id.as_db()),
@@ -337,6 +389,20 @@ pub async fn upsert_note(
 
     invalidate_note_basic_cache(rconn, note_id).await?;
 
+    // リモートのノートの添付ファイルをキャッシュする
+    if let (false, UpsertOperation::Set(uploads)) = (author.is_local(), &options.uploads) {
+        let remote_uploads = uploads
+            .iter()
+            .filter_map(|upload| match upload {
+                NoteUpload::URL(upload_id, url, mime_type, _) => {
+                    Some((upload_id.clone(), url.clone(), mime_type.clone()))
+                }
+                NoteUpload::File(..) | NoteUpload::Pending(..) => None,
+            })
+            .collect();
+        spawn_remote_media_cache(fed_data, remote_uploads);
+    }
+
     // send activitypub note
     let tx = conn.as_tx().await?.into();
     if author.is_local() {
@@ -410,6 +476,7 @@ fn set_note_active_model(
     url: UpsertOperation<Option<Url>>,
     view_url: UpsertOperation<Option<Url>>,
     sensitive: UpsertOperation<bool>,
+    summary: UpsertOperation<Option<String>>,
     is_update: bool,
     created_or_updated_at: DateTime<Utc>,
 ) {
@@ -430,6 +497,9 @@ fn set_note_active_model(
     if let UpsertOperation::Set(s) = sensitive {
         model.sensitive = Set(s as i8);
     }
+    if let UpsertOperation::Set(summary) = summary {
+        model.summary = Set(summary.filter(|s| !s.trim().is_empty()));
+    }
 
     if is_update {
         model.updated_at = Set(Some(created_or_updated_at.naive_utc()));
@@ -539,8 +609,11 @@ pub enum ExistingNote {
 
 #[derive(Debug, Clone)]
 pub enum NoteUpload {
-    File(UploadID, PathBuf, String), // filename, mime_type
-    URL(UploadID, Url, String),      // url, mime_type
+    File(UploadID, PathBuf, String, UploadMetadata), // filename, mime_type, metadata
+    URL(UploadID, Url, String, UploadMetadata),      // url, mime_type, metadata
+    /// [`register_pending_upload`](crate::services::pending_upload::register_pending_upload)
+    /// で登録済みのアップロード
+    Pending(UploadID),
 }
 
 /// ノート作成または更新時の追加オプション。
@@ -568,6 +641,15 @@ pub struct PostCreateOptions {
     /// ノートをセンシティブ設定するか否か。デフォルトは false。
     #[builder(default = "UpsertOperation::KeepOrSetDefault")]
     sensitive: UpsertOperation<bool>,
+    /// コンテンツ警告 (CW) の文言。空文字列の場合は CW なしとして扱う。デフォルトは None。
+    #[builder(default = "UpsertOperation::KeepOrSetDefault")]
+    summary: UpsertOperation<Option<String>>,
+    /// ノートに添付する投票。新規作成時のみ使用される。デフォルトは None。
+    #[builder(default)]
+    poll: Option<NotePollCreate>,
+    /// 引用するノートの ID。新規作成時のみ使用される。デフォルトは None。
+    #[builder(default)]
+    quote_of_id: Option<NoteID>,
 }
 
 impl Default for PostCreateOptions {
@@ -580,6 +662,9 @@ impl Default for PostCreateOptions {
             view_url: None,
             created_at: None,
             sensitive: UpsertOperation::KeepOrSetDefault,
+            summary: UpsertOperation::KeepOrSetDefault,
+            poll: None,
+            quote_of_id: None,
         }
     }
 }
@@ -598,6 +683,9 @@ pub enum NoteCreateError {
     #[error("renote target not found")]
     #[ee(status(StatusCode::NOT_FOUND))]
     RenoteTargetNotFound,
+    #[error("quote target not found")]
+    #[ee(status(StatusCode::NOT_FOUND))]
+    QuoteTargetNotFound,
     #[error("renote not allowed for this note")]
     #[ee(status(StatusCode::FORBIDDEN))]
     RenoteNotAllowed,
@@ -624,6 +712,13 @@ pub fn validate_note_content(content: &str) -> ServiceResult<()> {
     Ok(())
 }
 
+pub fn validate_note_summary(summary: &str) -> ServiceResult<()> {
+    if summary.chars().count() > 1000 {
+        return create_error_simple(StatusCode::BAD_REQUEST, "note summary too long");
+    }
+    Ok(())
+}
+
 pub async fn rebuild_note_fulltext_index(
     conn: &MaybeTxConn,
     ft: &FTClient,
@@ -73,7 +73,7 @@ pub async fn delete_note_by_id_(
         }
     }
 
-    let note = if note.renote_of_id.is_some() {
+    let note = if note.renote_of_id.is_some() && note.content.is_none() {
         // renote is not supported by this function
         return Err(ServiceError::known(NoteDeleteError::IsRenote));
     } else {
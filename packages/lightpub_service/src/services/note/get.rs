@@ -3,6 +3,7 @@ use crate::{
     services::{
         MapToUnknown,
         db::MaybeTxConn,
+        emoji::get_emojis_in_content,
         id::{Identifier, NoteID, UserID},
         kv::KVObject,
         user::{domain_to_optional, get_apubuser_by_id, get_user_by_id},
@@ -16,6 +17,8 @@ use super::{
     NoteApubData, NoteApubHashtagData, NoteApubMentionData, NoteAuthorModel, NoteContentModel,
     NoteMentionModel, NoteModelDetails, NoteSpecifier, NoteWithApubModel, VisibilityModel,
     calculate_to_and_cc, note_visibility_check,
+    poll::get_note_poll,
+    reaction::get_note_reactions,
     upload::{get_note_uploads, get_note_uploads_apub},
 };
 
@@ -173,6 +176,9 @@ async fn get_note_by_spec_impl(
         deleted_at: note.deleted_at.map(|d| d.and_utc()),
         uploads,
         sensitive: note.sensitive != 0,
+        summary: note.summary.clone(),
+        url: note.url.as_deref().and_then(|u| Url::parse(u).ok()),
+        view_url: note.view_url.as_deref().and_then(|u| Url::parse(u).ok()),
     };
     Ok(Some(note_model))
 }
@@ -223,7 +229,7 @@ async fn get_apubdata_by_note_id_with_inboxes(
 
     match note {
         None => Ok(None),
-        Some((note, _author)) => {
+        Some((note, author)) => {
             let url = get_url_of_note_model(&note, base_url);
 
             let in_reply_to_url = match &note.reply_to_id {
@@ -240,6 +246,17 @@ async fn get_apubdata_by_note_id_with_inboxes(
                 }
             };
 
+            // 引用ノートの場合、引用先ノートの URL
+            let quote_url = match (&note.content, &note.renote_of_id) {
+                (Some(_), Some(quote_of_id)) => {
+                    let quote_of_id = NoteID::from_db_trusted(quote_of_id.clone());
+                    get_rawnote_by_id(conn, quote_of_id)
+                        .await?
+                        .map(|(quoted_note, _)| get_url_of_note_model(&quoted_note, base_url))
+                }
+                _ => None,
+            };
+
             let author_model = get_apubuser_by_id(
                 conn,
                 UserID::from_db_trusted(note.author_id.clone()),
@@ -292,6 +309,15 @@ async fn get_apubdata_by_note_id_with_inboxes(
 
             let attachments = get_note_uploads_apub(conn, note_id, base_url).await?;
 
+            let poll = get_note_poll(conn, note_id, None).await?;
+
+            let emojis = get_emojis_in_content(
+                conn,
+                domain_to_optional(author.domain.as_str()),
+                note.content.as_deref().unwrap_or_default(),
+            )
+            .await?;
+
             Ok(Some(ApubDataWithInboxes {
                 data: NoteApubData {
                     url,
@@ -300,10 +326,13 @@ async fn get_apubdata_by_note_id_with_inboxes(
                     to,
                     cc,
                     in_reply_to_url,
+                    quote_url,
                     view_url: Some(view_url),
                     mentions,
                     hashtags,
                     attachments,
+                    poll,
+                    emojis,
                 },
                 inboxes,
             }))
@@ -413,6 +442,7 @@ async fn fill_note_user_interaction(
                 Condition::all()
                     .add(entity::note::Column::AuthorId.eq(viewer_id.as_db()))
                     .add(entity::note::Column::RenoteOfId.eq(note.id.as_db()))
+                    .add(entity::note::Column::Content.is_null())
                     .add(entity::note::Column::DeletedAt.is_null()), // これはなくてもいいはず
             )
             .one(conn)
@@ -495,6 +525,18 @@ async fn fill_note_user_interaction(
         });
     }
 
+    let poll = get_note_poll(conn, note.id, viewer_id).await?;
+
+    let (reactions, my_reaction) = get_note_reactions(conn, note.id, viewer_id).await?;
+
+    let emojis = match &note.content {
+        None => vec![],
+        Some(content) => {
+            get_emojis_in_content(conn, note.author.domain.as_deref(), content.as_raw_text())
+                .await?
+        }
+    };
+
     Ok(DetailedNoteModel {
         basic: note,
         details: NoteModelDetails {
@@ -506,6 +548,10 @@ async fn fill_note_user_interaction(
             bookmarked,
             hashtags,
             mentions,
+            poll,
+            emojis,
+            reactions,
+            my_reaction,
         },
     })
 }
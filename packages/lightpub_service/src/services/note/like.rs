@@ -1,11 +1,14 @@
-use sea_orm::{Condition, EntityTrait, IntoActiveModel, Set, SqlErr, TryIntoModel};
+use sea_orm::{
+    Condition, EntityTrait, IntoActiveModel, QueryOrder, QuerySelect, Set, SqlErr, TryIntoModel,
+};
 use url::Url;
 
 use crate::services::{
     MapToUnknown, ServiceError, ServiceResult,
     apub::{LikeActivity, LikeableObject, UndoActivity},
-    db::Conn,
+    db::{Conn, MaybeTxConn},
     id::{Identifier, NoteID, UserID},
+    kv::KVObject,
     queue::QConn,
     user::get_apubuser_by_id,
 };
@@ -14,8 +17,9 @@ use sea_orm::ColumnTrait;
 use sea_orm::QueryFilter;
 
 use super::{
-    CalculateToAndCcResult, NoteLikeError, NoteSpecifier, calculate_to_and_cc,
-    get::get_apubnote_by_spec, get_apubnote_by_id, note_visibility_check,
+    CalculateToAndCcResult, DetailedNoteModel, NoteLikeError, NoteSpecifier, calculate_to_and_cc,
+    get::get_apubnote_by_spec, get_apubnote_by_id, get_note_by_id_visibility_check,
+    note_visibility_check, reaction::get_reaction_emoji,
 };
 
 pub async fn note_like_add(
@@ -24,6 +28,7 @@ pub async fn note_like_add(
     user_id: UserID,
     note_id: NoteID,
     is_private: bool,
+    reaction: Option<String>,
     my_domain: &str,
     base_url: &Url,
 ) -> ServiceResult<()> {
@@ -43,6 +48,7 @@ pub async fn note_like_add(
         note_id: Set(note_id.as_db()),
         user_id: Set(user_id.as_db()),
         is_private: Set(is_private as i8),
+        reaction: Set(reaction.filter(|_| !is_private)),
         ..Default::default()
     };
     let like = match like.save(&tx).await {
@@ -62,12 +68,18 @@ pub async fn note_like_add(
         let note = get_apubnote_by_spec(&tx, &NoteSpecifier::ID(note_id), my_domain, base_url)
             .await?
             .expect("note should exist");
-        let like = LikeActivity::new(
+        let mut like_activity = LikeActivity::new(
             like.id,
             user.apub.url.clone(),
             LikeableObject::note(note.apub.url),
             base_url,
         );
+        if let Some(reaction) = like.reaction {
+            let emoji = get_reaction_emoji(&tx, &reaction)
+                .await?
+                .map(|e| e.to_apub(base_url));
+            like_activity = like_activity.with_reaction(reaction, emoji);
+        }
 
         let CalculateToAndCcResult {
             inboxes,
@@ -83,7 +95,7 @@ pub async fn note_like_add(
         )
         .await?;
 
-        qconn.queue_activity(like, user, inboxes).await?;
+        qconn.queue_activity(like_activity, user, inboxes).await?;
     }
 
     tx.commit().await?;
@@ -162,3 +174,52 @@ pub async fn note_like_remove(
     tx.commit().await?;
     Ok(())
 }
+
+/// いいね・ブックマークしたノート
+#[derive(Debug, Clone)]
+pub struct LikedNoteModel {
+    /// ページネーションに使う、いいね・ブックマークの ID
+    pub like_id: i32,
+    pub note: DetailedNoteModel,
+}
+
+/// ユーザーがいいねしたノートを新しい順に取得する。
+/// `is_private` が true の場合はブックマークしたノートを取得する。絵文字リアクションは含まない。
+/// `before_id` を指定すると、その ID より前のものを取得する。
+pub async fn get_liked_notes(
+    conn: &MaybeTxConn,
+    rconn: &KVObject,
+    user_id: UserID,
+    is_private: bool,
+    limit: u64,
+    before_id: Option<i32>,
+) -> ServiceResult<Vec<LikedNoteModel>> {
+    let likes = entity::note_like::Entity::find()
+        .filter(
+            Condition::all()
+                .add(entity::note_like::Column::UserId.eq(user_id.as_db()))
+                .add(entity::note_like::Column::IsPrivate.eq(is_private))
+                .add(entity::note_like::Column::Reaction.is_null())
+                .add_option(before_id.map(|id| entity::note_like::Column::Id.lt(id))),
+        )
+        .order_by_desc(entity::note_like::Column::Id)
+        .limit(limit)
+        .all(conn)
+        .await
+        .map_err_unknown()?;
+
+    let mut result = Vec::with_capacity(likes.len());
+    for like in likes {
+        let note_id = NoteID::from_db_trusted(like.note_id);
+        let note =
+            get_note_by_id_visibility_check(conn, rconn, note_id, Some(user_id), false).await?;
+        if let Some(note) = note {
+            result.push(LikedNoteModel {
+                like_id: like.id,
+                note,
+            });
+        }
+    }
+
+    Ok(result)
+}
@@ -21,14 +21,12 @@ use std::borrow::Cow;
 use activitypub_federation::config::Data;
 use activitypub_federation::fetch::object_id::ObjectId;
 use actix_web::http::StatusCode;
-use apub::OutboxActivity;
 use chrono::{DateTime, Utc};
 use create::ExistingNote;
 use create::NoteCreateError;
 use create::upsert_note;
 use entity::sea_orm_active_enums::Visibility;
 use expected_error_derive::ExpectedError;
-use get::get_apubnote_by_id;
 use get::get_url_of_note_model;
 use migration::Alias;
 use migration::Expr;
@@ -49,7 +47,6 @@ use sea_orm::{ActiveModelTrait, EntityTrait};
 use serde::Deserialize;
 use serde::Serialize;
 use thiserror::Error;
-use upload::NoteUploadModel;
 use url::Url;
 
 use crate::MyFederationData;
@@ -60,6 +57,7 @@ use crate::utils::sanitize::CleanString;
 use super::apub::AnnounceActivity;
 use super::create_error_simple;
 use super::db::MaybeTxConn;
+use super::emoji::EmojiModel;
 use super::fulltext::FTClient;
 use super::kv::KVObject;
 use super::notification::NotificationBody;
@@ -71,6 +69,7 @@ use super::timeline::get_timeline_note_ids;
 use super::user::SimpleUserModel;
 use super::user::get_apubuser_by_id;
 use super::user::get_user_by_id;
+use super::user::not_blocking_or_blocked_expr;
 use super::{
     ServiceResult,
     db::Conn,
@@ -87,14 +86,16 @@ mod get;
 pub mod hashtag;
 mod like;
 pub mod mention;
+mod poll;
+mod reaction;
 pub mod renderer;
 mod upload;
 mod visibility;
 
 pub use apub::{
-    ApubNoteAttachment, ApubNoteModel, ApubNoteSourceModel, ApubTagType, CalculateToAndCcResult,
-    NoteApubData, NoteApubHashtagData, NoteApubMentionData, NoteWithApubModel, calculate_to_and_cc,
-    calculate_to_and_cc_of_renote,
+    ApubNoteAttachment, ApubNoteKind, ApubNoteModel, ApubNoteSourceModel, ApubTagType,
+    CalculateToAndCcResult, NoteApubData, NoteApubHashtagData, NoteApubMentionData,
+    NoteWithApubModel, OutboxActivity, calculate_to_and_cc, calculate_to_and_cc_of_renote,
 };
 pub use cache::invalidate_note_basic_cache;
 pub use count::count_local_notes;
@@ -104,11 +105,20 @@ pub use create::{
 };
 pub use delete::{delete_note_by_id, delete_note_by_id_, delete_renote_by_id};
 pub use get::{
-    get_apubnote_by_id_visibility_check, get_note_by_id, get_note_by_id_visibility_check,
-    get_note_by_spec,
+    get_apubnote_by_id, get_apubnote_by_id_visibility_check, get_note_by_id,
+    get_note_by_id_visibility_check, get_note_by_spec,
+};
+pub use like::{LikedNoteModel, get_liked_notes, note_like_add, note_like_remove};
+pub use poll::{
+    NotePollCreate, NotePollError, NotePollModel, NotePollOptionModel, close_expired_polls,
+    get_note_poll, receive_poll_vote, vote_note_poll,
+};
+pub use reaction::{
+    NoteReactionModel, get_reacted_users, normalize_remote_reaction, note_reaction_add,
+};
+pub use upload::{
+    NoteUploadError, NoteUploadModel, NoteUploadModelData, update_note_upload_metadata,
 };
-pub use like::{note_like_add, note_like_remove};
-pub use upload::NoteUploadModelData;
 pub use visibility::{VisibilityModel, note_visibility_check};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -427,8 +437,17 @@ nest! {
         pub deleted_at: Option<DateTime<Utc>>,
 
         pub sensitive: bool,
+        /// コンテンツ警告 (CW) の文言
+        pub summary: Option<String>,
         pub uploads: Vec<NoteUploadModel>,
 
+        /// リモートのノートの URL。ローカルのノートの場合は None。
+        #[serde(default)]
+        pub url: Option<Url>,
+        /// リモートのノートの閲覧用 URL
+        #[serde(default)]
+        pub view_url: Option<Url>,
+
     }
 }
 
@@ -436,6 +455,12 @@ impl BasicNoteModel {
     pub fn is_renotable(&self) -> bool {
         self.visibility.is_renotable()
     }
+
+    /// 引用ノートの場合、引用先ノートの ID を返す。
+    /// 本文を持たないリノートの場合は None を返す。
+    pub fn quote_of_id(&self) -> Option<NoteID> {
+        self.content.as_ref().and(self.renote_of_id)
+    }
 }
 
 nest! {
@@ -459,6 +484,10 @@ nest! {
                 pub nickname: String,
                 pub domain: Option<String>,
             }>,
+            pub poll: Option<NotePollModel>,
+            pub emojis: Vec<EmojiModel>,
+            pub reactions: Vec<NoteReactionModel>,
+            pub my_reaction: Option<String>,
         }
     }
 }
@@ -501,6 +530,17 @@ impl NoteContentModel {
         }
     }
 
+    /// HTML に変換した上で、`:shortcode:` を絵文字画像に置き換える。
+    pub async fn render_to_html_with_emojis(
+        &self,
+        qconn: &QConn,
+        emojis: &[EmojiModel],
+        base_url: &Url,
+    ) -> ServiceResult<CleanString> {
+        let html = self.render_to_html(qconn).await?;
+        Ok(renderer::replace_emojis(html, emojis, base_url))
+    }
+
     pub fn as_raw_text(&self) -> &str {
         match self {
             NoteContentModel::Plain(c) => c,
@@ -556,7 +596,30 @@ pub async fn get_timeline_notes(
     limit: u64,
     before_date: Option<DateTime<Utc>>,
 ) -> ServiceResult<Vec<DetailedNoteModel>> {
-    let ids = get_timeline_note_ids(conn, viewer_id, include_public, limit, before_date).await?;
+    let ids =
+        get_timeline_note_ids(conn, viewer_id, include_public, limit, before_date, None).await?;
+    get_notes_for_viewer(conn, rconn, viewer_id, &ids).await
+}
+
+/// `after_date` より新しいタイムラインのノートを、`after_date` に近い (古い) 順に取得する。
+pub async fn get_timeline_notes_after(
+    conn: &MaybeTxConn,
+    rconn: &KVObject,
+    viewer_id: Option<UserID>,
+    include_public: bool,
+    limit: u64,
+    before_date: Option<DateTime<Utc>>,
+    after_date: DateTime<Utc>,
+) -> ServiceResult<Vec<DetailedNoteModel>> {
+    let ids = get_timeline_note_ids(
+        conn,
+        viewer_id,
+        include_public,
+        limit,
+        before_date,
+        Some(after_date),
+    )
+    .await?;
     get_notes_for_viewer(conn, rconn, viewer_id, &ids).await
 }
 
@@ -580,7 +643,7 @@ pub async fn get_user_notes(
     limit: u64,
     before_date: Option<DateTime<Utc>>,
 ) -> ServiceResult<Vec<DetailedNoteModel>> {
-    let ids = get_user_note_ids(conn, viewer_id, user_id, limit, before_date, true).await?;
+    let ids = get_user_note_ids(conn, viewer_id, user_id, limit, before_date, None, true).await?;
 
     let mut result = Vec::with_capacity(ids.len());
     for id in ids {
@@ -594,6 +657,29 @@ pub async fn get_user_notes(
     Ok(result)
 }
 
+/// `after_date` より新しいユーザーのノートを、`after_date` に近い (古い) 順に取得する。
+pub async fn get_user_notes_after(
+    conn: &MaybeTxConn,
+    rconn: &KVObject,
+    viewer_id: Option<UserID>,
+    user_id: UserID,
+    limit: u64,
+    before_date: Option<DateTime<Utc>>,
+    after_date: DateTime<Utc>,
+) -> ServiceResult<Vec<DetailedNoteModel>> {
+    let ids = get_user_note_ids(
+        conn,
+        viewer_id,
+        user_id,
+        limit,
+        before_date,
+        Some(after_date),
+        true,
+    )
+    .await?;
+    get_notes_for_viewer(conn, rconn, viewer_id, &ids).await
+}
+
 pub async fn get_user_apub_outbox(
     conn: &MaybeTxConn,
     viewer_id: Option<UserID>,
@@ -603,7 +689,7 @@ pub async fn get_user_apub_outbox(
     base_url: &Url,
     data: &Data<MyFederationData>,
 ) -> ServiceResult<Vec<OutboxActivity>> {
-    let ids = get_user_note_ids(conn, viewer_id, user_id, limit, before_date, false).await?;
+    let ids = get_user_note_ids(conn, viewer_id, user_id, limit, before_date, None, false).await?;
 
     let mut notes = Vec::with_capacity(ids.len());
     for id in ids {
@@ -624,14 +710,24 @@ pub async fn get_user_apub_outbox(
     Ok(result)
 }
 
+/// リノート先ノートの作成者を表す SQL 式。リノートでない場合は NULL になる。
+const RENOTE_TARGET_AUTHOR_EXPR: &str =
+    "(SELECT r.author_id FROM note r WHERE r.id = n.renote_of_id)";
+
 async fn get_user_note_ids(
     conn: &MaybeTxConn,
     viewer_id: Option<UserID>,
     user_id: UserID,
     limit: u64,
     before_date: Option<DateTime<Utc>>,
+    after_date: Option<DateTime<Utc>>,
     include_renotes: bool,
 ) -> ServiceResult<Vec<NoteID>> {
+    // after_date が指定された場合は after_date に近い (古い) 順に返す
+    let order = match after_date {
+        Some(_) => Order::Asc,
+        None => Order::Desc,
+    };
     let query = Query::select()
         .expr_as(Expr::cust("n.id"), Alias::new("note_id"))
         .from_as(entity::note::Entity, Alias::new("n"))
@@ -704,10 +800,15 @@ async fn get_user_note_ids(
                                 })), // mentioned notes,
                         ),
                 )
+                .add_option(viewer_id.map(|v| not_blocking_or_blocked_expr(v, "n.author_id")))
+                .add_option(
+                    viewer_id.map(|v| not_blocking_or_blocked_expr(v, RENOTE_TARGET_AUTHOR_EXPR)),
+                )
                 .add_option(before_date.map(|d| Expr::cust("n.created_at").lte(d)))
+                .add_option(after_date.map(|d| Expr::cust("n.created_at").gt(d)))
                 .add(Expr::cust("n.deleted_at").is_null()),
         )
-        .order_by_expr(Expr::cust("n.created_at"), Order::Desc)
+        .order_by_expr(Expr::cust("n.created_at"), order)
         .limit(limit)
         .to_owned();
 
@@ -806,6 +907,10 @@ pub async fn get_user_note_count(
                                 })), // mentioned notes,
                         ),
                 )
+                .add_option(viewer_id.map(|v| not_blocking_or_blocked_expr(v, "n.author_id")))
+                .add_option(
+                    viewer_id.map(|v| not_blocking_or_blocked_expr(v, RENOTE_TARGET_AUTHOR_EXPR)),
+                )
                 .add(Expr::cust("n.deleted_at").is_null()),
         )
         .to_owned();
@@ -831,6 +936,9 @@ pub enum NoteLikeError {
     #[error("note not found")]
     #[ee(status(StatusCode::NOT_FOUND))]
     NoteNotFound,
+    #[error("invalid reaction")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    InvalidReaction,
 }
 
 async fn create_user_list<T: Clone>(
@@ -0,0 +1,668 @@
+use activitypub_federation::{config::Data, fetch::object_id::ObjectId, traits::Object};
+use actix_web::http::StatusCode;
+use chrono::{DateTime, Duration, Utc};
+use expected_error_derive::ExpectedError;
+use itertools::Itertools;
+use nestify::nest;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder, Set, sea_query::Expr,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{info, warn};
+use url::Url;
+
+use crate::{
+    MyFederationData,
+    services::{
+        MapToUnknown, ServiceError, ServiceResult,
+        apub::{CreateActivity, UpdateActivity},
+        db::{Conn, MaybeTxConn},
+        id::{Identifier, NoteID, UserID},
+        queue::QConn,
+        user::get_apubuser_by_id,
+    },
+};
+
+use super::{
+    ApubNoteKind, ApubNoteModel, NoteSpecifier, get::get_apubnote_by_id_with_inboxes,
+    get_apubnote_by_id, get_note_by_spec, note_visibility_check,
+};
+
+/// 投票の選択肢の最大数
+const MAX_POLL_OPTIONS: usize = 10;
+/// 選択肢の最大文字数
+const MAX_POLL_OPTION_LENGTH: usize = 100;
+/// 投票期限の最大値
+const MAX_POLL_DURATION_DAYS: i64 = 30;
+
+#[derive(Debug, Clone, Error, ExpectedError)]
+pub enum NotePollError {
+    #[error("poll not found")]
+    #[ee(status(StatusCode::NOT_FOUND))]
+    PollNotFound,
+    #[error("a poll must have 2 to 10 options")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    InvalidOptionCount,
+    #[error("poll option is empty or too long")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    InvalidOption,
+    #[error("poll options must be unique")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    DuplicateOption,
+    #[error("poll expiry must be in the future and within 30 days")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    InvalidExpiry,
+    #[error("poll is closed")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    PollClosed,
+    #[error("invalid choice")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    InvalidChoice,
+    #[error("you have already voted")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    AlreadyVoted,
+    #[error("you cannot vote in your own poll")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    OwnPoll,
+}
+
+/// ノート作成時に添付する投票
+#[derive(Debug, Clone)]
+pub struct NotePollCreate {
+    /// 選択肢
+    pub options: Vec<String>,
+    /// 複数選択を許可するか
+    pub multiple: bool,
+    /// 締め切り日時。None の場合は締め切らない。
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl NotePollCreate {
+    pub fn validate(&self) -> ServiceResult<()> {
+        if self.options.len() < 2 || self.options.len() > MAX_POLL_OPTIONS {
+            return Err(ServiceError::known(NotePollError::InvalidOptionCount));
+        }
+        for option in &self.options {
+            let len = option.trim().chars().count();
+            if len == 0 || len > MAX_POLL_OPTION_LENGTH {
+                return Err(ServiceError::known(NotePollError::InvalidOption));
+            }
+        }
+        if !self.options.iter().map(|o| o.trim()).all_unique() {
+            return Err(ServiceError::known(NotePollError::DuplicateOption));
+        }
+        if let Some(expires_at) = self.expires_at {
+            let now = Utc::now();
+            if expires_at <= now || expires_at > now + Duration::days(MAX_POLL_DURATION_DAYS) {
+                return Err(ServiceError::known(NotePollError::InvalidExpiry));
+            }
+        }
+        Ok(())
+    }
+}
+
+nest! {
+    #[derive(Debug, Clone, Serialize, Deserialize)]*
+    #[serde(rename_all = "camelCase")]*
+    pub struct NotePollModel {
+        pub multiple: bool,
+        pub expires_at: Option<DateTime<Utc>>,
+        pub closed: bool,
+        pub voters_count: u64,
+        pub options: Vec<pub struct NotePollOptionModel {
+            pub title: String,
+            pub votes_count: u64,
+        }>,
+        /// 閲覧者が投票した選択肢の番号。閲覧者がいない場合は None。
+        pub voted: Option<Vec<usize>>,
+    }
+}
+
+impl NotePollModel {
+    pub fn total_votes(&self) -> u64 {
+        self.options.iter().map(|o| o.votes_count).sum()
+    }
+}
+
+/// リモートの Question から取り出した投票の状態
+#[derive(Debug, Clone)]
+pub(super) struct RemotePollData {
+    pub(super) multiple: bool,
+    pub(super) options: Vec<(String, u64)>,
+    pub(super) expires_at: Option<DateTime<Utc>>,
+    pub(super) closed_at: Option<DateTime<Utc>>,
+    pub(super) voters_count: Option<u64>,
+}
+
+fn is_poll_closed(poll: &entity::note_poll::Model) -> bool {
+    poll.closed_at.is_some() || poll.expires_at.is_some_and(|e| e.and_utc() <= Utc::now())
+}
+
+/// 新しく作成されたノートに投票を追加する。
+pub(super) async fn insert_note_poll(
+    tx: &MaybeTxConn,
+    note_id: NoteID,
+    poll: &NotePollCreate,
+) -> ServiceResult<()> {
+    let model = entity::note_poll::ActiveModel {
+        note_id: Set(note_id.as_db()),
+        multiple: Set(poll.multiple as i8),
+        expires_at: Set(poll.expires_at.map(|e| e.naive_utc())),
+        closed_at: Set(None),
+        voters_count: Set(0),
+    };
+    model.insert(tx).await.map_err_unknown()?;
+
+    for (i, title) in poll.options.iter().enumerate() {
+        let option = entity::note_poll_option::ActiveModel {
+            note_id: Set(note_id.as_db()),
+            position: Set(i as i32),
+            title: Set(title.trim().to_string()),
+            votes_count: Set(0),
+            ..Default::default()
+        };
+        option.insert(tx).await.map_err_unknown()?;
+    }
+
+    Ok(())
+}
+
+/// リモートのノートの投票を更新する。
+/// 選択肢が変わった場合は、既存の投票を破棄して作り直す。
+pub(super) async fn upsert_remote_note_poll(
+    conn: &Conn,
+    note_id: NoteID,
+    poll: Option<&RemotePollData>,
+) -> ServiceResult<()> {
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+
+    let existing = entity::note_poll::Entity::find_by_id(note_id.as_db())
+        .one(&tx)
+        .await
+        .map_err_unknown()?;
+
+    let poll = match (poll, &existing) {
+        (None, None) => return Ok(()),
+        (None, Some(_)) => {
+            // 投票が取り除かれた
+            entity::note_poll::Entity::delete_by_id(note_id.as_db())
+                .exec(&tx)
+                .await
+                .map_err_unknown()?;
+            tx.commit().await?;
+            return Ok(());
+        }
+        (Some(poll), _) => poll,
+    };
+
+    let options = entity::note_poll_option::Entity::find()
+        .filter(entity::note_poll_option::Column::NoteId.eq(note_id.as_db()))
+        .order_by_asc(entity::note_poll_option::Column::Position)
+        .all(&tx)
+        .await
+        .map_err_unknown()?;
+
+    let voters_count = poll
+        .voters_count
+        .unwrap_or_else(|| poll.options.iter().map(|(_, c)| *c).sum());
+
+    match existing {
+        Some(existing) => {
+            let mut model = existing.into_active_model();
+            model.multiple = Set(poll.multiple as i8);
+            model.expires_at = Set(poll.expires_at.map(|e| e.naive_utc()));
+            model.closed_at = Set(poll.closed_at.map(|e| e.naive_utc()));
+            model.voters_count = Set(voters_count as i32);
+            model.update(&tx).await.map_err_unknown()?;
+        }
+        None => {
+            let model = entity::note_poll::ActiveModel {
+                note_id: Set(note_id.as_db()),
+                multiple: Set(poll.multiple as i8),
+                expires_at: Set(poll.expires_at.map(|e| e.naive_utc())),
+                closed_at: Set(poll.closed_at.map(|e| e.naive_utc())),
+                voters_count: Set(voters_count as i32),
+            };
+            model.insert(&tx).await.map_err_unknown()?;
+        }
+    }
+
+    let same_options = options.len() == poll.options.len()
+        && options
+            .iter()
+            .zip(poll.options.iter())
+            .all(|(o, (title, _))| &o.title == title);
+    if same_options {
+        for (option, (_, count)) in options.into_iter().zip(poll.options.iter()) {
+            let mut model = option.into_active_model();
+            model.votes_count = Set(*count as i32);
+            model.update(&tx).await.map_err_unknown()?;
+        }
+    } else {
+        entity::note_poll_option::Entity::delete_many()
+            .filter(entity::note_poll_option::Column::NoteId.eq(note_id.as_db()))
+            .exec(&tx)
+            .await
+            .map_err_unknown()?;
+        for (i, (title, count)) in poll.options.iter().enumerate() {
+            let option = entity::note_poll_option::ActiveModel {
+                note_id: Set(note_id.as_db()),
+                position: Set(i as i32),
+                title: Set(title.clone()),
+                votes_count: Set(*count as i32),
+                ..Default::default()
+            };
+            option.insert(&tx).await.map_err_unknown()?;
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// ノートの投票を取得する。
+/// `viewer_id` が指定された場合は、閲覧者が投票した選択肢も取得する。
+pub async fn get_note_poll(
+    conn: &MaybeTxConn,
+    note_id: NoteID,
+    viewer_id: Option<UserID>,
+) -> ServiceResult<Option<NotePollModel>> {
+    let poll = match entity::note_poll::Entity::find_by_id(note_id.as_db())
+        .one(conn)
+        .await
+        .map_err_unknown()?
+    {
+        None => return Ok(None),
+        Some(p) => p,
+    };
+
+    let options = entity::note_poll_option::Entity::find()
+        .filter(entity::note_poll_option::Column::NoteId.eq(note_id.as_db()))
+        .order_by_asc(entity::note_poll_option::Column::Position)
+        .all(conn)
+        .await
+        .map_err_unknown()?;
+
+    let voted = match viewer_id {
+        None => None,
+        Some(viewer_id) => {
+            let votes = entity::note_poll_vote::Entity::find()
+                .filter(
+                    Condition::all()
+                        .add(entity::note_poll_vote::Column::UserId.eq(viewer_id.as_db()))
+                        .add(
+                            entity::note_poll_vote::Column::OptionId
+                                .is_in(options.iter().map(|o| o.id)),
+                        ),
+                )
+                .all(conn)
+                .await
+                .map_err_unknown()?;
+            Some(
+                votes
+                    .iter()
+                    .filter_map(|v| options.iter().position(|o| o.id == v.option_id))
+                    .sorted()
+                    .collect(),
+            )
+        }
+    };
+
+    Ok(Some(NotePollModel {
+        multiple: poll.multiple != 0,
+        expires_at: poll.expires_at.map(|e| e.and_utc()),
+        closed: is_poll_closed(&poll),
+        voters_count: poll.voters_count.max(0) as u64,
+        options: options
+            .into_iter()
+            .map(|o| NotePollOptionModel {
+                title: o.title,
+                votes_count: o.votes_count.max(0) as u64,
+            })
+            .collect(),
+        voted,
+    }))
+}
+
+/// 投票を記録し、得票数と投票者数を更新する。
+/// 投票者がすでに投票済みの場合、単一選択の投票では何もしない。
+async fn add_poll_votes(
+    tx: &MaybeTxConn,
+    poll: entity::note_poll::Model,
+    voter_id: UserID,
+    options: &[entity::note_poll_option::Model],
+) -> ServiceResult<Vec<entity::note_poll_vote::Model>> {
+    let all_option_ids: Vec<i32> = entity::note_poll_option::Entity::find()
+        .filter(entity::note_poll_option::Column::NoteId.eq(poll.note_id.clone()))
+        .all(tx)
+        .await
+        .map_err_unknown()?
+        .into_iter()
+        .map(|o| o.id)
+        .collect();
+    let existing_votes = entity::note_poll_vote::Entity::find()
+        .filter(
+            Condition::all()
+                .add(entity::note_poll_vote::Column::UserId.eq(voter_id.as_db()))
+                .add(entity::note_poll_vote::Column::OptionId.is_in(all_option_ids)),
+        )
+        .all(tx)
+        .await
+        .map_err_unknown()?;
+    if poll.multiple == 0 && !existing_votes.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut votes = vec![];
+    for option in options {
+        if existing_votes.iter().any(|v| v.option_id == option.id) {
+            continue;
+        }
+        let vote = entity::note_poll_vote::ActiveModel {
+            option_id: Set(option.id),
+            user_id: Set(voter_id.as_db()),
+            created_at: Set(Utc::now().naive_utc()),
+            ..Default::default()
+        };
+        votes.push(vote.insert(tx).await.map_err_unknown()?);
+
+        // 同時に投票されても数え漏れないように、データベース上で加算する
+        entity::note_poll_option::Entity::update_many()
+            .col_expr(
+                entity::note_poll_option::Column::VotesCount,
+                Expr::col(entity::note_poll_option::Column::VotesCount).add(1),
+            )
+            .filter(entity::note_poll_option::Column::Id.eq(option.id))
+            .exec(tx)
+            .await
+            .map_err_unknown()?;
+    }
+
+    if existing_votes.is_empty() && !votes.is_empty() {
+        entity::note_poll::Entity::update_many()
+            .col_expr(
+                entity::note_poll::Column::VotersCount,
+                Expr::col(entity::note_poll::Column::VotersCount).add(1),
+            )
+            .filter(entity::note_poll::Column::NoteId.eq(poll.note_id))
+            .exec(tx)
+            .await
+            .map_err_unknown()?;
+    }
+
+    Ok(votes)
+}
+
+/// ローカルユーザーが投票する。
+/// リモートの投票の場合は、`name` を持つ Note を投票の作成者に配送する。
+pub async fn vote_note_poll(
+    conn: &Conn,
+    qconn: &QConn,
+    voter_id: UserID,
+    note_id: NoteID,
+    choices: &[usize],
+    base_url: &Url,
+) -> ServiceResult<()> {
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+
+    if !note_visibility_check(&tx, note_id, Some(voter_id), false).await? {
+        return Err(ServiceError::known(NotePollError::PollNotFound));
+    }
+    let note = get_apubnote_by_id(&tx, note_id, base_url, false)
+        .await?
+        .ok_or(ServiceError::known(NotePollError::PollNotFound))?;
+    if note.basic.author.id == voter_id {
+        return Err(ServiceError::known(NotePollError::OwnPoll));
+    }
+
+    let poll = entity::note_poll::Entity::find_by_id(note_id.as_db())
+        .one(&tx)
+        .await
+        .map_err_unknown()?
+        .ok_or(ServiceError::known(NotePollError::PollNotFound))?;
+    if is_poll_closed(&poll) {
+        return Err(ServiceError::known(NotePollError::PollClosed));
+    }
+
+    let options = entity::note_poll_option::Entity::find()
+        .filter(entity::note_poll_option::Column::NoteId.eq(note_id.as_db()))
+        .order_by_asc(entity::note_poll_option::Column::Position)
+        .all(&tx)
+        .await
+        .map_err_unknown()?;
+
+    let choices: Vec<usize> = choices.iter().copied().unique().collect();
+    if choices.is_empty()
+        || (poll.multiple == 0 && choices.len() > 1)
+        || choices.iter().any(|c| *c >= options.len())
+    {
+        return Err(ServiceError::known(NotePollError::InvalidChoice));
+    }
+    let chosen: Vec<_> = choices.iter().map(|c| options[*c].clone()).collect();
+
+    let votes = add_poll_votes(&tx, poll, voter_id, &chosen).await?;
+    if votes.is_empty() {
+        return Err(ServiceError::known(NotePollError::AlreadyVoted));
+    }
+
+    if note.basic.author.is_remote() {
+        let voter = get_apubuser_by_id(&tx, voter_id, base_url)
+            .await?
+            .expect("voter should exist");
+        let poll_author = get_apubuser_by_id(&tx, note.basic.author.id, base_url)
+            .await?
+            .expect("poll author should exist");
+        let inbox = poll_author.shared_inbox_or_inbox().clone();
+        for vote in votes {
+            let option = chosen
+                .iter()
+                .find(|o| o.id == vote.option_id)
+                .expect("voted option should exist");
+            let vote_note = ApubNoteModel::poll_vote(
+                Url::parse(&format!("{}#votes/{}", voter.apub.url, vote.id)).unwrap(),
+                voter.apub.url.clone(),
+                poll_author.apub.url.clone(),
+                note.apub.url.clone(),
+                option.title.clone(),
+                vote.created_at.and_utc(),
+            );
+            let activity = CreateActivity::from_note(vote_note);
+            qconn
+                .queue_activity(activity, voter.clone(), vec![inbox.clone()])
+                .await?;
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// リモートから投票を受け取る。
+/// `poll_url` がローカルの投票を指していない場合は false を返す。
+pub async fn receive_poll_vote(
+    conn: &Conn,
+    voter_id: UserID,
+    poll_url: &Url,
+    name: &str,
+    my_domain: &str,
+) -> ServiceResult<bool> {
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+
+    let note = match get_note_by_spec(&tx, &NoteSpecifier::url(poll_url.clone()), my_domain, false)
+        .await?
+    {
+        Some(n) if n.author.is_local() => n,
+        _ => return Ok(false),
+    };
+    let poll = match entity::note_poll::Entity::find_by_id(note.id.as_db())
+        .one(&tx)
+        .await
+        .map_err_unknown()?
+    {
+        None => return Ok(false),
+        Some(p) => p,
+    };
+
+    if is_poll_closed(&poll) {
+        warn!("vote for closed poll {} ignored", note.id);
+        return Ok(true);
+    }
+    if note.author.id == voter_id {
+        warn!("vote by poll author {} ignored", voter_id);
+        return Ok(true);
+    }
+
+    let option = entity::note_poll_option::Entity::find()
+        .filter(
+            Condition::all()
+                .add(entity::note_poll_option::Column::NoteId.eq(note.id.as_db()))
+                .add(entity::note_poll_option::Column::Title.eq(name)),
+        )
+        .one(&tx)
+        .await
+        .map_err_unknown()?;
+    let option = match option {
+        None => {
+            warn!(
+                "vote for unknown option {name:?} of poll {} ignored",
+                note.id
+            );
+            return Ok(true);
+        }
+        Some(o) => o,
+    };
+
+    add_poll_votes(&tx, poll, voter_id, &[option]).await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// 締め切りを過ぎた投票を閉じる。
+/// ローカルの投票は、最終結果を Update で配送する。
+pub async fn close_expired_polls(
+    conn: &Conn,
+    qconn: &QConn,
+    base_url: &Url,
+    data: &Data<MyFederationData>,
+) -> ServiceResult<()> {
+    let maybe_conn = MaybeTxConn::Conn(conn.clone());
+    let now = Utc::now();
+
+    let polls = entity::note_poll::Entity::find()
+        .filter(
+            Condition::all()
+                .add(entity::note_poll::Column::ClosedAt.is_null())
+                .add(entity::note_poll::Column::ExpiresAt.lte(now.naive_utc())),
+        )
+        .all(&maybe_conn)
+        .await
+        .map_err_unknown()?;
+
+    for poll in polls {
+        let note_id = NoteID::from_db_trusted(poll.note_id.clone());
+
+        let mut model = poll.into_active_model();
+        model.closed_at = Set(Some(now.naive_utc()));
+        model.update(&maybe_conn).await.map_err_unknown()?;
+
+        let (note, inboxes) =
+            match get_apubnote_by_id_with_inboxes(&maybe_conn, note_id, None, base_url, false)
+                .await?
+            {
+                Some(n) if n.0.basic.author.is_local() => n,
+                _ => continue,
+            };
+        let author = get_apubuser_by_id(&maybe_conn, note.basic.author.id, base_url)
+            .await?
+            .expect("author should exist");
+        let note_obj = note.into_json(data).await?;
+        let activity = UpdateActivity::from_note(note_obj);
+        qconn.queue_activity(activity, author, inboxes).await?;
+        info!("poll {note_id} closed");
+    }
+
+    Ok(())
+}
+
+impl ApubNoteModel {
+    /// 投票を表す Note を作成する。
+    pub(crate) fn poll_vote(
+        id: Url,
+        voter: Url,
+        poll_author: Url,
+        poll: Url,
+        name: String,
+        published: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            id: ObjectId::from(id),
+            kind: ApubNoteKind::Note,
+            attributed_to: ObjectId::from(voter),
+            content: String::new(),
+            published,
+            updated: None,
+            to: vec![poll_author],
+            cc: vec![],
+            url: None,
+            source: None,
+            in_reply_to: Some(ObjectId::from(poll)),
+            sensitive: None,
+            summary: None,
+            tags: None,
+            attachment: None,
+            name: Some(name),
+            one_of: None,
+            any_of: None,
+            end_time: None,
+            closed: None,
+            voters_count: None,
+            quote_url: None,
+            misskey_quote: None,
+        }
+    }
+
+    /// 投票を表す Note であれば、投票先の URL と選択肢を返す。
+    pub(crate) fn as_poll_vote(&self) -> Option<(&Url, &str)> {
+        if self.kind != ApubNoteKind::Note || !self.content.trim().is_empty() {
+            return None;
+        }
+        match (&self.in_reply_to, &self.name) {
+            (Some(poll), Some(name)) => Some((poll.inner(), name.as_str())),
+            _ => None,
+        }
+    }
+
+    pub(super) fn remote_poll_data(&self) -> Option<RemotePollData> {
+        if self.kind != ApubNoteKind::Question {
+            return None;
+        }
+        let (multiple, options) = match (&self.one_of, &self.any_of) {
+            (Some(o), _) => (false, o),
+            (None, Some(o)) => (true, o),
+            (None, None) => return None,
+        };
+        Some(RemotePollData {
+            multiple,
+            options: options
+                .iter()
+                .take(MAX_POLL_OPTIONS)
+                .map(|o| {
+                    let title: String = o.name.chars().take(MAX_POLL_OPTION_LENGTH).collect();
+                    (
+                        title,
+                        o.replies.as_ref().map(|r| r.total_items).unwrap_or(0),
+                    )
+                })
+                .collect(),
+            expires_at: self.end_time,
+            closed_at: self.closed,
+            voters_count: self.voters_count,
+        })
+    }
+}
@@ -0,0 +1,266 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use actix_web::http::StatusCode;
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    ColumnTrait, Condition, EntityTrait, QueryFilter, QueryOrder, QuerySelect, sea_query::Expr,
+};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::services::{
+    MapToUnknown, ServiceError, ServiceResult, create_error_simple,
+    db::{Conn, MaybeTxConn},
+    emoji::{
+        ApubEmojiTag, EmojiModel, get_emojis_by_shortcodes, register_remote_emojis,
+        validate_shortcode,
+    },
+    id::{Identifier, NoteID, UserID},
+    kv::KVObject,
+    queue::QConn,
+    user::SimpleUserModel,
+};
+
+use super::{NoteLikeError, create_user_list, note_like_add, note_visibility_check};
+
+/// Unicode 絵文字として許容する最大の文字数 (ZWJ シーケンスを考慮)
+const MAX_UNICODE_REACTION_CHARS: usize = 16;
+
+/// ノートに付けられた絵文字リアクションの集計
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteReactionModel {
+    /// Unicode 絵文字、`:shortcode:` (ローカル) または `:shortcode@domain:` (リモート)
+    pub reaction: String,
+    pub count: u64,
+    /// カスタム絵文字の場合の画像
+    pub emoji: Option<EmojiModel>,
+}
+
+fn is_unicode_reaction(reaction: &str) -> bool {
+    let count = reaction.chars().count();
+    count > 0
+        && count <= MAX_UNICODE_REACTION_CHARS
+        && !reaction.is_ascii()
+        && !reaction
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, ':' | '<' | '>' | '&' | '"' | '\''))
+}
+
+/// `:shortcode:` または `:shortcode@domain:` を分解する。
+fn parse_custom_reaction(reaction: &str) -> Option<(&str, Option<&str>)> {
+    let inner = reaction.strip_prefix(':')?.strip_suffix(':')?;
+    let (shortcode, domain) = match inner.split_once('@') {
+        None => (inner, None),
+        Some((shortcode, domain)) => (shortcode, Some(domain)),
+    };
+    if !validate_shortcode(shortcode) || domain.is_some_and(|d| d.is_empty()) {
+        return None;
+    }
+    Some((shortcode, domain))
+}
+
+/// カスタム絵文字のリアクションに対応する絵文字を取得する。
+pub(super) async fn get_reaction_emoji(
+    conn: &MaybeTxConn,
+    reaction: &str,
+) -> ServiceResult<Option<EmojiModel>> {
+    match parse_custom_reaction(reaction) {
+        None => Ok(None),
+        Some((shortcode, domain)) => {
+            get_emojis_by_shortcodes(conn, domain, &[shortcode.to_string()])
+                .await
+                .map(|mut e| e.pop())
+        }
+    }
+}
+
+/// ローカルユーザーのリアクションを検証する。
+/// カスタム絵文字はローカルに登録されているもののみ使える。
+async fn validate_local_reaction(conn: &MaybeTxConn, reaction: &str) -> ServiceResult<String> {
+    let reaction = reaction.trim();
+    if is_unicode_reaction(reaction) {
+        return Ok(reaction.to_string());
+    }
+
+    match parse_custom_reaction(reaction) {
+        Some((shortcode, None)) => {
+            let emojis = get_emojis_by_shortcodes(conn, None, &[shortcode.to_string()]).await?;
+            if emojis.is_empty() {
+                return Err(ServiceError::known(NoteLikeError::InvalidReaction));
+            }
+            Ok(format!(":{shortcode}:"))
+        }
+        _ => Err(ServiceError::known(NoteLikeError::InvalidReaction)),
+    }
+}
+
+/// 受信したリアクションを保存用の形式に変換する。
+/// カスタム絵文字は `tag` からキャッシュし、`:shortcode@domain:` として保存する。
+/// 解釈できないリアクションの場合は None (通常のお気に入り) を返す。
+pub async fn normalize_remote_reaction(
+    conn: &MaybeTxConn,
+    client: &reqwest_middleware::ClientWithMiddleware,
+    domain: &str,
+    reaction: &str,
+    tags: &[&ApubEmojiTag],
+) -> ServiceResult<Option<String>> {
+    if is_unicode_reaction(reaction) {
+        return Ok(Some(reaction.to_string()));
+    }
+
+    let shortcode = match parse_custom_reaction(reaction) {
+        // Misskey はローカルの絵文字を `:shortcode@.:` と表すことがある
+        Some((shortcode, None | Some("."))) => shortcode,
+        _ => return Ok(None),
+    };
+    let tag = match tags.iter().find(|t| t.shortcode() == shortcode) {
+        Some(tag) => *tag,
+        None => return Ok(None),
+    };
+
+    register_remote_emojis(conn, client, domain, &[tag]).await;
+    let emojis = get_emojis_by_shortcodes(conn, Some(domain), &[shortcode.to_string()]).await?;
+    if emojis.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(format!(":{shortcode}@{domain}:")))
+}
+
+/// ノートに絵文字リアクションを付ける。
+pub async fn note_reaction_add(
+    conn: &Conn,
+    qconn: &QConn,
+    user_id: UserID,
+    note_id: NoteID,
+    reaction: &str,
+    my_domain: &str,
+    base_url: &Url,
+) -> ServiceResult<()> {
+    let reaction = validate_local_reaction(&MaybeTxConn::Conn(conn.clone()), reaction).await?;
+    note_like_add(
+        conn,
+        qconn,
+        user_id,
+        note_id,
+        false,
+        Some(reaction),
+        my_domain,
+        base_url,
+    )
+    .await
+}
+
+/// ノートの絵文字リアクションを集計する。
+/// `viewer_id` が指定された場合は、閲覧者のリアクションも返す。
+pub(super) async fn get_note_reactions(
+    conn: &MaybeTxConn,
+    note_id: NoteID,
+    viewer_id: Option<UserID>,
+) -> ServiceResult<(Vec<NoteReactionModel>, Option<String>)> {
+    let counts: Vec<(String, i64)> = entity::note_like::Entity::find()
+        .select_only()
+        .column(entity::note_like::Column::Reaction)
+        .column_as(Expr::col(entity::note_like::Column::Id).count(), "count")
+        .filter(
+            Condition::all()
+                .add(entity::note_like::Column::NoteId.eq(note_id.as_db()))
+                .add(entity::note_like::Column::IsPrivate.eq(false))
+                .add(entity::note_like::Column::Reaction.is_not_null()),
+        )
+        .group_by(entity::note_like::Column::Reaction)
+        .order_by_desc(Expr::col(entity::note_like::Column::Id).count())
+        .into_tuple()
+        .all(conn)
+        .await
+        .map_err_unknown()?;
+
+    let mut reactions = Vec::with_capacity(counts.len());
+    for (reaction, count) in counts {
+        let emoji = get_reaction_emoji(conn, &reaction).await?;
+        reactions.push(NoteReactionModel {
+            reaction,
+            count: count.max(0) as u64,
+            emoji,
+        });
+    }
+
+    let my_reaction = match viewer_id {
+        None => None,
+        Some(viewer_id) => entity::note_like::Entity::find()
+            .filter(
+                Condition::all()
+                    .add(entity::note_like::Column::NoteId.eq(note_id.as_db()))
+                    .add(entity::note_like::Column::UserId.eq(viewer_id.as_db()))
+                    .add(entity::note_like::Column::IsPrivate.eq(false)),
+            )
+            .one(conn)
+            .await
+            .map_err_unknown()?
+            .and_then(|l| l.reaction),
+    };
+
+    Ok((reactions, my_reaction))
+}
+
+/// ノートにリアクションしたユーザーを取得する。
+/// `reaction` が指定された場合は、そのリアクションをしたユーザーのみを返す。
+pub async fn get_reacted_users(
+    conn: &MaybeTxConn,
+    rconn: &KVObject,
+    viewer_id: Option<UserID>,
+    target_note_id: NoteID,
+    reaction: Option<&str>,
+    limit: u64,
+    before_date: Option<DateTime<Utc>>,
+) -> ServiceResult<Vec<(SimpleUserModel, DateTime<Utc>)>> {
+    // visibility check
+    let viz = note_visibility_check(conn, target_note_id, viewer_id, false).await?;
+    if !viz {
+        return create_error_simple(StatusCode::NOT_FOUND, "note not found");
+    }
+
+    let likes = entity::note_like::Entity::find()
+        .filter(
+            Condition::all()
+                .add(entity::note_like::Column::NoteId.eq(target_note_id.as_db()))
+                .add(entity::note_like::Column::IsPrivate.eq(false))
+                .add(entity::note_like::Column::Reaction.is_not_null())
+                .add_option(reaction.map(|r| entity::note_like::Column::Reaction.eq(r)))
+                .add_option(before_date.map(|d| entity::note_like::Column::CreatedAt.lte(d))),
+        )
+        .order_by_desc(entity::note_like::Column::CreatedAt)
+        .limit(limit)
+        .all(conn)
+        .await
+        .map_err_unknown()?;
+
+    let user_ids: Vec<_> = likes
+        .into_iter()
+        .map(|like| {
+            (
+                UserID::from_db_trusted(like.user_id),
+                like.created_at.and_utc(),
+            )
+        })
+        .collect();
+
+    create_user_list(conn, rconn, &user_ids).await
+}
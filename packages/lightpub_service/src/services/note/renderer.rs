@@ -22,7 +22,16 @@ use pulldown_cmark::{Parser, html};
 use serde::Deserialize;
 use serde_json::json;
 
-use crate::{ServiceResult, services::queue::QConn, utils::sanitize::CleanString};
+use url::Url;
+
+use crate::{
+    ServiceResult,
+    services::{
+        emoji::{EmojiModel, replace_emoji_shortcodes},
+        queue::QConn,
+    },
+    utils::sanitize::CleanString,
+};
 
 const MATHJAX_RENDERER_SUBJECT: &str = "lightpub.mathjax.render";
 
@@ -84,6 +93,18 @@ impl LatexNoteRenderer {
     }
 }
 
+/// サニタイズ済みの HTML に含まれる `:shortcode:` を絵文字画像に置き換える。
+/// 置き換えによって属性値の中の文字列がタグとして解釈されることがあるので、
+/// ローカルの `/upload` を指す画像だけを許可してもう一度サニタイズする。
+pub fn replace_emojis(html: CleanString, emojis: &[EmojiModel], base_url: &Url) -> CleanString {
+    if emojis.is_empty() {
+        return html;
+    }
+    let replaced = replace_emoji_shortcodes(html.inner(), emojis, base_url);
+    let upload_prefix = base_url.join("/upload/").expect("failed to join URL");
+    CleanString::clean_with_local_images(&replaced, upload_prefix.as_str())
+}
+
 #[derive(Debug, Clone, Deserialize)]
 struct MathJaxResponse {
     result: String,
@@ -1,17 +1,36 @@
 use std::str::FromStr;
 
-use crate::services::{
-    MapToUnknown, ServiceResult,
-    db::MaybeTxConn,
-    id::{Identifier, NoteID, UploadID},
+use crate::{
+    MyFederationData,
+    services::{
+        MapToUnknown, ServiceError, ServiceResult,
+        apub::UpdateActivity,
+        db::{Conn, MaybeTxConn},
+        id::{Identifier, NoteID, UploadID, UserID},
+        kv::KVObject,
+        queue::QConn,
+        upload::{UploadKind, UploadMetadata, validate_upload_description},
+        user::get_apubuser_by_id,
+    },
 };
+use activitypub_federation::{config::Data, traits::Object};
+use actix_web::http::StatusCode;
 use derive_getters::Getters;
-use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use expected_error_derive::ExpectedError;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, EntityTrait, IntoActiveModel, QueryFilter,
+};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use url::Url;
 
+use super::{get::get_apubnote_by_id_with_inboxes, invalidate_note_basic_cache};
+
 fn convert_to_apub(model: NoteUploadModel, base_url: &Url) -> NoteUploadApubModel {
-    let kind = "Image".to_string(); // TODO: infer from mime type
+    let kind = UploadKind::from_mime_type(&model.mime_type)
+        .map(|k| k.apub_type())
+        .unwrap_or("Document")
+        .to_string();
     let mime_type = model.mime_type;
     let url = match model.data {
         NoteUploadModelData::File(upload_id) => {
@@ -24,6 +43,7 @@ fn convert_to_apub(model: NoteUploadModel, base_url: &Url) -> NoteUploadApubMode
         kind,
         mime_type,
         url,
+        metadata: model.metadata,
     }
 }
 
@@ -52,9 +72,11 @@ pub async fn get_note_uploads(
 
     let mut result = vec![];
     for (upload, _) in uploads {
+        let metadata = UploadMetadata::from_model(&upload);
         let upload_id = UploadID::from_db_trusted(upload.id);
         result.push(NoteUploadModel {
             mime_type: upload.mime_type,
+            metadata,
             data: match (upload.filename, upload.url) {
                 (Some(_), None) => NoteUploadModelData::File(upload_id),
                 (None, Some(url)) => {
@@ -73,6 +95,7 @@ pub struct NoteUploadApubModel {
     pub(crate) kind: String,
     pub(crate) mime_type: String,
     pub(crate) url: Url,
+    pub(crate) metadata: UploadMetadata,
 }
 
 #[derive(Debug, Clone, Getters, Serialize, Deserialize)]
@@ -80,6 +103,8 @@ pub struct NoteUploadApubModel {
 pub struct NoteUploadModel {
     mime_type: String,
     data: NoteUploadModelData,
+    #[serde(default)]
+    metadata: UploadMetadata,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -97,3 +122,83 @@ impl NoteUploadModelData {
         }
     }
 }
+
+#[derive(Debug, Clone, Error, ExpectedError)]
+pub enum NoteUploadError {
+    #[error("note upload not found")]
+    #[ee(status(StatusCode::NOT_FOUND))]
+    NotFound,
+    #[error("Not allowed to edit note")]
+    #[ee(status(StatusCode::FORBIDDEN))]
+    NotAuthor,
+}
+
+/// ノートに添付されたファイルの説明文とフォーカルポイントを更新する。
+pub async fn update_note_upload_metadata(
+    conn: &Conn,
+    rconn: &KVObject,
+    qconn: &QConn,
+    editor_id: UserID,
+    note_id: NoteID,
+    upload_id: UploadID,
+    description: Option<String>,
+    focal_point: Option<(f32, f32)>,
+    base_url: &Url,
+    fed_data: &Data<MyFederationData>,
+) -> ServiceResult<()> {
+    let description = description.filter(|d| !d.trim().is_empty());
+    validate_upload_description(description.as_deref(), focal_point)?;
+
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+
+    let note = entity::note::Entity::find()
+        .filter(
+            Condition::all()
+                .add(entity::note::Column::Id.eq(note_id.as_db()))
+                .add(entity::note::Column::DeletedAt.is_null()),
+        )
+        .one(&tx)
+        .await
+        .map_err_unknown()?
+        .ok_or(ServiceError::known(NoteUploadError::NotFound))?;
+    if UserID::from_db_trusted(note.author_id) != editor_id {
+        return Err(ServiceError::known(NoteUploadError::NotAuthor));
+    }
+
+    let (upload, _) = entity::upload::Entity::find()
+        .find_also_related(entity::note_upload::Entity)
+        .filter(
+            Condition::all()
+                .add(entity::note_upload::Column::NoteId.eq(note_id.as_db()))
+                .add(entity::upload::Column::Id.eq(upload_id.as_db())),
+        )
+        .one(&tx)
+        .await
+        .map_err_unknown()?
+        .ok_or(ServiceError::known(NoteUploadError::NotFound))?;
+
+    let mut metadata = UploadMetadata::from_model(&upload);
+    metadata.description = description;
+    metadata.focal_point = focal_point;
+    let mut model = upload.into_active_model();
+    metadata.set_to_model(&mut model);
+    model.update(&tx).await.map_err_unknown()?;
+
+    invalidate_note_basic_cache(rconn, note_id).await?;
+
+    // ノートの更新を配送する
+    let author = get_apubuser_by_id(&tx, editor_id, base_url)
+        .await?
+        .expect("author should exist");
+    if author.is_local() {
+        let (note, inboxes) = get_apubnote_by_id_with_inboxes(&tx, note_id, None, base_url, false)
+            .await?
+            .expect("note should exist");
+        let activity = UpdateActivity::from_note(note.into_json(fed_data).await?);
+        qconn.queue_activity(activity, author, inboxes).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
@@ -48,6 +48,8 @@ use super::id::NotificationID;
 use super::kv::KVObject;
 use super::user::UserDetailedProfile;
 use super::user::get_user_profile_by_id;
+use super::user::is_muting_user;
+use super::user::not_muted_expr;
 use super::{
     MapToUnknown, ServiceResult,
     db::Conn,
@@ -64,6 +66,22 @@ pub enum NotificationBody {
     Replied(UserID, NoteID, NoteID), // author_id, reply_note_id, replied_note_id
     Mentioned(UserID, NoteID),
     Renoted(UserID, NoteID),
+    Quoted(UserID, NoteID, NoteID), // author_id, quote_note_id, quoted_note_id
+}
+
+impl NotificationBody {
+    /// 通知のきっかけとなったユーザーの ID を返す。
+    pub fn actor_id(&self) -> UserID {
+        match self {
+            NotificationBody::Followed(u) => *u,
+            NotificationBody::FollowRequested(u) => *u,
+            NotificationBody::FollowAccepted(u) => *u,
+            NotificationBody::Replied(u, _, _) => *u,
+            NotificationBody::Mentioned(u, _) => *u,
+            NotificationBody::Renoted(u, _) => *u,
+            NotificationBody::Quoted(u, _, _) => *u,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -90,6 +108,11 @@ pub enum NotificationBodyData {
         user: UserDetailedProfile,
         renoted_note: NotificationNoteData,
     },
+    Quoted {
+        user: UserDetailedProfile,
+        quote_note: NotificationNoteData,
+        quoted_note: NotificationNoteData,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -110,7 +133,18 @@ pub enum NotificationError {
 
 pub async fn get_notifications(conn: &Conn, user_id: UserID) -> ServiceResult<Vec<Notification>> {
     let notifications = entity::notification::Entity::find()
-        .filter(entity::notification::Column::UserId.eq(user_id.as_db()))
+        .filter(
+            Condition::all()
+                .add(entity::notification::Column::UserId.eq(user_id.as_db()))
+                // ミュート中のユーザーによる通知は表示しない
+                .add(not_muted_expr(
+                    user_id,
+                    (
+                        entity::notification::Entity,
+                        entity::notification::Column::ActorId,
+                    ),
+                )),
+        )
         .order_by_desc(entity::notification::Column::CreatedAt)
         .all(conn.db())
         .await
@@ -137,11 +171,18 @@ pub async fn add_notification(
     body: &NotificationBody,
     base_url: &Url,
 ) -> ServiceResult<()> {
+    // ミュート中のユーザーからの通知は作成しない
+    if is_muting_user(tx, user_id, body.actor_id()).await? {
+        debug!("User has muted the actor, skipping notification");
+        return Ok(());
+    }
+
     let body_json = serde_json::to_string(body).map_err_unknown()?;
 
     let model = entity::notification::ActiveModel {
         user_id: Set(user_id.as_db()),
         body: Set(body_json),
+        actor_id: Set(Some(body.actor_id().as_db())),
         ..Default::default()
     };
     model.insert(tx).await.map_err_unknown()?;
@@ -227,7 +268,15 @@ pub async fn count_unread_notifications(conn: &Conn, user_id: UserID) -> Service
         .filter(
             Condition::all()
                 .add(entity::notification::Column::UserId.eq(user_id.as_db()))
-                .add(entity::notification::Column::ReadAt.is_null()),
+                .add(entity::notification::Column::ReadAt.is_null())
+                // ミュート中のユーザーによる通知は数えない
+                .add(not_muted_expr(
+                    user_id,
+                    (
+                        entity::notification::Entity,
+                        entity::notification::Column::ActorId,
+                    ),
+                )),
         )
         .count(conn.db())
         .await
@@ -300,5 +349,24 @@ pub async fn get_related_notification_data(
                 },
             }))
         }
+        NotificationBody::Quoted(author_id, quote_note_id, quoted_note_id) => {
+            let author_model =
+                try_opt_res!(get_user_profile_by_id(conn, rconn, None, *author_id).await?);
+            Ok(Some(NotificationBodyData::Quoted {
+                user: author_model,
+                quote_note: NotificationNoteData {
+                    id: *quote_note_id,
+                    view_url: base_url
+                        .join(&format!("/client/note/{quote_note_id}"))
+                        .unwrap(),
+                },
+                quoted_note: NotificationNoteData {
+                    id: *quoted_note_id,
+                    view_url: base_url
+                        .join(&format!("/client/note/{quoted_note_id}"))
+                        .unwrap(),
+                },
+            }))
+        }
     }
 }
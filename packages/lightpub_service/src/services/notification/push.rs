@@ -192,6 +192,14 @@ impl PushNotificationBody {
                 let url = renoted_note.view_url.clone();
                 (title, body, url)
             }
+            NotificationBodyData::Quoted {
+                user, quote_note, ..
+            } => {
+                let title = "引用されました".to_string();
+                let body = format!("{} があなたのノートを引用しました", user.basic.nickname);
+                let url = quote_note.view_url.clone();
+                (title, body, url)
+            }
             NotificationBodyData::Replied {
                 author, reply_note, ..
             } => {
@@ -0,0 +1,613 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! OAuth 2.0 認可サーバー
+//!
+//! サードパーティのクライアントアプリの登録と、PKCE に対応した認可コードフローを提供する。
+//! 認可コードは短時間しか使わないので KV に保存し、
+//! アクセストークンとリフレッシュトークンは SHA-256 ハッシュをデータベースに保存する。
+
+use std::{collections::BTreeSet, fmt::Display, time::Duration};
+
+use actix_web::http::StatusCode;
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use chrono::{DateTime, Utc};
+use expected_error_derive::ExpectedError;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, EntityTrait, IntoActiveModel,
+    QueryFilter, Set,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use url::Url;
+
+use super::{
+    MapToUnknown, ServiceError, ServiceResult,
+    db::Conn,
+    id::{Identifier, UserID},
+    kv::KVObject,
+    user::{is_user_deleted, is_user_suspended},
+};
+
+/// リダイレクトせずに認可コードを画面に表示するための redirect_uri
+pub const OOB_REDIRECT_URI: &str = "urn:ietf:wg:oauth:2.0:oob";
+
+/// 利用できるスコープ。
+/// read と write には "write:notes" のような細かいスコープも指定できる。
+pub const OAUTH_SCOPES: &[&str] = &["read", "write", "follow", "push", "notifications"];
+
+/// スコープを指定しなかった場合のスコープ
+const DEFAULT_SCOPE: &str = "read";
+
+/// 認可コードの有効期間
+const AUTHORIZATION_CODE_TTL: Duration = Duration::from_secs(60 * 10);
+
+/// アクセストークンの有効期間
+const ACCESS_TOKEN_LIFETIME: chrono::Duration = chrono::Duration::days(1);
+
+const APP_NAME_MAX_LENGTH: usize = 255;
+const SCOPES_MAX_LENGTH: usize = 255;
+const WEBSITE_MAX_LENGTH: usize = 512;
+
+#[derive(Debug, Clone, Error, ExpectedError)]
+pub enum OAuthError {
+    #[error("invalid_client")]
+    #[ee(status(StatusCode::UNAUTHORIZED))]
+    InvalidClient,
+    #[error("invalid_grant")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    InvalidGrant,
+    #[error("invalid_scope")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    InvalidScope,
+    #[error("invalid redirect_uri")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    InvalidRedirectUri,
+    #[error("invalid code_challenge")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    InvalidCodeChallenge,
+    #[error("invalid application name")]
+    #[ee(status(StatusCode::UNPROCESSABLE_ENTITY))]
+    InvalidAppName,
+    #[error("invalid website")]
+    #[ee(status(StatusCode::UNPROCESSABLE_ENTITY))]
+    InvalidWebsite,
+}
+
+/// スペース区切りのスコープの集合
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OAuthScopes(BTreeSet<String>);
+
+impl OAuthScopes {
+    /// スペース区切りのスコープを解析する。
+    /// 空の場合は read のみを許可する。
+    pub fn parse(s: &str) -> ServiceResult<Self> {
+        let mut scopes = BTreeSet::new();
+        for scope in s.split_whitespace() {
+            if !Self::is_valid_scope(scope) {
+                return Err(ServiceError::known(OAuthError::InvalidScope));
+            }
+            scopes.insert(scope.to_string());
+        }
+        if scopes.is_empty() {
+            scopes.insert(DEFAULT_SCOPE.to_string());
+        }
+        Ok(Self(scopes))
+    }
+
+    fn is_valid_scope(scope: &str) -> bool {
+        if OAUTH_SCOPES.contains(&scope) {
+            return true;
+        }
+        match scope.split_once(':') {
+            Some(("read" | "write", sub)) => {
+                !sub.is_empty() && sub.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+            }
+            _ => false,
+        }
+    }
+
+    /// scope が許可されているかどうか。
+    /// "write" は "write:notes" のような細かいスコープも許可する。
+    /// また、Mastodon のクライアントが使うスコープも対応するスコープとして扱う。
+    pub fn allows(&self, scope: &str) -> bool {
+        if self.0.contains(scope) {
+            return true;
+        }
+        let Some((parent, sub)) = scope.split_once(':') else {
+            return false;
+        };
+        if self.0.contains(parent) {
+            return true;
+        }
+        match sub {
+            "notes" => self.0.contains(&format!("{parent}:statuses")),
+            "follows" => self.0.contains("follow"),
+            "notifications" => self.0.contains("notifications"),
+            _ => false,
+        }
+    }
+
+    /// other のすべてのスコープが許可されているかどうか
+    pub fn allows_all(&self, other: &OAuthScopes) -> bool {
+        other.0.iter().all(|s| self.allows(s))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|s| s.as_str())
+    }
+}
+
+impl Display for OAuthScopes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut first = true;
+        for scope in &self.0 {
+            if !first {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", scope)?;
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OAuthAppModel {
+    pub id: i32,
+    pub client_id: String,
+    pub client_secret: String,
+    pub name: String,
+    pub website: Option<String>,
+    pub redirect_uris: Vec<String>,
+    pub scopes: OAuthScopes,
+}
+
+impl OAuthAppModel {
+    fn from_db(app: entity::oauth_app::Model) -> ServiceResult<Self> {
+        Ok(Self {
+            id: app.id,
+            client_id: app.client_id,
+            client_secret: app.client_secret,
+            name: app.name,
+            website: app.website,
+            redirect_uris: app.redirect_uris.lines().map(|s| s.to_string()).collect(),
+            scopes: OAuthScopes::parse(&app.scopes)?,
+        })
+    }
+
+    /// 登録された redirect_uri と完全に一致するかどうか
+    pub fn is_valid_redirect_uri(&self, redirect_uri: &str) -> bool {
+        self.redirect_uris.iter().any(|u| u == redirect_uri)
+    }
+}
+
+/// ランダムなトークンを生成する
+pub(crate) fn generate_token() -> String {
+    format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+pub(crate) fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn validate_redirect_uri(redirect_uri: &str) -> ServiceResult<()> {
+    if redirect_uri == OOB_REDIRECT_URI {
+        return Ok(());
+    }
+    match Url::parse(redirect_uri) {
+        Ok(url) if url.fragment().is_none() && !url.cannot_be_a_base() => Ok(()),
+        _ => Err(ServiceError::known(OAuthError::InvalidRedirectUri)),
+    }
+}
+
+/// クライアントアプリを登録する。
+pub async fn create_oauth_app(
+    conn: &Conn,
+    name: &str,
+    redirect_uris: &[&str],
+    scopes: &OAuthScopes,
+    website: Option<&str>,
+) -> ServiceResult<OAuthAppModel> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > APP_NAME_MAX_LENGTH {
+        return Err(ServiceError::known(OAuthError::InvalidAppName));
+    }
+    if redirect_uris.is_empty() {
+        return Err(ServiceError::known(OAuthError::InvalidRedirectUri));
+    }
+    for redirect_uri in redirect_uris {
+        validate_redirect_uri(redirect_uri)?;
+    }
+    let scopes_str = scopes.to_string();
+    if scopes_str.len() > SCOPES_MAX_LENGTH {
+        return Err(ServiceError::known(OAuthError::InvalidScope));
+    }
+    let website = website.map(|w| w.trim()).filter(|w| !w.is_empty());
+    if let Some(website) = website
+        && (website.len() > WEBSITE_MAX_LENGTH || Url::parse(website).is_err())
+    {
+        return Err(ServiceError::known(OAuthError::InvalidWebsite));
+    }
+
+    let app = entity::oauth_app::ActiveModel {
+        client_id: Set(uuid::Uuid::new_v4().simple().to_string()),
+        client_secret: Set(generate_token()),
+        name: Set(name.to_string()),
+        website: Set(website.map(|w| w.to_string())),
+        redirect_uris: Set(redirect_uris.join("\n")),
+        scopes: Set(scopes_str),
+        created_at: Set(Utc::now().naive_utc()),
+        ..Default::default()
+    };
+    let app = app.insert(conn.db()).await.map_err_unknown()?;
+
+    OAuthAppModel::from_db(app)
+}
+
+pub async fn get_oauth_app_by_client_id(
+    conn: &Conn,
+    client_id: &str,
+) -> ServiceResult<Option<OAuthAppModel>> {
+    let app = entity::oauth_app::Entity::find()
+        .filter(entity::oauth_app::Column::ClientId.eq(client_id))
+        .one(conn.db())
+        .await
+        .map_err_unknown()?;
+
+    app.map(OAuthAppModel::from_db).transpose()
+}
+
+/// PKCE のチャレンジ
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PkceChallenge {
+    S256(String),
+    Plain(String),
+}
+
+impl PkceChallenge {
+    /// code_challenge と code_challenge_method からチャレンジを作成する。
+    /// code_challenge_method を省略した場合は plain として扱う。
+    pub fn new(challenge: &str, method: Option<&str>) -> ServiceResult<Self> {
+        if !is_valid_pkce_string(challenge) {
+            return Err(ServiceError::known(OAuthError::InvalidCodeChallenge));
+        }
+        match method {
+            Some("S256") => Ok(Self::S256(challenge.to_string())),
+            Some("plain") | None => Ok(Self::Plain(challenge.to_string())),
+            Some(_) => Err(ServiceError::known(OAuthError::InvalidCodeChallenge)),
+        }
+    }
+
+    fn verify(&self, verifier: &str) -> bool {
+        if !is_valid_pkce_string(verifier) {
+            return false;
+        }
+        match self {
+            Self::S256(challenge) => {
+                BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())) == *challenge
+            }
+            Self::Plain(challenge) => verifier == challenge,
+        }
+    }
+}
+
+/// RFC 7636 で定められた code_verifier の形式かどうか
+fn is_valid_pkce_string(s: &str) -> bool {
+    (43..=128).contains(&s.len())
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~'))
+}
+
+/// 認可リクエストを検証し、アプリと許可するスコープを返す。
+pub async fn validate_authorization_request(
+    conn: &Conn,
+    client_id: &str,
+    redirect_uri: &str,
+    scope: Option<&str>,
+) -> ServiceResult<(OAuthAppModel, OAuthScopes)> {
+    let app = match get_oauth_app_by_client_id(conn, client_id).await? {
+        Some(app) => app,
+        None => return Err(ServiceError::known(OAuthError::InvalidClient)),
+    };
+    if !app.is_valid_redirect_uri(redirect_uri) {
+        return Err(ServiceError::known(OAuthError::InvalidRedirectUri));
+    }
+    let scopes = match scope {
+        Some(scope) => OAuthScopes::parse(scope)?,
+        None => app.scopes.clone(),
+    };
+    if !app.scopes.allows_all(&scopes) {
+        return Err(ServiceError::known(OAuthError::InvalidScope));
+    }
+    Ok((app, scopes))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AuthorizationCode {
+    app_id: i32,
+    user_id: UserID,
+    redirect_uri: String,
+    scopes: OAuthScopes,
+    challenge: Option<PkceChallenge>,
+}
+
+fn authorization_code_key(code: &str) -> String {
+    format!("oauth:code:{}", hash_token(code))
+}
+
+/// ユーザーが許可した認可リクエストに対して認可コードを発行する。
+pub async fn create_authorization_code(
+    rconn: &KVObject,
+    app: &OAuthAppModel,
+    user_id: UserID,
+    redirect_uri: &str,
+    scopes: &OAuthScopes,
+    challenge: Option<PkceChallenge>,
+) -> ServiceResult<String> {
+    let code = generate_token();
+    let data = AuthorizationCode {
+        app_id: app.id,
+        user_id,
+        redirect_uri: redirect_uri.to_string(),
+        scopes: scopes.clone(),
+        challenge,
+    };
+    rconn
+        .set_ttl(authorization_code_key(&code), &data, AUTHORIZATION_CODE_TTL)
+        .await?;
+    Ok(code)
+}
+
+/// 発行したトークン
+#[derive(Debug, Clone)]
+pub struct IssuedOAuthToken {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub scopes: OAuthScopes,
+    pub created_at: DateTime<Utc>,
+    pub expires_in: i64,
+}
+
+async fn authenticate_client(
+    conn: &Conn,
+    client_id: &str,
+    client_secret: Option<&str>,
+) -> ServiceResult<OAuthAppModel> {
+    let app = match get_oauth_app_by_client_id(conn, client_id).await? {
+        Some(app) => app,
+        None => return Err(ServiceError::known(OAuthError::InvalidClient)),
+    };
+    // 比較にかかる時間からシークレットを推測されないように、ハッシュ同士を比較する
+    if let Some(client_secret) = client_secret
+        && hash_token(client_secret) != hash_token(&app.client_secret)
+    {
+        return Err(ServiceError::known(OAuthError::InvalidClient));
+    }
+    Ok(app)
+}
+
+/// 認可コードをアクセストークンと交換する。
+///
+/// client_secret を持たないクライアント (パブリッククライアント) は PKCE を使う必要がある。
+pub async fn exchange_authorization_code(
+    conn: &Conn,
+    rconn: &KVObject,
+    client_id: &str,
+    client_secret: Option<&str>,
+    code: &str,
+    redirect_uri: &str,
+    code_verifier: Option<&str>,
+) -> ServiceResult<IssuedOAuthToken> {
+    let app = authenticate_client(conn, client_id, client_secret).await?;
+
+    // 認可コードは一度しか使えない
+    let key = authorization_code_key(code);
+    let data: Option<AuthorizationCode> = rconn.get(&key).await?;
+    rconn.delete(&key).await?;
+    let data = match data {
+        Some(data) if data.app_id == app.id && data.redirect_uri == redirect_uri => data,
+        _ => return Err(ServiceError::known(OAuthError::InvalidGrant)),
+    };
+
+    match (&data.challenge, code_verifier) {
+        (Some(challenge), Some(verifier)) if challenge.verify(verifier) => {}
+        (None, _) if client_secret.is_some() => {}
+        _ => return Err(ServiceError::known(OAuthError::InvalidGrant)),
+    }
+
+    let maybe_conn = conn.clone().into();
+    if is_user_suspended(&maybe_conn, data.user_id).await?
+        || is_user_deleted(&maybe_conn, data.user_id).await?
+    {
+        return Err(ServiceError::known(OAuthError::InvalidGrant));
+    }
+
+    issue_token(conn, &app, data.user_id, &data.scopes).await
+}
+
+async fn issue_token(
+    conn: &Conn,
+    app: &OAuthAppModel,
+    user_id: UserID,
+    scopes: &OAuthScopes,
+) -> ServiceResult<IssuedOAuthToken> {
+    let access_token = generate_token();
+    let refresh_token = generate_token();
+    let now = Utc::now();
+
+    let token = entity::oauth_token::ActiveModel {
+        app_id: Set(app.id),
+        user_id: Set(user_id.as_db()),
+        access_token: Set(hash_token(&access_token)),
+        refresh_token: Set(Some(hash_token(&refresh_token))),
+        scopes: Set(scopes.to_string()),
+        created_at: Set(now.naive_utc()),
+        expires_at: Set((now + ACCESS_TOKEN_LIFETIME).naive_utc()),
+        ..Default::default()
+    };
+    token.insert(conn.db()).await.map_err_unknown()?;
+
+    Ok(IssuedOAuthToken {
+        access_token,
+        refresh_token,
+        scopes: scopes.clone(),
+        created_at: now,
+        expires_in: ACCESS_TOKEN_LIFETIME.num_seconds(),
+    })
+}
+
+/// リフレッシュトークンを使って新しいトークンを発行する。
+/// 使用したリフレッシュトークンは無効になる。
+pub async fn refresh_oauth_token(
+    conn: &Conn,
+    client_id: &str,
+    client_secret: Option<&str>,
+    refresh_token: &str,
+) -> ServiceResult<IssuedOAuthToken> {
+    let app = authenticate_client(conn, client_id, client_secret).await?;
+
+    let token = entity::oauth_token::Entity::find()
+        .filter(
+            Condition::all()
+                .add(entity::oauth_token::Column::AppId.eq(app.id))
+                .add(entity::oauth_token::Column::RefreshToken.eq(hash_token(refresh_token))),
+        )
+        .one(conn.db())
+        .await
+        .map_err_unknown()?;
+    let token = match token {
+        Some(token) => token,
+        None => return Err(ServiceError::known(OAuthError::InvalidGrant)),
+    };
+    let user_id = UserID::from_db_trusted(token.user_id.clone());
+    let scopes = OAuthScopes::parse(&token.scopes)?;
+
+    let maybe_conn = conn.clone().into();
+    if is_user_suspended(&maybe_conn, user_id).await?
+        || is_user_deleted(&maybe_conn, user_id).await?
+    {
+        return Err(ServiceError::known(OAuthError::InvalidGrant));
+    }
+
+    let access_token = generate_token();
+    let refresh_token = generate_token();
+    let now = Utc::now();
+
+    let mut token = token.into_active_model();
+    token.access_token = Set(hash_token(&access_token));
+    token.refresh_token = Set(Some(hash_token(&refresh_token)));
+    token.created_at = Set(now.naive_utc());
+    token.expires_at = Set((now + ACCESS_TOKEN_LIFETIME).naive_utc());
+    token.update(conn.db()).await.map_err_unknown()?;
+
+    Ok(IssuedOAuthToken {
+        access_token,
+        refresh_token,
+        scopes,
+        created_at: now,
+        expires_in: ACCESS_TOKEN_LIFETIME.num_seconds(),
+    })
+}
+
+/// アクセストークンまたはリフレッシュトークンを無効にする。
+/// トークンが存在しない場合も成功として扱う (RFC 7009)。
+pub async fn revoke_oauth_token(
+    conn: &Conn,
+    client_id: &str,
+    client_secret: Option<&str>,
+    token: &str,
+) -> ServiceResult<()> {
+    let app = authenticate_client(conn, client_id, client_secret).await?;
+
+    let hashed = hash_token(token);
+    entity::oauth_token::Entity::delete_many()
+        .filter(
+            Condition::all()
+                .add(entity::oauth_token::Column::AppId.eq(app.id))
+                .add(
+                    Condition::any()
+                        .add(entity::oauth_token::Column::AccessToken.eq(hashed.clone()))
+                        .add(entity::oauth_token::Column::RefreshToken.eq(hashed)),
+                ),
+        )
+        .exec(conn.db())
+        .await
+        .map_err_unknown()?;
+
+    Ok(())
+}
+
+/// アクセストークンで認証されたユーザー
+#[derive(Debug, Clone)]
+pub struct OAuthTokenUser {
+    pub user_id: UserID,
+    pub app_id: i32,
+    pub scopes: OAuthScopes,
+}
+
+/// アクセストークンを検証する。
+/// 無効または期限切れの場合は None を返す。
+pub async fn authenticate_oauth_token(
+    conn: &Conn,
+    access_token: &str,
+) -> ServiceResult<Option<OAuthTokenUser>> {
+    let token = entity::oauth_token::Entity::find()
+        .filter(
+            Condition::all()
+                .add(entity::oauth_token::Column::AccessToken.eq(hash_token(access_token)))
+                .add(entity::oauth_token::Column::ExpiresAt.gt(Utc::now().naive_utc())),
+        )
+        .one(conn.db())
+        .await
+        .map_err_unknown()?;
+
+    let Some(token) = token else {
+        return Ok(None);
+    };
+
+    // 削除されたユーザーのトークンは使えない
+    let user_id = UserID::from_db_trusted(token.user_id);
+    if is_user_deleted(&conn.clone().into(), user_id).await? {
+        return Ok(None);
+    }
+
+    Ok(Some(OAuthTokenUser {
+        user_id,
+        app_id: token.app_id,
+        scopes: OAuthScopes::parse(&token.scopes)?,
+    }))
+}
+
+/// ユーザーのすべてのトークンを無効にする。
+pub(crate) async fn revoke_all_oauth_tokens(
+    tx: &impl ConnectionTrait,
+    user_id: UserID,
+) -> ServiceResult<()> {
+    entity::oauth_token::Entity::delete_many()
+        .filter(entity::oauth_token::Column::UserId.eq(user_id.as_db()))
+        .exec(tx)
+        .await
+        .map_err_unknown()?;
+    Ok(())
+}
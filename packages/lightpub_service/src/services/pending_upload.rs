@@ -0,0 +1,214 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! ノートに添付される前のアップロード
+//!
+//! Mastodon API ではファイルを先にアップロードし、投稿時に ID で指定する。
+//! アップロード時に `upload` と `pending_upload` に登録しておき、ノートに添付されたら
+//! `pending_upload` から取り除く。添付されないまま [`PENDING_UPLOAD_TTL`] を過ぎたものは
+//! [`delete_expired_pending_uploads`] でファイルごと削除する。
+
+use std::path::Path;
+
+use actix_web::http::StatusCode;
+use chrono::{TimeDelta, Utc};
+use expected_error_derive::ExpectedError;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, EntityTrait, IntoActiveModel, QueryFilter,
+    QuerySelect, Set,
+};
+use thiserror::Error;
+
+use super::{
+    MapToUnknown, ServiceError, ServiceResult,
+    db::{Conn, MaybeTxConn},
+    id::{Identifier, UploadID, UserID},
+    storage::StorageObject,
+    upload::{
+        UploadMetadata, delete_uploads, remove_upload_files, save_upload_file_info,
+        validate_upload_description,
+    },
+};
+use crate::impl_into_known;
+
+/// 投稿に使われなかったアップロードを保持する期間
+pub const PENDING_UPLOAD_TTL: TimeDelta = TimeDelta::hours(24);
+
+#[derive(Debug, Clone, Error, ExpectedError)]
+pub enum PendingUploadError {
+    #[error("media not found or already attached")]
+    #[ee(status(StatusCode::UNPROCESSABLE_ENTITY))]
+    NotFound,
+}
+
+impl_into_known!(PendingUploadError);
+
+/// ノートに添付される前のアップロード
+#[derive(Debug, Clone)]
+pub struct PendingUploadModel {
+    pub upload_id: UploadID,
+    pub mime_type: String,
+    pub metadata: UploadMetadata,
+}
+
+/// 保存したファイルを、ノートに添付される前のアップロードとして登録する。
+pub async fn register_pending_upload(
+    conn: &Conn,
+    user_id: UserID,
+    upload_id: &UploadID,
+    filename: &Path,
+    mime_type: &str,
+    metadata: &UploadMetadata,
+) -> ServiceResult<()> {
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+
+    save_upload_file_info(&tx, upload_id, filename, mime_type, metadata).await?;
+    let model = entity::pending_upload::ActiveModel {
+        upload_id: Set(upload_id.as_db()),
+        user_id: Set(user_id.as_db()),
+        created_at: Set(Utc::now().naive_utc()),
+    };
+    model.insert(&tx).await.map_err_unknown()?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn find_pending_upload(
+    conn: &MaybeTxConn,
+    user_id: UserID,
+    upload_id: &UploadID,
+) -> ServiceResult<Option<entity::upload::Model>> {
+    let upload = entity::pending_upload::Entity::find()
+        .find_also_related(entity::upload::Entity)
+        .filter(
+            Condition::all()
+                .add(entity::pending_upload::Column::UploadId.eq(upload_id.as_db()))
+                .add(entity::pending_upload::Column::UserId.eq(user_id.as_db())),
+        )
+        .one(conn)
+        .await
+        .map_err_unknown()?;
+    Ok(upload.and_then(|(_, upload)| upload))
+}
+
+/// `user_id` がアップロードした、ノートに添付される前のアップロードを取得する。
+pub async fn get_pending_upload(
+    conn: &MaybeTxConn,
+    user_id: UserID,
+    upload_id: &UploadID,
+) -> ServiceResult<Option<PendingUploadModel>> {
+    let upload = find_pending_upload(conn, user_id, upload_id).await?;
+    Ok(upload.map(|upload| PendingUploadModel {
+        upload_id: *upload_id,
+        metadata: UploadMetadata::from_model(&upload),
+        mime_type: upload.mime_type,
+    }))
+}
+
+/// ノートに添付される前のアップロードの説明文とフォーカルポイントを更新する。
+/// None の項目は変更しない。
+pub async fn update_pending_upload_metadata(
+    conn: &MaybeTxConn,
+    user_id: UserID,
+    upload_id: &UploadID,
+    description: Option<String>,
+    focal_point: Option<(f32, f32)>,
+) -> ServiceResult<PendingUploadModel> {
+    let upload = find_pending_upload(conn, user_id, upload_id)
+        .await?
+        .ok_or(ServiceError::known(PendingUploadError::NotFound))?;
+
+    let mut metadata = UploadMetadata::from_model(&upload);
+    if let Some(description) = description {
+        let description = description.trim();
+        metadata.description = Some(description.to_string()).filter(|d| !d.is_empty());
+    }
+    if let Some(focal_point) = focal_point {
+        metadata.focal_point = Some(focal_point);
+    }
+    validate_upload_description(metadata.description.as_deref(), metadata.focal_point)?;
+
+    let mime_type = upload.mime_type.clone();
+    let mut model = upload.into_active_model();
+    metadata.set_to_model(&mut model);
+    model.update(conn).await.map_err_unknown()?;
+
+    Ok(PendingUploadModel {
+        upload_id: *upload_id,
+        mime_type,
+        metadata,
+    })
+}
+
+/// ノートに添付するアップロードを、添付前の一覧から取り除く。
+/// `user_id` のアップロードでないか、既に添付されている場合はエラーになる。
+pub(crate) async fn attach_pending_upload(
+    tx: &MaybeTxConn,
+    user_id: UserID,
+    upload_id: &UploadID,
+) -> ServiceResult<()> {
+    let res = entity::pending_upload::Entity::delete_many()
+        .filter(
+            Condition::all()
+                .add(entity::pending_upload::Column::UploadId.eq(upload_id.as_db()))
+                .add(entity::pending_upload::Column::UserId.eq(user_id.as_db())),
+        )
+        .exec(tx)
+        .await
+        .map_err_unknown()?;
+    if res.rows_affected == 0 {
+        return Err(ServiceError::known(PendingUploadError::NotFound));
+    }
+    Ok(())
+}
+
+/// ノートに添付されないまま [`PENDING_UPLOAD_TTL`] を過ぎたアップロードを、ファイルごと削除する。
+/// 削除したアップロードの数を返す。
+pub async fn delete_expired_pending_uploads(
+    conn: &Conn,
+    storage: &StorageObject,
+) -> ServiceResult<usize> {
+    let expires_before = Utc::now().naive_utc() - PENDING_UPLOAD_TTL;
+
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+
+    let expired: Vec<Vec<u8>> = entity::pending_upload::Entity::find()
+        .select_only()
+        .column(entity::pending_upload::Column::UploadId)
+        .filter(entity::pending_upload::Column::CreatedAt.lt(expires_before))
+        .into_tuple()
+        .all(&tx)
+        .await
+        .map_err_unknown()?;
+    if expired.is_empty() {
+        return Ok(0);
+    }
+
+    let upload_ids = expired
+        .into_iter()
+        .map(UploadID::from_db_trusted)
+        .collect::<Vec<_>>();
+    // pending_upload の行は外部キーによって一緒に削除される
+    let keys = delete_uploads(&tx, &upload_ids).await?;
+
+    tx.commit().await?;
+
+    remove_upload_files(storage, keys).await;
+    Ok(upload_ids.len())
+}
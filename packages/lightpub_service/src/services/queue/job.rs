@@ -0,0 +1,179 @@
+use activitypub_federation::config::Data;
+use async_nats::jetstream::{self, stream};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::select;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info};
+
+use crate::{
+    MyFederationData, ServiceResult,
+    services::{
+        MapToUnknown, ServiceError, export::process_user_export, import::process_user_import,
+    },
+};
+
+use super::QConn;
+
+const JOB_STREAM_NAME: &str = "JOB";
+const JOB_SUBJECT: &str = "job.user";
+const JOB_CONSUMER: &str = "user-job-processor";
+
+#[derive(Clone, Debug)]
+pub struct JobQ {
+    jetstream: jetstream::Context,
+    stream: stream::Stream,
+}
+
+#[derive(Clone, Debug)]
+pub struct JobQConsumer {
+    consumer: jetstream::consumer::Consumer<jetstream::consumer::pull::Config>,
+}
+
+/// バックグラウンドで処理するユーザー単位のジョブ
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum BackgroundJob {
+    UserExport { export_id: i32 },
+    UserImport { import_id: i32 },
+}
+
+impl QConn {
+    pub async fn get_job_stream(&self) -> ServiceResult<JobQ> {
+        let jetstream = jetstream::new(self.client.clone());
+        let stream = jetstream
+            .get_or_create_stream(jetstream::stream::Config {
+                name: JOB_STREAM_NAME.to_owned(),
+                retention: stream::RetentionPolicy::WorkQueue,
+                subjects: vec![JOB_SUBJECT.to_owned()],
+                storage: stream::StorageType::File,
+                ..Default::default()
+            })
+            .await
+            .map_err_unknown()?;
+
+        Ok(JobQ { jetstream, stream })
+    }
+
+    pub async fn queue_job(&self, job: BackgroundJob) -> ServiceResult<()> {
+        self.get_job_stream().await?.enqueue(job).await
+    }
+}
+
+impl JobQ {
+    pub async fn create_consumer(&self) -> ServiceResult<JobQConsumer> {
+        let consumer = self
+            .stream
+            .get_or_create_consumer(
+                JOB_CONSUMER,
+                jetstream::consumer::pull::Config {
+                    durable_name: JOB_CONSUMER.to_owned().into(),
+                    filter_subject: JOB_SUBJECT.to_owned(),
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err_unknown()?;
+
+        Ok(JobQConsumer { consumer })
+    }
+
+    pub async fn enqueue(&self, job: BackgroundJob) -> ServiceResult<()> {
+        let data = serde_json::to_string(&job).map_err_unknown()?;
+        self.jetstream
+            .publish(JOB_SUBJECT, data.into())
+            .await
+            .map_err_unknown()?
+            .await
+            .map_err_unknown()?;
+        debug!("Published to {JOB_SUBJECT}: {job:?}");
+
+        Ok(())
+    }
+}
+
+impl JobQConsumer {
+    pub async fn process_loop(
+        &self,
+        data: &Data<MyFederationData>,
+        cancel: &CancellationToken,
+    ) -> ServiceResult<()> {
+        let mut msg_stream = self.consumer.messages().await.map_err_unknown()?;
+        loop {
+            select! {
+                _ = cancel.cancelled() => {
+                    info!("JOB consumer loop cancelled");
+                    break;
+                }
+                Some(Ok(message)) = msg_stream.next() => {
+                    debug!("JOB worker received message");
+                    let job = serde_json::from_slice::<BackgroundJob>(&message.payload);
+                    match job {
+                        Ok(job) => {
+                            // 失敗は DB に記録されるので、再試行はしない
+                            if let Err(e) = process_job(data, job).await {
+                                error!("JOB failed to process: {:#?}", e);
+                            }
+                        }
+                        Err(e) => {
+                            error!("Failed to deserialize JOB message (discarding job): {:#?}", e);
+                        }
+                    }
+                    message.ack().await.map_err(ServiceError::unknown_box)?;
+                }
+            }
+        }
+
+        info!("JOB consumer loop ended");
+        Ok(())
+    }
+}
+
+async fn process_job(data: &Data<MyFederationData>, job: BackgroundJob) -> ServiceResult<()> {
+    match job {
+        BackgroundJob::UserExport { export_id } => {
+            process_user_export(
+                data.conn(),
+                &data.rconn(),
+                export_id,
+                &data.my_domain(),
+                data.base_url(),
+                data,
+            )
+            .await
+        }
+        BackgroundJob::UserImport { import_id } => {
+            process_user_import(
+                data.conn(),
+                &data.rconn(),
+                data.qconn(),
+                data.wp(),
+                import_id,
+                &data.my_domain(),
+                data.base_url(),
+                data,
+            )
+            .await
+        }
+    }
+}
+
+pub struct JobWorker {
+    qconn: QConn,
+}
+
+impl JobWorker {
+    pub fn new(qconn: QConn) -> Self {
+        Self { qconn }
+    }
+
+    pub async fn start(
+        &self,
+        data: &Data<MyFederationData>,
+        cancel: &CancellationToken,
+    ) -> ServiceResult<()> {
+        let jobs = self.qconn.get_job_stream().await?;
+        let consumer = jobs.create_consumer().await?;
+        consumer.process_loop(data, cancel).await
+    }
+}
@@ -21,15 +21,18 @@ use tracing::{debug, error, info};
 use url::Url;
 
 mod delay;
+mod job;
 mod worker;
 use crate::{MyFederationData, services::ServiceError};
+pub use job::{BackgroundJob, JobWorker};
 pub use worker::ApubWorker;
 
 use super::{
     MapToUnknown, ServiceResult,
     apub::{
-        AcceptActivity, AnnounceActivity, CreateActivity, DeleteActivity, FollowActivity,
-        LikeActivity, RejectActivity, UndoActivity, UpdateActivity,
+        AcceptActivity, AnnounceActivity, BlockActivity, CreateActivity, DeleteActivity,
+        FlagActivity, FollowActivity, LikeActivity, MoveActivity, RejectActivity, UndoActivity,
+        UpdateActivity,
     },
     user::UserWithApubModel,
 };
@@ -144,10 +147,13 @@ pub struct ApubDeliveryData {
 pub enum SendableActivityInner {
     Accept(AcceptActivity),
     Announce(AnnounceActivity),
+    Block(BlockActivity),
     Create(CreateActivity),
     Delete(DeleteActivity),
+    Flag(FlagActivity),
     Follow(FollowActivity),
     Like(LikeActivity),
+    Move(MoveActivity),
     Reject(RejectActivity),
     Undo(UndoActivity),
     Update(UpdateActivity),
@@ -0,0 +1,443 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use actix_web::http::StatusCode;
+use chrono::{DateTime, Utc};
+use entity::sea_orm_active_enums::ReportStatus;
+use expected_error_derive::ExpectedError;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder, QuerySelect, Set,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use url::Url;
+
+use super::{
+    MapToUnknown, ServiceError, ServiceResult,
+    apub::FlagActivity,
+    db::{Conn, MaybeTxConn, is_unique_constraint_error},
+    id::{Identifier, NoteID, UserID},
+    kv::KVObject,
+    note::{delete_note_by_id_, get_apubnote_by_id},
+    queue::QConn,
+    user::{
+        UserAccountStateModel, get_apubuser_by_id, get_instance_actor, get_user_by_id,
+        set_user_account_state,
+    },
+};
+
+/// 通報コメントの最大文字数
+pub const REPORT_COMMENT_MAX_LENGTH: usize = 2000;
+
+#[derive(Debug, Clone, Error, ExpectedError)]
+pub enum ReportError {
+    #[error("User {0} does not exist")]
+    #[ee(status(StatusCode::NOT_FOUND))]
+    UserDoesNotExist(UserID),
+    #[error("Cannot report self")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    SelfReport,
+    #[error("Note {0} is not a note of the reported user")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    InvalidNote(NoteID),
+    #[error("Report comment is too long")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    CommentTooLong,
+    #[error("Report not found")]
+    #[ee(status(StatusCode::NOT_FOUND))]
+    NotFound,
+    #[error("Report is already closed")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    AlreadyClosed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReportStatusModel {
+    Open,
+    Resolved,
+    Dismissed,
+}
+
+impl ReportStatusModel {
+    pub fn from_db(status: ReportStatus) -> Self {
+        match status {
+            ReportStatus::Open => ReportStatusModel::Open,
+            ReportStatus::Resolved => ReportStatusModel::Resolved,
+            ReportStatus::Dismissed => ReportStatusModel::Dismissed,
+        }
+    }
+
+    pub fn as_db(self) -> ReportStatus {
+        match self {
+            ReportStatusModel::Open => ReportStatus::Open,
+            ReportStatusModel::Resolved => ReportStatus::Resolved,
+            ReportStatusModel::Dismissed => ReportStatus::Dismissed,
+        }
+    }
+}
+
+/// 通報を解決する際に対象に対してとる措置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReportResolveAction {
+    /// 何もしない
+    #[default]
+    None,
+    /// 通報されたノートを削除する
+    DeleteNotes,
+    /// 通報されたユーザーを凍結する
+    SuspendUser,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportModel {
+    pub id: i32,
+    pub reporter_id: UserID,
+    pub target_user_id: UserID,
+    pub note_ids: Vec<NoteID>,
+    pub comment: String,
+    pub status: ReportStatusModel,
+    /// リモートサーバーから受け取った通報かどうか
+    pub is_remote: bool,
+    /// リモートサーバーに転送済みかどうか
+    pub forwarded: bool,
+    pub created_at: DateTime<Utc>,
+    pub closed_at: Option<DateTime<Utc>>,
+    pub closed_by: Option<UserID>,
+}
+
+async fn get_report_note_ids(tx: &MaybeTxConn, report_id: i32) -> ServiceResult<Vec<NoteID>> {
+    let notes = entity::report_note::Entity::find()
+        .filter(entity::report_note::Column::ReportId.eq(report_id))
+        .order_by_asc(entity::report_note::Column::Id)
+        .all(tx)
+        .await
+        .map_err_unknown()?;
+
+    Ok(notes
+        .into_iter()
+        .map(|n| NoteID::from_db_trusted(n.note_id))
+        .collect())
+}
+
+async fn report_from_db(
+    tx: &MaybeTxConn,
+    model: entity::report::Model,
+) -> ServiceResult<ReportModel> {
+    let note_ids = get_report_note_ids(tx, model.id).await?;
+    Ok(ReportModel {
+        id: model.id,
+        reporter_id: UserID::from_db_trusted(model.reporter_id),
+        target_user_id: UserID::from_db_trusted(model.target_user_id),
+        note_ids,
+        comment: model.comment,
+        status: ReportStatusModel::from_db(model.status),
+        is_remote: model.url.is_some(),
+        forwarded: model.forwarded != 0,
+        created_at: model.created_at.and_utc(),
+        closed_at: model.closed_at.map(|d| d.and_utc()),
+        closed_by: model.closed_by.map(UserID::from_db_trusted),
+    })
+}
+
+/// 通報対象のノートが全て対象ユーザーのもの (リノートを除く) であることを確認する。
+async fn check_report_notes(
+    tx: &MaybeTxConn,
+    target_user_id: UserID,
+    note_ids: &[NoteID],
+) -> ServiceResult<()> {
+    for note_id in note_ids {
+        let note = entity::note::Entity::find_by_id(note_id.as_db())
+            .one(tx)
+            .await
+            .map_err_unknown()?;
+        match note {
+            Some(note) if note.author_id == target_user_id.as_db() && note.content.is_some() => {}
+            _ => return Err(ServiceError::known(ReportError::InvalidNote(*note_id))),
+        }
+    }
+    Ok(())
+}
+
+async fn insert_report(
+    tx: &MaybeTxConn,
+    url: Option<&Url>,
+    reporter_id: UserID,
+    target_user_id: UserID,
+    note_ids: &[NoteID],
+    comment: &str,
+) -> ServiceResult<entity::report::Model> {
+    let model = entity::report::ActiveModel {
+        url: Set(url.map(|u| u.to_string())),
+        reporter_id: Set(reporter_id.as_db()),
+        target_user_id: Set(target_user_id.as_db()),
+        comment: Set(comment.to_string()),
+        status: Set(ReportStatus::Open),
+        forwarded: Set(0),
+        created_at: Set(Utc::now().naive_utc()),
+        ..Default::default()
+    };
+    let model = model.insert(tx).await.map_err_unknown()?;
+
+    for note_id in note_ids {
+        let note_model = entity::report_note::ActiveModel {
+            report_id: Set(model.id),
+            note_id: Set(note_id.as_db()),
+            ..Default::default()
+        };
+        match note_model.insert(tx).await {
+            Ok(_) => {}
+            Err(e) if is_unique_constraint_error(&e) => {} // 重複したノートは無視する
+            Err(e) => return Err(ServiceError::unknown(e)),
+        }
+    }
+
+    Ok(model)
+}
+
+/// ローカルユーザーによる通報を作成する。
+///
+/// `forward` が true で、対象ユーザーがリモートユーザーの場合は、相手のサーバーに Flag を送信する。
+/// Flag は通報者ではなくサーバー自身のアクターから送信する。
+pub async fn create_report(
+    conn: &Conn,
+    rconn: &KVObject,
+    qconn: &QConn,
+    reporter_id: UserID,
+    target_user_id: UserID,
+    note_ids: &[NoteID],
+    comment: &str,
+    forward: bool,
+    base_url: &Url,
+) -> ServiceResult<i32> {
+    if reporter_id == target_user_id {
+        return Err(ServiceError::known(ReportError::SelfReport));
+    }
+    if comment.chars().count() > REPORT_COMMENT_MAX_LENGTH {
+        return Err(ServiceError::known(ReportError::CommentTooLong));
+    }
+
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+
+    get_user_by_id(&tx, rconn, reporter_id)
+        .await?
+        .ok_or(ServiceError::known(ReportError::UserDoesNotExist(
+            reporter_id,
+        )))?;
+    let target = get_user_by_id(&tx, rconn, target_user_id)
+        .await?
+        .ok_or(ServiceError::known(ReportError::UserDoesNotExist(
+            target_user_id,
+        )))?;
+
+    check_report_notes(&tx, target_user_id, note_ids).await?;
+
+    let model = insert_report(&tx, None, reporter_id, target_user_id, note_ids, comment).await?;
+    let report_id = model.id;
+
+    if forward && target.is_remote() {
+        // 通報者が特定されないように、サーバー自身のアクターとして送信する
+        let instance_actor = get_instance_actor(&conn.clone().into(), base_url).await?;
+        let target_model = get_apubuser_by_id(&tx, target_user_id, base_url)
+            .await?
+            .expect("target user should exist");
+
+        let mut note_urls = vec![];
+        for note_id in note_ids {
+            let note = get_apubnote_by_id(&tx, *note_id, base_url, false).await?;
+            if let Some(note) = note {
+                note_urls.push(note.apub.url);
+            }
+        }
+
+        let target_inbox = target_model.shared_inbox_or_inbox().clone();
+        let flag = FlagActivity::new(
+            report_id,
+            instance_actor.apub.url.clone(),
+            target_model.apub.url.clone(),
+            note_urls,
+            comment.to_string(),
+            base_url,
+        );
+        qconn
+            .queue_activity(flag, instance_actor, vec![target_inbox])
+            .await?;
+
+        let mut model = model.into_active_model();
+        model.forwarded = Set(1);
+        model.update(&tx).await.map_err_unknown()?;
+    }
+
+    tx.commit().await?;
+
+    Ok(report_id)
+}
+
+/// リモートサーバーから受け取った Flag を通報として保存する。同じ Flag を複数回受け取った場合は無視する。
+pub async fn receive_remote_report(
+    conn: &MaybeTxConn,
+    reporter_id: UserID,
+    target_user_id: UserID,
+    note_ids: &[NoteID],
+    comment: &str,
+    url: &Url,
+) -> ServiceResult<()> {
+    let existing = entity::report::Entity::find()
+        .filter(entity::report::Column::Url.eq(url.as_str()))
+        .one(conn)
+        .await
+        .map_err_unknown()?;
+    if existing.is_some() {
+        return Ok(());
+    }
+
+    let comment: String = comment.chars().take(REPORT_COMMENT_MAX_LENGTH).collect();
+    insert_report(
+        conn,
+        Some(url),
+        reporter_id,
+        target_user_id,
+        note_ids,
+        &comment,
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_report_by_id(
+    conn: &MaybeTxConn,
+    report_id: i32,
+) -> ServiceResult<Option<ReportModel>> {
+    let model = entity::report::Entity::find_by_id(report_id)
+        .one(conn)
+        .await
+        .map_err_unknown()?;
+    match model {
+        Some(model) => Ok(Some(report_from_db(conn, model).await?)),
+        None => Ok(None),
+    }
+}
+
+/// 通報を新しい順に取得する。`status` が None の場合は全ての通報を返す。
+pub async fn list_reports(
+    conn: &MaybeTxConn,
+    status: Option<ReportStatusModel>,
+    limit: u64,
+    before_date: Option<DateTime<Utc>>,
+) -> ServiceResult<Vec<ReportModel>> {
+    let models = entity::report::Entity::find()
+        .filter(
+            Condition::all()
+                .add_option(status.map(|s| entity::report::Column::Status.eq(s.as_db())))
+                .add_option(
+                    before_date.map(|d| entity::report::Column::CreatedAt.lte(d.naive_utc())),
+                ),
+        )
+        .order_by_desc(entity::report::Column::CreatedAt)
+        .limit(limit)
+        .all(conn)
+        .await
+        .map_err_unknown()?;
+
+    let mut reports = Vec::with_capacity(models.len());
+    for model in models {
+        reports.push(report_from_db(conn, model).await?);
+    }
+    Ok(reports)
+}
+
+async fn close_report(
+    tx: &MaybeTxConn,
+    report_id: i32,
+    admin_id: UserID,
+    status: ReportStatus,
+) -> ServiceResult<entity::report::Model> {
+    let model = entity::report::Entity::find_by_id(report_id)
+        .one(tx)
+        .await
+        .map_err_unknown()?
+        .ok_or(ServiceError::known(ReportError::NotFound))?;
+
+    if model.status != ReportStatus::Open {
+        return Err(ServiceError::known(ReportError::AlreadyClosed));
+    }
+
+    let mut model = model.into_active_model();
+    model.status = Set(status);
+    model.closed_at = Set(Some(Utc::now().naive_utc()));
+    model.closed_by = Set(Some(admin_id.as_db()));
+    model.update(tx).await.map_err_unknown()
+}
+
+/// 通報を解決済みにし、`action` で指定された措置をとる。
+///
+/// 措置に失敗した場合に通報が解決済みのまま残らないよう、措置をとってから解決済みにする。
+/// 措置はどれも繰り返しても問題ないので、失敗した場合はもう一度解決すればよい。
+pub async fn resolve_report(
+    conn: &Conn,
+    rconn: &KVObject,
+    qconn: &QConn,
+    report_id: i32,
+    admin_id: UserID,
+    action: ReportResolveAction,
+    base_url: &Url,
+) -> ServiceResult<()> {
+    let maybe_conn: MaybeTxConn = conn.clone().into();
+    let model = entity::report::Entity::find_by_id(report_id)
+        .one(&maybe_conn)
+        .await
+        .map_err_unknown()?
+        .ok_or(ServiceError::known(ReportError::NotFound))?;
+    if model.status != ReportStatus::Open {
+        return Err(ServiceError::known(ReportError::AlreadyClosed));
+    }
+
+    match action {
+        ReportResolveAction::None => {}
+        ReportResolveAction::DeleteNotes => {
+            // 作成者として削除することで、ローカルユーザーのノートであれば Delete が配送される
+            let author_id = UserID::from_db_trusted(model.target_user_id);
+            for note_id in get_report_note_ids(&maybe_conn, model.id).await? {
+                delete_note_by_id_(conn, qconn, note_id, Some(author_id), base_url).await?;
+            }
+        }
+        ReportResolveAction::SuspendUser => {
+            let target_id = UserID::from_db_trusted(model.target_user_id);
+            set_user_account_state(conn, rconn, target_id, UserAccountStateModel::Suspended)
+                .await?;
+        }
+    }
+
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+    close_report(&tx, report_id, admin_id, ReportStatus::Resolved).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// 通報を却下する。
+pub async fn dismiss_report(conn: &Conn, report_id: i32, admin_id: UserID) -> ServiceResult<()> {
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+    close_report(&tx, report_id, admin_id, ReportStatus::Dismissed).await?;
+    tx.commit().await?;
+    Ok(())
+}
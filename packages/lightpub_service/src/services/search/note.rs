@@ -1,11 +1,17 @@
+use std::collections::HashSet;
+
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QuerySelect};
+
 use crate::{
     ServiceResult,
     services::{
+        MapToUnknown,
         db::MaybeTxConn,
         fulltext::{FTClient, note::ft_search_note_by_content},
-        id::UserID,
+        id::{Identifier, NoteID, UserID},
         kv::KVObject,
         note::{DetailedNoteModel, get_note_by_id_visibility_check},
+        user::get_blocking_or_blocked_user_ids,
     },
 };
 
@@ -18,15 +24,52 @@ pub async fn search_note_by_content(
 ) -> ServiceResult<Vec<DetailedNoteModel>> {
     let ft_notes = ft_search_note_by_content(ft, content_query).await?;
 
+    let blocks = match viewer_id {
+        Some(viewer_id) => get_blocking_or_blocked_user_ids(conn, viewer_id).await?,
+        None => Default::default(),
+    };
+
     let mut notes = Vec::new();
     for ft_note in ft_notes {
         let note =
             get_note_by_id_visibility_check(conn, rconn, ft_note.id.into_inner(), viewer_id, false)
                 .await?;
         if let Some(note) = note {
+            if blocks.contains(&note.basic.author.id) {
+                continue;
+            }
             notes.push(note);
         }
     }
 
+    // ブロック関係にあるユーザーのノートをリノート・引用したノートも除く
+    if !blocks.is_empty() {
+        let renote_of_ids = notes
+            .iter()
+            .filter_map(|n| n.basic.renote_of_id)
+            .map(|id| id.as_db())
+            .collect::<HashSet<_>>();
+        if !renote_of_ids.is_empty() {
+            let blocked_targets: Vec<Vec<u8>> = entity::note::Entity::find()
+                .select_only()
+                .column(entity::note::Column::Id)
+                .filter(entity::note::Column::Id.is_in(renote_of_ids))
+                .filter(entity::note::Column::AuthorId.is_in(blocks.iter().map(|u| u.as_db())))
+                .into_tuple()
+                .all(conn)
+                .await
+                .map_err_unknown()?;
+            let blocked_targets = blocked_targets
+                .into_iter()
+                .map(NoteID::from_db_trusted)
+                .collect::<HashSet<_>>();
+            notes.retain(|n| {
+                n.basic
+                    .renote_of_id
+                    .is_none_or(|id| !blocked_targets.contains(&id))
+            });
+        }
+    }
+
     Ok(notes)
 }
@@ -0,0 +1,77 @@
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+
+use crate::services::{MapToUnknown, ServiceResult};
+
+use super::{StorageReader, StorageServe, UploadStorage, validate_key};
+
+/// ローカルのディレクトリにファイルを保存するストレージ
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn path(&self, key: &str) -> ServiceResult<PathBuf> {
+        validate_key(key)?;
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl UploadStorage for LocalStorage {
+    async fn put(&self, key: &str, data: Vec<u8>, _content_type: &str) -> ServiceResult<()> {
+        let path = self.path(key)?;
+        tokio::fs::create_dir_all(&self.root)
+            .await
+            .map_err_unknown()?;
+        tokio::fs::write(path, data).await.map_err_unknown()
+    }
+
+    async fn put_file(&self, key: &str, path: &Path, _content_type: &str) -> ServiceResult<()> {
+        let dest = self.path(key)?;
+        tokio::fs::create_dir_all(&self.root)
+            .await
+            .map_err_unknown()?;
+        tokio::fs::copy(path, dest).await.map_err_unknown()?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> ServiceResult<Option<Vec<u8>>> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).map_err_unknown(),
+        }
+    }
+
+    async fn open(&self, key: &str) -> ServiceResult<Option<StorageReader>> {
+        let file = match tokio::fs::File::open(self.path(key)?).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).map_err_unknown(),
+        };
+        let size = file.metadata().await.map_err_unknown()?.len();
+        Ok(Some(StorageReader {
+            size,
+            reader: Box::pin(file),
+        }))
+    }
+
+    async fn delete(&self, key: &str) -> ServiceResult<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).map_err_unknown(),
+        }
+    }
+
+    async fn serve(&self, key: &str) -> ServiceResult<StorageServe> {
+        Ok(StorageServe::File(self.path(key)?))
+    }
+}
@@ -0,0 +1,226 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! アップロードされたファイルの保存先
+
+use std::{path::Path, pin::Pin, sync::Arc};
+
+use actix_web::http::StatusCode;
+use async_trait::async_trait;
+use expected_error_derive::ExpectedError;
+use sea_orm::{ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter};
+use thiserror::Error;
+use tokio::io::AsyncRead;
+use tracing::{info, warn};
+use url::Url;
+
+use super::{
+    MapToUnknown, ServiceError, ServiceResult,
+    db::MaybeTxConn,
+    id::{Identifier, UploadID},
+    upload::stored_files,
+};
+
+mod local;
+pub(crate) mod s3;
+
+pub use local::LocalStorage;
+pub use s3::{S3Config, S3Storage};
+
+pub type StorageObject = Arc<dyn UploadStorage + Send + Sync>;
+
+#[derive(Debug, Clone, Error, ExpectedError)]
+pub enum StorageError {
+    #[error("invalid storage key")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    InvalidKey,
+    #[error("file not found in storage")]
+    #[ee(status(StatusCode::NOT_FOUND))]
+    NotFound,
+}
+
+/// ファイルの配信方法
+#[derive(Debug)]
+pub enum StorageServe {
+    /// ローカルのファイルをそのまま返す
+    File(std::path::PathBuf),
+    /// 別の URL (公開 URL や署名付き URL) にリダイレクトする
+    Redirect(Url),
+    /// ファイルの内容をそのまま返す
+    Data(Vec<u8>),
+}
+
+/// 読み込み用に開いたファイル
+pub struct StorageReader {
+    /// ファイルの大きさ (バイト)
+    pub size: u64,
+    pub reader: Pin<Box<dyn AsyncRead + Send>>,
+}
+
+#[async_trait]
+pub trait UploadStorage {
+    /// `key` でファイルを保存する。既に存在する場合は上書きする。
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> ServiceResult<()>;
+
+    /// ローカルのファイルを `key` で保存する。
+    /// 大きなファイルも扱うので、実装はファイル全体をメモリに読み込まないこと。
+    async fn put_file(&self, key: &str, path: &Path, content_type: &str) -> ServiceResult<()>;
+
+    /// `key` のファイルを読み込む。存在しない場合は None を返す。
+    async fn get(&self, key: &str) -> ServiceResult<Option<Vec<u8>>>;
+
+    /// `key` のファイルを、全体をメモリに読み込まずに少しずつ読めるように開く。
+    /// 存在しない場合は None を返す。
+    async fn open(&self, key: &str) -> ServiceResult<Option<StorageReader>>;
+
+    /// `key` のファイルを削除する。存在しない場合は何もしない。
+    async fn delete(&self, key: &str) -> ServiceResult<()>;
+
+    /// `key` のファイルをクライアントに配信する方法を返す。
+    async fn serve(&self, key: &str) -> ServiceResult<StorageServe>;
+}
+
+/// キーとして使える文字列かどうかを検証する。
+/// パスの区切り文字などを含むキーは受け付けない。
+pub(crate) fn validate_key(key: &str) -> ServiceResult<()> {
+    let valid = !key.is_empty()
+        && !key.starts_with('.')
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(ServiceError::known(StorageError::InvalidKey))
+    }
+}
+
+/// メモリ上にファイルを保存するストレージ。テスト用。
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct InMemoryStorage {
+    files: std::sync::Mutex<std::collections::HashMap<String, (Vec<u8>, String)>>,
+}
+
+#[cfg(test)]
+impl InMemoryStorage {
+    /// 保存されているファイルの Content-Type
+    pub fn content_type(&self, key: &str) -> Option<String> {
+        self.files.lock().unwrap().get(key).map(|(_, c)| c.clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl UploadStorage for InMemoryStorage {
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> ServiceResult<()> {
+        validate_key(key)?;
+        self.files
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), (data, content_type.to_string()));
+        Ok(())
+    }
+
+    async fn put_file(&self, key: &str, path: &Path, content_type: &str) -> ServiceResult<()> {
+        let data = tokio::fs::read(path).await.map_err_unknown()?;
+        self.put(key, data, content_type).await
+    }
+
+    async fn get(&self, key: &str) -> ServiceResult<Option<Vec<u8>>> {
+        validate_key(key)?;
+        Ok(self.files.lock().unwrap().get(key).map(|(d, _)| d.clone()))
+    }
+
+    async fn open(&self, key: &str) -> ServiceResult<Option<StorageReader>> {
+        Ok(self.get(key).await?.map(|data| StorageReader {
+            size: data.len() as u64,
+            reader: Box::pin(std::io::Cursor::new(data)),
+        }))
+    }
+
+    async fn delete(&self, key: &str) -> ServiceResult<()> {
+        validate_key(key)?;
+        self.files.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    async fn serve(&self, key: &str) -> ServiceResult<StorageServe> {
+        self.get(key)
+            .await?
+            .map(StorageServe::Data)
+            .ok_or(ServiceError::known(StorageError::NotFound))
+    }
+}
+
+/// アップロードされたファイルを `from` から `to` に移動する。
+/// `remove_source` が true の場合は、コピーに成功したファイルを `from` から削除する。
+/// 移動したアップロードの数を返す。
+pub async fn migrate_upload_storage(
+    conn: &MaybeTxConn,
+    from: &StorageObject,
+    to: &StorageObject,
+    remove_source: bool,
+) -> ServiceResult<usize> {
+    let mut pages = entity::upload::Entity::find()
+        .filter(entity::upload::Column::Filename.is_not_null())
+        .paginate(conn, 100);
+
+    let mut moved = 0;
+    while let Some(uploads) = pages.fetch_and_next().await.map_err_unknown()? {
+        for upload in uploads {
+            let Some(filename) = &upload.filename else {
+                continue;
+            };
+            let upload_id = UploadID::from_db_trusted(upload.id.clone());
+            let files = stored_files(&upload_id, filename, &upload.mime_type, upload.has_variants);
+
+            let mut copied = vec![];
+            for (key, content_type) in files {
+                match from.get(&key).await? {
+                    Some(data) => {
+                        to.put(&key, data, &content_type).await?;
+                        copied.push(key);
+                    }
+                    None => warn!("{} is not found in the source storage (skipped)", key),
+                }
+            }
+            if copied.is_empty() {
+                continue;
+            }
+
+            if remove_source {
+                for key in &copied {
+                    from.delete(key).await?;
+                }
+            }
+            moved += 1;
+            info!("moved upload {} ({} files)", upload_id, copied.len());
+        }
+    }
+
+    Ok(moved)
+}
@@ -0,0 +1,538 @@
+//! S3 互換のオブジェクトストレージ
+//!
+//! 署名は AWS Signature Version 4 で行う。
+//! ファイルは読み込みながら送り、[`MULTIPART_THRESHOLD`] を超える場合はマルチパートアップロードを使う。
+
+use std::{collections::BTreeMap, fmt::Debug, io::SeekFrom, path::Path};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use hmac::{Hmac, Mac};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use reqwest::{Method, StatusCode, header::CONTENT_LENGTH};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::{ReaderStream, StreamReader};
+use tracing::warn;
+use url::{Position, Url};
+
+use crate::services::{MapToUnknown, ServiceError, ServiceResult};
+
+use super::{StorageReader, StorageServe, UploadStorage, validate_key};
+
+/// SigV4 で URI エンコードしない文字
+const SIGV4_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+const EMPTY_PAYLOAD_HASH: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// これより大きいファイルはマルチパートアップロードで保存する
+pub(crate) const MULTIPART_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+/// マルチパートアップロードの 1 パートの大きさ。S3 では最後のパート以外は 5 MiB 以上である必要がある。
+pub(crate) const MULTIPART_PART_SIZE: u64 = 16 * 1024 * 1024;
+
+/// リクエストの本文
+enum S3Body<'a> {
+    Empty,
+    /// メモリ上のデータ。ハッシュを署名に含める。
+    Data(Vec<u8>),
+    /// ファイルの `offset` から `len` バイト。読み込みながら送るので、ペイロードは署名しない。
+    File {
+        path: &'a Path,
+        offset: u64,
+        len: u64,
+    },
+}
+
+#[derive(Clone)]
+pub struct S3Config {
+    /// エンドポイント (例: https://s3.ap-northeast-1.amazonaws.com, http://minio:9000)
+    pub endpoint: Url,
+    pub bucket: String,
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+    /// パス形式 (`{endpoint}/{bucket}/{key}`) でアクセスするかどうか。
+    /// false の場合は仮想ホスト形式 (`{bucket}.{endpoint}/{key}`) でアクセスする。
+    pub path_style: bool,
+    /// 公開 URL。設定されている場合は署名付き URL の代わりにこの URL にリダイレクトする。
+    pub public_url: Option<Url>,
+    /// 署名付き URL の有効期限 (秒)
+    pub presign_expires: u64,
+}
+
+impl Debug for S3Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("S3Config")
+            .field("endpoint", &self.endpoint)
+            .field("bucket", &self.bucket)
+            .field("region", &self.region)
+            .field("path_style", &self.path_style)
+            .field("public_url", &self.public_url)
+            .field("presign_expires", &self.presign_expires)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct S3Storage {
+    config: S3Config,
+    client: reqwest::Client,
+}
+
+impl S3Storage {
+    pub fn new(config: S3Config) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn object_url(&self, key: &str) -> ServiceResult<Url> {
+        validate_key(key)?;
+        let endpoint = &self.config.endpoint;
+        if self.config.path_style {
+            endpoint
+                .join(&format!("{}/{}", self.config.bucket, key))
+                .map_err_unknown()
+        } else {
+            let host = endpoint
+                .host_str()
+                .ok_or(ServiceError::ise("S3 endpoint has no host"))?;
+            let mut url = endpoint.clone();
+            url.set_host(Some(&format!("{}.{}", self.config.bucket, host)))
+                .map_err_unknown()?;
+            url.set_path(key);
+            Ok(url)
+        }
+    }
+
+    fn scope(&self, now: &DateTime<Utc>) -> String {
+        format!(
+            "{}/{}/s3/aws4_request",
+            now.format("%Y%m%d"),
+            self.config.region
+        )
+    }
+
+    /// 正規リクエストに署名する。
+    /// `headers` のキーは小文字で、Host を含んでいること。
+    fn signature(
+        &self,
+        method: &Method,
+        url: &Url,
+        query: &BTreeMap<String, String>,
+        headers: &BTreeMap<String, String>,
+        payload_hash: &str,
+        now: &DateTime<Utc>,
+    ) -> String {
+        let canonical_query = canonical_query(query);
+        let canonical_headers = headers
+            .iter()
+            .map(|(k, v)| format!("{}:{}\n", k, v.trim()))
+            .collect::<String>();
+        let signed_headers = headers.keys().cloned().collect::<Vec<_>>().join(";");
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method.as_str(),
+            url.path(),
+            canonical_query,
+            canonical_headers,
+            signed_headers,
+            payload_hash
+        );
+
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            now.format("%Y%m%dT%H%M%SZ"),
+            self.scope(now),
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let date_key = hmac_sha256(
+            format!("AWS4{}", self.config.secret_key).as_bytes(),
+            now.format("%Y%m%d").to_string().as_bytes(),
+        );
+        let region_key = hmac_sha256(&date_key, self.config.region.as_bytes());
+        let service_key = hmac_sha256(&region_key, b"s3");
+        let signing_key = hmac_sha256(&service_key, b"aws4_request");
+        hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()))
+    }
+
+    /// 署名付き URL を生成する。
+    pub(crate) fn presign(&self, key: &str, now: &DateTime<Utc>) -> ServiceResult<Url> {
+        let mut url = self.object_url(key)?;
+        let mut query = BTreeMap::new();
+        query.insert(
+            "X-Amz-Algorithm".to_string(),
+            "AWS4-HMAC-SHA256".to_string(),
+        );
+        query.insert(
+            "X-Amz-Credential".to_string(),
+            format!("{}/{}", self.config.access_key, self.scope(now)),
+        );
+        query.insert(
+            "X-Amz-Date".to_string(),
+            now.format("%Y%m%dT%H%M%SZ").to_string(),
+        );
+        query.insert(
+            "X-Amz-Expires".to_string(),
+            self.config.presign_expires.to_string(),
+        );
+        query.insert("X-Amz-SignedHeaders".to_string(), "host".to_string());
+
+        let mut headers = BTreeMap::new();
+        headers.insert("host".to_string(), host_header(&url));
+
+        let signature = self.signature(&Method::GET, &url, &query, &headers, UNSIGNED_PAYLOAD, now);
+        url.set_query(Some(&format!(
+            "{}&X-Amz-Signature={}",
+            canonical_query(&query),
+            signature
+        )));
+        Ok(url)
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        key: &str,
+        query: &BTreeMap<String, String>,
+        body: S3Body<'_>,
+        content_type: Option<&str>,
+    ) -> ServiceResult<reqwest::Response> {
+        let mut url = self.object_url(key)?;
+        if !query.is_empty() {
+            url.set_query(Some(&canonical_query(query)));
+        }
+        let now = Utc::now();
+        let payload_hash = match &body {
+            S3Body::Empty => EMPTY_PAYLOAD_HASH.to_string(),
+            S3Body::Data(data) => hex::encode(Sha256::digest(data)),
+            S3Body::File { .. } => UNSIGNED_PAYLOAD.to_string(),
+        };
+
+        let mut headers = BTreeMap::new();
+        headers.insert("host".to_string(), host_header(&url));
+        headers.insert("x-amz-content-sha256".to_string(), payload_hash.clone());
+        headers.insert(
+            "x-amz-date".to_string(),
+            now.format("%Y%m%dT%H%M%SZ").to_string(),
+        );
+        if let Some(content_type) = content_type {
+            headers.insert("content-type".to_string(), content_type.to_string());
+        }
+
+        let signature = self.signature(&method, &url, query, &headers, &payload_hash, &now);
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.config.access_key,
+            self.scope(&now),
+            headers.keys().cloned().collect::<Vec<_>>().join(";"),
+            signature
+        );
+
+        let mut req = self.client.request(method, url);
+        for (name, value) in headers.iter().filter(|(k, _)| k.as_str() != "host") {
+            req = req.header(name, value);
+        }
+        req = req.header("authorization", authorization);
+        req = match body {
+            S3Body::Empty => req,
+            S3Body::Data(data) => req.body(data),
+            S3Body::File { path, offset, len } => {
+                let mut file = tokio::fs::File::open(path).await.map_err_unknown()?;
+                file.seek(SeekFrom::Start(offset)).await.map_err_unknown()?;
+                // S3 はチャンク転送を受け付けないので、長さを指定する
+                req.header(CONTENT_LENGTH, len)
+                    .body(reqwest::Body::wrap_stream(ReaderStream::new(
+                        file.take(len),
+                    )))
+            }
+        };
+        req.send().await.map_err_unknown()
+    }
+
+    /// マルチパートアップロードでファイルを保存する。失敗した場合はアップロードを中止する。
+    async fn put_multipart(
+        &self,
+        key: &str,
+        path: &Path,
+        size: u64,
+        content_type: &str,
+    ) -> ServiceResult<()> {
+        let mut query = BTreeMap::new();
+        query.insert("uploads".to_string(), "".to_string());
+        let res = self
+            .send(Method::POST, key, &query, S3Body::Empty, Some(content_type))
+            .await?;
+        let status = res.status();
+        let body = res.text().await.map_err_unknown()?;
+        let upload_id = match xml_text(&body, "UploadId") {
+            Some(upload_id) if status.is_success() => upload_id.to_string(),
+            _ => {
+                return Err(ServiceError::ise(format!(
+                    "failed to create multipart upload of {} to S3: {}",
+                    key, status
+                )));
+            }
+        };
+
+        let mut query = BTreeMap::new();
+        query.insert("uploadId".to_string(), upload_id);
+        let result = self.upload_parts(key, path, size, &query).await;
+        if result.is_err() {
+            match self
+                .send(Method::DELETE, key, &query, S3Body::Empty, None)
+                .await
+            {
+                Ok(res) if res.status().is_success() => {}
+                Ok(res) => warn!(
+                    "failed to abort multipart upload of {}: {}",
+                    key,
+                    res.status()
+                ),
+                Err(e) => warn!("failed to abort multipart upload of {}: {:?}", key, e),
+            }
+        }
+        result
+    }
+
+    /// マルチパートアップロードの各パートを送り、アップロードを完了する。
+    /// `query` には uploadId が含まれていること。
+    async fn upload_parts(
+        &self,
+        key: &str,
+        path: &Path,
+        size: u64,
+        query: &BTreeMap<String, String>,
+    ) -> ServiceResult<()> {
+        let mut etags = vec![];
+        for (i, (offset, len)) in multipart_ranges(size).into_iter().enumerate() {
+            let mut query = query.clone();
+            query.insert("partNumber".to_string(), (i + 1).to_string());
+            let res = self
+                .send(
+                    Method::PUT,
+                    key,
+                    &query,
+                    S3Body::File { path, offset, len },
+                    None,
+                )
+                .await?;
+            let etag = res
+                .headers()
+                .get("etag")
+                .and_then(|e| e.to_str().ok())
+                .map(|e| e.to_string());
+            match etag {
+                Some(etag) if res.status().is_success() => etags.push(etag),
+                _ => {
+                    return Err(ServiceError::ise(format!(
+                        "failed to upload part {} of {} to S3: {}",
+                        i + 1,
+                        key,
+                        res.status()
+                    )));
+                }
+            }
+        }
+
+        let res = self
+            .send(
+                Method::POST,
+                key,
+                query,
+                S3Body::Data(complete_multipart_body(&etags).into_bytes()),
+                Some("application/xml"),
+            )
+            .await?;
+        // 完了のリクエストは、失敗しても 200 でエラーを返すことがある
+        let status = res.status();
+        let body = res.text().await.map_err_unknown()?;
+        if !status.is_success() || xml_text(&body, "Code").is_some() {
+            return Err(ServiceError::ise(format!(
+                "failed to complete multipart upload of {} to S3: {} {}",
+                key, status, body
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UploadStorage for S3Storage {
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> ServiceResult<()> {
+        let res = self
+            .send(
+                Method::PUT,
+                key,
+                &BTreeMap::new(),
+                S3Body::Data(data),
+                Some(content_type),
+            )
+            .await?;
+        if !res.status().is_success() {
+            return Err(ServiceError::ise(format!(
+                "failed to put {} to S3: {}",
+                key,
+                res.status()
+            )));
+        }
+        Ok(())
+    }
+
+    async fn put_file(&self, key: &str, path: &Path, content_type: &str) -> ServiceResult<()> {
+        let size = tokio::fs::metadata(path).await.map_err_unknown()?.len();
+        if size > MULTIPART_THRESHOLD {
+            return self.put_multipart(key, path, size, content_type).await;
+        }
+
+        let body = S3Body::File {
+            path,
+            offset: 0,
+            len: size,
+        };
+        let res = self
+            .send(Method::PUT, key, &BTreeMap::new(), body, Some(content_type))
+            .await?;
+        if !res.status().is_success() {
+            return Err(ServiceError::ise(format!(
+                "failed to put {} to S3: {}",
+                key,
+                res.status()
+            )));
+        }
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> ServiceResult<Option<Vec<u8>>> {
+        let res = self
+            .send(Method::GET, key, &BTreeMap::new(), S3Body::Empty, None)
+            .await?;
+        match res.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            s if s.is_success() => Ok(Some(res.bytes().await.map_err_unknown()?.to_vec())),
+            s => Err(ServiceError::ise(format!(
+                "failed to get {} from S3: {}",
+                key, s
+            ))),
+        }
+    }
+
+    async fn open(&self, key: &str) -> ServiceResult<Option<StorageReader>> {
+        let res = self
+            .send(Method::GET, key, &BTreeMap::new(), S3Body::Empty, None)
+            .await?;
+        match res.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            s if s.is_success() => {
+                let size = res.content_length().ok_or(ServiceError::ise(format!(
+                    "S3 did not return the size of {}",
+                    key
+                )))?;
+                let stream = res.bytes_stream().map_err(std::io::Error::other);
+                Ok(Some(StorageReader {
+                    size,
+                    reader: Box::pin(StreamReader::new(stream)),
+                }))
+            }
+            s => Err(ServiceError::ise(format!(
+                "failed to get {} from S3: {}",
+                key, s
+            ))),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> ServiceResult<()> {
+        let res = self
+            .send(Method::DELETE, key, &BTreeMap::new(), S3Body::Empty, None)
+            .await?;
+        match res.status() {
+            StatusCode::NOT_FOUND => Ok(()),
+            s if s.is_success() => Ok(()),
+            s => Err(ServiceError::ise(format!(
+                "failed to delete {} from S3: {}",
+                key, s
+            ))),
+        }
+    }
+
+    async fn serve(&self, key: &str) -> ServiceResult<StorageServe> {
+        let url = match &self.config.public_url {
+            Some(public_url) => {
+                validate_key(key)?;
+                public_url.join(key).map_err_unknown()?
+            }
+            None => self.presign(key, &Utc::now())?,
+        };
+        Ok(StorageServe::Redirect(url))
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC can take key of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn canonical_query(query: &BTreeMap<String, String>) -> String {
+    query
+        .iter()
+        .map(|(k, v)| {
+            format!(
+                "{}={}",
+                utf8_percent_encode(k, SIGV4_ENCODE_SET),
+                utf8_percent_encode(v, SIGV4_ENCODE_SET)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn host_header(url: &Url) -> String {
+    url[Position::BeforeHost..Position::AfterPort].to_string()
+}
+
+/// マルチパートアップロードの各パートの (開始位置, 長さ)
+pub(crate) fn multipart_ranges(size: u64) -> Vec<(u64, u64)> {
+    (0..size)
+        .step_by(MULTIPART_PART_SIZE as usize)
+        .map(|offset| (offset, MULTIPART_PART_SIZE.min(size - offset)))
+        .collect()
+}
+
+/// CompleteMultipartUpload の本文
+pub(crate) fn complete_multipart_body(etags: &[String]) -> String {
+    let parts = etags
+        .iter()
+        .enumerate()
+        .map(|(i, etag)| {
+            format!(
+                "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                i + 1,
+                etag.replace('&', "&amp;")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;")
+            )
+        })
+        .collect::<String>();
+    format!(
+        "<CompleteMultipartUpload>{}</CompleteMultipartUpload>",
+        parts
+    )
+}
+
+/// XML から最初の `tag` 要素のテキストを取り出す。
+pub(crate) fn xml_text<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    Some(&xml[start..end])
+}
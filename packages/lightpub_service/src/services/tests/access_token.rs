@@ -0,0 +1,95 @@
+use crate::services::{
+    access_token::{
+        PERSONAL_ACCESS_TOKEN_PREFIX, authenticate_personal_access_token,
+        create_personal_access_token, get_personal_access_tokens, revoke_personal_access_token,
+    },
+    auth::logout_all,
+    oauth::OAuthScopes,
+    tests::common::test_setup,
+};
+
+use super::auth::register_user_for_test;
+
+#[tokio::test]
+async fn test_personal_access_token() {
+    let st = test_setup().await;
+    let app = &st.app;
+
+    let user1 = register_user_for_test(&st, "user1").await;
+    let user2 = register_user_for_test(&st, "user2").await;
+
+    let scopes = OAuthScopes::parse("read write:notes").unwrap();
+
+    create_personal_access_token(app.conn(), user1, "  ", &scopes)
+        .await
+        .unwrap_err();
+
+    let (model, token) = create_personal_access_token(app.conn(), user1, "bot", &scopes)
+        .await
+        .unwrap();
+    assert!(token.starts_with(PERSONAL_ACCESS_TOKEN_PREFIX));
+    assert_eq!(model.name, "bot");
+    assert!(model.last_used_at.is_none());
+
+    let authed = authenticate_personal_access_token(app.conn(), &token)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(authed.user_id, user1);
+    assert_eq!(authed.token_id, model.id);
+    assert!(authed.scopes.allows("write:notes"));
+    assert!(!authed.scopes.allows("write:follows"));
+
+    // 使用すると最終使用日時が記録される
+    let tokens = get_personal_access_tokens(&app.maybe_conn(), user1)
+        .await
+        .unwrap();
+    assert_eq!(tokens.len(), 1);
+    assert!(tokens[0].last_used_at.is_some());
+
+    assert!(
+        authenticate_personal_access_token(app.conn(), &format!("{token}x"))
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    // 他のユーザーのトークンは無効にできない
+    revoke_personal_access_token(app.conn(), user2, model.id)
+        .await
+        .unwrap_err();
+
+    revoke_personal_access_token(app.conn(), user1, model.id)
+        .await
+        .unwrap();
+    assert!(
+        authenticate_personal_access_token(app.conn(), &token)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        get_personal_access_tokens(&app.maybe_conn(), user1)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    // すべての端末からログアウトするとトークンも無効になる
+    let (_, token) = create_personal_access_token(app.conn(), user2, "bot", &scopes)
+        .await
+        .unwrap();
+    logout_all(app.conn(), user2).await.unwrap();
+    assert!(
+        authenticate_personal_access_token(app.conn(), &token)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        get_personal_access_tokens(&app.maybe_conn(), user2)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
@@ -1,6 +1,7 @@
 use crate::services::{
     auth::{check_password_user, register_user},
     id::UserID,
+    user::{UserAccountStateModel, set_user_account_state},
 };
 
 use super::common::{TestState, test_setup};
@@ -47,6 +48,42 @@ async fn test_user_login_pass_wrong() {
     assert!(login.is_none());
 }
 
+#[tokio::test]
+async fn test_user_login_suspended() {
+    let st = test_setup().await;
+
+    let reg = register_user(st.app.conn(), "testuser", "testnick", "testpass")
+        .await
+        .unwrap();
+
+    set_user_account_state(
+        st.app.conn(),
+        &st.app.rconn(),
+        reg.user_id,
+        UserAccountStateModel::Suspended,
+    )
+    .await
+    .unwrap();
+
+    check_password_user(st.app.conn(), "testuser", "testpass")
+        .await
+        .unwrap_err();
+
+    set_user_account_state(
+        st.app.conn(),
+        &st.app.rconn(),
+        reg.user_id,
+        UserAccountStateModel::Active,
+    )
+    .await
+    .unwrap();
+
+    let login = check_password_user(st.app.conn(), "testuser", "testpass")
+        .await
+        .unwrap();
+    assert!(login.is_some());
+}
+
 #[tokio::test]
 async fn test_user_login_not_exists() {
     let st = test_setup().await;
@@ -16,7 +16,9 @@ use crate::{
     ServiceState, ServiceStateBase,
     services::{
         db::{Conn, RedisConn},
+        media_cache::MediaCacheConfig,
         queue::QConn,
+        storage::InMemoryStorage,
     },
 };
 
@@ -108,6 +110,8 @@ pub async fn test_setup() -> TestState {
         client,
         None, // disabled fulltext search
         None, // disabled web push
+        Arc::new(InMemoryStorage::default()),
+        MediaCacheConfig::default(),
     );
     let fed = FederationConfig::builder()
         .domain(MY_DOMAIN)
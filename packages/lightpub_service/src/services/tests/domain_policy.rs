@@ -0,0 +1,80 @@
+use crate::services::{
+    domain_policy::{
+        DomainPolicyActionModel, add_domain_policy, get_domain_policy, list_domain_policies,
+        remove_domain_policy,
+    },
+    tests::common::test_setup,
+};
+
+#[tokio::test]
+async fn test_domain_policy_add_and_remove() {
+    let st = test_setup().await;
+    let conn = st.app.maybe_conn();
+
+    let reject = add_domain_policy(
+        &conn,
+        "Bad.Example.com",
+        DomainPolicyActionModel::Reject,
+        Some("spam".to_string()),
+    )
+    .await
+    .unwrap();
+    assert_eq!(reject.domain, "bad.example.com");
+
+    add_domain_policy(
+        &conn,
+        "example.org",
+        DomainPolicyActionModel::RejectMedia,
+        None,
+    )
+    .await
+    .unwrap();
+
+    let policies = list_domain_policies(&conn).await.unwrap();
+    assert_eq!(policies.len(), 2);
+
+    // サブドメインにも適用される
+    let policy = get_domain_policy(&conn, "sub.bad.example.com")
+        .await
+        .unwrap();
+    assert!(policy.rejects_activities());
+    assert!(policy.rejects_media());
+
+    let policy = get_domain_policy(&conn, "example.com").await.unwrap();
+    assert!(!policy.rejects_activities());
+    assert!(!policy.rejects_media());
+
+    let policy = get_domain_policy(&conn, "media.example.org").await.unwrap();
+    assert!(!policy.rejects_activities());
+    assert!(policy.rejects_media());
+
+    remove_domain_policy(&conn, reject.id).await.unwrap();
+    let policy = get_domain_policy(&conn, "bad.example.com").await.unwrap();
+    assert!(!policy.rejects_activities());
+
+    remove_domain_policy(&conn, reject.id).await.unwrap_err();
+}
+
+#[tokio::test]
+async fn test_domain_policy_duplicate() {
+    let st = test_setup().await;
+    let conn = st.app.maybe_conn();
+
+    let first = add_domain_policy(&conn, "example.com", DomainPolicyActionModel::Silence, None)
+        .await
+        .unwrap();
+    let second = add_domain_policy(
+        &conn,
+        "example.com",
+        DomainPolicyActionModel::Silence,
+        Some("updated".to_string()),
+    )
+    .await
+    .unwrap();
+    assert_eq!(first.id, second.id);
+    assert_eq!(second.reason.as_deref(), Some("updated"));
+
+    add_domain_policy(&conn, "not a domain", DomainPolicyActionModel::Reject, None)
+        .await
+        .unwrap_err();
+}
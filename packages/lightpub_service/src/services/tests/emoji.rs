@@ -0,0 +1,107 @@
+use std::str::FromStr;
+
+use url::Url;
+
+use crate::services::{
+    emoji::{EmojiModel, extract_shortcodes, replace_emoji_shortcodes, validate_shortcode},
+    id::UploadID,
+    note::{ApubNoteModel, renderer::replace_emojis},
+};
+use crate::utils::sanitize::CleanString;
+
+#[test]
+fn test_extract_shortcodes() {
+    assert_eq!(
+        extract_shortcodes("hello :blobcat: and :blob_fox::blobcat: 12:30:00 :not valid:"),
+        vec!["blobcat", "blob_fox", "30"]
+    );
+    assert!(extract_shortcodes("no emoji here").is_empty());
+
+    assert!(validate_shortcode("blob_cat2"));
+    assert!(!validate_shortcode(""));
+    assert!(!validate_shortcode("blob-cat"));
+    assert!(!validate_shortcode(&"a".repeat(65)));
+}
+
+#[test]
+fn test_replace_emoji_shortcodes() {
+    let upload_id = UploadID::from_str("0195e8a4-3f5e-7c2a-9d1b-2b6f0c3e4a5d").unwrap();
+    let emojis = vec![EmojiModel {
+        shortcode: "blobcat".to_string(),
+        domain: None,
+        upload_id,
+        mime_type: "image/png".to_string(),
+        url: None,
+    }];
+
+    let base_url = Url::parse("https://example.com").unwrap();
+    let html = r#"<p title=":blobcat:">hi :blobcat: :unknown:</p>"#;
+    assert_eq!(
+        replace_emoji_shortcodes(html, &emojis, &base_url),
+        format!(
+            r#"<p title=":blobcat:">hi <img src="{}upload/{}" alt=":blobcat:" title=":blobcat:" class="emoji" /> :unknown:</p>"#,
+            base_url, upload_id
+        )
+    );
+}
+
+#[test]
+fn test_replace_emojis_resanitizes() {
+    let upload_id = UploadID::from_str("0195e8a4-3f5e-7c2a-9d1b-2b6f0c3e4a5d").unwrap();
+    let emojis = vec![EmojiModel {
+        shortcode: "blobcat".to_string(),
+        domain: None,
+        upload_id,
+        mime_type: "image/png".to_string(),
+        url: None,
+    }];
+    let base_url = Url::parse("https://example.com").unwrap();
+
+    // 属性値の中の `>` でタグの終わりを誤認しても、挿入した画像がタグとして扱われない
+    let html = CleanString::clean(
+        r#"<p><span title="> :blobcat: <img src=x onerror=alert(1)>">hi :blobcat:</span></p>"#,
+    );
+    let replaced = replace_emojis(html, &emojis, &base_url);
+    let replaced = replaced.inner();
+    assert!(!replaced.contains("onerror"), "{replaced}");
+    assert!(!replaced.contains(r#"src="x""#), "{replaced}");
+    assert!(
+        replaced.contains(&format!(
+            r#"<img src="{base_url}upload/{upload_id}" alt=":blobcat:" title=":blobcat:" class="emoji">"#
+        )),
+        "{replaced}"
+    );
+}
+
+#[test]
+fn test_emoji_tag_deserialize() {
+    let json = r#"{
+        "id": "https://remote.example.com/notes/1",
+        "type": "Note",
+        "attributedTo": "https://remote.example.com/users/alice",
+        "content": "<p>:blobcat:</p>",
+        "published": "2025-04-01T00:00:00Z",
+        "to": ["https://www.w3.org/ns/activitystreams#Public"],
+        "cc": [],
+        "sensitive": false,
+        "tag": [{
+            "id": "https://remote.example.com/emojis/1",
+            "type": "Emoji",
+            "name": ":blobcat:",
+            "updated": "2025-04-01T00:00:00Z",
+            "icon": {
+                "type": "Image",
+                "mediaType": "image/png",
+                "url": "https://remote.example.com/files/blobcat.png"
+            }
+        }]
+    }"#;
+    let note: ApubNoteModel = serde_json::from_str(json).unwrap();
+    let json = serde_json::to_value(&note).unwrap();
+    assert_eq!(json["tag"][0]["type"], "Emoji");
+    assert_eq!(json["tag"][0]["name"], ":blobcat:");
+    assert_eq!(
+        Url::parse(json["tag"][0]["icon"]["url"].as_str().unwrap()).unwrap(),
+        Url::parse("https://remote.example.com/files/blobcat.png").unwrap()
+    );
+}
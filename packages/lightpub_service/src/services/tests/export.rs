@@ -0,0 +1,127 @@
+use std::{io::Read, path::PathBuf};
+
+use flate2::read::GzDecoder;
+
+use crate::services::{
+    UpsertOperation,
+    export::{
+        UserExportStatusModel, get_user_export_file, get_user_exports, process_user_export,
+        request_user_export,
+    },
+    id::UploadID,
+    note::{ContentType, NoteUpload, PostCreateOptionsBuilder, VisibilityModel},
+    tests::common::test_setup,
+    upload::UploadMetadata,
+};
+
+use super::{auth::register_user_for_test, note::create_note_for_test, user::user_follow_for_test};
+
+#[tokio::test]
+async fn test_user_export() {
+    let st = test_setup().await;
+    let app = &st.app;
+
+    let user1 = register_user_for_test(&st, "user1").await;
+    let user2 = register_user_for_test(&st, "user2").await;
+    user_follow_for_test(&st, user1, user2, true).await;
+
+    let opts = PostCreateOptionsBuilder::default().build().unwrap();
+    create_note_for_test(
+        &st,
+        user1,
+        "exported note",
+        ContentType::Plain,
+        VisibilityModel::Public,
+        &opts,
+    )
+    .await
+    .unwrap();
+
+    // 添付ファイルはストレージから読み込んでアーカイブに含める
+    let upload_id = UploadID::new_random();
+    let upload_filename = format!("{upload_id}.txt");
+    let upload_data = vec![b'a'; 100_000];
+    app.storage()
+        .put(&upload_filename, upload_data.clone(), "text/plain")
+        .await
+        .unwrap();
+    let opts = PostCreateOptionsBuilder::default()
+        .uploads(UpsertOperation::Set(vec![NoteUpload::File(
+            upload_id,
+            PathBuf::from(&upload_filename),
+            "text/plain".to_string(),
+            UploadMetadata::default(),
+        )]))
+        .build()
+        .unwrap();
+    create_note_for_test(
+        &st,
+        user1,
+        "note with file",
+        ContentType::Plain,
+        VisibilityModel::Public,
+        &opts,
+    )
+    .await
+    .unwrap();
+
+    let export_id = request_user_export(app.conn(), app.qconn(), user1)
+        .await
+        .unwrap();
+
+    // 処理中のエクスポートがある間は新しく作成できない
+    request_user_export(app.conn(), app.qconn(), user1)
+        .await
+        .unwrap_err();
+    get_user_export_file(&app.maybe_conn(), user1, export_id)
+        .await
+        .unwrap_err();
+
+    let data = app.fed().to_request_data();
+    process_user_export(
+        app.conn(),
+        &app.rconn(),
+        export_id,
+        &app.my_domain(),
+        app.base_url(),
+        &data,
+    )
+    .await
+    .unwrap();
+
+    let exports = get_user_exports(&app.maybe_conn(), user1).await.unwrap();
+    assert_eq!(exports.len(), 1);
+    assert_eq!(exports[0].status, UserExportStatusModel::Completed);
+
+    // 他人のエクスポートは取得できない
+    get_user_export_file(&app.maybe_conn(), user2, export_id)
+        .await
+        .unwrap_err();
+
+    let path = get_user_export_file(&app.maybe_conn(), user1, export_id)
+        .await
+        .unwrap();
+    let file = std::fs::File::open(&path).unwrap();
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    let mut names = vec![];
+    let mut outbox = String::new();
+    let mut media = vec![];
+    for entry in archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let name = entry.path().unwrap().to_string_lossy().to_string();
+        if name == "outbox.json" {
+            entry.read_to_string(&mut outbox).unwrap();
+        }
+        if name == format!("media/{upload_filename}") {
+            entry.read_to_end(&mut media).unwrap();
+        }
+        names.push(name);
+    }
+    assert!(names.contains(&"actor.json".to_string()));
+    assert!(names.contains(&"following_accounts.csv".to_string()));
+    assert!(names.contains(&"blocked_accounts.csv".to_string()));
+    assert!(outbox.contains("exported note"));
+    assert_eq!(media, upload_data);
+
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,132 @@
+use crate::services::{
+    follow::{FollowState, is_following},
+    import::{
+        UserImportKindModel, UserImportRowStatusModel, UserImportStatusModel, get_user_import,
+        get_user_imports, parse_account_csv, process_user_import, request_user_import,
+    },
+    tests::common::test_setup,
+    user::is_blocking_user,
+};
+
+use super::auth::register_user_for_test;
+
+#[test]
+fn test_parse_account_csv() {
+    let csv = "Account address,Show boosts,Notify on new posts,Languages\n\
+               alice@example.com,true,false,\n\
+               \n\
+               @bob@example.org,true,false,\n\
+               alice@example.com,true,false,\n";
+    assert_eq!(
+        parse_account_csv(csv),
+        vec!["@alice@example.com", "@bob@example.org"]
+    );
+}
+
+#[tokio::test]
+async fn test_user_import() {
+    let st = test_setup().await;
+    let app = &st.app;
+
+    let user1 = register_user_for_test(&st, "user1").await;
+    let user2 = register_user_for_test(&st, "user2").await;
+    let user3 = register_user_for_test(&st, "user3").await;
+
+    let data = app.fed().to_request_data();
+    let my_domain = app.my_domain();
+
+    let follows = format!(
+        "Account address,Show boosts,Notify on new posts,Languages\n\
+         user2@{my_domain},true,false,\n\
+         @bad@spec@ifier,true,false,\n"
+    );
+    let import_id = request_user_import(
+        app.conn(),
+        app.qconn(),
+        user1,
+        UserImportKindModel::Follows,
+        &follows,
+    )
+    .await
+    .unwrap();
+
+    // 処理中のインポートがある間は新しく作成できない
+    request_user_import(
+        app.conn(),
+        app.qconn(),
+        user1,
+        UserImportKindModel::Blocks,
+        "user3",
+    )
+    .await
+    .unwrap_err();
+
+    process_user_import(
+        app.conn(),
+        &app.rconn(),
+        app.qconn(),
+        app.wp(),
+        import_id,
+        &my_domain,
+        app.base_url(),
+        &data,
+    )
+    .await
+    .unwrap();
+
+    let import = get_user_import(&app.maybe_conn(), user1, import_id)
+        .await
+        .unwrap();
+    assert_eq!(import.import.status, UserImportStatusModel::Completed);
+    assert_eq!(import.import.total_count, 2);
+    assert_eq!(import.import.succeeded_count, 1);
+    assert_eq!(import.import.failed_count, 1);
+    assert_eq!(import.rows[0].status, UserImportRowStatusModel::Succeeded);
+    assert_eq!(import.rows[1].status, UserImportRowStatusModel::Failed);
+    assert!(import.rows[1].message.is_some());
+    assert_eq!(
+        is_following(&app.maybe_conn(), user1, user2).await.unwrap(),
+        FollowState::Yes
+    );
+
+    // 他人のインポートは取得できない
+    get_user_import(&app.maybe_conn(), user2, import_id)
+        .await
+        .unwrap_err();
+
+    let import_id = request_user_import(
+        app.conn(),
+        app.qconn(),
+        user1,
+        UserImportKindModel::Blocks,
+        "user3",
+    )
+    .await
+    .unwrap();
+    process_user_import(
+        app.conn(),
+        &app.rconn(),
+        app.qconn(),
+        app.wp(),
+        import_id,
+        &my_domain,
+        app.base_url(),
+        &data,
+    )
+    .await
+    .unwrap();
+
+    assert!(
+        is_blocking_user(&app.maybe_conn(), user1, user3)
+            .await
+            .unwrap()
+    );
+
+    let imports = get_user_imports(&app.maybe_conn(), user1).await.unwrap();
+    assert_eq!(imports.len(), 2);
+    assert!(
+        imports
+            .iter()
+            .all(|i| i.status == UserImportStatusModel::Completed)
+    );
+}
@@ -0,0 +1,108 @@
+use crate::services::{
+    note::{
+        ContentType, PostCreateOptions, VisibilityModel, get_liked_notes, note_like_add,
+        note_like_remove, note_reaction_add,
+    },
+    tests::{
+        common::{MY_DOMAIN, test_setup},
+        note::create_note_for_test,
+    },
+};
+
+use super::auth::register_user_for_test;
+
+#[tokio::test]
+async fn test_get_liked_notes() {
+    let st = test_setup().await;
+    let app = &st.app;
+    let base_url = app.base_url();
+
+    let user1 = register_user_for_test(&st, "user1").await;
+    let user2 = register_user_for_test(&st, "user2").await;
+
+    let mut note_ids = vec![];
+    for content in ["note1", "note2", "note3"] {
+        let note_id = create_note_for_test(
+            &st,
+            user1,
+            content,
+            ContentType::Plain,
+            VisibilityModel::Public,
+            &PostCreateOptions::default(),
+        )
+        .await
+        .unwrap();
+        note_ids.push(note_id);
+    }
+
+    for (note_id, is_private) in [
+        (note_ids[0], false),
+        (note_ids[1], true),
+        (note_ids[2], false),
+    ] {
+        note_like_add(
+            app.conn(),
+            app.qconn(),
+            user2,
+            note_id,
+            is_private,
+            None,
+            MY_DOMAIN,
+            base_url,
+        )
+        .await
+        .unwrap();
+    }
+    // リアクションはいいねに含まない
+    note_reaction_add(
+        app.conn(),
+        app.qconn(),
+        user1,
+        note_ids[1],
+        "👍",
+        MY_DOMAIN,
+        base_url,
+    )
+    .await
+    .unwrap();
+
+    let liked = get_liked_notes(&app.maybe_conn(), &app.rconn(), user2, false, 1, None)
+        .await
+        .unwrap();
+    assert_eq!(liked.len(), 1);
+    assert_eq!(liked[0].note.basic.id, note_ids[2]);
+
+    let liked = get_liked_notes(
+        &app.maybe_conn(),
+        &app.rconn(),
+        user2,
+        false,
+        10,
+        Some(liked[0].like_id),
+    )
+    .await
+    .unwrap();
+    assert_eq!(liked.len(), 1);
+    assert_eq!(liked[0].note.basic.id, note_ids[0]);
+
+    let bookmarked = get_liked_notes(&app.maybe_conn(), &app.rconn(), user2, true, 10, None)
+        .await
+        .unwrap();
+    assert_eq!(bookmarked.len(), 1);
+    assert_eq!(bookmarked[0].note.basic.id, note_ids[1]);
+    assert_eq!(bookmarked[0].note.details.bookmarked, Some(true));
+
+    let liked = get_liked_notes(&app.maybe_conn(), &app.rconn(), user1, false, 10, None)
+        .await
+        .unwrap();
+    assert!(liked.is_empty());
+
+    note_like_remove(app.conn(), app.qconn(), user2, note_ids[0], false, base_url)
+        .await
+        .unwrap();
+    let liked = get_liked_notes(&app.maybe_conn(), &app.rconn(), user2, false, 10, None)
+        .await
+        .unwrap();
+    assert_eq!(liked.len(), 1);
+    assert_eq!(liked[0].note.basic.id, note_ids[2]);
+}
@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use chrono::{TimeDelta, Utc};
+use sea_orm::{ActiveModelTrait, EntityTrait, Set};
+
+use crate::services::{
+    db::MaybeTxConn,
+    id::{Identifier, UploadID},
+    media_cache::{MediaCacheConfig, evict_remote_media_cache, save_remote_media_cache},
+    storage::{InMemoryStorage, StorageObject, StorageServe},
+    tests::common::test_setup,
+    upload::{GetUpload, UploadVariant, delete_uploads, get_upload},
+};
+
+async fn create_remote_upload(conn: &MaybeTxConn, mime_type: &str) -> UploadID {
+    let upload_id = UploadID::new_random();
+    entity::upload::ActiveModel {
+        id: Set(upload_id.as_db()),
+        url: Set(Some(format!(
+            "https://remote.example.com/media/{}",
+            upload_id
+        ))),
+        mime_type: Set(mime_type.to_string()),
+        ..Default::default()
+    }
+    .insert(conn)
+    .await
+    .unwrap();
+    upload_id
+}
+
+async fn set_last_accessed_at(conn: &MaybeTxConn, upload_id: &UploadID, ago: TimeDelta) {
+    entity::remote_media_cache::ActiveModel {
+        upload_id: Set(upload_id.as_db()),
+        last_accessed_at: Set(Utc::now().naive_utc() - ago),
+        ..Default::default()
+    }
+    .update(conn)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn test_evict_remote_media_cache() {
+    let st = test_setup().await;
+    let conn = st.app.maybe_conn();
+    let memory = Arc::new(InMemoryStorage::default());
+    let storage: StorageObject = memory.clone();
+
+    let mut upload_ids = vec![];
+    for hours in [3, 2, 1] {
+        let upload_id = create_remote_upload(&conn, "audio/mpeg").await;
+        save_remote_media_cache(&conn, &storage, &upload_id, "audio/mpeg", vec![0; 100])
+            .await
+            .unwrap();
+        set_last_accessed_at(&conn, &upload_id, TimeDelta::hours(hours)).await;
+        upload_ids.push(upload_id);
+    }
+    assert_eq!(memory.len(), 3);
+
+    // 上限を超えた分は最後にアクセスされたのが古いものから削除する
+    let config = MediaCacheConfig {
+        enabled: true,
+        quota: 250,
+        ttl: None,
+        eager: false,
+    };
+    let evicted = evict_remote_media_cache(&conn, &storage, &config)
+        .await
+        .unwrap();
+    assert_eq!(evicted, 1);
+    assert_eq!(memory.len(), 2);
+    assert!(
+        entity::remote_media_cache::Entity::find_by_id(upload_ids[0].as_db())
+            .one(&conn)
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    // 期限切れのものは上限に関わらず削除する
+    let config = MediaCacheConfig {
+        ttl: Some(TimeDelta::minutes(90)),
+        ..config
+    };
+    let evicted = evict_remote_media_cache(&conn, &storage, &config)
+        .await
+        .unwrap();
+    assert_eq!(evicted, 1);
+    assert_eq!(memory.len(), 1);
+    assert!(
+        entity::remote_media_cache::Entity::find_by_id(upload_ids[2].as_db())
+            .one(&conn)
+            .await
+            .unwrap()
+            .is_some()
+    );
+
+    // キャッシュされていても、リモートのアップロードを削除すればファイルも削除対象になる
+    let keys = delete_uploads(&conn, &upload_ids).await.unwrap();
+    assert_eq!(keys.len(), 1);
+}
+
+#[tokio::test]
+async fn test_get_upload_from_remote_media_cache() {
+    let st = test_setup().await;
+    let conn = st.app.maybe_conn();
+    let storage: StorageObject = Arc::new(InMemoryStorage::default());
+
+    let file = tempfile::Builder::new().suffix(".png").tempfile().unwrap();
+    image::RgbImage::new(2000, 1000).save(file.path()).unwrap();
+    let data = std::fs::read(file.path()).unwrap();
+
+    let upload_id = create_remote_upload(&conn, "image/png").await;
+    let cache = save_remote_media_cache(&conn, &storage, &upload_id, "image/png", data)
+        .await
+        .unwrap();
+    assert!(cache.has_variants);
+    assert_eq!(cache.filename, format!("{}.png", upload_id));
+
+    let config = MediaCacheConfig {
+        enabled: true,
+        ..Default::default()
+    };
+    let upload = get_upload(
+        &conn,
+        &storage,
+        &config,
+        upload_id,
+        st.app.proxy_client(),
+        Some(UploadVariant::Thumbnail),
+        None,
+    )
+    .await
+    .unwrap();
+    match upload {
+        GetUpload::Stored {
+            serve: StorageServe::Data(data),
+            mime_type,
+        } => {
+            assert_eq!(mime_type, "image/webp");
+            let image = image::load_from_memory(&data).unwrap();
+            assert_eq!((image.width(), image.height()), (400, 200));
+        }
+        other => panic!("unexpected upload: {:?}", other),
+    }
+}
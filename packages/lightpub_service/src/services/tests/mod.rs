@@ -1,5 +1,21 @@
+pub mod access_token;
 pub mod auth;
 pub mod common;
+pub mod domain_policy;
+pub mod emoji;
+pub mod export;
+pub mod import;
+pub mod like;
+pub mod media_cache;
 pub mod note;
+pub mod oauth;
+pub mod poll;
+pub mod quote;
+pub mod reaction;
+pub mod report;
+pub mod storage;
 pub mod timeline;
+pub mod two_factor;
+pub mod upload;
 pub mod user;
+pub mod webauthn;
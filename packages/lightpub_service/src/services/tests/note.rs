@@ -660,3 +660,53 @@ async fn test_user_notes_mentioned_viewer() {
     assert_eq!(notes[1].basic.id, fix.note_unlisted);
     assert_eq!(notes[2].basic.id, fix.note_public);
 }
+
+#[tokio::test]
+async fn test_create_note_with_summary() {
+    let st = test_setup().await;
+
+    let user_id = register_sample_user(&st).await;
+
+    // 空の CW は CW なしとして扱う
+    for (summary, expected) in [("spoiler", Some("spoiler")), ("  ", None)] {
+        let note_id = create_note_for_test(
+            &st,
+            user_id,
+            "content",
+            ContentType::Plain,
+            VisibilityModel::Public,
+            &PostCreateOptionsBuilder::default()
+                .summary(UpsertOperation::Set(Some(summary.to_string())))
+                .build()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+        let note = get_note_by_id_visibility_check(
+            &st.app.maybe_conn(),
+            &st.app.rconn(),
+            note_id,
+            None,
+            false,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(note.basic.summary.as_deref(), expected);
+    }
+
+    create_note_for_test(
+        &st,
+        user_id,
+        "content",
+        ContentType::Plain,
+        VisibilityModel::Public,
+        &PostCreateOptionsBuilder::default()
+            .summary(UpsertOperation::Set(Some("a".repeat(1001))))
+            .build()
+            .unwrap(),
+    )
+    .await
+    .unwrap_err();
+}
@@ -0,0 +1,290 @@
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use sha2::{Digest, Sha256};
+
+use crate::services::{
+    auth::logout_all,
+    oauth::{
+        OAuthScopes, PkceChallenge, authenticate_oauth_token, create_authorization_code,
+        create_oauth_app, exchange_authorization_code, refresh_oauth_token, revoke_oauth_token,
+        validate_authorization_request,
+    },
+    tests::common::test_setup,
+    user::delete_local_user,
+};
+
+use super::auth::register_user_for_test;
+
+const REDIRECT_URI: &str = "https://client.example.net/callback";
+const CODE_VERIFIER: &str = "dBjftJeZ4CVP-mJ92K9UpyaXnLrAm_ZmMH8mL0uHz8bfdMj5";
+
+#[test]
+fn test_oauth_scopes() {
+    let scopes = OAuthScopes::parse("read write:statuses").unwrap();
+    assert!(scopes.allows("read"));
+    assert!(scopes.allows("read:accounts"));
+    assert!(scopes.allows("write:statuses"));
+    assert!(!scopes.allows("write"));
+    assert!(!scopes.allows("write:follows"));
+    // Mastodon のスコープは対応するスコープとして扱う
+    assert!(scopes.allows("write:notes"));
+
+    let scopes = OAuthScopes::parse("follow notifications").unwrap();
+    assert!(scopes.allows("write:follows"));
+    assert!(scopes.allows("read:notifications"));
+    assert!(scopes.allows("write:notifications"));
+    assert!(!scopes.allows("write:notes"));
+
+    assert_eq!(OAuthScopes::parse("").unwrap().to_string(), "read");
+    assert!(OAuthScopes::parse("read admin").is_err());
+}
+
+#[tokio::test]
+async fn test_oauth_authorization_code_flow() {
+    let st = test_setup().await;
+    let app = &st.app;
+
+    let user = register_user_for_test(&st, "user1").await;
+
+    let client = create_oauth_app(
+        app.conn(),
+        "test client",
+        &[REDIRECT_URI],
+        &OAuthScopes::parse("read write").unwrap(),
+        None,
+    )
+    .await
+    .unwrap();
+
+    // 登録されていない redirect_uri やスコープは拒否する
+    validate_authorization_request(
+        app.conn(),
+        &client.client_id,
+        "https://evil.example.net/callback",
+        None,
+    )
+    .await
+    .unwrap_err();
+    validate_authorization_request(app.conn(), &client.client_id, REDIRECT_URI, Some("push"))
+        .await
+        .unwrap_err();
+
+    let (client, scopes) =
+        validate_authorization_request(app.conn(), &client.client_id, REDIRECT_URI, Some("read"))
+            .await
+            .unwrap();
+
+    let challenge = BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(CODE_VERIFIER.as_bytes()));
+    let code = create_authorization_code(
+        &app.rconn(),
+        &client,
+        user,
+        REDIRECT_URI,
+        &scopes,
+        Some(PkceChallenge::new(&challenge, Some("S256")).unwrap()),
+    )
+    .await
+    .unwrap();
+
+    // PKCE を使う場合は client_secret を省略できる
+    let token = exchange_authorization_code(
+        app.conn(),
+        &app.rconn(),
+        &client.client_id,
+        None,
+        &code,
+        REDIRECT_URI,
+        Some(CODE_VERIFIER),
+    )
+    .await
+    .unwrap();
+    assert_eq!(token.scopes.to_string(), "read");
+
+    // 認可コードは一度しか使えない
+    exchange_authorization_code(
+        app.conn(),
+        &app.rconn(),
+        &client.client_id,
+        None,
+        &code,
+        REDIRECT_URI,
+        Some(CODE_VERIFIER),
+    )
+    .await
+    .unwrap_err();
+
+    let authed = authenticate_oauth_token(app.conn(), &token.access_token)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(authed.user_id, user);
+    assert!(authed.scopes.allows("read"));
+    assert!(!authed.scopes.allows("write"));
+
+    // リフレッシュすると古いトークンは使えなくなる
+    let refreshed = refresh_oauth_token(
+        app.conn(),
+        &client.client_id,
+        Some(&client.client_secret),
+        &token.refresh_token,
+    )
+    .await
+    .unwrap();
+    assert!(
+        authenticate_oauth_token(app.conn(), &token.access_token)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    refresh_oauth_token(
+        app.conn(),
+        &client.client_id,
+        Some(&client.client_secret),
+        &token.refresh_token,
+    )
+    .await
+    .unwrap_err();
+    assert!(
+        authenticate_oauth_token(app.conn(), &refreshed.access_token)
+            .await
+            .unwrap()
+            .is_some()
+    );
+
+    revoke_oauth_token(
+        app.conn(),
+        &client.client_id,
+        Some(&client.client_secret),
+        &refreshed.access_token,
+    )
+    .await
+    .unwrap();
+    assert!(
+        authenticate_oauth_token(app.conn(), &refreshed.access_token)
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn test_oauth_pkce_and_logout_all() {
+    let st = test_setup().await;
+    let app = &st.app;
+
+    let user = register_user_for_test(&st, "user1").await;
+
+    let client = create_oauth_app(
+        app.conn(),
+        "test client",
+        &[REDIRECT_URI],
+        &OAuthScopes::parse("read").unwrap(),
+        None,
+    )
+    .await
+    .unwrap();
+    let scopes = OAuthScopes::parse("read").unwrap();
+
+    // code_verifier が一致しない
+    let challenge = BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(CODE_VERIFIER.as_bytes()));
+    let code = create_authorization_code(
+        &app.rconn(),
+        &client,
+        user,
+        REDIRECT_URI,
+        &scopes,
+        Some(PkceChallenge::new(&challenge, Some("S256")).unwrap()),
+    )
+    .await
+    .unwrap();
+    exchange_authorization_code(
+        app.conn(),
+        &app.rconn(),
+        &client.client_id,
+        None,
+        &code,
+        REDIRECT_URI,
+        Some(&CODE_VERIFIER.replace('d', "e")),
+    )
+    .await
+    .unwrap_err();
+
+    // PKCE を使わない場合は client_secret が必要
+    let code = create_authorization_code(&app.rconn(), &client, user, REDIRECT_URI, &scopes, None)
+        .await
+        .unwrap();
+    exchange_authorization_code(
+        app.conn(),
+        &app.rconn(),
+        &client.client_id,
+        None,
+        &code,
+        REDIRECT_URI,
+        None,
+    )
+    .await
+    .unwrap_err();
+
+    let code = create_authorization_code(&app.rconn(), &client, user, REDIRECT_URI, &scopes, None)
+        .await
+        .unwrap();
+    let token = exchange_authorization_code(
+        app.conn(),
+        &app.rconn(),
+        &client.client_id,
+        Some(&client.client_secret),
+        &code,
+        REDIRECT_URI,
+        None,
+    )
+    .await
+    .unwrap();
+
+    logout_all(app.conn(), user).await.unwrap();
+    assert!(
+        authenticate_oauth_token(app.conn(), &token.access_token)
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    // 削除されたユーザーのトークンは使えない
+    let code = create_authorization_code(&app.rconn(), &client, user, REDIRECT_URI, &scopes, None)
+        .await
+        .unwrap();
+    let token = exchange_authorization_code(
+        app.conn(),
+        &app.rconn(),
+        &client.client_id,
+        Some(&client.client_secret),
+        &code,
+        REDIRECT_URI,
+        None,
+    )
+    .await
+    .unwrap();
+    delete_local_user(
+        app.conn(),
+        &app.rconn(),
+        app.qconn(),
+        app.storage(),
+        user,
+        "testpass",
+        app.base_url(),
+    )
+    .await
+    .unwrap();
+    assert!(
+        authenticate_oauth_token(app.conn(), &token.access_token)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    refresh_oauth_token(
+        app.conn(),
+        &client.client_id,
+        Some(&client.client_secret),
+        &token.refresh_token,
+    )
+    .await
+    .unwrap_err();
+}
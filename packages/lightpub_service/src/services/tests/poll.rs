@@ -0,0 +1,120 @@
+use chrono::{Duration, Utc};
+
+use crate::services::{
+    note::{
+        ContentType, NotePollCreate, PostCreateOptionsBuilder, VisibilityModel, get_note_poll,
+        receive_poll_vote, vote_note_poll,
+    },
+    tests::{
+        common::{MY_DOMAIN, test_setup},
+        note::create_note_for_test,
+    },
+};
+
+use super::auth::register_user_for_test;
+
+#[test]
+fn test_poll_validate() {
+    let poll = |options: &[&str], expires_at| NotePollCreate {
+        options: options.iter().map(|o| o.to_string()).collect(),
+        multiple: false,
+        expires_at,
+    };
+
+    poll(&["a", "b"], None).validate().unwrap();
+    poll(&["a", "b"], Some(Utc::now() + Duration::hours(1)))
+        .validate()
+        .unwrap();
+    poll(&["a"], None).validate().unwrap_err();
+    poll(&["a", "a"], None).validate().unwrap_err();
+    poll(&["a", ""], None).validate().unwrap_err();
+    poll(&["a", "b"], Some(Utc::now() - Duration::hours(1)))
+        .validate()
+        .unwrap_err();
+    poll(&["a", "b"], Some(Utc::now() + Duration::days(31)))
+        .validate()
+        .unwrap_err();
+}
+
+#[tokio::test]
+async fn test_poll_vote() {
+    let st = test_setup().await;
+    let app = &st.app;
+    let base_url = app.base_url();
+
+    let user1 = register_user_for_test(&st, "user1").await;
+    let user2 = register_user_for_test(&st, "user2").await;
+    let user3 = register_user_for_test(&st, "user3").await;
+
+    let note_id = create_note_for_test(
+        &st,
+        user1,
+        "which?",
+        ContentType::Plain,
+        VisibilityModel::Public,
+        &PostCreateOptionsBuilder::default()
+            .poll(Some(NotePollCreate {
+                options: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+                multiple: false,
+                expires_at: Some(Utc::now() + Duration::hours(1)),
+            }))
+            .build()
+            .unwrap(),
+    )
+    .await
+    .unwrap();
+
+    // 自分の投票には投票できない
+    vote_note_poll(app.conn(), app.qconn(), user1, note_id, &[0], base_url)
+        .await
+        .unwrap_err();
+
+    // 単一選択の投票で複数選択はできない
+    vote_note_poll(app.conn(), app.qconn(), user2, note_id, &[0, 1], base_url)
+        .await
+        .unwrap_err();
+
+    // 範囲外の選択肢
+    vote_note_poll(app.conn(), app.qconn(), user2, note_id, &[3], base_url)
+        .await
+        .unwrap_err();
+
+    vote_note_poll(app.conn(), app.qconn(), user2, note_id, &[1], base_url)
+        .await
+        .unwrap();
+
+    // 二重投票はできない
+    vote_note_poll(app.conn(), app.qconn(), user2, note_id, &[2], base_url)
+        .await
+        .unwrap_err();
+
+    // リモートからの投票として受け取る
+    let poll_url = base_url.join(&format!("/note/{}", note_id)).unwrap();
+    assert!(
+        receive_poll_vote(app.conn(), user3, &poll_url, "b", MY_DOMAIN)
+            .await
+            .unwrap()
+    );
+
+    let poll = get_note_poll(&app.maybe_conn(), note_id, Some(user2))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!poll.multiple);
+    assert!(!poll.closed);
+    assert_eq!(poll.voters_count, 2);
+    assert_eq!(
+        poll.options
+            .iter()
+            .map(|o| o.votes_count)
+            .collect::<Vec<_>>(),
+        vec![0, 2, 0]
+    );
+    assert_eq!(poll.voted, Some(vec![1]));
+
+    let poll = get_note_poll(&app.maybe_conn(), note_id, Some(user1))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(poll.voted, Some(vec![]));
+}
@@ -0,0 +1,142 @@
+use crate::services::{
+    note::{
+        ApubNoteModel, ContentType, PostCreateOptionsBuilder, VisibilityModel, create_renote,
+        get_note_by_id_visibility_check,
+    },
+    notification::{NotificationBody, get_notifications},
+    tests::{common::test_setup, note::create_note_for_test},
+};
+
+use super::auth::register_user_for_test;
+
+#[tokio::test]
+async fn test_quote_note() {
+    let st = test_setup().await;
+    let app = &st.app;
+
+    let user1 = register_user_for_test(&st, "user1").await;
+    let user2 = register_user_for_test(&st, "user2").await;
+
+    let note_id = create_note_for_test(
+        &st,
+        user1,
+        "original",
+        ContentType::Plain,
+        VisibilityModel::Public,
+        &Default::default(),
+    )
+    .await
+    .unwrap();
+    let private_note_id = create_note_for_test(
+        &st,
+        user1,
+        "private",
+        ContentType::Plain,
+        VisibilityModel::Private,
+        &Default::default(),
+    )
+    .await
+    .unwrap();
+
+    let quote_id = create_note_for_test(
+        &st,
+        user2,
+        "quote",
+        ContentType::Plain,
+        VisibilityModel::Public,
+        &PostCreateOptionsBuilder::default()
+            .quote_of_id(Some(note_id))
+            .build()
+            .unwrap(),
+    )
+    .await
+    .unwrap();
+
+    let quote =
+        get_note_by_id_visibility_check(&app.maybe_conn(), &app.rconn(), quote_id, None, false)
+            .await
+            .unwrap()
+            .unwrap();
+    assert_eq!(quote.basic.quote_of_id(), Some(note_id));
+
+    // 引用元の作者に通知される
+    let notifications = get_notifications(app.conn(), user1).await.unwrap();
+    assert!(notifications.iter().any(|n| matches!(
+        n.body,
+        NotificationBody::Quoted(u, q, t) if u == user2 && q == quote_id && t == note_id
+    )));
+
+    // 引用していても通常のリノートはできる
+    create_renote(
+        app.conn(),
+        &app.rconn(),
+        app.qconn(),
+        app.wp(),
+        user2,
+        note_id,
+        VisibilityModel::Public,
+        app.base_url(),
+    )
+    .await
+    .unwrap();
+
+    // 見えないノートは引用できない
+    create_note_for_test(
+        &st,
+        user2,
+        "quote",
+        ContentType::Plain,
+        VisibilityModel::Public,
+        &PostCreateOptionsBuilder::default()
+            .quote_of_id(Some(private_note_id))
+            .build()
+            .unwrap(),
+    )
+    .await
+    .unwrap_err();
+}
+
+#[test]
+fn test_deserialize_quote_note() {
+    let note = |extra: &str| {
+        let json = format!(
+            r#"{{
+                "id": "https://remote.example.com/notes/2",
+                "type": "Note",
+                "attributedTo": "https://remote.example.com/users/1",
+                "content": "quote",
+                "sensitive": false
+                {extra}
+            }}"#
+        );
+        serde_json::from_str::<ApubNoteModel>(&json).unwrap()
+    };
+
+    let target = "https://remote.example.com/notes/1";
+
+    let n = note(&format!(r#", "quoteUrl": "{target}""#));
+    assert_eq!(n.quote_target().map(|u| u.as_str()), Some(target));
+
+    let n = note(&format!(r#", "_misskey_quote": "{target}""#));
+    assert_eq!(n.quote_target().map(|u| u.as_str()), Some(target));
+
+    let n = note(&format!(
+        r#", "tag": [{{
+            "type": "Link",
+            "mediaType": "application/ld+json; profile=\"https://www.w3.org/ns/activitystreams\"",
+            "href": "{target}",
+            "name": "RE: {target}"
+        }}]"#
+    ));
+    assert_eq!(n.quote_target().map(|u| u.as_str()), Some(target));
+
+    // 関係のないリンク
+    let n = note(
+        r#", "tag": [{
+            "type": "Link",
+            "mediaType": "text/html",
+            "href": "https://remote.example.com/page"
+        }]"#,
+    );
+    assert_eq!(n.quote_target(), None);
+}
@@ -0,0 +1,182 @@
+use crate::services::{
+    apub::LikeActivity,
+    note::{
+        ContentType, PostCreateOptions, VisibilityModel, get_note_by_id_visibility_check,
+        get_reacted_users, note_like_remove, note_reaction_add,
+    },
+    tests::{
+        common::{MY_DOMAIN, test_setup},
+        note::create_note_for_test,
+    },
+};
+
+use super::auth::register_user_for_test;
+
+#[tokio::test]
+async fn test_note_reaction() {
+    let st = test_setup().await;
+    let app = &st.app;
+    let base_url = app.base_url();
+
+    let user1 = register_user_for_test(&st, "user1").await;
+    let user2 = register_user_for_test(&st, "user2").await;
+    let user3 = register_user_for_test(&st, "user3").await;
+
+    let note_id = create_note_for_test(
+        &st,
+        user1,
+        "hello",
+        ContentType::Plain,
+        VisibilityModel::Public,
+        &PostCreateOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    // 不正なリアクション
+    for reaction in ["", "like", ":unknown:", ":a@remote.example.com:"] {
+        note_reaction_add(
+            app.conn(),
+            app.qconn(),
+            user2,
+            note_id,
+            reaction,
+            MY_DOMAIN,
+            base_url,
+        )
+        .await
+        .unwrap_err();
+    }
+
+    note_reaction_add(
+        app.conn(),
+        app.qconn(),
+        user2,
+        note_id,
+        "👍",
+        MY_DOMAIN,
+        base_url,
+    )
+    .await
+    .unwrap();
+    note_reaction_add(
+        app.conn(),
+        app.qconn(),
+        user3,
+        note_id,
+        "🎉",
+        MY_DOMAIN,
+        base_url,
+    )
+    .await
+    .unwrap();
+
+    let note = get_note_by_id_visibility_check(
+        &app.maybe_conn(),
+        &app.rconn(),
+        note_id,
+        Some(user2),
+        false,
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(note.details.reactions.len(), 2);
+    assert!(note.details.reactions.iter().all(|r| r.count == 1));
+    assert_eq!(note.details.my_reaction.as_deref(), Some("👍"));
+
+    let users = get_reacted_users(
+        &app.maybe_conn(),
+        &app.rconn(),
+        None,
+        note_id,
+        Some("🎉"),
+        10,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].0.id, user3);
+
+    let users = get_reacted_users(
+        &app.maybe_conn(),
+        &app.rconn(),
+        None,
+        note_id,
+        None,
+        10,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(users.len(), 2);
+
+    // リアクションを取り消す
+    note_like_remove(app.conn(), app.qconn(), user2, note_id, false, base_url)
+        .await
+        .unwrap();
+
+    let note = get_note_by_id_visibility_check(
+        &app.maybe_conn(),
+        &app.rconn(),
+        note_id,
+        Some(user2),
+        false,
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(note.details.reactions.len(), 1);
+    assert_eq!(note.details.reactions[0].reaction, "🎉");
+    assert_eq!(note.details.my_reaction, None);
+}
+
+#[test]
+fn test_deserialize_reaction_activity() {
+    // Misskey
+    let json = r#"{
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "type": "Like",
+        "id": "https://misskey.example.com/likes/1",
+        "actor": "https://misskey.example.com/users/1",
+        "object": "https://example.com/note/1",
+        "content": ":blobcat:",
+        "_misskey_reaction": ":blobcat:",
+        "tag": [{
+            "id": "https://misskey.example.com/emojis/blobcat",
+            "type": "Emoji",
+            "name": ":blobcat:",
+            "updated": "2025-01-01T00:00:00.000Z",
+            "icon": {
+                "type": "Image",
+                "mediaType": "image/png",
+                "url": "https://misskey.example.com/files/blobcat.png"
+            }
+        }]
+    }"#;
+    let like: LikeActivity = serde_json::from_str(json).unwrap();
+    assert_eq!(like.reaction(), Some(":blobcat:"));
+    assert_eq!(like.tags.len(), 1);
+
+    // Pleroma
+    let json = r#"{
+        "type": "EmojiReact",
+        "id": "https://pleroma.example.com/activities/1",
+        "actor": "https://pleroma.example.com/users/alice",
+        "object": "https://example.com/note/1",
+        "content": "👍"
+    }"#;
+    let like: LikeActivity = serde_json::from_str(json).unwrap();
+    assert_eq!(like.reaction(), Some("👍"));
+
+    // 通常のお気に入り
+    let json = r#"{
+        "type": "Like",
+        "id": "https://mastodon.example.com/likes/1",
+        "actor": "https://mastodon.example.com/users/bob",
+        "object": "https://example.com/note/1"
+    }"#;
+    let like: LikeActivity = serde_json::from_str(json).unwrap();
+    assert_eq!(like.reaction(), None);
+}
@@ -0,0 +1,270 @@
+use crate::services::{
+    auth::check_password_user,
+    note::{ContentType, PostCreateOptionsBuilder, VisibilityModel, create_renote, get_note_by_id},
+    report::{
+        ReportResolveAction, ReportStatusModel, create_report, dismiss_report, get_report_by_id,
+        list_reports, resolve_report,
+    },
+    tests::common::test_setup,
+    user::{
+        INSTANCE_ACTOR_USERNAME, UserAccountStateModel, get_instance_actor, get_total_users_count,
+        get_user_account_state,
+    },
+};
+
+use super::{auth::register_user_for_test, note::create_note_for_test};
+
+#[tokio::test]
+async fn test_report_create_and_dismiss() {
+    let st = test_setup().await;
+    let app = &st.app;
+
+    let reporter = register_user_for_test(&st, "reporter").await;
+    let target = register_user_for_test(&st, "target").await;
+    let admin = register_user_for_test(&st, "admin").await;
+
+    let opts = PostCreateOptionsBuilder::default().build().unwrap();
+    let note = create_note_for_test(
+        &st,
+        target,
+        "bad content",
+        ContentType::Plain,
+        VisibilityModel::Public,
+        &opts,
+    )
+    .await
+    .unwrap();
+
+    let report_id = create_report(
+        app.conn(),
+        &app.rconn(),
+        app.qconn(),
+        reporter,
+        target,
+        &[note],
+        "spam",
+        false,
+        app.base_url(),
+    )
+    .await
+    .unwrap();
+
+    let open = list_reports(&app.maybe_conn(), Some(ReportStatusModel::Open), 10, None)
+        .await
+        .unwrap();
+    assert_eq!(open.len(), 1);
+    assert_eq!(open[0].id, report_id);
+    assert_eq!(open[0].reporter_id, reporter);
+    assert_eq!(open[0].target_user_id, target);
+    assert_eq!(open[0].note_ids, vec![note]);
+    assert!(!open[0].is_remote);
+    assert!(!open[0].forwarded);
+
+    dismiss_report(app.conn(), report_id, admin).await.unwrap();
+
+    let report = get_report_by_id(&app.maybe_conn(), report_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(report.status, ReportStatusModel::Dismissed);
+    assert_eq!(report.closed_by, Some(admin));
+
+    // 閉じられた通報は再度処理できない
+    dismiss_report(app.conn(), report_id, admin)
+        .await
+        .unwrap_err();
+
+    let open = list_reports(&app.maybe_conn(), Some(ReportStatusModel::Open), 10, None)
+        .await
+        .unwrap();
+    assert!(open.is_empty());
+}
+
+#[tokio::test]
+async fn test_report_resolve_delete_notes() {
+    let st = test_setup().await;
+    let app = &st.app;
+
+    let reporter = register_user_for_test(&st, "reporter").await;
+    let target = register_user_for_test(&st, "target").await;
+    let admin = register_user_for_test(&st, "admin").await;
+
+    let opts = PostCreateOptionsBuilder::default().build().unwrap();
+    let note = create_note_for_test(
+        &st,
+        target,
+        "bad content",
+        ContentType::Plain,
+        VisibilityModel::Public,
+        &opts,
+    )
+    .await
+    .unwrap();
+
+    let report_id = create_report(
+        app.conn(),
+        &app.rconn(),
+        app.qconn(),
+        reporter,
+        target,
+        &[note],
+        "",
+        false,
+        app.base_url(),
+    )
+    .await
+    .unwrap();
+
+    resolve_report(
+        app.conn(),
+        &app.rconn(),
+        app.qconn(),
+        report_id,
+        admin,
+        ReportResolveAction::DeleteNotes,
+        app.base_url(),
+    )
+    .await
+    .unwrap();
+
+    let report = get_report_by_id(&app.maybe_conn(), report_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(report.status, ReportStatusModel::Resolved);
+
+    let deleted = get_note_by_id(&app.maybe_conn(), note, false)
+        .await
+        .unwrap();
+    assert!(deleted.is_none());
+
+    // 解決済みの通報に対して再び措置をとることはできない
+    resolve_report(
+        app.conn(),
+        &app.rconn(),
+        app.qconn(),
+        report_id,
+        admin,
+        ReportResolveAction::SuspendUser,
+        app.base_url(),
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(
+        get_user_account_state(&app.maybe_conn(), target)
+            .await
+            .unwrap(),
+        Some(UserAccountStateModel::Active)
+    );
+}
+
+#[tokio::test]
+async fn test_report_invalid() {
+    let st = test_setup().await;
+    let app = &st.app;
+
+    let reporter = register_user_for_test(&st, "reporter").await;
+    let target = register_user_for_test(&st, "target").await;
+
+    let opts = PostCreateOptionsBuilder::default().build().unwrap();
+    let reporter_note = create_note_for_test(
+        &st,
+        reporter,
+        "my content",
+        ContentType::Plain,
+        VisibilityModel::Public,
+        &opts,
+    )
+    .await
+    .unwrap();
+    let renote = create_renote(
+        app.conn(),
+        &app.rconn(),
+        app.qconn(),
+        app.wp(),
+        target,
+        reporter_note,
+        VisibilityModel::Public,
+        app.base_url(),
+    )
+    .await
+    .unwrap();
+
+    // 自分自身は通報できない
+    create_report(
+        app.conn(),
+        &app.rconn(),
+        app.qconn(),
+        reporter,
+        reporter,
+        &[],
+        "",
+        false,
+        app.base_url(),
+    )
+    .await
+    .unwrap_err();
+
+    // 対象ユーザー以外のノートは指定できない
+    create_report(
+        app.conn(),
+        &app.rconn(),
+        app.qconn(),
+        reporter,
+        target,
+        &[reporter_note],
+        "",
+        false,
+        app.base_url(),
+    )
+    .await
+    .unwrap_err();
+
+    // リノートは指定できない
+    create_report(
+        app.conn(),
+        &app.rconn(),
+        app.qconn(),
+        reporter,
+        target,
+        &[renote],
+        "",
+        false,
+        app.base_url(),
+    )
+    .await
+    .unwrap_err();
+
+    let reports = list_reports(&app.maybe_conn(), None, 10, None)
+        .await
+        .unwrap();
+    assert!(reports.is_empty());
+}
+
+#[tokio::test]
+async fn test_instance_actor() {
+    let st = test_setup().await;
+    let app = &st.app;
+
+    register_user_for_test(&st, "user1").await;
+
+    let actor = get_instance_actor(&app.maybe_conn(), app.base_url())
+        .await
+        .unwrap();
+    assert_eq!(actor.basic.username, INSTANCE_ACTOR_USERNAME);
+
+    // 2 回目以降は同じアクターを返す
+    let again = get_instance_actor(&app.maybe_conn(), app.base_url())
+        .await
+        .unwrap();
+    assert_eq!(again.basic.id, actor.basic.id);
+
+    // ユーザー数には含めず、ログインもできない
+    assert_eq!(get_total_users_count(&app.maybe_conn()).await.unwrap(), 1);
+    assert!(
+        check_password_user(app.conn(), INSTANCE_ACTOR_USERNAME, "")
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
@@ -1,10 +1,21 @@
+use chrono::{Duration, Utc};
+
 use crate::services::{
+    UpsertOperation,
     id::{NoteID, UserID},
-    note::{ContentType, PostCreateOptionsBuilder, VisibilityModel, get_timeline_notes},
+    note::{
+        ContentType, PostCreateOptionsBuilder, VisibilityModel, create_renote, get_note_replies,
+        get_timeline_notes, get_timeline_notes_after, get_user_notes, get_user_notes_after,
+    },
     tests::common::{TestState, test_setup},
+    user::{UserAccountStateModel, mute_user, set_user_account_state, unmute_user},
 };
 
-use super::{auth::register_user_for_test, note::create_note_for_test, user::user_follow_for_test};
+use super::{
+    auth::register_user_for_test,
+    note::create_note_for_test,
+    user::{user_block_for_test, user_follow_for_test},
+};
 
 struct TimelineTestFixture {
     user1: UserID,
@@ -282,3 +293,383 @@ async fn test_public_timeline_other_viewer() {
     assert_eq!(timeline.len(), 1);
     assert_eq!(timeline[0].basic.id, fix.note_public);
 }
+
+#[tokio::test]
+async fn test_public_timeline_blocking_viewer() {
+    let st = test_setup().await;
+
+    let fix = setup_timeline_fixture(&st).await;
+
+    user_block_for_test(&st, fix.other_user, fix.user1).await;
+
+    let timeline = get_timeline_notes(
+        &st.app.maybe_conn(),
+        &st.app.rconn(),
+        Some(fix.other_user),
+        true,
+        20,
+        None,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(timeline.len(), 0);
+}
+
+#[tokio::test]
+async fn test_public_timeline_blocked_viewer() {
+    let st = test_setup().await;
+
+    let fix = setup_timeline_fixture(&st).await;
+
+    user_block_for_test(&st, fix.user1, fix.other_user).await;
+
+    let timeline = get_timeline_notes(
+        &st.app.maybe_conn(),
+        &st.app.rconn(),
+        Some(fix.other_user),
+        true,
+        20,
+        None,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(timeline.len(), 0);
+
+    // 関係のないユーザーには引き続き表示される
+    let timeline = get_timeline_notes(
+        &st.app.maybe_conn(),
+        &st.app.rconn(),
+        Some(fix.mentioned_user),
+        true,
+        20,
+        None,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(timeline.len(), 2);
+    assert_eq!(timeline[0].basic.id, fix.note_private);
+    assert_eq!(timeline[1].basic.id, fix.note_public);
+}
+
+#[tokio::test]
+async fn test_timeline_blocked_renote() {
+    let st = test_setup().await;
+
+    let fix = setup_timeline_fixture(&st).await;
+
+    user_follow_for_test(&st, fix.mentioned_user, fix.other_user, true).await;
+    let renote_id = create_renote(
+        st.app.conn(),
+        &st.app.rconn(),
+        st.app.qconn(),
+        st.app.wp(),
+        fix.other_user,
+        fix.note_public,
+        VisibilityModel::Public,
+        st.app.base_url(),
+    )
+    .await
+    .unwrap();
+
+    let timeline = get_timeline_notes(
+        &st.app.maybe_conn(),
+        &st.app.rconn(),
+        Some(fix.mentioned_user),
+        false,
+        20,
+        None,
+    )
+    .await
+    .unwrap();
+    assert!(timeline.iter().any(|n| n.basic.id == renote_id));
+
+    user_block_for_test(&st, fix.mentioned_user, fix.user1).await;
+
+    let timeline = get_timeline_notes(
+        &st.app.maybe_conn(),
+        &st.app.rconn(),
+        Some(fix.mentioned_user),
+        false,
+        20,
+        None,
+    )
+    .await
+    .unwrap();
+    assert!(timeline.iter().all(|n| n.basic.id != renote_id));
+}
+
+#[tokio::test]
+async fn test_note_replies_blocked_author() {
+    let st = test_setup().await;
+
+    let fix = setup_timeline_fixture(&st).await;
+
+    let opts = PostCreateOptionsBuilder::default()
+        .reply_to_id(UpsertOperation::Set(Some(fix.note_public)))
+        .build()
+        .unwrap();
+    let reply = create_note_for_test(
+        &st,
+        fix.other_user,
+        "reply",
+        ContentType::Plain,
+        VisibilityModel::Public,
+        &opts,
+    )
+    .await
+    .unwrap();
+
+    user_block_for_test(&st, fix.follower_user, fix.other_user).await;
+
+    let replies = get_note_replies(
+        &st.app.maybe_conn(),
+        &st.app.rconn(),
+        Some(fix.follower_user),
+        fix.note_public,
+        20,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(replies.len(), 0);
+
+    let replies = get_note_replies(
+        &st.app.maybe_conn(),
+        &st.app.rconn(),
+        Some(fix.user1),
+        fix.note_public,
+        20,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(replies.len(), 1);
+    assert_eq!(replies[0].basic.id, reply);
+}
+
+#[tokio::test]
+async fn test_user_notes_blocked_viewer() {
+    let st = test_setup().await;
+
+    let fix = setup_timeline_fixture(&st).await;
+
+    user_block_for_test(&st, fix.user1, fix.other_user).await;
+
+    let notes = get_user_notes(
+        &st.app.maybe_conn(),
+        &st.app.rconn(),
+        Some(fix.other_user),
+        fix.user1,
+        20,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(notes.len(), 0);
+
+    let notes = get_user_notes(
+        &st.app.maybe_conn(),
+        &st.app.rconn(),
+        None,
+        fix.user1,
+        20,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(notes.len(), 2);
+}
+
+#[tokio::test]
+async fn test_timeline_muted_author() {
+    let st = test_setup().await;
+
+    let fix = setup_timeline_fixture(&st).await;
+
+    mute_user(
+        st.app.conn(),
+        &st.app.rconn(),
+        fix.follower_user,
+        fix.user1,
+        Some(Utc::now() + Duration::days(1)),
+    )
+    .await
+    .unwrap();
+
+    let timeline = get_timeline_notes(
+        &st.app.maybe_conn(),
+        &st.app.rconn(),
+        Some(fix.follower_user),
+        false,
+        20,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(timeline.len(), 0);
+
+    unmute_user(st.app.conn(), fix.follower_user, fix.user1)
+        .await
+        .unwrap();
+
+    let timeline = get_timeline_notes(
+        &st.app.maybe_conn(),
+        &st.app.rconn(),
+        Some(fix.follower_user),
+        false,
+        20,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(timeline.len(), 3);
+}
+
+#[tokio::test]
+async fn test_timeline_limited_and_suspended_author() {
+    let st = test_setup().await;
+
+    let fix = setup_timeline_fixture(&st).await;
+
+    set_user_account_state(
+        st.app.conn(),
+        &st.app.rconn(),
+        fix.user1,
+        UserAccountStateModel::Limited,
+    )
+    .await
+    .unwrap();
+
+    // 制限されたユーザーのノートは公開タイムラインに表示されない
+    let timeline = get_timeline_notes(
+        &st.app.maybe_conn(),
+        &st.app.rconn(),
+        Some(fix.other_user),
+        true,
+        20,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(timeline.len(), 0);
+
+    // フォロワーのタイムラインには表示される
+    let timeline = get_timeline_notes(
+        &st.app.maybe_conn(),
+        &st.app.rconn(),
+        Some(fix.follower_user),
+        false,
+        20,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(timeline.len(), 3);
+
+    set_user_account_state(
+        st.app.conn(),
+        &st.app.rconn(),
+        fix.user1,
+        UserAccountStateModel::Suspended,
+    )
+    .await
+    .unwrap();
+
+    let timeline = get_timeline_notes(
+        &st.app.maybe_conn(),
+        &st.app.rconn(),
+        Some(fix.follower_user),
+        false,
+        20,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(timeline.len(), 0);
+
+    set_user_account_state(
+        st.app.conn(),
+        &st.app.rconn(),
+        fix.user1,
+        UserAccountStateModel::Active,
+    )
+    .await
+    .unwrap();
+
+    let timeline = get_timeline_notes(
+        &st.app.maybe_conn(),
+        &st.app.rconn(),
+        Some(fix.other_user),
+        true,
+        20,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(timeline.len(), 1);
+}
+
+#[tokio::test]
+async fn test_notes_after_date_oldest_first() {
+    let st = test_setup().await;
+
+    let user1 = register_user_for_test(&st, "user1").await;
+
+    let opts = PostCreateOptionsBuilder::default().build().unwrap();
+    let mut notes = vec![];
+    for i in 0..5 {
+        let note = create_note_for_test(
+            &st,
+            user1,
+            &format!("content{i}"),
+            ContentType::Plain,
+            VisibilityModel::Public,
+            &opts,
+        )
+        .await
+        .unwrap();
+        notes.push(note);
+    }
+
+    let timeline = get_timeline_notes(&st.app.maybe_conn(), &st.app.rconn(), None, true, 20, None)
+        .await
+        .unwrap();
+    let oldest = timeline.iter().find(|n| n.basic.id == notes[0]).unwrap();
+    let after_date = oldest.basic.created_at;
+
+    // limit より多くの新しいノートがあっても、境界の直後から取得される
+    let timeline = get_timeline_notes_after(
+        &st.app.maybe_conn(),
+        &st.app.rconn(),
+        None,
+        true,
+        2,
+        None,
+        after_date,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        timeline.iter().map(|n| n.basic.id).collect::<Vec<_>>(),
+        vec![notes[1], notes[2]]
+    );
+
+    let user_notes = get_user_notes_after(
+        &st.app.maybe_conn(),
+        &st.app.rconn(),
+        None,
+        user1,
+        2,
+        None,
+        after_date,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        user_notes.iter().map(|n| n.basic.id).collect::<Vec<_>>(),
+        vec![notes[1], notes[2]]
+    );
+}
@@ -0,0 +1,192 @@
+use chrono::{DateTime, Duration, Utc};
+
+use crate::services::{
+    tests::common::test_setup,
+    two_factor::{
+        begin_totp_enrollment, complete_pending_login, confirm_totp_enrollment,
+        count_remaining_recovery_codes, create_pending_login, disable_two_factor,
+        is_two_factor_enabled, totp_code_at, verify_two_factor_code,
+    },
+};
+
+use super::auth::register_user_for_test;
+
+#[test]
+fn test_totp_code() {
+    // RFC 6238 Appendix B のテストベクトル (SHA-1) の下 6 桁
+    let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+    let at = |t| DateTime::<Utc>::from_timestamp(t, 0).unwrap();
+    assert_eq!(totp_code_at(secret, at(59)).unwrap(), "287082");
+    assert_eq!(totp_code_at(secret, at(1111111109)).unwrap(), "081804");
+    assert_eq!(totp_code_at(secret, at(1234567890)).unwrap(), "005924");
+    assert_eq!(totp_code_at(secret, at(2000000000)).unwrap(), "279037");
+
+    assert!(totp_code_at("not base32!", at(59)).is_none());
+}
+
+#[tokio::test]
+async fn test_totp_enrollment_and_recovery_codes() {
+    let st = test_setup().await;
+    let app = &st.app;
+
+    let user = register_user_for_test(&st, "user1").await;
+
+    // 設定を開始していない場合は有効にできない
+    confirm_totp_enrollment(app.conn(), user, "000000")
+        .await
+        .unwrap_err();
+
+    let enrollment = begin_totp_enrollment(app.conn(), user, "example.com")
+        .await
+        .unwrap();
+    assert!(
+        enrollment
+            .otpauth_uri
+            .starts_with("otpauth://totp/example.com:user1?")
+    );
+    assert!(
+        enrollment
+            .otpauth_uri
+            .contains(&format!("secret={}", enrollment.secret))
+    );
+    // 確認するまでは有効にならない
+    assert!(
+        !is_two_factor_enabled(&app.maybe_conn(), user)
+            .await
+            .unwrap()
+    );
+
+    let now = Utc::now();
+    let code = totp_code_at(&enrollment.secret, now).unwrap();
+    let wrong_code = if code == "000000" { "111111" } else { "000000" };
+    confirm_totp_enrollment(app.conn(), user, wrong_code)
+        .await
+        .unwrap_err();
+
+    let recovery_codes = confirm_totp_enrollment(app.conn(), user, &code)
+        .await
+        .unwrap();
+    assert_eq!(recovery_codes.len(), 10);
+    assert!(
+        is_two_factor_enabled(&app.maybe_conn(), user)
+            .await
+            .unwrap()
+    );
+    assert_eq!(
+        count_remaining_recovery_codes(&app.maybe_conn(), user)
+            .await
+            .unwrap(),
+        10
+    );
+
+    // 有効な間は設定をやり直せない
+    begin_totp_enrollment(app.conn(), user, "example.com")
+        .await
+        .unwrap_err();
+
+    // 使用済みのコードは再利用できない
+    assert!(
+        !verify_two_factor_code(app.conn(), user, &code)
+            .await
+            .unwrap()
+    );
+    let next_code = totp_code_at(&enrollment.secret, now + Duration::seconds(30)).unwrap();
+    assert!(
+        verify_two_factor_code(app.conn(), user, &next_code)
+            .await
+            .unwrap()
+    );
+
+    // リカバリーコードは 1 回だけ使える。大文字や区切り文字の有無は問わない
+    let recovery = recovery_codes[0].to_uppercase().replace('-', "");
+    assert!(
+        verify_two_factor_code(app.conn(), user, &recovery)
+            .await
+            .unwrap()
+    );
+    assert!(
+        !verify_two_factor_code(app.conn(), user, &recovery_codes[0])
+            .await
+            .unwrap()
+    );
+    assert_eq!(
+        count_remaining_recovery_codes(&app.maybe_conn(), user)
+            .await
+            .unwrap(),
+        9
+    );
+
+    // 無効にするにはパスワードとコードの両方が必要
+    disable_two_factor(app.conn(), user, "wrongpass", &recovery_codes[1])
+        .await
+        .unwrap_err();
+    disable_two_factor(app.conn(), user, "testpass", "invalid")
+        .await
+        .unwrap_err();
+    disable_two_factor(app.conn(), user, "testpass", &recovery_codes[1])
+        .await
+        .unwrap();
+    assert!(
+        !is_two_factor_enabled(&app.maybe_conn(), user)
+            .await
+            .unwrap()
+    );
+    assert_eq!(
+        count_remaining_recovery_codes(&app.maybe_conn(), user)
+            .await
+            .unwrap(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn test_two_factor_pending_login() {
+    let st = test_setup().await;
+    let app = &st.app;
+
+    let user = register_user_for_test(&st, "user1").await;
+    begin_totp_enrollment(app.conn(), user, "example.com")
+        .await
+        .unwrap();
+    let enrollment = begin_totp_enrollment(app.conn(), user, "example.com")
+        .await
+        .unwrap();
+    let recovery_codes = confirm_totp_enrollment(
+        app.conn(),
+        user,
+        &totp_code_at(&enrollment.secret, Utc::now()).unwrap(),
+    )
+    .await
+    .unwrap();
+
+    let ticket = create_pending_login(&app.rconn(), user).await.unwrap();
+    complete_pending_login(app.conn(), &app.rconn(), &ticket, "invalid")
+        .await
+        .unwrap_err();
+    let logged_in = complete_pending_login(app.conn(), &app.rconn(), &ticket, &recovery_codes[0])
+        .await
+        .unwrap();
+    assert_eq!(logged_in, user);
+
+    // チケットは 1 回しか使えない
+    complete_pending_login(app.conn(), &app.rconn(), &ticket, &recovery_codes[1])
+        .await
+        .unwrap_err();
+
+    // コードを間違えすぎるとチケットが無効になる
+    let ticket = create_pending_login(&app.rconn(), user).await.unwrap();
+    for _ in 0..5 {
+        complete_pending_login(app.conn(), &app.rconn(), &ticket, "invalid")
+            .await
+            .unwrap_err();
+    }
+    complete_pending_login(app.conn(), &app.rconn(), &ticket, &recovery_codes[1])
+        .await
+        .unwrap_err();
+
+    // パスワードを入力し直しても、しばらくはコードを受け付けない
+    let ticket = create_pending_login(&app.rconn(), user).await.unwrap();
+    complete_pending_login(app.conn(), &app.rconn(), &ticket, &recovery_codes[1])
+        .await
+        .unwrap_err();
+}
@@ -0,0 +1,399 @@
+use std::{path::Path, sync::Arc};
+
+use chrono::Utc;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, sea_query::Expr};
+use url::Url;
+
+use crate::services::{
+    UpsertOperation,
+    id::{Identifier, UploadID},
+    note::{
+        ApubNoteAttachment, ContentType, NoteUpload, PostCreateOptionsBuilder, VisibilityModel,
+        get_note_by_id_visibility_check,
+    },
+    pending_upload::{
+        PENDING_UPLOAD_TTL, delete_expired_pending_uploads, get_pending_upload,
+        register_pending_upload, update_pending_upload_metadata,
+    },
+    storage::{InMemoryStorage, StorageObject},
+    tests::{auth::register_user_for_test, common::test_setup, note::create_note_for_test},
+    upload::{
+        UploadKind, UploadMetadata, UploadVariant, compute_image_metadata, generate_image_variants,
+        save_upload_file, validate_upload_description,
+    },
+};
+
+#[test]
+fn test_compute_image_metadata() {
+    let file = tempfile::Builder::new().suffix(".png").tempfile().unwrap();
+    let image = image::RgbImage::from_fn(32, 16, |x, _| {
+        if x < 16 {
+            image::Rgb([255, 0, 0])
+        } else {
+            image::Rgb([0, 0, 255])
+        }
+    });
+    image.save(file.path()).unwrap();
+
+    let metadata = compute_image_metadata(file.path());
+    assert_eq!(metadata.width, Some(32));
+    assert_eq!(metadata.height, Some(16));
+    assert!(metadata.blurhash.is_some());
+    assert_eq!(metadata.description, None);
+
+    // 画像でないファイル
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), b"not an image").unwrap();
+    assert_eq!(
+        compute_image_metadata(file.path()),
+        UploadMetadata::default()
+    );
+}
+
+#[test]
+fn test_validate_upload_description() {
+    validate_upload_description(Some("a cat"), Some((0.5, -0.5))).unwrap();
+    validate_upload_description(None, None).unwrap();
+    validate_upload_description(Some(&"a".repeat(1501)), None).unwrap_err();
+    validate_upload_description(None, Some((1.5, 0.0))).unwrap_err();
+}
+
+#[test]
+fn test_deserialize_attachment_metadata() {
+    let json = r#"{
+        "type": "Document",
+        "mediaType": "image/png",
+        "url": "https://remote.example.com/files/1.png",
+        "name": "a cat",
+        "blurhash": "LEHV6nWB2yk8pyo0adR*.7kCMdnj",
+        "width": 640,
+        "height": 480,
+        "focalPoint": [0.5, -0.25]
+    }"#;
+    let attachment: ApubNoteAttachment = serde_json::from_str(json).unwrap();
+    let metadata = attachment.metadata();
+    assert_eq!(metadata.description.as_deref(), Some("a cat"));
+    assert_eq!(
+        metadata.blurhash.as_deref(),
+        Some("LEHV6nWB2yk8pyo0adR*.7kCMdnj")
+    );
+    assert_eq!(metadata.width, Some(640));
+    assert_eq!(metadata.height, Some(480));
+    assert_eq!(metadata.focal_point, Some((0.5, -0.25)));
+
+    // 不正な値は捨てる
+    let json = r#"{
+        "type": "Document",
+        "mediaType": "image/png",
+        "url": "https://remote.example.com/files/1.png",
+        "name": "",
+        "blurhash": "<script>",
+        "focalPoint": [3.0, 0.0]
+    }"#;
+    let attachment: ApubNoteAttachment = serde_json::from_str(json).unwrap();
+    let metadata = attachment.metadata();
+    assert_eq!(metadata.description, None);
+    assert_eq!(metadata.blurhash, None);
+    assert_eq!(metadata.focal_point, None);
+}
+
+#[test]
+fn test_upload_kind_from_mime_type() {
+    assert_eq!(
+        UploadKind::from_mime_type("image/png"),
+        Some(UploadKind::Image)
+    );
+    assert_eq!(
+        UploadKind::from_mime_type("video/webm"),
+        Some(UploadKind::Video)
+    );
+    assert_eq!(
+        UploadKind::from_mime_type("audio/ogg"),
+        Some(UploadKind::Audio)
+    );
+    assert_eq!(UploadKind::from_mime_type("application/pdf"), None);
+    assert_eq!(UploadKind::Video.apub_type(), "Video");
+    assert_eq!(UploadKind::Audio.apub_type(), "Audio");
+}
+
+#[tokio::test]
+async fn test_save_upload_file_rejects_unaccepted_kind() {
+    let storage: StorageObject = Arc::new(InMemoryStorage::default());
+
+    // 画像はアバターなどでは受け付けるが、動画のみの場合は拒否する
+    let file = tempfile::Builder::new().suffix(".png").tempfile().unwrap();
+    image::RgbImage::new(4, 4).save(file.path()).unwrap();
+    save_upload_file(&storage, file, &[UploadKind::Video])
+        .await
+        .unwrap_err();
+
+    // 種類が判別できないファイルは拒否する
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), b"plain text").unwrap();
+    save_upload_file(
+        &storage,
+        file,
+        &[UploadKind::Image, UploadKind::Video, UploadKind::Audio],
+    )
+    .await
+    .unwrap_err();
+}
+
+#[tokio::test]
+async fn test_save_upload_file_stores_variants() {
+    let memory = Arc::new(InMemoryStorage::default());
+    let storage: StorageObject = memory.clone();
+
+    let file = tempfile::Builder::new().suffix(".png").tempfile().unwrap();
+    image::RgbImage::new(2000, 1000).save(file.path()).unwrap();
+    let (upload_id, filename, _, metadata) = save_upload_file(&storage, file, &[UploadKind::Image])
+        .await
+        .unwrap();
+
+    assert!(metadata.has_variants);
+    assert_eq!(memory.len(), 3);
+    assert_eq!(
+        memory.content_type(filename.to_str().unwrap()).as_deref(),
+        Some("image/png")
+    );
+    let preview = storage
+        .get(&UploadVariant::Preview.filename(&upload_id))
+        .await
+        .unwrap()
+        .unwrap();
+    let preview = image::load_from_memory(&preview).unwrap();
+    assert_eq!((preview.width(), preview.height()), (1280, 640));
+}
+
+#[tokio::test]
+async fn test_note_with_video_upload() {
+    let st = test_setup().await;
+    let user_id = register_user_for_test(&st, "user1").await;
+
+    let metadata = UploadMetadata {
+        width: Some(1280),
+        height: Some(720),
+        duration: Some(12.5),
+        ..Default::default()
+    };
+    let note_id = create_note_for_test(
+        &st,
+        user_id,
+        "video",
+        ContentType::Plain,
+        VisibilityModel::Public,
+        &PostCreateOptionsBuilder::default()
+            .uploads(UpsertOperation::Set(vec![NoteUpload::URL(
+                UploadID::new_random(),
+                Url::parse("https://remote.example.com/files/1.mp4").unwrap(),
+                "video/mp4".to_string(),
+                metadata.clone(),
+            )]))
+            .build()
+            .unwrap(),
+    )
+    .await
+    .unwrap();
+
+    let note = get_note_by_id_visibility_check(
+        &st.app.maybe_conn(),
+        &st.app.rconn(),
+        note_id,
+        Some(user_id),
+        false,
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(note.basic.uploads.len(), 1);
+    let upload = &note.basic.uploads[0];
+    assert_eq!(upload.mime_type(), "video/mp4");
+    assert_eq!(upload.metadata(), &metadata);
+}
+
+#[test]
+fn test_upload_variant() {
+    let upload_id = UploadID::new_random();
+    assert_eq!(
+        UploadVariant::Preview.filename(&upload_id),
+        format!("{upload_id}_preview.webp")
+    );
+    assert_eq!(
+        serde_json::from_str::<UploadVariant>(r#""thumbnail""#).unwrap(),
+        UploadVariant::Thumbnail
+    );
+}
+
+#[test]
+fn test_animated_gif_has_no_variants() {
+    let file = tempfile::Builder::new().suffix(".gif").tempfile().unwrap();
+    {
+        let mut encoder = image::codecs::gif::GifEncoder::new(file.reopen().unwrap());
+        let frames = [image::Rgba([255, 0, 0, 255]), image::Rgba([0, 0, 255, 255])]
+            .into_iter()
+            .map(|color| image::Frame::new(image::RgbaImage::from_pixel(8, 8, color)));
+        encoder.encode_frames(frames).unwrap();
+    }
+
+    // アニメーションを保つため、バリアントは生成しない
+    let out_dir = tempfile::tempdir().unwrap();
+    assert!(!generate_image_variants(
+        file.path(),
+        out_dir.path(),
+        &UploadID::new_random()
+    ));
+    // メタデータは 1 フレーム目から計算する
+    let metadata = compute_image_metadata(file.path());
+    assert_eq!(metadata.width, Some(8));
+    assert_eq!(metadata.height, Some(8));
+}
+
+#[tokio::test]
+async fn test_pending_upload_attach() {
+    let st = test_setup().await;
+    let user_id = register_user_for_test(&st, "user1").await;
+    let other_id = register_user_for_test(&st, "user2").await;
+
+    let upload_id = UploadID::new_random();
+    let filename = format!("{upload_id}.png");
+    register_pending_upload(
+        st.app.conn(),
+        user_id,
+        &upload_id,
+        Path::new(&filename),
+        "image/png",
+        &UploadMetadata::default(),
+    )
+    .await
+    .unwrap();
+
+    // 他のユーザーからは見えない
+    assert!(
+        get_pending_upload(&st.app.maybe_conn(), other_id, &upload_id)
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    let media = update_pending_upload_metadata(
+        &st.app.maybe_conn(),
+        user_id,
+        &upload_id,
+        Some("a cat".to_string()),
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(media.metadata.description.as_deref(), Some("a cat"));
+
+    let opts = PostCreateOptionsBuilder::default()
+        .uploads(UpsertOperation::Set(vec![NoteUpload::Pending(upload_id)]))
+        .build()
+        .unwrap();
+    let note_id = create_note_for_test(
+        &st,
+        user_id,
+        "with image",
+        ContentType::Plain,
+        VisibilityModel::Public,
+        &opts,
+    )
+    .await
+    .unwrap();
+
+    let note = get_note_by_id_visibility_check(
+        &st.app.maybe_conn(),
+        &st.app.rconn(),
+        note_id,
+        Some(user_id),
+        false,
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(note.basic.uploads.len(), 1);
+    assert_eq!(
+        note.basic.uploads[0].metadata().description.as_deref(),
+        Some("a cat")
+    );
+
+    // 添付済みのアップロードは再び添付できない
+    assert!(
+        get_pending_upload(&st.app.maybe_conn(), user_id, &upload_id)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    create_note_for_test(
+        &st,
+        user_id,
+        "again",
+        ContentType::Plain,
+        VisibilityModel::Public,
+        &opts,
+    )
+    .await
+    .unwrap_err();
+}
+
+#[tokio::test]
+async fn test_delete_expired_pending_uploads() {
+    let st = test_setup().await;
+    let user_id = register_user_for_test(&st, "user1").await;
+
+    let memory = Arc::new(InMemoryStorage::default());
+    let storage: StorageObject = memory.clone();
+
+    let mut upload_ids = vec![];
+    for _ in 0..2 {
+        let file = tempfile::Builder::new().suffix(".png").tempfile().unwrap();
+        image::RgbImage::new(4, 4).save(file.path()).unwrap();
+        let (upload_id, filename, mime_type, metadata) =
+            save_upload_file(&storage, file, &[UploadKind::Image])
+                .await
+                .unwrap();
+        register_pending_upload(
+            st.app.conn(),
+            user_id,
+            &upload_id,
+            &filename,
+            mime_type.mime_type(),
+            &metadata,
+        )
+        .await
+        .unwrap();
+        upload_ids.push(upload_id);
+    }
+    assert_eq!(memory.len(), 2);
+
+    // 1 つ目だけ期限切れにする
+    entity::pending_upload::Entity::update_many()
+        .col_expr(
+            entity::pending_upload::Column::CreatedAt,
+            Expr::value(Utc::now().naive_utc() - PENDING_UPLOAD_TTL * 2),
+        )
+        .filter(entity::pending_upload::Column::UploadId.eq(upload_ids[0].as_db()))
+        .exec(&st.app.maybe_conn())
+        .await
+        .unwrap();
+
+    let deleted = delete_expired_pending_uploads(st.app.conn(), &storage)
+        .await
+        .unwrap();
+    assert_eq!(deleted, 1);
+    assert_eq!(memory.len(), 1);
+
+    assert!(
+        entity::upload::Entity::find_by_id(upload_ids[0].as_db())
+            .one(&st.app.maybe_conn())
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        get_pending_upload(&st.app.maybe_conn(), user_id, &upload_ids[1])
+            .await
+            .unwrap()
+            .is_some()
+    );
+}
@@ -1,15 +1,18 @@
 use crate::services::{
+    auth::{check_password_user, register_user},
     follow::{FollowStats, follow_user, get_follow_stats, unfollow_user},
     id::UserID,
-    note::{ContentType, PostCreateOptionsBuilder, VisibilityModel},
+    note::{ContentType, PostCreateOptionsBuilder, VisibilityModel, get_note_by_id},
+    notification::{count_unread_notifications, get_notifications},
     tests::{
         auth::{register_sample_user, register_user_for_test},
         common::test_setup,
         note::create_note_for_test,
     },
     user::{
-        UserProfileUpdate, block_user, get_user_profile_by_id, is_blocking_or_blocked,
-        is_blocking_user, unblock_user, update_user_profile,
+        UserProfileUpdate, block_user, delete_local_user, get_user_profile_by_id,
+        is_blocking_or_blocked, is_blocking_user, is_muting_user, is_user_deleted, move_user,
+        mute_user, set_user_aliases, unblock_user, unmute_user, update_user_profile,
     },
 };
 
@@ -173,9 +176,16 @@ async fn test_user_block() {
             .unwrap()
     );
 
-    block_user(&st.app.maybe_conn(), user1, user2)
-        .await
-        .unwrap();
+    block_user(
+        st.app.conn(),
+        &st.app.rconn(),
+        st.app.qconn(),
+        user1,
+        user2,
+        st.app.base_url(),
+    )
+    .await
+    .unwrap();
 
     assert!(
         is_blocking_user(&st.app.maybe_conn(), user1, user2)
@@ -193,9 +203,16 @@ async fn test_user_block() {
             .unwrap()
     );
 
-    unblock_user(&st.app.maybe_conn(), user1, user2)
-        .await
-        .unwrap();
+    unblock_user(
+        st.app.conn(),
+        &st.app.rconn(),
+        st.app.qconn(),
+        user1,
+        user2,
+        st.app.base_url(),
+    )
+    .await
+    .unwrap();
 
     assert!(
         !is_blocking_user(&st.app.maybe_conn(), user1, user2)
@@ -204,6 +221,183 @@ async fn test_user_block() {
     );
 }
 
+#[tokio::test]
+async fn test_user_block_removes_follows() {
+    let st = test_setup().await;
+
+    let user1 = register_user_for_test(&st, "user1").await;
+    let user2 = register_user_for_test(&st, "user2").await;
+
+    user_follow_for_test(&st, user1, user2, true).await;
+    user_follow_for_test(&st, user2, user1, true).await;
+
+    block_user(
+        st.app.conn(),
+        &st.app.rconn(),
+        st.app.qconn(),
+        user1,
+        user2,
+        st.app.base_url(),
+    )
+    .await
+    .unwrap();
+
+    let u1_stats = get_follow_stats_for_test(&st, user1).await;
+    let u2_stats = get_follow_stats_for_test(&st, user2).await;
+    assert_eq!(u1_stats.following, 0);
+    assert_eq!(u1_stats.followers, 0);
+    assert_eq!(u2_stats.following, 0);
+    assert_eq!(u2_stats.followers, 0);
+}
+
+#[tokio::test]
+async fn test_user_mute_suppresses_notifications() {
+    let st = test_setup().await;
+
+    let user1 = register_user_for_test(&st, "user1").await;
+    let user2 = register_user_for_test(&st, "user2").await;
+
+    mute_user(st.app.conn(), &st.app.rconn(), user2, user1, None)
+        .await
+        .unwrap();
+    assert!(
+        is_muting_user(&st.app.maybe_conn(), user2, user1)
+            .await
+            .unwrap()
+    );
+    assert!(
+        !is_muting_user(&st.app.maybe_conn(), user1, user2)
+            .await
+            .unwrap()
+    );
+
+    user_follow_for_test(&st, user1, user2, true).await;
+
+    // ミュートはフォロー関係には影響しない
+    let u2_stats = get_follow_stats_for_test(&st, user2).await;
+    assert_eq!(u2_stats.followers, 1);
+
+    let notifications = get_notifications(st.app.conn(), user2).await.unwrap();
+    assert_eq!(notifications.len(), 0);
+}
+
+#[tokio::test]
+async fn test_user_mute_hides_existing_notifications() {
+    let st = test_setup().await;
+
+    let user1 = register_user_for_test(&st, "user1").await;
+    let user2 = register_user_for_test(&st, "user2").await;
+
+    user_follow_for_test(&st, user1, user2, true).await;
+    assert_eq!(
+        count_unread_notifications(st.app.conn(), user2)
+            .await
+            .unwrap(),
+        1
+    );
+
+    // ミュートする前に作られた通知も数えない
+    mute_user(st.app.conn(), &st.app.rconn(), user2, user1, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        count_unread_notifications(st.app.conn(), user2)
+            .await
+            .unwrap(),
+        0
+    );
+    assert_eq!(
+        get_notifications(st.app.conn(), user2).await.unwrap().len(),
+        0
+    );
+
+    unmute_user(st.app.conn(), user2, user1).await.unwrap();
+    assert_eq!(
+        count_unread_notifications(st.app.conn(), user2)
+            .await
+            .unwrap(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_user_mute_self() {
+    let st = test_setup().await;
+
+    let user1 = register_user_for_test(&st, "user1").await;
+
+    mute_user(st.app.conn(), &st.app.rconn(), user1, user1, None)
+        .await
+        .unwrap_err();
+}
+
+#[tokio::test]
+async fn test_user_delete_local() {
+    let st = test_setup().await;
+
+    let user1 = register_user_for_test(&st, "user1").await;
+    let user2 = register_user_for_test(&st, "user2").await;
+
+    user_follow_for_test(&st, user2, user1, true).await;
+
+    let opts = PostCreateOptionsBuilder::default().build().unwrap();
+    let note = create_note_for_test(
+        &st,
+        user1,
+        "content",
+        ContentType::Plain,
+        VisibilityModel::Public,
+        &opts,
+    )
+    .await
+    .unwrap();
+
+    delete_local_user(
+        st.app.conn(),
+        &st.app.rconn(),
+        st.app.qconn(),
+        st.app.storage(),
+        user1,
+        "wrongpass",
+        st.app.base_url(),
+    )
+    .await
+    .unwrap_err();
+    assert!(!is_user_deleted(&st.app.maybe_conn(), user1).await.unwrap());
+
+    delete_local_user(
+        st.app.conn(),
+        &st.app.rconn(),
+        st.app.qconn(),
+        st.app.storage(),
+        user1,
+        "testpass",
+        st.app.base_url(),
+    )
+    .await
+    .unwrap();
+    assert!(is_user_deleted(&st.app.maybe_conn(), user1).await.unwrap());
+
+    let deleted = get_note_by_id(&st.app.maybe_conn(), note, false)
+        .await
+        .unwrap();
+    assert!(deleted.is_none());
+
+    let u2_stats = get_follow_stats_for_test(&st, user2).await;
+    assert_eq!(u2_stats.following, 0);
+
+    // 削除されたユーザーはログインできない
+    let login = check_password_user(st.app.conn(), "user1", "testpass")
+        .await
+        .unwrap();
+    assert!(login.is_none());
+
+    // ユーザー名は再利用できない
+    register_user(st.app.conn(), "user1", "user1_nick", "testpass")
+        .await
+        .unwrap_err();
+}
+
 pub async fn user_follow_for_test(st: &TestState, user1: UserID, user2: UserID, add: bool) {
     if add {
         follow_user(
@@ -230,3 +424,98 @@ pub async fn user_follow_for_test(st: &TestState, user1: UserID, user2: UserID,
         .unwrap();
     }
 }
+
+pub async fn user_block_for_test(st: &TestState, blocker: UserID, blocked: UserID) {
+    block_user(
+        st.app.conn(),
+        &st.app.rconn(),
+        st.app.qconn(),
+        blocker,
+        blocked,
+        st.app.base_url(),
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn test_user_move_local() {
+    let st = test_setup().await;
+
+    let old = register_user_for_test(&st, "olduser").await;
+    let new = register_user_for_test(&st, "newuser").await;
+    let follower = register_user_for_test(&st, "follower").await;
+
+    user_follow_for_test(&st, follower, old, true).await;
+
+    let data = st.app.fed().to_request_data();
+
+    // 移行先にエイリアスが設定されていなければ移行できない
+    move_user(
+        st.app.conn(),
+        &st.app.rconn(),
+        st.app.qconn(),
+        st.app.wp(),
+        old,
+        "@newuser",
+        &st.app.my_domain(),
+        st.app.base_url(),
+        &data,
+    )
+    .await
+    .unwrap_err();
+
+    set_user_aliases(
+        st.app.conn(),
+        &st.app.rconn(),
+        st.app.qconn(),
+        new,
+        &["@olduser".to_string()],
+        &st.app.my_domain(),
+        st.app.base_url(),
+        &data,
+    )
+    .await
+    .unwrap();
+
+    move_user(
+        st.app.conn(),
+        &st.app.rconn(),
+        st.app.qconn(),
+        st.app.wp(),
+        old,
+        "@newuser",
+        &st.app.my_domain(),
+        st.app.base_url(),
+        &data,
+    )
+    .await
+    .unwrap();
+
+    let profile = get_user_profile_by_id(&st.app.maybe_conn(), &st.app.rconn(), None, old)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(profile.moved_to.map(|u| u.id), Some(new));
+
+    // ローカルのフォロワーは移行先をフォローする
+    let old_stats = get_follow_stats_for_test(&st, old).await;
+    assert_eq!(old_stats.followers, 0);
+    let new_stats = get_follow_stats_for_test(&st, new).await;
+    assert_eq!(new_stats.followers, 1);
+
+    // 二重の移行はできない
+    move_user(
+        st.app.conn(),
+        &st.app.rconn(),
+        st.app.qconn(),
+        st.app.wp(),
+        old,
+        "@newuser",
+        &st.app.my_domain(),
+        st.app.base_url(),
+        &data,
+    )
+    .await
+    .unwrap_err();
+}
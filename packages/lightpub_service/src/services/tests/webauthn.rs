@@ -0,0 +1,383 @@
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use chrono::Utc;
+use ciborium::Value;
+use p256::ecdsa::{Signature, SigningKey, signature::Signer};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::services::{
+    tests::common::test_setup,
+    two_factor::{
+        begin_totp_enrollment, confirm_totp_enrollment, create_pending_login, totp_code_at,
+    },
+    webauthn::{
+        AuthenticationCredential, AuthenticatorAssertionResponse, AuthenticatorAttestationResponse,
+        CredentialCreationOptions, CredentialRequestOptions, RegistrationCredential, RelyingParty,
+        authenticate_with_passkey, begin_passkey_authentication, begin_passkey_registration,
+        begin_pending_login_passkey, complete_pending_login_with_passkey, delete_passkey,
+        finish_passkey_registration, get_passkeys,
+    },
+};
+
+use super::auth::register_user_for_test;
+
+/// テスト用のソフトウェア認証器
+struct SoftwareAuthenticator {
+    key: SigningKey,
+    credential_id: Vec<u8>,
+    sign_count: u32,
+}
+
+impl SoftwareAuthenticator {
+    fn new() -> Self {
+        Self {
+            key: SigningKey::random(&mut rand::rngs::OsRng),
+            credential_id: rand::random::<[u8; 16]>().to_vec(),
+            sign_count: 0,
+        }
+    }
+
+    fn client_data(type_: &str, challenge: &str, origin: &str) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "type": type_,
+            "challenge": challenge,
+            "origin": origin,
+        }))
+        .unwrap()
+    }
+
+    fn cose_public_key(&self) -> Value {
+        let point = self.key.verifying_key().to_encoded_point(false);
+        Value::Map(vec![
+            (Value::Integer(1.into()), Value::Integer(2.into())),
+            (Value::Integer(3.into()), Value::Integer((-7).into())),
+            (Value::Integer((-1).into()), Value::Integer(1.into())),
+            (
+                Value::Integer((-2).into()),
+                Value::Bytes(point.x().unwrap().to_vec()),
+            ),
+            (
+                Value::Integer((-3).into()),
+                Value::Bytes(point.y().unwrap().to_vec()),
+            ),
+        ])
+    }
+
+    fn create(
+        &self,
+        rp_id: &str,
+        origin: &str,
+        options: &CredentialCreationOptions,
+    ) -> RegistrationCredential {
+        let client_data = Self::client_data("webauthn.create", &options.challenge, origin);
+
+        let mut auth_data = Sha256::digest(rp_id.as_bytes()).to_vec();
+        // UP | UV | AT
+        auth_data.push(0x45);
+        auth_data.extend_from_slice(&self.sign_count.to_be_bytes());
+        auth_data.extend_from_slice(&[0u8; 16]);
+        auth_data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+        auth_data.extend_from_slice(&self.credential_id);
+        ciborium::into_writer(&self.cose_public_key(), &mut auth_data).unwrap();
+
+        let attestation_object = Value::Map(vec![
+            (Value::Text("fmt".into()), Value::Text("none".into())),
+            (Value::Text("attStmt".into()), Value::Map(vec![])),
+            (Value::Text("authData".into()), Value::Bytes(auth_data)),
+        ]);
+        let mut attestation_object_bytes = vec![];
+        ciborium::into_writer(&attestation_object, &mut attestation_object_bytes).unwrap();
+
+        RegistrationCredential {
+            raw_id: BASE64_URL_SAFE_NO_PAD.encode(&self.credential_id),
+            response: AuthenticatorAttestationResponse {
+                client_data_json: BASE64_URL_SAFE_NO_PAD.encode(client_data),
+                attestation_object: BASE64_URL_SAFE_NO_PAD.encode(attestation_object_bytes),
+            },
+        }
+    }
+
+    fn get(
+        &mut self,
+        rp_id: &str,
+        origin: &str,
+        options: &CredentialRequestOptions,
+        user_verified: bool,
+    ) -> AuthenticationCredential {
+        self.sign_count += 1;
+        let client_data = Self::client_data("webauthn.get", &options.challenge, origin);
+
+        let mut auth_data = Sha256::digest(rp_id.as_bytes()).to_vec();
+        auth_data.push(if user_verified { 0x05 } else { 0x01 });
+        auth_data.extend_from_slice(&self.sign_count.to_be_bytes());
+
+        let mut signed_data = auth_data.clone();
+        signed_data.extend_from_slice(&Sha256::digest(&client_data));
+        let signature: Signature = self.key.sign(&signed_data);
+
+        AuthenticationCredential {
+            raw_id: BASE64_URL_SAFE_NO_PAD.encode(&self.credential_id),
+            response: AuthenticatorAssertionResponse {
+                client_data_json: BASE64_URL_SAFE_NO_PAD.encode(client_data),
+                authenticator_data: BASE64_URL_SAFE_NO_PAD.encode(auth_data),
+                signature: BASE64_URL_SAFE_NO_PAD.encode(signature.to_der().as_bytes()),
+                user_handle: None,
+            },
+        }
+    }
+}
+
+fn test_rp() -> RelyingParty {
+    RelyingParty::from_base_url(&Url::parse("https://example.com").unwrap())
+}
+
+#[tokio::test]
+async fn test_passkey_registration_and_login() {
+    let st = test_setup().await;
+    let app = &st.app;
+    let rp = test_rp();
+    assert_eq!(rp.id, "example.com");
+    assert_eq!(rp.origin, "https://example.com");
+
+    let user = register_user_for_test(&st, "user1").await;
+    let mut authenticator = SoftwareAuthenticator::new();
+
+    // 登録にはパスワードが必要
+    begin_passkey_registration(app.conn(), &app.rconn(), &rp, user, "wrongpass", None)
+        .await
+        .unwrap_err();
+
+    // 別のオリジンで作成したクレデンシャルは登録できない
+    let options = begin_passkey_registration(app.conn(), &app.rconn(), &rp, user, "testpass", None)
+        .await
+        .unwrap();
+    let credential = authenticator.create(&rp.id, "https://evil.example.net", &options);
+    finish_passkey_registration(
+        app.conn(),
+        &app.rconn(),
+        &rp,
+        user,
+        "testpass",
+        "laptop",
+        &credential,
+    )
+    .await
+    .unwrap_err();
+
+    let options = begin_passkey_registration(app.conn(), &app.rconn(), &rp, user, "testpass", None)
+        .await
+        .unwrap();
+    assert!(options.exclude_credentials.is_empty());
+    let credential = authenticator.create(&rp.id, &rp.origin, &options);
+    let passkey = finish_passkey_registration(
+        app.conn(),
+        &app.rconn(),
+        &rp,
+        user,
+        "testpass",
+        "laptop",
+        &credential,
+    )
+    .await
+    .unwrap();
+    assert_eq!(passkey.name, "laptop");
+
+    // チャレンジは 1 回しか使えない
+    finish_passkey_registration(
+        app.conn(),
+        &app.rconn(),
+        &rp,
+        user,
+        "testpass",
+        "laptop",
+        &credential,
+    )
+    .await
+    .unwrap_err();
+
+    let options = begin_passkey_registration(app.conn(), &app.rconn(), &rp, user, "testpass", None)
+        .await
+        .unwrap();
+    assert_eq!(options.exclude_credentials.len(), 1);
+
+    // パスワードの代わりにログインする
+    let options = begin_passkey_authentication(app.conn(), &app.rconn(), &rp, None)
+        .await
+        .unwrap();
+    assert!(options.allow_credentials.is_empty());
+    let assertion = authenticator.get(&rp.id, &rp.origin, &options, true);
+    let logged_in = authenticate_with_passkey(app.conn(), &app.rconn(), &rp, &assertion)
+        .await
+        .unwrap();
+    assert_eq!(logged_in, user);
+
+    // 同じ応答を再送しても認証できない
+    authenticate_with_passkey(app.conn(), &app.rconn(), &rp, &assertion)
+        .await
+        .unwrap_err();
+
+    // パスワードの代わりに使う場合は本人確認が必要
+    let options = begin_passkey_authentication(app.conn(), &app.rconn(), &rp, None)
+        .await
+        .unwrap();
+    let assertion = authenticator.get(&rp.id, &rp.origin, &options, false);
+    authenticate_with_passkey(app.conn(), &app.rconn(), &rp, &assertion)
+        .await
+        .unwrap_err();
+
+    // 登録していない鍵の署名は受け付けない
+    let options = begin_passkey_authentication(app.conn(), &app.rconn(), &rp, None)
+        .await
+        .unwrap();
+    let mut forged = SoftwareAuthenticator::new();
+    forged.credential_id = authenticator.credential_id.clone();
+    forged.sign_count = 100;
+    let assertion = forged.get(&rp.id, &rp.origin, &options, true);
+    authenticate_with_passkey(app.conn(), &app.rconn(), &rp, &assertion)
+        .await
+        .unwrap_err();
+
+    // 署名カウンタが戻った場合は認証器が複製されたとみなす
+    let options = begin_passkey_authentication(app.conn(), &app.rconn(), &rp, None)
+        .await
+        .unwrap();
+    authenticator.sign_count = 0;
+    let assertion = authenticator.get(&rp.id, &rp.origin, &options, true);
+    authenticate_with_passkey(app.conn(), &app.rconn(), &rp, &assertion)
+        .await
+        .unwrap_err();
+
+    let passkeys = get_passkeys(&app.maybe_conn(), user).await.unwrap();
+    assert_eq!(passkeys.len(), 1);
+    assert!(passkeys[0].last_used_at.is_some());
+
+    delete_passkey(app.conn(), user, passkey.id, "wrongpass", None)
+        .await
+        .unwrap_err();
+    delete_passkey(app.conn(), user, passkey.id, "testpass", None)
+        .await
+        .unwrap();
+    delete_passkey(app.conn(), user, passkey.id, "testpass", None)
+        .await
+        .unwrap_err();
+    assert!(
+        get_passkeys(&app.maybe_conn(), user)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn test_passkey_second_factor() {
+    let st = test_setup().await;
+    let app = &st.app;
+    let rp = test_rp();
+
+    let user1 = register_user_for_test(&st, "user1").await;
+    let user2 = register_user_for_test(&st, "user2").await;
+
+    let enrollment = begin_totp_enrollment(app.conn(), user1, "example.com")
+        .await
+        .unwrap();
+    let recovery_codes = confirm_totp_enrollment(
+        app.conn(),
+        user1,
+        &totp_code_at(&enrollment.secret, Utc::now()).unwrap(),
+    )
+    .await
+    .unwrap();
+
+    // 二要素認証が有効な場合は、パスワードに加えてコードが必要
+    begin_passkey_registration(app.conn(), &app.rconn(), &rp, user1, "testpass", None)
+        .await
+        .unwrap_err();
+    begin_passkey_registration(
+        app.conn(),
+        &app.rconn(),
+        &rp,
+        user1,
+        "testpass",
+        Some("invalid"),
+    )
+    .await
+    .unwrap_err();
+
+    let mut authenticator1 = SoftwareAuthenticator::new();
+    let options = begin_passkey_registration(
+        app.conn(),
+        &app.rconn(),
+        &rp,
+        user1,
+        "testpass",
+        Some(&recovery_codes[0]),
+    )
+    .await
+    .unwrap();
+    let credential = authenticator1.create(&rp.id, &rp.origin, &options);
+    finish_passkey_registration(
+        app.conn(),
+        &app.rconn(),
+        &rp,
+        user1,
+        "testpass",
+        "key",
+        &credential,
+    )
+    .await
+    .unwrap();
+
+    let mut authenticator2 = SoftwareAuthenticator::new();
+    let options =
+        begin_passkey_registration(app.conn(), &app.rconn(), &rp, user2, "testpass", None)
+            .await
+            .unwrap();
+    let credential = authenticator2.create(&rp.id, &rp.origin, &options);
+    finish_passkey_registration(
+        app.conn(),
+        &app.rconn(),
+        &rp,
+        user2,
+        "testpass",
+        "key",
+        &credential,
+    )
+    .await
+    .unwrap();
+
+    let ticket = create_pending_login(&app.rconn(), user1).await.unwrap();
+
+    // 二要素認証ではパスワードを確認したユーザーのパスキーだけを受け付ける
+    let options = begin_pending_login_passkey(app.conn(), &app.rconn(), &rp, &ticket)
+        .await
+        .unwrap();
+    assert_eq!(options.allow_credentials.len(), 1);
+    let assertion = authenticator2.get(&rp.id, &rp.origin, &options, false);
+    complete_pending_login_with_passkey(app.conn(), &app.rconn(), &rp, &ticket, &assertion)
+        .await
+        .unwrap_err();
+
+    // 二要素認証用のチャレンジはパスワードなしのログインには使えない
+    let options = begin_pending_login_passkey(app.conn(), &app.rconn(), &rp, &ticket)
+        .await
+        .unwrap();
+    let assertion = authenticator1.get(&rp.id, &rp.origin, &options, true);
+    authenticate_with_passkey(app.conn(), &app.rconn(), &rp, &assertion)
+        .await
+        .unwrap_err();
+
+    let options = begin_pending_login_passkey(app.conn(), &app.rconn(), &rp, &ticket)
+        .await
+        .unwrap();
+    let assertion = authenticator1.get(&rp.id, &rp.origin, &options, false);
+    let logged_in =
+        complete_pending_login_with_passkey(app.conn(), &app.rconn(), &rp, &ticket, &assertion)
+            .await
+            .unwrap();
+    assert_eq!(logged_in, user1);
+
+    // チケットは 1 回しか使えない
+    begin_pending_login_passkey(app.conn(), &app.rconn(), &rp, &ticket)
+        .await
+        .unwrap_err();
+}
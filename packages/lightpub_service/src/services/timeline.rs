@@ -27,12 +27,17 @@ use super::{
     id::{NoteID, UserID},
 };
 
+/// タイムラインのノート ID を取得する。
+///
+/// `after_date` を指定した場合は、それより新しいノートを古い順に返す。
+/// 指定しない場合は新しい順に返す。
 pub async fn get_timeline_note_ids(
     conn: &MaybeTxConn,
     viewer_id: Option<UserID>,
     include_public: bool,
     limit: u64,
     before_date: Option<DateTime<Utc>>,
+    after_date: Option<DateTime<Utc>>,
 ) -> ServiceResult<Vec<NoteID>> {
     get_note_ids_generalized(
         conn,
@@ -43,6 +48,7 @@ pub async fn get_timeline_note_ids(
         None,
         limit,
         before_date,
+        after_date,
     )
     .await
 }
@@ -63,6 +69,7 @@ pub async fn get_note_reply_ids(
         Some(target_note_id),
         limit,
         before_date,
+        None,
     )
     .await
 }
@@ -76,11 +83,12 @@ async fn get_note_ids_generalized(
     limit_reply_to_id: Option<NoteID>,
     limit: u64,
     before_date: Option<DateTime<Utc>>,
+    after_date: Option<DateTime<Utc>>,
 ) -> ServiceResult<Vec<NoteID>> {
     let ids = conn
         .query_all(Statement::from_sql_and_values(
             conn.get_database_backend(),
-            r#"CALL get_note_ids_generalized(?,?,?,?,?,?,?)"#,
+            r#"CALL get_note_ids_generalized(?,?,?,?,?,?,?,?)"#,
             [
                 viewer_id.map(|a| a.as_db()).into(),
                 include_self.into(),
@@ -89,6 +97,7 @@ async fn get_note_ids_generalized(
                 limit_reply_to_id.map(|a| a.as_db()).into(),
                 limit.into(),
                 before_date.map(|d| d.naive_utc()).into(),
+                after_date.map(|d| d.naive_utc()).into(),
             ],
         ))
         .await
@@ -0,0 +1,563 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! 二要素認証
+//!
+//! 認証アプリで生成するワンタイムパスワード (RFC 6238 の TOTP) と、
+//! 認証アプリを使えなくなったときのためのリカバリーコードを提供する。
+//! 二要素認証を有効にしたユーザーは、パスワードを確認した後に
+//! コードを入力するまでログイン用のトークンを受け取れない。
+
+use std::time::Duration;
+
+use actix_web::http::StatusCode;
+use chrono::{DateTime, Utc};
+use expected_error_derive::ExpectedError;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, EntityTrait, PaginatorTrait, QueryFilter, Set,
+    TransactionTrait, sea_query::Expr,
+};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use thiserror::Error;
+
+use super::{
+    MapToUnknown, ServiceError, ServiceResult,
+    db::{Conn, MaybeTxConn},
+    id::{Identifier, UserID},
+    kv::KVObject,
+    oauth::{generate_token, hash_token},
+    user::{UserSuspendError, is_user_suspended},
+};
+
+/// ワンタイムパスワードの桁数
+const TOTP_DIGITS: u32 = 6;
+
+/// ワンタイムパスワードが切り替わる間隔 (秒)
+const TOTP_PERIOD: i64 = 30;
+
+/// 端末の時計のずれを考慮して、前後何ステップまでのコードを受け付けるか
+const TOTP_SKEW: i64 = 1;
+
+/// 共有鍵の長さ (バイト)。RFC 4226 では 160 ビットを推奨している。
+const TOTP_SECRET_LENGTH: usize = 20;
+
+/// 一度に発行するリカバリーコードの数
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// リカバリーコードの文字数 (区切り文字を除く)
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+/// パスワードを確認してから、コードを入力するまでの制限時間
+const PENDING_LOGIN_TTL: Duration = Duration::from_secs(60 * 5);
+
+/// ログイン時にコードを間違えられる回数。チケットではなくユーザーごとに数える。
+const LOGIN_MAX_ATTEMPTS: u64 = 5;
+
+/// コードを間違えた回数を数える期間。最初に間違えてからこの時間が経つとリセットされる。
+const LOGIN_ATTEMPTS_WINDOW: Duration = Duration::from_secs(60 * 15);
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+#[derive(Debug, Clone, Error, ExpectedError)]
+pub enum TwoFactorError {
+    #[error("two-factor authentication is already enabled")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    AlreadyEnabled,
+    #[error("two-factor authentication is not enabled")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    NotEnabled,
+    #[error("two-factor authentication setup has not been started")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    NotEnrolling,
+    #[error("invalid code")]
+    #[ee(status(StatusCode::UNAUTHORIZED))]
+    InvalidCode,
+    #[error("incorrect password")]
+    #[ee(status(StatusCode::UNAUTHORIZED))]
+    IncorrectPassword,
+    #[error("login expired")]
+    #[ee(status(StatusCode::UNAUTHORIZED))]
+    LoginExpired,
+}
+
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for &b in bytes {
+        buffer = (buffer << 8) | b as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    out
+}
+
+fn base32_decode(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len() * 5 / 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in s.bytes().filter(|&c| c != b'=') {
+        let v = BASE32_ALPHABET
+            .iter()
+            .position(|&a| a == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | v as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// 比較にかかる時間からコードが推測されないように、長さ以外は常に全体を比較する。
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn totp_step(at: DateTime<Utc>) -> i64 {
+    at.timestamp().div_euclid(TOTP_PERIOD)
+}
+
+fn hotp(secret: &[u8], counter: u64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // RFC 4226 5.3 の dynamic truncation
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset],
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]) & 0x7fff_ffff;
+
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(TOTP_DIGITS),
+        width = TOTP_DIGITS as usize
+    )
+}
+
+/// 指定した時刻のワンタイムパスワードを計算する。
+/// secret は Base32 でエンコードした共有鍵。
+pub fn totp_code_at(secret: &str, at: DateTime<Utc>) -> Option<String> {
+    let secret = base32_decode(secret)?;
+    Some(hotp(&secret, totp_step(at) as u64))
+}
+
+/// コードが一致したタイムステップを返す。
+fn find_totp_step(secret: &str, code: &str, at: DateTime<Utc>) -> Option<i64> {
+    let secret = base32_decode(secret)?;
+    let now = totp_step(at);
+    (now - TOTP_SKEW..=now + TOTP_SKEW)
+        .find(|&step| constant_time_eq(hotp(&secret, step as u64).as_bytes(), code.as_bytes()))
+}
+
+fn generate_totp_secret() -> String {
+    let mut secret = [0u8; TOTP_SECRET_LENGTH];
+    rand::thread_rng().fill(&mut secret);
+    base32_encode(&secret)
+}
+
+fn generate_recovery_code() -> String {
+    let mut rng = rand::thread_rng();
+    let code: String = (0..RECOVERY_CODE_LENGTH)
+        .map(|_| BASE32_ALPHABET[rng.gen_range(0..32)].to_ascii_lowercase() as char)
+        .collect();
+    let (first, second) = code.split_at(RECOVERY_CODE_LENGTH / 2);
+    format!("{first}-{second}")
+}
+
+/// 入力されたリカバリーコードから区切り文字や空白を取り除く。
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn hash_recovery_code(code: &str) -> String {
+    hash_token(&normalize_recovery_code(code))
+}
+
+#[derive(Debug, Clone)]
+pub struct TotpEnrollment {
+    /// Base32 でエンコードした共有鍵。QR コードを読み取れない場合に手動で入力する。
+    pub secret: String,
+    /// 認証アプリに登録するための otpauth:// URI
+    pub otpauth_uri: String,
+}
+
+fn build_otpauth_uri(issuer: &str, username: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        urlencoding::encode(issuer),
+        urlencoding::encode(username),
+        secret,
+        urlencoding::encode(issuer),
+        TOTP_DIGITS,
+        TOTP_PERIOD
+    )
+}
+
+pub async fn is_two_factor_enabled(tx: &MaybeTxConn, user_id: UserID) -> ServiceResult<bool> {
+    let totp = entity::user_totp::Entity::find_by_id(user_id.as_db())
+        .one(tx)
+        .await
+        .map_err_unknown()?;
+    Ok(totp.is_some_and(|t| t.enabled))
+}
+
+/// 未使用のリカバリーコードの数
+pub async fn count_remaining_recovery_codes(
+    tx: &MaybeTxConn,
+    user_id: UserID,
+) -> ServiceResult<u64> {
+    entity::user_recovery_code::Entity::find()
+        .filter(
+            Condition::all()
+                .add(entity::user_recovery_code::Column::UserId.eq(user_id.as_db()))
+                .add(entity::user_recovery_code::Column::UsedAt.is_null()),
+        )
+        .count(tx)
+        .await
+        .map_err_unknown()
+}
+
+/// 二要素認証の設定を開始する。
+/// 新しい共有鍵を生成するが、confirm_totp_enrollment でコードを確認するまでは有効にならない。
+/// issuer は認証アプリに表示されるサービス名。
+pub async fn begin_totp_enrollment(
+    conn: &Conn,
+    user_id: UserID,
+    issuer: &str,
+) -> ServiceResult<TotpEnrollment> {
+    let user = entity::user::Entity::find_by_id(user_id.as_db())
+        .one(conn.db())
+        .await
+        .map_err_unknown()?
+        .ok_or_else(|| ServiceError::ise("user not found"))?;
+
+    let txn = conn.db().begin().await.map_err_unknown()?;
+
+    let existing = entity::user_totp::Entity::find_by_id(user_id.as_db())
+        .one(&txn)
+        .await
+        .map_err_unknown()?;
+    if let Some(existing) = existing {
+        if existing.enabled {
+            return Err(ServiceError::known(TwoFactorError::AlreadyEnabled));
+        }
+        entity::user_totp::Entity::delete_by_id(user_id.as_db())
+            .exec(&txn)
+            .await
+            .map_err_unknown()?;
+    }
+
+    let secret = generate_totp_secret();
+    let model = entity::user_totp::ActiveModel {
+        user_id: Set(user_id.as_db()),
+        secret: Set(secret.clone()),
+        enabled: Set(false),
+        last_used_step: Set(None),
+        created_at: Set(Utc::now().naive_utc()),
+    };
+    model.insert(&txn).await.map_err_unknown()?;
+
+    txn.commit().await.map_err_unknown()?;
+
+    Ok(TotpEnrollment {
+        otpauth_uri: build_otpauth_uri(issuer, &user.username, &secret),
+        secret,
+    })
+}
+
+/// 認証アプリに表示されたコードを確認して、二要素認証を有効にする。
+/// 発行したリカバリーコードを返す。リカバリーコードはここでしか取得できない。
+pub async fn confirm_totp_enrollment(
+    conn: &Conn,
+    user_id: UserID,
+    code: &str,
+) -> ServiceResult<Vec<String>> {
+    let txn = conn.db().begin().await.map_err_unknown()?;
+
+    let totp = entity::user_totp::Entity::find_by_id(user_id.as_db())
+        .one(&txn)
+        .await
+        .map_err_unknown()?;
+    let totp = match totp {
+        None => return Err(ServiceError::known(TwoFactorError::NotEnrolling)),
+        Some(t) if t.enabled => return Err(ServiceError::known(TwoFactorError::AlreadyEnabled)),
+        Some(t) => t,
+    };
+
+    let Some(step) = find_totp_step(&totp.secret, code.trim(), Utc::now()) else {
+        return Err(ServiceError::known(TwoFactorError::InvalidCode));
+    };
+
+    let mut totp: entity::user_totp::ActiveModel = totp.into();
+    totp.enabled = Set(true);
+    totp.last_used_step = Set(Some(step));
+    totp.update(&txn).await.map_err_unknown()?;
+
+    entity::user_recovery_code::Entity::delete_many()
+        .filter(entity::user_recovery_code::Column::UserId.eq(user_id.as_db()))
+        .exec(&txn)
+        .await
+        .map_err_unknown()?;
+
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| generate_recovery_code())
+        .collect();
+    entity::user_recovery_code::Entity::insert_many(codes.iter().map(|code| {
+        entity::user_recovery_code::ActiveModel {
+            user_id: Set(user_id.as_db()),
+            code: Set(hash_recovery_code(code)),
+            used_at: Set(None),
+            ..Default::default()
+        }
+    }))
+    .exec(&txn)
+    .await
+    .map_err_unknown()?;
+
+    txn.commit().await.map_err_unknown()?;
+
+    Ok(codes)
+}
+
+/// 認証アプリのコードまたはリカバリーコードを確認する。
+/// 一度使用したコードは使えなくなる。
+/// 二要素認証が有効でない場合は false を返す。
+pub async fn verify_two_factor_code(
+    conn: &Conn,
+    user_id: UserID,
+    code: &str,
+) -> ServiceResult<bool> {
+    let totp = entity::user_totp::Entity::find_by_id(user_id.as_db())
+        .one(conn.db())
+        .await
+        .map_err_unknown()?;
+    let totp = match totp {
+        Some(t) if t.enabled => t,
+        _ => return Ok(false),
+    };
+
+    let code = code.trim();
+    if code.len() == TOTP_DIGITS as usize && code.bytes().all(|c| c.is_ascii_digit()) {
+        let Some(step) = find_totp_step(&totp.secret, code, Utc::now()) else {
+            return Ok(false);
+        };
+        // 同じコードや、それより前のコードは再利用できない
+        let result = entity::user_totp::Entity::update_many()
+            .col_expr(
+                entity::user_totp::Column::LastUsedStep,
+                Expr::value(Some(step)),
+            )
+            .filter(
+                Condition::all()
+                    .add(entity::user_totp::Column::UserId.eq(user_id.as_db()))
+                    .add(
+                        Condition::any()
+                            .add(entity::user_totp::Column::LastUsedStep.is_null())
+                            .add(entity::user_totp::Column::LastUsedStep.lt(step)),
+                    ),
+            )
+            .exec(conn.db())
+            .await
+            .map_err_unknown()?;
+        return Ok(result.rows_affected == 1);
+    }
+
+    let result = entity::user_recovery_code::Entity::update_many()
+        .col_expr(
+            entity::user_recovery_code::Column::UsedAt,
+            Expr::value(Some(Utc::now().naive_utc())),
+        )
+        .filter(
+            Condition::all()
+                .add(entity::user_recovery_code::Column::UserId.eq(user_id.as_db()))
+                .add(entity::user_recovery_code::Column::Code.eq(hash_recovery_code(code)))
+                .add(entity::user_recovery_code::Column::UsedAt.is_null()),
+        )
+        .exec(conn.db())
+        .await
+        .map_err_unknown()?;
+    Ok(result.rows_affected > 0)
+}
+
+pub(crate) async fn verify_password(
+    conn: &Conn,
+    user_id: UserID,
+    password: &str,
+) -> ServiceResult<()> {
+    let user = entity::user::Entity::find_by_id(user_id.as_db())
+        .one(conn.db())
+        .await
+        .map_err_unknown()?
+        .ok_or_else(|| ServiceError::ise("user not found"))?;
+    let password_ok = match &user.password {
+        Some(hashed) => bcrypt::verify(password, hashed).map_err_unknown()?,
+        None => false,
+    };
+    if !password_ok {
+        return Err(ServiceError::known(TwoFactorError::IncorrectPassword));
+    }
+    Ok(())
+}
+
+/// ログイン方法を変更する操作の前に、ログイン時と同じ確認をやり直す。
+/// セッションを盗まれただけでは操作できないように、パスワードと、
+/// 二要素認証が有効な場合は認証アプリのコードまたはリカバリーコードを確認する。
+pub async fn verify_reauthentication(
+    conn: &Conn,
+    user_id: UserID,
+    password: &str,
+    code: Option<&str>,
+) -> ServiceResult<()> {
+    verify_password(conn, user_id, password).await?;
+
+    if is_two_factor_enabled(&conn.clone().into(), user_id).await? {
+        let Some(code) = code else {
+            return Err(ServiceError::known(TwoFactorError::InvalidCode));
+        };
+        if !verify_two_factor_code(conn, user_id, code).await? {
+            return Err(ServiceError::known(TwoFactorError::InvalidCode));
+        }
+    }
+    Ok(())
+}
+
+/// 二要素認証を無効にする。
+/// アカウントを乗っ取られた場合に無効にされないように、パスワードと現在のコードの両方を確認する。
+pub async fn disable_two_factor(
+    conn: &Conn,
+    user_id: UserID,
+    password: &str,
+    code: &str,
+) -> ServiceResult<()> {
+    if !is_two_factor_enabled(&conn.clone().into(), user_id).await? {
+        return Err(ServiceError::known(TwoFactorError::NotEnabled));
+    }
+
+    verify_reauthentication(conn, user_id, password, Some(code)).await?;
+
+    let txn = conn.db().begin().await.map_err_unknown()?;
+    entity::user_recovery_code::Entity::delete_many()
+        .filter(entity::user_recovery_code::Column::UserId.eq(user_id.as_db()))
+        .exec(&txn)
+        .await
+        .map_err_unknown()?;
+    entity::user_totp::Entity::delete_by_id(user_id.as_db())
+        .exec(&txn)
+        .await
+        .map_err_unknown()?;
+    txn.commit().await.map_err_unknown()?;
+
+    Ok(())
+}
+
+/// パスワードを確認済みで、二要素認証を待っているログイン
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PendingLogin {
+    user_id: UserID,
+}
+
+fn pending_login_key(ticket: &str) -> String {
+    format!("login:2fa:{}", hash_token(ticket))
+}
+
+fn login_attempts_key(user_id: UserID) -> String {
+    format!("login:2fa:attempts:{user_id}")
+}
+
+/// パスワードを確認したユーザーに、二要素認証のためのチケットを発行する。
+pub async fn create_pending_login(rconn: &KVObject, user_id: UserID) -> ServiceResult<String> {
+    let ticket = generate_token();
+    let data = PendingLogin { user_id };
+    rconn
+        .set_ttl(pending_login_key(&ticket), &data, PENDING_LOGIN_TTL)
+        .await?;
+    Ok(ticket)
+}
+
+async fn get_pending_login(rconn: &KVObject, ticket: &str) -> ServiceResult<PendingLogin> {
+    let data: Option<PendingLogin> = rconn.get(pending_login_key(ticket)).await?;
+    data.ok_or_else(|| ServiceError::known(TwoFactorError::LoginExpired))
+}
+
+/// チケットを発行したユーザーを返す。
+pub async fn get_pending_login_user(rconn: &KVObject, ticket: &str) -> ServiceResult<UserID> {
+    Ok(get_pending_login(rconn, ticket).await?.user_id)
+}
+
+/// 二要素認証を確認した後に呼び出して、チケットを無効にする。
+pub(crate) async fn finish_pending_login(
+    conn: &Conn,
+    rconn: &KVObject,
+    ticket: &str,
+    user_id: UserID,
+) -> ServiceResult<UserID> {
+    rconn.delete(pending_login_key(ticket)).await?;
+    if is_user_suspended(&conn.clone().into(), user_id).await? {
+        return Err(ServiceError::known(UserSuspendError::Suspended));
+    }
+    Ok(user_id)
+}
+
+/// チケットとコードを確認して、ログインを完了する。
+/// 一定時間内にコードを間違えすぎた場合はチケットが無効になり、
+/// パスワードを入力し直しても、期間が過ぎるまではコードを受け付けない。
+pub async fn complete_pending_login(
+    conn: &Conn,
+    rconn: &KVObject,
+    ticket: &str,
+    code: &str,
+) -> ServiceResult<UserID> {
+    let data = get_pending_login(rconn, ticket).await?;
+
+    // 並行して試されても回数を取りこぼさないように、確認する前に原子的に数える
+    let attempts = rconn
+        .incr_ttl(login_attempts_key(data.user_id), LOGIN_ATTEMPTS_WINDOW)
+        .await?;
+    if attempts > LOGIN_MAX_ATTEMPTS {
+        rconn.delete(pending_login_key(ticket)).await?;
+        return Err(ServiceError::known(TwoFactorError::InvalidCode));
+    }
+
+    if verify_two_factor_code(conn, data.user_id, code).await? {
+        rconn.delete(login_attempts_key(data.user_id)).await?;
+        return finish_pending_login(conn, rconn, ticket, data.user_id).await;
+    }
+
+    if attempts == LOGIN_MAX_ATTEMPTS {
+        rconn.delete(pending_login_key(ticket)).await?;
+    }
+    Err(ServiceError::known(TwoFactorError::InvalidCode))
+}
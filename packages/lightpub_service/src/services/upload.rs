@@ -18,7 +18,6 @@ along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use std::{
     path::{Path, PathBuf},
-    str::FromStr,
     sync::Once,
 };
 
@@ -29,16 +28,26 @@ use url::Url;
 use super::{
     MapToUnknown, ServiceResult, create_error_simple,
     db::MaybeTxConn,
+    domain_policy::{DomainPolicyError, get_domain_policy_for_url},
     id::{Identifier, UploadID},
+    media_cache::{MediaCacheConfig, cache_remote_media, cached_media_keys},
+    storage::{StorageObject, StorageServe},
 };
 use actix_web::http::StatusCode;
+use image::{
+    AnimationDecoder, DynamicImage, ImageDecoder, ImageError, ImageFormat,
+    codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+    imageops::FilterType,
+    metadata::Orientation,
+};
 use infer::Type;
 use rexiv2::Metadata;
 use sea_orm::Set;
-use sea_orm::{ActiveModelTrait, EntityTrait};
+use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
 use tempfile::NamedTempFile;
 use thiserror::Error;
-use tracing::error;
+use tracing::{error, warn};
 
 static START: Once = Once::new();
 
@@ -47,33 +56,556 @@ pub enum UploadError {
     #[error("exif error")]
     #[ee(status(StatusCode::BAD_REQUEST))]
     ExifError,
+    #[error("unsupported file type")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    UnsupportedFileType,
+    #[error("file too large")]
+    #[ee(status(StatusCode::PAYLOAD_TOO_LARGE))]
+    FileTooLarge,
+    #[error("invalid media file")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    InvalidMedia,
 }
 
-pub fn save_upload_file(tmp: NamedTempFile) -> ServiceResult<(UploadID, PathBuf, Type)> {
-    let upload_id = UploadID::new_random();
-    let ext = {
-        let mime = infer::get_from_path(tmp.path()).map_err_unknown()?;
-        mime.map(|m| format!(".{}", m.extension()))
-            .unwrap_or_else(|| "".to_string())
+/// アップロードできるファイルの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadKind {
+    Image,
+    Video,
+    Audio,
+}
+
+impl UploadKind {
+    /// MIME タイプから種類を判定する。
+    pub fn from_mime_type(mime_type: &str) -> Option<Self> {
+        if mime_type.starts_with("image/") {
+            Some(Self::Image)
+        } else if mime_type.starts_with("video/") {
+            Some(Self::Video)
+        } else if mime_type.starts_with("audio/") {
+            Some(Self::Audio)
+        } else {
+            None
+        }
+    }
+
+    /// ActivityPub の添付ファイルの type
+    pub fn apub_type(&self) -> &'static str {
+        match self {
+            Self::Image => "Image",
+            Self::Video => "Video",
+            Self::Audio => "Audio",
+        }
+    }
+
+    /// ローカルにアップロードできる最大のファイルサイズ (バイト)
+    pub fn max_size(&self) -> u64 {
+        match self {
+            Self::Image => 10 * 1024 * 1024,
+            Self::Video => 100 * 1024 * 1024,
+            Self::Audio => 40 * 1024 * 1024,
+        }
+    }
+
+    /// ローカルにアップロードできるかどうか
+    fn accepts(&self, mime_type: &str) -> bool {
+        match self {
+            Self::Image => true,
+            Self::Video => matches!(mime_type, "video/mp4" | "video/webm"),
+            Self::Audio => matches!(mime_type, "audio/mpeg" | "audio/ogg" | "audio/opus"),
+        }
+    }
+}
+
+/// 1 回のリクエストでアップロードできるファイルの合計サイズ (バイト)
+pub const MAX_UPLOAD_REQUEST_SIZE: usize = 256 * 1024 * 1024;
+
+/// 説明文 (代替テキスト) の最大の文字数
+const MAX_UPLOAD_DESCRIPTION_CHARS: usize = 1500;
+
+/// blurhash の計算に使う縮小画像の大きさ
+const BLURHASH_SAMPLE_SIZE: u32 = 64;
+
+/// アップロードされたファイルのメタデータ
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadMetadata {
+    /// 説明文 (代替テキスト)
+    pub description: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub blurhash: Option<String>,
+    /// フォーカルポイント (x, y)。それぞれ -1.0 ~ 1.0 で、(0, 0) が中心。
+    pub focal_point: Option<(f32, f32)>,
+    /// 動画・音声の長さ (秒)
+    #[serde(default)]
+    pub duration: Option<f64>,
+    /// 動画のサムネイル
+    #[serde(default)]
+    pub thumbnail_id: Option<UploadID>,
+    /// 画像のバリアント ([`UploadVariant`]) が生成済みかどうか
+    #[serde(default)]
+    pub has_variants: bool,
+}
+
+impl UploadMetadata {
+    pub(crate) fn from_model(model: &entity::upload::Model) -> Self {
+        Self {
+            description: model.description.clone(),
+            width: model.width.and_then(|w| u32::try_from(w).ok()),
+            height: model.height.and_then(|h| u32::try_from(h).ok()),
+            blurhash: model.blurhash.clone(),
+            focal_point: model.focal_x.zip(model.focal_y),
+            duration: model.duration,
+            thumbnail_id: model.thumbnail_id.clone().map(UploadID::from_db_trusted),
+            has_variants: model.has_variants,
+        }
+    }
+
+    pub(crate) fn set_to_model(&self, model: &mut entity::upload::ActiveModel) {
+        model.description = Set(self.description.clone());
+        model.width = Set(self.width.and_then(|w| i32::try_from(w).ok()));
+        model.height = Set(self.height.and_then(|h| i32::try_from(h).ok()));
+        model.blurhash = Set(self.blurhash.clone());
+        model.focal_x = Set(self.focal_point.map(|(x, _)| x));
+        model.focal_y = Set(self.focal_point.map(|(_, y)| y));
+        model.duration = Set(self.duration);
+        model.thumbnail_id = Set(self.thumbnail_id.as_ref().map(|t| t.as_db()));
+        model.has_variants = Set(self.has_variants);
+    }
+
+    /// リモートから受け取ったメタデータを保存できる形に整える。
+    /// 不正な値は捨て、長すぎる説明文は切り詰める。
+    pub fn sanitize_remote(mut self) -> Self {
+        self.description = self
+            .description
+            .map(|d| {
+                d.chars()
+                    .take(MAX_UPLOAD_DESCRIPTION_CHARS)
+                    .collect::<String>()
+            })
+            .filter(|d| !d.trim().is_empty());
+        self.blurhash = self.blurhash.filter(|b| validate_blurhash(b));
+        self.focal_point = self.focal_point.filter(|p| validate_focal_point(*p));
+        self.duration = self.duration.filter(|d| d.is_finite() && *d >= 0.0);
+        self.thumbnail_id = None;
+        self.has_variants = false;
+        self
+    }
+}
+
+/// 説明文とフォーカルポイントを検証する。
+pub fn validate_upload_description(
+    description: Option<&str>,
+    focal_point: Option<(f32, f32)>,
+) -> ServiceResult<()> {
+    if description.is_some_and(|d| d.chars().count() > MAX_UPLOAD_DESCRIPTION_CHARS) {
+        return create_error_simple(StatusCode::BAD_REQUEST, "description too long");
+    }
+    if focal_point.is_some_and(|p| !validate_focal_point(p)) {
+        return create_error_simple(StatusCode::BAD_REQUEST, "invalid focal point");
+    }
+    Ok(())
+}
+
+fn validate_focal_point((x, y): (f32, f32)) -> bool {
+    (-1.0..=1.0).contains(&x) && (-1.0..=1.0).contains(&y)
+}
+
+fn validate_blurhash(blurhash: &str) -> bool {
+    (6..=128).contains(&blurhash.len())
+        && blurhash
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "#$%*+,-.:;=?@[]^_{|}~".contains(c))
+}
+
+/// 画像を読み込み、EXIF の向きを適用する。読み込めない場合は None を返す。
+fn open_image(path: &Path) -> Option<DynamicImage> {
+    let mut decoder = match image::ImageReader::open(path)
+        .and_then(|r| r.with_guessed_format())
+        .map_err(ImageError::from)
+        .and_then(|r| r.into_decoder())
+    {
+        Ok(decoder) => decoder,
+        Err(e) => {
+            warn!("failed to open image {}: {}", path.display(), e);
+            return None;
+        }
     };
-    let filename = PathBuf::from_str(&format!("{}{}", upload_id.to_string(), ext)).unwrap();
-
-    let upload_path = get_uploads_dir().join(&filename);
-    // copy tmp to upload_path
-    std::fs::copy(tmp.path(), &upload_path).map_err_unknown()?;
-    std::mem::drop(tmp);
-
-    // check if it's an image
-    let kind = infer::get_from_path(&upload_path).map_err_unknown()?;
-    match kind {
-        None => create_error_simple(StatusCode::BAD_REQUEST, "invalid file type"),
-        Some(ty) => {
-            if ty.mime_type().starts_with("image/") {
-                remove_exif(&upload_path)?;
-                return Ok((upload_id.clone(), filename, ty));
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    match DynamicImage::from_decoder(decoder) {
+        Ok(mut image) => {
+            image.apply_orientation(orientation);
+            Some(image)
+        }
+        Err(e) => {
+            warn!("failed to decode image {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn image_metadata(image: &DynamicImage) -> UploadMetadata {
+    let sample = image
+        .thumbnail(BLURHASH_SAMPLE_SIZE, BLURHASH_SAMPLE_SIZE)
+        .to_rgba8();
+    let blurhash = blurhash::encode(4, 3, sample.width(), sample.height(), sample.as_raw())
+        .inspect_err(|e| warn!("failed to compute blurhash: {e:?}"))
+        .ok();
+
+    UploadMetadata {
+        width: Some(image.width()),
+        height: Some(image.height()),
+        blurhash,
+        ..Default::default()
+    }
+}
+
+/// 画像の大きさと blurhash を計算する。画像として読み込めない場合は空のメタデータを返す。
+pub fn compute_image_metadata(path: &Path) -> UploadMetadata {
+    open_image(path)
+        .map(|image| image_metadata(&image))
+        .unwrap_or_default()
+}
+
+/// 画像のバリアント。原寸の画像とは別に、縮小した WebP を生成して保存する。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UploadVariant {
+    /// 一覧などで使う小さな画像 (400px)
+    Thumbnail,
+    /// タイムラインで表示する画像 (1280px)
+    Preview,
+}
+
+impl UploadVariant {
+    pub const ALL: [UploadVariant; 2] = [UploadVariant::Thumbnail, UploadVariant::Preview];
+
+    /// 長辺の最大の大きさ
+    fn max_size(&self) -> u32 {
+        match self {
+            Self::Thumbnail => 400,
+            Self::Preview => 1280,
+        }
+    }
+
+    /// バリアントのファイル名
+    pub fn filename(&self, upload_id: &UploadID) -> String {
+        let suffix = match self {
+            Self::Thumbnail => "thumbnail",
+            Self::Preview => "preview",
+        };
+        format!("{upload_id}_{suffix}.webp")
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        "image/webp"
+    }
+}
+
+/// アニメーション画像 (GIF, APNG, アニメーション WebP) かどうか
+fn is_animated_image(path: &Path) -> bool {
+    let format = match image::ImageReader::open(path).and_then(|r| r.with_guessed_format()) {
+        Ok(reader) => reader.format(),
+        Err(_) => return false,
+    };
+    let open = || std::fs::File::open(path).map(std::io::BufReader::new);
+    let animated = match format {
+        Some(ImageFormat::Gif) => open()
+            .map_err(ImageError::from)
+            .and_then(GifDecoder::new)
+            .map(|d| d.into_frames().take(2).count() > 1),
+        Some(ImageFormat::Png) => open()
+            .map_err(ImageError::from)
+            .and_then(PngDecoder::new)
+            .and_then(|d| d.is_apng()),
+        Some(ImageFormat::WebP) => open()
+            .map_err(ImageError::from)
+            .and_then(WebPDecoder::new)
+            .map(|d| d.has_animation()),
+        _ => Ok(false),
+    };
+    animated.unwrap_or_else(|e| {
+        warn!("failed to check animation of {}: {}", path.display(), e);
+        false
+    })
+}
+
+/// 画像のバリアントを生成して `out_dir` に保存する。
+/// アニメーション画像の場合は、アニメーションを保つためにバリアントを生成せず原寸の画像を使う。
+/// 生成できた場合は true を返す。
+pub fn generate_image_variants(path: &Path, out_dir: &Path, upload_id: &UploadID) -> bool {
+    if is_animated_image(path) {
+        return false;
+    }
+    let Some(image) = open_image(path) else {
+        return false;
+    };
+    let image = if image.color().has_alpha() {
+        DynamicImage::ImageRgba8(image.to_rgba8())
+    } else {
+        DynamicImage::ImageRgb8(image.to_rgb8())
+    };
+
+    let mut generated = vec![];
+    for variant in UploadVariant::ALL {
+        let max = variant.max_size();
+        let resized = if image.width() > max || image.height() > max {
+            image.resize(max, max, FilterType::Lanczos3)
+        } else {
+            image.clone()
+        };
+        let variant_path = out_dir.join(variant.filename(upload_id));
+        if let Err(e) = resized.save_with_format(&variant_path, ImageFormat::WebP) {
+            warn!(
+                "failed to generate {:?} variant of {}: {}",
+                variant,
+                path.display(),
+                e
+            );
+            for path in generated {
+                let _ = std::fs::remove_file(path);
             }
+            return false;
+        }
+        generated.push(variant_path);
+    }
+    true
+}
+
+/// アップロードされたファイルをストレージに保存する。
+/// `accept` に含まれない種類のファイルや、種類ごとの上限を超える大きさのファイルは拒否する。
+/// 画像のバリアントや動画のサムネイルも生成して保存し、[`save_upload_file_info`] でまとめて登録する。
+pub async fn save_upload_file(
+    storage: &StorageObject,
+    tmp: NamedTempFile,
+    accept: &[UploadKind],
+) -> ServiceResult<(UploadID, PathBuf, Type, UploadMetadata)> {
+    let kind = infer::get_from_path(tmp.path())
+        .map_err_unknown()?
+        .ok_or(ServiceError::known(UploadError::UnsupportedFileType))?;
+    let upload_kind = UploadKind::from_mime_type(kind.mime_type())
+        .filter(|k| accept.contains(k) && k.accepts(kind.mime_type()))
+        .ok_or(ServiceError::known(UploadError::UnsupportedFileType))?;
+
+    let size = tmp.as_file().metadata().map_err_unknown()?.len();
+    if size > upload_kind.max_size() {
+        return Err(ServiceError::known(UploadError::FileTooLarge));
+    }
+
+    let upload_id = UploadID::new_random();
+    let filename = format!("{}.{}", upload_id, kind.extension());
+
+    // バリアントやサムネイルは作業ディレクトリに生成してからストレージに保存する
+    let work_dir = tempfile::tempdir().map_err_unknown()?;
+    let metadata = match upload_kind {
+        UploadKind::Image => {
+            // 画像の処理は CPU を使うので、非同期ランタイムをブロックしないように別スレッドで行う
+            let path = tmp.path().to_path_buf();
+            let out_dir = work_dir.path().to_path_buf();
+            tokio::task::spawn_blocking(move || -> ServiceResult<UploadMetadata> {
+                remove_exif(&path)?;
+                let mut metadata = compute_image_metadata(&path);
+                metadata.has_variants = generate_image_variants(&path, &out_dir, &upload_id);
+                Ok(metadata)
+            })
+            .await
+            .map_err_unknown()??
+        }
+        UploadKind::Video | UploadKind::Audio => {
+            probe_media(tmp.path(), upload_kind, work_dir.path()).await?
+        }
+    };
 
-            create_error_simple(StatusCode::BAD_REQUEST, "invalid file type")
+    let mut files = stored_files(
+        &upload_id,
+        &filename,
+        kind.mime_type(),
+        metadata.has_variants,
+    );
+    if let Some(thumbnail_id) = &metadata.thumbnail_id {
+        files.push((thumbnail_filename(thumbnail_id), "image/jpeg".to_string()));
+    }
+    let mut saved = vec![];
+    for (key, content_type) in files {
+        let path = if key == filename {
+            tmp.path().to_path_buf()
+        } else {
+            work_dir.path().join(&key)
+        };
+        if let Err(e) = storage.put_file(&key, &path, &content_type).await {
+            remove_upload_files(storage, saved).await;
+            return Err(e);
+        }
+        saved.push(key);
+    }
+
+    Ok((upload_id, PathBuf::from(filename), kind, metadata))
+}
+
+/// アップロードに対応してストレージに保存されているファイルのキーと Content-Type の一覧
+pub(crate) fn stored_files(
+    upload_id: &UploadID,
+    filename: &str,
+    mime_type: &str,
+    has_variants: bool,
+) -> Vec<(String, String)> {
+    let mut files = vec![(filename.to_string(), mime_type.to_string())];
+    if has_variants {
+        for variant in UploadVariant::ALL {
+            files.push((variant.filename(upload_id), variant.mime_type().to_string()));
+        }
+    }
+    files
+}
+
+fn ffprobe_path() -> String {
+    std::env::var("FFPROBE_PATH").unwrap_or_else(|_| "ffprobe".to_string())
+}
+
+fn ffmpeg_path() -> String {
+    std::env::var("FFMPEG_PATH").unwrap_or_else(|_| "ffmpeg".to_string())
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeOutput {
+    #[serde(default)]
+    streams: Vec<FfprobeStream>,
+    format: Option<FfprobeFormat>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeStream {
+    codec_type: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeFormat {
+    duration: Option<String>,
+}
+
+/// 動画のサムネイルの最大の幅
+const VIDEO_THUMBNAIL_MAX_WIDTH: u32 = 1280;
+
+/// ffprobe で動画・音声の長さと大きさを調べる。動画の場合はサムネイルも `out_dir` に生成する。
+/// ffprobe が使えない環境では警告を出して空のメタデータを返す。
+async fn probe_media(
+    path: &Path,
+    kind: UploadKind,
+    out_dir: &Path,
+) -> ServiceResult<UploadMetadata> {
+    let output = match tokio::process::Command::new(ffprobe_path())
+        .args([
+            "-v",
+            "error",
+            "-print_format",
+            "json",
+            "-show_format",
+            "-show_streams",
+        ])
+        .arg(path)
+        .output()
+        .await
+    {
+        Ok(output) => output,
+        Err(e) => {
+            warn!("failed to run ffprobe: {}", e);
+            return Ok(UploadMetadata::default());
+        }
+    };
+    if !output.status.success() {
+        warn!(
+            "ffprobe failed for {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        return Err(ServiceError::known(UploadError::InvalidMedia));
+    }
+    let probe: FfprobeOutput = serde_json::from_slice(&output.stdout).map_err_unknown()?;
+
+    let stream_type = match kind {
+        UploadKind::Video => "video",
+        UploadKind::Audio => "audio",
+        UploadKind::Image => unreachable!("images are not probed"),
+    };
+    let stream = probe
+        .streams
+        .iter()
+        .find(|s| s.codec_type.as_deref() == Some(stream_type))
+        .ok_or(ServiceError::known(UploadError::InvalidMedia))?;
+
+    let duration = probe
+        .format
+        .and_then(|f| f.duration)
+        .and_then(|d| d.parse::<f64>().ok())
+        .filter(|d| d.is_finite() && *d >= 0.0);
+
+    let mut metadata = UploadMetadata {
+        duration,
+        ..Default::default()
+    };
+    if kind == UploadKind::Video {
+        metadata.width = stream.width;
+        metadata.height = stream.height;
+        if let Some((thumbnail_id, thumbnail)) =
+            generate_video_thumbnail(path, duration, out_dir).await
+        {
+            metadata.blurhash = thumbnail.blurhash;
+            metadata.thumbnail_id = Some(thumbnail_id);
+        }
+    }
+    Ok(metadata)
+}
+
+/// サムネイルのファイル名
+fn thumbnail_filename(thumbnail_id: &UploadID) -> String {
+    format!("{}.jpg", thumbnail_id)
+}
+
+/// 動画から 1 フレームを切り出してサムネイルを生成する。失敗した場合は None を返す。
+async fn generate_video_thumbnail(
+    path: &Path,
+    duration: Option<f64>,
+    out_dir: &Path,
+) -> Option<(UploadID, UploadMetadata)> {
+    let thumbnail_id = UploadID::new_random();
+    let thumbnail_path = out_dir.join(thumbnail_filename(&thumbnail_id));
+    // 先頭は黒いフレームのことが多いので、少し進めた位置から切り出す
+    let position = duration.map(|d| (d * 0.1).min(1.0)).unwrap_or(0.0);
+    let result = tokio::process::Command::new(ffmpeg_path())
+        .args(["-v", "error", "-y", "-ss", &format!("{position:.3}"), "-i"])
+        .arg(path)
+        .args([
+            "-frames:v",
+            "1",
+            "-vf",
+            &format!("scale='min({VIDEO_THUMBNAIL_MAX_WIDTH},iw)':-2"),
+        ])
+        .arg(&thumbnail_path)
+        .output()
+        .await;
+    match result {
+        Ok(output) if output.status.success() => {
+            let metadata =
+                tokio::task::spawn_blocking(move || compute_image_metadata(&thumbnail_path))
+                    .await
+                    .inspect_err(|e| warn!("failed to compute thumbnail metadata: {e}"))
+                    .ok()?;
+            Some((thumbnail_id, metadata))
+        }
+        Ok(output) => {
+            warn!(
+                "failed to generate thumbnail for {}: {}",
+                path.display(),
+                String::from_utf8_lossy(&output.stderr)
+            );
+            None
+        }
+        Err(e) => {
+            warn!("failed to run ffmpeg: {}", e);
+            None
         }
     }
 }
@@ -100,17 +632,118 @@ pub async fn save_upload_file_info(
     upload_id: &UploadID,
     filename: &Path,
     mime_type: &str,
+    metadata: &UploadMetadata,
 ) -> ServiceResult<()> {
-    let model = entity::upload::ActiveModel {
+    save_upload_thumbnail_info(tx, metadata).await?;
+    let mut model = entity::upload::ActiveModel {
         id: Set(upload_id.as_db()),
         filename: Set(Some(filename.to_str().expect("bad filename").to_string())),
         mime_type: Set(mime_type.to_string()),
         ..Default::default()
     };
+    metadata.set_to_model(&mut model);
+    model.insert(tx).await.map_err_unknown()?;
+    Ok(())
+}
+
+/// [`save_upload_file`] で生成したサムネイルを登録する。
+pub(crate) async fn save_upload_thumbnail_info(
+    tx: &MaybeTxConn,
+    metadata: &UploadMetadata,
+) -> ServiceResult<()> {
+    let Some(thumbnail_id) = &metadata.thumbnail_id else {
+        return Ok(());
+    };
+    let model = entity::upload::ActiveModel {
+        id: Set(thumbnail_id.as_db()),
+        filename: Set(Some(thumbnail_filename(thumbnail_id))),
+        mime_type: Set("image/jpeg".to_string()),
+        ..Default::default()
+    };
     model.insert(tx).await.map_err_unknown()?;
     Ok(())
 }
 
+/// アップロードをデータベースから削除し、ストレージに保存されているファイルのキーを返す。
+/// ファイルはトランザクションのコミット後に [`remove_upload_files`] で削除すること。
+pub async fn delete_uploads(
+    tx: &MaybeTxConn,
+    upload_ids: &[UploadID],
+) -> ServiceResult<Vec<String>> {
+    if upload_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let ids = upload_ids.iter().map(|u| u.as_db()).collect::<Vec<_>>();
+    let mut uploads = entity::upload::Entity::find()
+        .filter(entity::upload::Column::Id.is_in(ids.clone()))
+        .all(tx)
+        .await
+        .map_err_unknown()?;
+
+    // サムネイルも一緒に削除する
+    let thumbnail_ids = uploads
+        .iter()
+        .filter_map(|u| u.thumbnail_id.clone())
+        .collect::<Vec<_>>();
+    let ids = if thumbnail_ids.is_empty() {
+        ids
+    } else {
+        let thumbnails = entity::upload::Entity::find()
+            .filter(entity::upload::Column::Id.is_in(thumbnail_ids))
+            .all(tx)
+            .await
+            .map_err_unknown()?;
+        uploads.extend(thumbnails);
+        uploads.iter().map(|u| u.id.clone()).collect()
+    };
+
+    // リモートのアップロードのキャッシュも削除する
+    let caches = entity::remote_media_cache::Entity::find()
+        .filter(entity::remote_media_cache::Column::UploadId.is_in(ids.clone()))
+        .all(tx)
+        .await
+        .map_err_unknown()?;
+    let mut keys = caches
+        .iter()
+        .flat_map(cached_media_keys)
+        .collect::<Vec<_>>();
+
+    entity::upload::Entity::delete_many()
+        .filter(entity::upload::Column::Id.is_in(ids))
+        .exec(tx)
+        .await
+        .map_err_unknown()?;
+
+    for upload in uploads {
+        let Some(filename) = upload.filename else {
+            continue;
+        };
+        let upload_id = UploadID::from_db_trusted(upload.id);
+        keys.extend(
+            stored_files(
+                &upload_id,
+                &filename,
+                &upload.mime_type,
+                upload.has_variants,
+            )
+            .into_iter()
+            .map(|(key, _)| key),
+        );
+    }
+    Ok(keys)
+}
+
+/// ストレージに保存されたアップロードファイルを削除する。失敗しても処理は継続する。
+pub async fn remove_upload_files(storage: &StorageObject, keys: Vec<String>) {
+    for key in keys {
+        if let Err(e) = storage.delete(&key).await {
+            warn!("failed to remove upload file {}: {:?}", key, e);
+        }
+    }
+}
+
+/// ローカルストレージの保存先ディレクトリ
 pub fn get_uploads_dir() -> PathBuf {
     let upload_dir = std::env::var("UPLOAD_DIR").unwrap_or_else(|_| "./uploads".to_string());
     PathBuf::from(upload_dir)
@@ -118,8 +751,9 @@ pub fn get_uploads_dir() -> PathBuf {
 
 #[derive(Debug)]
 pub enum GetUpload {
-    Local {
-        relative_path: String,
+    /// ストレージに保存されているファイル
+    Stored {
+        serve: StorageServe,
         mime_type: String,
     },
     Proxy {
@@ -147,10 +781,32 @@ pub enum GetUploadError {
     InvalidRemote,
 }
 
+/// バリアントが指定されていれば、そのファイルのキーと Content-Type を返す。
+fn upload_variant_key(
+    upload_id: &UploadID,
+    filename: String,
+    mime_type: String,
+    has_variants: bool,
+    variant: Option<UploadVariant>,
+) -> (String, String) {
+    match variant.filter(|_| has_variants) {
+        Some(variant) => (variant.filename(upload_id), variant.mime_type().to_string()),
+        None => (filename, mime_type),
+    }
+}
+
+/// アップロードを取得する。
+/// `variant` が指定されていても、バリアントが生成されていない場合は原寸のファイルを返す。
+/// リモートのアップロードは、キャッシュが有効ならキャッシュから返し、無効ならリモートにプロキシする。
+/// プロキシする場合、`range` が指定されていればそのままリモートに転送する。
 pub async fn get_upload(
     conn: &MaybeTxConn,
+    storage: &StorageObject,
+    cache: &MediaCacheConfig,
     upload_id: UploadID,
     client: &reqwest_middleware::ClientWithMiddleware,
+    variant: Option<UploadVariant>,
+    range: Option<&str>,
 ) -> ServiceResult<GetUpload> {
     let upload = entity::upload::Entity::find_by_id(upload_id.as_db())
         .one(conn)
@@ -161,14 +817,61 @@ pub async fn get_upload(
         None => Err(ServiceError::known(GetUploadError::NotFound)),
         Some(upload) => {
             if let Some(filename) = upload.filename {
-                Ok(GetUpload::Local {
-                    relative_path: filename,
-                    mime_type: upload.mime_type,
+                let (key, mime_type) = upload_variant_key(
+                    &upload_id,
+                    filename,
+                    upload.mime_type,
+                    upload.has_variants,
+                    variant,
+                );
+                Ok(GetUpload::Stored {
+                    serve: storage.serve(&key).await?,
+                    mime_type,
                 })
             } else if let Some(url) = &upload.url {
+                if cache.enabled {
+                    let cached = match Url::parse(url) {
+                        Ok(url) => {
+                            cache_remote_media(
+                                conn,
+                                storage,
+                                client,
+                                cache,
+                                &upload_id,
+                                &url,
+                                &upload.mime_type,
+                            )
+                            .await
+                        }
+                        Err(e) => Err(ServiceError::unknown(e)),
+                    };
+                    match cached {
+                        Ok(Some(cached)) => {
+                            let (key, mime_type) = upload_variant_key(
+                                &upload_id,
+                                cached.filename,
+                                upload.mime_type,
+                                cached.has_variants,
+                                variant,
+                            );
+                            return Ok(GetUpload::Stored {
+                                serve: storage.serve(&key).await?,
+                                mime_type,
+                            });
+                        }
+                        // キャッシュできない場合はプロキシする
+                        Ok(None) => {}
+                        Err(e) => warn!("failed to cache remote upload {}: {:?}", url, e),
+                    }
+                }
+
                 let expected_content_type = &upload.mime_type;
 
-                let res = client.get(url).send().await.map_err(|e| {
+                let mut req = client.get(url);
+                if let Some(range) = range {
+                    req = req.header(reqwest::header::RANGE, range);
+                }
+                let res = req.send().await.map_err(|e| {
                     error!("failed to fetch upload: {}", e);
                     ServiceError::unknown(e)
                 })?;
@@ -183,7 +886,9 @@ pub async fn get_upload(
                     }
                 };
 
-                if res.status() != reqwest::StatusCode::OK {
+                if res.status() != reqwest::StatusCode::OK
+                    && res.status() != reqwest::StatusCode::PARTIAL_CONTENT
+                {
                     return Ok(GetUpload::Proxy {
                         cache_control: cache_control,
                         res: ProxyResult::Failed(
@@ -222,6 +927,12 @@ pub async fn register_remote_upload(
     url: &Url,
     client: &reqwest_middleware::ClientWithMiddleware,
 ) -> ServiceResult<UploadID> {
+    if get_domain_policy_for_url(conn, url).await?.rejects_media() {
+        return Err(ServiceError::known(DomainPolicyError::MediaRejected(
+            url.host_str().unwrap_or_default().to_string(),
+        )));
+    }
+
     let mime_type = check_remote_mime_type(url.as_str(), client).await?;
     let upload_id = UploadID::new_random();
     let model = entity::upload::ActiveModel {
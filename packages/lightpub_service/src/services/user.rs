@@ -35,6 +35,7 @@ use crate::MyFederationData;
 use crate::services::ServiceError;
 
 use super::db::MaybeTxConn;
+use super::domain_policy::get_domain_policy;
 use super::id::UploadID;
 use super::kv::KVObject;
 use super::{FederationServiceError, MapToUnknown};
@@ -43,23 +44,42 @@ use super::{
     id::{Identifier, UserID},
 };
 
+mod alias;
 mod apub;
 mod block;
+mod delete;
 mod follow;
+mod instance;
+mod mute;
 mod profile;
 mod specifier;
+mod suspend;
 
+pub use alias::{UserAliasError, move_user, receive_user_move, set_user_aliases};
 pub use apub::{
     ApubUserEndpointsModel, ApubUserIconModel, ApubUserKind, ApubUserModel, UserApubData,
     UserWithApubModel,
 };
-pub use block::{block_user, is_blocking_or_blocked, is_blocking_user, unblock_user};
+pub(crate) use block::not_blocking_or_blocked_expr;
+pub use block::{
+    UserBlockError, block_user, get_blocking_or_blocked_user_ids, is_blocking_or_blocked,
+    is_blocking_user, unblock_user,
+};
+pub use delete::{UserDeleteError, delete_local_user, delete_remote_user, is_user_deleted};
 pub use follow::{UserFollow, get_user_followers, get_user_followings};
+pub use instance::INSTANCE_ACTOR_USERNAME;
+pub(crate) use instance::get_instance_actor;
+pub(crate) use mute::not_muted_expr;
+pub use mute::{UserMuteError, get_muted_user_ids, is_muting_user, mute_user, unmute_user};
 pub use profile::{
     UserAvatar, UserDetailedProfile, UserProfileUpdate, get_user_avatar, get_user_profile,
     get_user_profile_by_id, update_user_profile,
 };
 pub use specifier::UserSpecifier;
+pub use suspend::{
+    UserAccountStateModel, UserSuspendError, get_user_account_state, is_actor_url_suspended,
+    is_user_suspended, set_user_account_state,
+};
 
 #[derive(Debug, Clone, Error, ExpectedError)]
 pub enum UserGetError {
@@ -110,6 +130,21 @@ pub async fn get_user_by_spec_with_remote(
         .try_parse_specifier(my_domain)
         .ok_or(ServiceError::known(UserGetError::BadSpecifier))?;
 
+    // 拒否されたドメインのユーザーは取得しない
+    let remote_domain = match &user_spec {
+        UserSpecifier::Username(_, Some(domain)) => Some(domain.clone()),
+        UserSpecifier::URL(url) => url.host_str().map(|h| h.to_string()),
+        _ => None,
+    };
+    let rejected = match &remote_domain {
+        Some(domain) => get_domain_policy(conn, domain).await?.rejects_activities(),
+        None => false,
+    };
+    if rejected {
+        warn!("remote user fetch skipped: domain {remote_domain:?} is rejected");
+        return Ok(None);
+    }
+
     match user_spec {
         UserSpecifier::ID(_) => {
             // ID ではリモート取得は不可能
@@ -361,6 +396,7 @@ pub async fn get_apubuser_by_id(
         .avatar
         .as_ref()
         .map(|a| UploadID::from_db_trusted(a.clone()));
+    let url = user_url_of_model(&user, base_url);
 
     let basic = SimpleUserModel {
         id,
@@ -395,12 +431,28 @@ pub async fn get_apubuser_by_id(
         }
     };
 
+    let also_known_as = entity::user_alias::Entity::find()
+        .filter(entity::user_alias::Column::UserId.eq(id.as_db()))
+        .order_by_asc(entity::user_alias::Column::Id)
+        .all(tx)
+        .await
+        .map_err_unknown()?
+        .into_iter()
+        .map(|a| a.url.parse().expect("alias is a url"))
+        .collect();
+
+    let moved_to = match &user.moved_to_id {
+        None => None,
+        Some(moved_to_id) => entity::user::Entity::find_by_id(moved_to_id.clone())
+            .one(tx)
+            .await
+            .map_err_unknown()?
+            .map(|m| user_url_of_model(&m, base_url)),
+    };
+
     let is_remote = user.domain != "";
     let apub = UserApubData {
-        url: user
-            .url
-            .map(|u| u.parse().expect("url is a url"))
-            .unwrap_or_else(|| base_url.join(&format!("user/{}", id)).unwrap()),
+        url,
         view_url: {
             if is_remote {
                 user.view_url.map(|u| u.parse().expect("view_url is a url"))
@@ -446,11 +498,22 @@ pub async fn get_apubuser_by_id(
         auto_follow_accept: user.auto_follow_accept != 0,
         fetched_at: user.fetched_at.map(|d| d.and_utc()),
         created_at: user.created_at.map(|d| d.and_utc()),
+        also_known_as,
+        moved_to,
     };
 
     Ok(Some(UserWithApubModel { basic, apub }))
 }
 
+/// ユーザーの ActivityPub ID を返す。
+fn user_url_of_model(user: &entity::user::Model, base_url: &Url) -> Url {
+    let id = UserID::from_db_trusted(user.id.clone());
+    user.url
+        .as_ref()
+        .map(|u| u.parse().expect("url is a url"))
+        .unwrap_or_else(|| base_url.join(&format!("user/{}", id)).unwrap())
+}
+
 #[test]
 fn test_baseurl_join() {
     let base = Url::parse("https://example.com").unwrap();
@@ -513,9 +576,44 @@ async fn get_follower_inboxes(conn: &MaybeTxConn, user_id: UserID) -> ServiceRes
     Ok(inboxes)
 }
 
+/// データベースに保存されている全てのリモートユーザーの shared_inbox または inbox を取得する。
+/// 重複は省かれている。
+async fn get_all_known_inboxes(conn: &MaybeTxConn) -> ServiceResult<Vec<Url>> {
+    use entity::user;
+    let query = Query::select()
+        .column(user::Column::PreferredInbox)
+        .from(user::Entity)
+        .and_where(Expr::col(user::Column::Domain).ne(""))
+        .and_where(Expr::col(user::Column::PreferredInbox).is_not_null())
+        .and_where(Expr::col(user::Column::DeletedAt).is_null())
+        .distinct()
+        .to_owned();
+
+    let result = conn
+        .query_all(StatementBuilder::build(
+            &query,
+            &conn.get_database_backend(),
+        ))
+        .await
+        .map_err_unknown()?;
+
+    let inboxes = result
+        .into_iter()
+        .map(|r| {
+            let value: String = r
+                .try_get("", user::Column::PreferredInbox.as_str())
+                .unwrap();
+            Url::parse(&value).expect("preferred_inbox is a url")
+        })
+        .collect();
+
+    Ok(inboxes)
+}
+
 pub async fn get_total_users_count(tx: &MaybeTxConn) -> ServiceResult<u64> {
     let count = entity::user::Entity::find()
         .filter(entity::user::Column::Domain.eq(""))
+        .filter(entity::user::Column::Username.ne(INSTANCE_ACTOR_USERNAME))
         .count(tx)
         .await
         .map_err_unknown()?;
@@ -0,0 +1,337 @@
+use activitypub_federation::config::Data;
+use activitypub_federation::fetch::object_id::ObjectId;
+use actix_web::http::StatusCode;
+use chrono::Utc;
+use expected_error_derive::ExpectedError;
+use migration::Query;
+use sea_orm::{Condition, IntoActiveModel, Set};
+use thiserror::Error;
+use tracing::warn;
+use url::Url;
+
+use crate::{
+    MyFederationData, ServiceResult,
+    services::{
+        MapToUnknown, ServiceError,
+        apub::MoveActivity,
+        db::{Conn, MaybeTxConn},
+        follow::{follow_user, unfollow_user},
+        id::{Identifier, UserID},
+        kv::KVObject,
+        notification::push::WPClient,
+        queue::QConn,
+    },
+};
+use sea_orm::prelude::*;
+
+use super::profile::publish_user_profile_update_to_followers;
+use super::{
+    UserSpecifier, UserWithApubModel, get_apubuser_by_id, get_follower_inboxes,
+    get_user_by_spec_with_remote, invalidate_user_cache,
+};
+
+/// 1ユーザーが設定できるエイリアスの最大数
+const MAX_ALIASES: usize = 10;
+
+#[derive(Debug, Error, ExpectedError)]
+pub enum UserAliasError {
+    #[error("User {0} does not exist")]
+    #[ee(status(StatusCode::NOT_FOUND))]
+    UserDoesNotExist(UserID),
+    #[error("User is not a local user")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    NotLocalUser,
+    #[error("Too many aliases")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    TooManyAliases,
+    #[error("Alias {0} could not be resolved")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    AliasNotFound(String),
+    #[error("Cannot alias or move to yourself")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    SelfAlias,
+    #[error("Move target could not be resolved")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    MoveTargetNotFound,
+    #[error("Move target does not list this account in alsoKnownAs")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    MoveTargetNotAliased,
+    #[error("Account has already moved")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    AlreadyMoved,
+}
+
+/// ユーザー指定文字列 (`@user@domain` または URL) を解釈する。
+fn parse_alias_spec(spec: &str, my_domain: &str) -> Option<UserSpecifier> {
+    let spec = spec.trim();
+    match Url::parse(spec) {
+        Ok(url) if url.scheme() == "https" || url.scheme() == "http" => {
+            Some(UserSpecifier::url(url))
+        }
+        _ if spec.starts_with("@") => UserSpecifier::from_str(spec, my_domain),
+        _ => None,
+    }
+}
+
+/// ユーザー指定文字列から、ローカルまたはリモートのユーザーを取得する。
+async fn resolve_user_spec(
+    tx: &MaybeTxConn,
+    rconn: &KVObject,
+    spec: &str,
+    my_domain: &str,
+    base_url: &Url,
+    data: &Data<MyFederationData>,
+) -> ServiceResult<Option<UserWithApubModel>> {
+    let user_spec = match parse_alias_spec(spec, my_domain) {
+        Some(s) => s,
+        None => return Ok(None),
+    };
+    let user = match get_user_by_spec_with_remote(tx, rconn, &user_spec, my_domain, data).await? {
+        Some(u) => u,
+        None => return Ok(None),
+    };
+    get_apubuser_by_id(tx, user.id, base_url).await
+}
+
+/// ユーザーのエイリアス (alsoKnownAs) を置き換える。
+pub(super) async fn replace_user_aliases(
+    tx: &MaybeTxConn,
+    user_id: UserID,
+    urls: &[Url],
+) -> ServiceResult<()> {
+    entity::user_alias::Entity::delete_many()
+        .filter(entity::user_alias::Column::UserId.eq(user_id.as_db()))
+        .exec(tx)
+        .await
+        .map_err_unknown()?;
+
+    let mut seen = vec![];
+    for url in urls.iter().take(MAX_ALIASES) {
+        if seen.contains(&url) {
+            continue;
+        }
+        seen.push(url);
+
+        let model = entity::user_alias::ActiveModel {
+            user_id: Set(user_id.as_db()),
+            url: Set(url.to_string()),
+            ..Default::default()
+        };
+        model.insert(tx).await.map_err_unknown()?;
+    }
+
+    Ok(())
+}
+
+/// ローカルユーザーのエイリアスを設定する。
+/// 移行元のアカウントを指定しておくことで、移行元から Move を受け入れられるようになる。
+/// 設定後、フォロワーに Update(Person) を配送する。
+pub async fn set_user_aliases(
+    conn: &Conn,
+    rconn: &KVObject,
+    qconn: &QConn,
+    user_id: UserID,
+    aliases: &[String],
+    my_domain: &str,
+    base_url: &Url,
+    data: &Data<MyFederationData>,
+) -> ServiceResult<()> {
+    if aliases.len() > MAX_ALIASES {
+        return Err(ServiceError::known(UserAliasError::TooManyAliases));
+    }
+
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+
+    let user = get_apubuser_by_id(&tx, user_id, base_url)
+        .await?
+        .ok_or(ServiceError::known(UserAliasError::UserDoesNotExist(
+            user_id,
+        )))?;
+    if !user.is_local() {
+        return Err(ServiceError::known(UserAliasError::NotLocalUser));
+    }
+
+    let mut urls = vec![];
+    for alias in aliases {
+        let alias_user = resolve_user_spec(&tx, rconn, alias, my_domain, base_url, data)
+            .await?
+            .ok_or_else(|| ServiceError::known(UserAliasError::AliasNotFound(alias.clone())))?;
+        if alias_user.basic.id == user_id {
+            return Err(ServiceError::known(UserAliasError::SelfAlias));
+        }
+        urls.push(alias_user.apub.url);
+    }
+
+    replace_user_aliases(&tx, user_id, &urls).await?;
+    publish_user_profile_update_to_followers(&tx, qconn, user_id, base_url, data).await?;
+
+    tx.commit().await?;
+
+    invalidate_user_cache(rconn, user_id).await?;
+
+    Ok(())
+}
+
+/// ローカルユーザーを別のアカウントに移行する。
+///
+/// 移行先のアカウントの alsoKnownAs に移行元が含まれている必要がある。
+/// リモートのフォロワーには Move を配送し、ローカルのフォロワーはこの場で移行先をフォローさせる。
+pub async fn move_user(
+    conn: &Conn,
+    rconn: &KVObject,
+    qconn: &QConn,
+    wp: Option<&WPClient>,
+    user_id: UserID,
+    target: &str,
+    my_domain: &str,
+    base_url: &Url,
+    data: &Data<MyFederationData>,
+) -> ServiceResult<()> {
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+
+    let user = entity::user::Entity::find_by_id(user_id.as_db())
+        .one(&tx)
+        .await
+        .map_err_unknown()?
+        .ok_or(ServiceError::known(UserAliasError::UserDoesNotExist(
+            user_id,
+        )))?;
+    if !user.domain.is_empty() {
+        return Err(ServiceError::known(UserAliasError::NotLocalUser));
+    }
+    if user.moved_to_id.is_some() {
+        return Err(ServiceError::known(UserAliasError::AlreadyMoved));
+    }
+
+    let target = resolve_user_spec(&tx, rconn, target, my_domain, base_url, data)
+        .await?
+        .ok_or(ServiceError::known(UserAliasError::MoveTargetNotFound))?;
+    if target.basic.id == user_id {
+        return Err(ServiceError::known(UserAliasError::SelfAlias));
+    }
+
+    // リモートの移行先は alsoKnownAs が古い可能性があるので再取得する
+    let target = if target.is_remote() {
+        ObjectId::<UserWithApubModel>::from(target.apub.url.clone())
+            .dereference_forced(data)
+            .await
+            .map_err(|e| {
+                warn!("failed to refetch move target: {e}");
+                ServiceError::known(UserAliasError::MoveTargetNotFound)
+            })?
+    } else {
+        target
+    };
+
+    let actor = get_apubuser_by_id(&tx, user_id, base_url)
+        .await?
+        .expect("user should exist");
+    if !target.apub.also_known_as.contains(&actor.apub.url) {
+        return Err(ServiceError::known(UserAliasError::MoveTargetNotAliased));
+    }
+
+    record_user_move(&tx, user_id, target.basic.id).await?;
+
+    let inboxes = get_follower_inboxes(&tx, user_id).await?;
+    let activity = MoveActivity::new(actor.apub.url.clone(), target.apub.url.clone());
+    qconn.queue_activity(activity, actor, inboxes).await?;
+
+    tx.commit().await?;
+
+    invalidate_user_cache(rconn, user_id).await?;
+
+    migrate_local_followers(conn, rconn, qconn, wp, user_id, target.basic.id, base_url).await?;
+
+    Ok(())
+}
+
+/// リモートから Move を受け取ったときに、移行を記録してローカルのフォロワーを移行先に付け替える。
+/// 移行先の alsoKnownAs は呼び出し側で検証されている必要がある。
+pub async fn receive_user_move(
+    conn: &Conn,
+    rconn: &KVObject,
+    qconn: &QConn,
+    wp: Option<&WPClient>,
+    old_user_id: UserID,
+    new_user_id: UserID,
+    base_url: &Url,
+) -> ServiceResult<()> {
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+    record_user_move(&tx, old_user_id, new_user_id).await?;
+    tx.commit().await?;
+
+    invalidate_user_cache(rconn, old_user_id).await?;
+
+    migrate_local_followers(conn, rconn, qconn, wp, old_user_id, new_user_id, base_url).await
+}
+
+async fn record_user_move(
+    tx: &MaybeTxConn,
+    old_user_id: UserID,
+    new_user_id: UserID,
+) -> ServiceResult<()> {
+    let user = entity::user::Entity::find_by_id(old_user_id.as_db())
+        .one(tx)
+        .await
+        .map_err_unknown()?
+        .ok_or(ServiceError::known(UserAliasError::UserDoesNotExist(
+            old_user_id,
+        )))?;
+
+    let mut model = user.into_active_model();
+    model.moved_to_id = Set(Some(new_user_id.as_db()));
+    model.moved_at = Set(Some(Utc::now().naive_utc()));
+    model.update(tx).await.map_err_unknown()?;
+
+    Ok(())
+}
+
+/// 移行元をフォローしているローカルユーザーに移行先をフォローさせ、移行元のフォローを解除する。
+/// 個々のフォローの失敗はログに記録して続行する。
+async fn migrate_local_followers(
+    conn: &Conn,
+    rconn: &KVObject,
+    qconn: &QConn,
+    wp: Option<&WPClient>,
+    old_user_id: UserID,
+    new_user_id: UserID,
+    base_url: &Url,
+) -> ServiceResult<()> {
+    let follows = entity::user_follow::Entity::find()
+        .filter(
+            Condition::all()
+                .add(entity::user_follow::Column::FollowedId.eq(old_user_id.as_db()))
+                .add(entity::user_follow::Column::Pending.eq(false))
+                .add(
+                    entity::user_follow::Column::FollowerId.in_subquery(
+                        Query::select()
+                            .column(entity::user::Column::Id)
+                            .from(entity::user::Entity)
+                            .and_where(entity::user::Column::Domain.eq(""))
+                            .to_owned(),
+                    ),
+                ),
+        )
+        .all(&MaybeTxConn::Conn(conn.clone()))
+        .await
+        .map_err_unknown()?;
+
+    for follow in follows {
+        let follower_id = UserID::from_db_trusted(follow.follower_id);
+        if follower_id == new_user_id {
+            continue;
+        }
+        if let Err(e) =
+            follow_user(conn, rconn, qconn, wp, follower_id, new_user_id, base_url).await
+        {
+            warn!("failed to follow move target for {follower_id} (skipped): {e:?}");
+            continue;
+        }
+        if let Err(e) = unfollow_user(conn, rconn, qconn, follower_id, old_user_id, base_url).await
+        {
+            warn!("failed to unfollow moved user for {follower_id} (skipped): {e:?}");
+        }
+    }
+
+    Ok(())
+}
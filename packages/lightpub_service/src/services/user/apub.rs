@@ -39,6 +39,7 @@ use crate::services::ServiceError;
 
 use super::super::auth::{validate_nickname, validate_username};
 use super::super::db::MaybeTxConn;
+use super::super::emoji::{ApubMaybeEmojiTag, get_emojis_in_content, register_remote_emojis};
 use super::super::kv::KVObject;
 use super::super::upload::register_remote_upload;
 use super::super::{FederationServiceError, MapToUnknown};
@@ -47,9 +48,10 @@ use super::super::{
     db::Conn,
     id::{Identifier, UserID},
 };
+use super::alias::replace_user_aliases;
 use super::{
-    SimpleUserModel, UserSpecifier, get_apubuser_by_id, get_apubuser_by_spec, get_user_id_from_url,
-    invalidate_user_cache,
+    SimpleUserModel, UserSpecifier, delete_remote_user, get_apubuser_by_id, get_apubuser_by_spec,
+    get_user_id_from_url, invalidate_user_cache,
 };
 
 /// Apub JSON からユーザー情報を更新または新規追加する。
@@ -71,6 +73,12 @@ pub async fn upsert_verified_apub_user(
         None => insert_verified_apub_user(&tx, client, url, user).await?,
         Some(user_id) => update_verified_apub_user(&tx, rconn, client, user_id, user).await?,
     };
+    replace_user_aliases(
+        &tx,
+        user_id,
+        user.also_known_as.as_deref().unwrap_or_default(),
+    )
+    .await?;
 
     tx.commit().await?;
 
@@ -97,6 +105,9 @@ async fn set_user_active_model_from_apub(
     let bio =
         CleanString::clean(user.summary.as_ref().map(|s| s.as_str()).unwrap_or("")).into_inner();
 
+    let emojis = ApubMaybeEmojiTag::emojis(&user.tags);
+    register_remote_emojis(conn, client, user_url.domain().unwrap(), &emojis).await;
+
     let avatar_upload_id = if let Some(icon) = &user.icon {
         match register_remote_upload(conn, &icon.url, client).await {
             Ok(upload_id) => Some(upload_id),
@@ -242,6 +253,12 @@ pub struct UserApubData {
     pub auto_follow_accept: bool,
     pub fetched_at: Option<DateTime<Utc>>,
     pub created_at: Option<DateTime<Utc>>,
+    /// このアカウントの別名 (移行元または移行先のアカウント)
+    #[serde(default)]
+    pub also_known_as: Vec<Url>,
+    /// 移行先のアカウント
+    #[serde(default)]
+    pub moved_to: Option<Url>,
 }
 
 nest! {
@@ -271,6 +288,12 @@ nest! {
             pub(crate) url: Url,
         }>,
         pub(crate) url: Option<Url>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub(crate) also_known_as: Option<Vec<Url>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub(crate) moved_to: Option<Url>,
+        #[serde(default, rename = "tag", skip_serializing_if = "Vec::is_empty")]
+        pub(crate) tags: Vec<ApubMaybeEmojiTag>,
     }
 }
 
@@ -348,6 +371,16 @@ impl Object for UserWithApubModel {
             }
         });
 
+        let emojis = get_emojis_in_content(
+            &data.maybe_conn(),
+            None,
+            &format!("{} {}", self.basic.nickname, self.basic.bio),
+        )
+        .await?
+        .into_iter()
+        .map(|e| ApubMaybeEmojiTag::Emoji(Box::new(e.to_apub(data.base_url()))))
+        .collect();
+
         Ok(ApubUserModel {
             id: ObjectId::parse(&user_url.to_string()).unwrap(),
             kind: ApubUserKind::create(self.apub.is_bot),
@@ -370,6 +403,13 @@ impl Object for UserWithApubModel {
             }),
             url: self.apub.view_url,
             icon: avatar_obj,
+            also_known_as: if self.apub.also_known_as.is_empty() {
+                None
+            } else {
+                Some(self.apub.also_known_as)
+            },
+            moved_to: self.apub.moved_to,
+            tags: emojis,
         })
     }
 
@@ -405,8 +445,11 @@ impl Object for UserWithApubModel {
         .map_err(|e| e.into())
     }
 
-    async fn delete(self, _data: &Data<Self::DataType>) -> Result<(), Self::Error> {
-        // TODO: delete remote user on request
+    async fn delete(self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        if self.is_local() {
+            return Ok(());
+        }
+        delete_remote_user(data.conn(), &data.rconn(), data.storage(), self.basic.id).await?;
         Ok(())
     }
 
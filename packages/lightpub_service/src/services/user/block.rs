@@ -1,70 +1,191 @@
+use std::collections::HashSet;
+
+use actix_web::http::StatusCode;
+use expected_error_derive::ExpectedError;
+use migration::{Expr, Query, SimpleExpr};
 use sea_orm::{Condition, Set};
+use thiserror::Error;
+use url::Url;
 
 use crate::{
     ServiceResult,
     services::{
         MapToUnknown, ServiceError,
-        db::{MaybeTxConn, is_unique_constraint_error},
+        apub::{BlockActivity, UndoActivity},
+        db::{Conn, MaybeTxConn, is_unique_constraint_error},
+        follow::{FollowState, is_following, reject_pending_follow_tx, unfollow_user_tx},
         id::{Identifier, UserID},
+        kv::KVObject,
+        queue::QConn,
     },
 };
 use sea_orm::prelude::*;
 
+use super::{get_apubuser_by_id, get_user_by_id};
+
+#[derive(Debug, Error, ExpectedError)]
+pub enum UserBlockError {
+    #[error("User {0} does not exist")]
+    #[ee(status(StatusCode::NOT_FOUND))]
+    UserDoesNotExist(UserID),
+    #[error("Cannot block self")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    SameUser,
+}
+
 pub async fn block_user(
-    tx: &MaybeTxConn,
+    conn: &Conn,
+    rconn: &KVObject,
+    qconn: &QConn,
     blocker_id: UserID,
     blocked_id: UserID,
+    base_url: &Url,
 ) -> ServiceResult<()> {
+    if blocker_id == blocked_id {
+        return Err(ServiceError::known(UserBlockError::SameUser));
+    }
+
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+
+    let blocker = get_user_by_id(&tx, rconn, blocker_id)
+        .await?
+        .ok_or(ServiceError::known(UserBlockError::UserDoesNotExist(
+            blocker_id,
+        )))?;
+    let blocked = get_user_by_id(&tx, rconn, blocked_id)
+        .await?
+        .ok_or(ServiceError::known(UserBlockError::UserDoesNotExist(
+            blocked_id,
+        )))?;
+
+    if is_blocking_user(&tx, blocker_id, blocked_id).await? {
+        // no further action needed
+        tx.commit().await?;
+        return Ok(());
+    }
+
+    // ブロックと同じトランザクションで、フォロー関係を双方向とも解除する
+    if is_following(&tx, blocker_id, blocked_id).await? != FollowState::No {
+        unfollow_user_tx(&tx, rconn, qconn, blocker_id, blocked_id, base_url).await?;
+    }
+    if is_following(&tx, blocked_id, blocker_id).await? != FollowState::No {
+        reject_pending_follow_tx(&tx, rconn, qconn, blocked_id, blocker_id, base_url).await?;
+    }
+
     let model = entity::user_block::ActiveModel {
         blocker_id: Set(blocker_id.as_db()),
         blocked_id: Set(blocked_id.as_db()),
         ..Default::default()
     };
 
-    let result = model.insert(tx).await;
-    match result {
-        Ok(_) => {}
+    let result = model.insert(&tx).await;
+    let model = match result {
+        Ok(model) => model,
         Err(e) if is_unique_constraint_error(&e) => {
             // no further action needed
+            tx.commit().await?;
             return Ok(());
         }
         Err(e) => {
             return Err(ServiceError::unknown(e));
         }
+    };
+
+    // send activitypub block if blocker is local and blocked user is remote
+    if blocker.is_local() && blocked.is_remote() {
+        let blocker_model = get_apubuser_by_id(&tx, blocker_id, base_url)
+            .await?
+            .expect("blocker should exist");
+        let blocked_model = get_apubuser_by_id(&tx, blocked_id, base_url)
+            .await?
+            .expect("blocked user should exist");
+
+        let blocked_inbox = blocked_model.shared_inbox_or_inbox().clone();
+        let block = BlockActivity::new(
+            model.id,
+            blocker_model.apub.url.clone(),
+            blocked_model.apub.url.clone(),
+            base_url,
+        );
+        qconn
+            .queue_activity(block, blocker_model, vec![blocked_inbox])
+            .await?;
     }
 
-    // TODO: activitypub send
+    tx.commit().await?;
 
     Ok(())
 }
 
 pub async fn unblock_user(
-    tx: &MaybeTxConn,
+    conn: &Conn,
+    rconn: &KVObject,
+    qconn: &QConn,
     blocker_id: UserID,
     blocked_id: UserID,
+    base_url: &Url,
 ) -> ServiceResult<()> {
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+
     let model = entity::user_block::Entity::find()
         .filter(
             Condition::all()
                 .add(entity::user_block::Column::BlockerId.eq(blocker_id.as_db()))
                 .add(entity::user_block::Column::BlockedId.eq(blocked_id.as_db())),
         )
-        .one(tx)
+        .one(&tx)
         .await
         .map_err_unknown()?;
 
-    match model {
-        Some(model) => {
-            model.delete(tx).await.map_err_unknown()?;
-
-            // TODO: activitypub send
-            Ok(())
-        }
+    let model = match model {
+        Some(model) => model,
         None => {
             // no further action needed
-            Ok(())
+            tx.commit().await?;
+            return Ok(());
         }
+    };
+
+    let block_id = model.id;
+    model.delete(&tx).await.map_err_unknown()?;
+
+    let blocker = get_user_by_id(&tx, rconn, blocker_id)
+        .await?
+        .ok_or(ServiceError::known(UserBlockError::UserDoesNotExist(
+            blocker_id,
+        )))?;
+    let blocked = get_user_by_id(&tx, rconn, blocked_id)
+        .await?
+        .ok_or(ServiceError::known(UserBlockError::UserDoesNotExist(
+            blocked_id,
+        )))?;
+
+    // send activitypub undo if blocker is local and blocked user is remote
+    if blocker.is_local() && blocked.is_remote() {
+        let blocker_model = get_apubuser_by_id(&tx, blocker_id, base_url)
+            .await?
+            .expect("blocker should exist");
+        let blocked_model = get_apubuser_by_id(&tx, blocked_id, base_url)
+            .await?
+            .expect("blocked user should exist");
+
+        let blocker_url = blocker_model.apub.url.clone();
+        let blocked_inbox = blocked_model.shared_inbox_or_inbox().clone();
+        let block = BlockActivity::new(
+            block_id,
+            blocker_url.clone(),
+            blocked_model.apub.url.clone(),
+            base_url,
+        );
+        let undo = UndoActivity::new(blocker_url, block);
+        qconn
+            .queue_activity(undo, blocker_model, vec![blocked_inbox])
+            .await?;
     }
+
+    tx.commit().await?;
+
+    Ok(())
 }
 
 pub async fn is_blocking_user(
@@ -110,3 +231,63 @@ pub async fn is_blocking_or_blocked(
 
     Ok(count > 0)
 }
+
+/// 対象ユーザーがブロックしている、または対象ユーザーをブロックしているユーザーの ID を全て取得する。
+pub async fn get_blocking_or_blocked_user_ids(
+    tx: &MaybeTxConn,
+    user_id: UserID,
+) -> ServiceResult<HashSet<UserID>> {
+    let blocks = entity::user_block::Entity::find()
+        .filter(
+            Condition::any()
+                .add(entity::user_block::Column::BlockerId.eq(user_id.as_db()))
+                .add(entity::user_block::Column::BlockedId.eq(user_id.as_db())),
+        )
+        .all(tx)
+        .await
+        .map_err_unknown()?;
+
+    let user_id_db = user_id.as_db();
+    Ok(blocks
+        .into_iter()
+        .map(|b| {
+            if b.blocker_id == user_id_db {
+                UserID::from_db_trusted(b.blocked_id)
+            } else {
+                UserID::from_db_trusted(b.blocker_id)
+            }
+        })
+        .collect())
+}
+
+/// `author_expr` が指すユーザーと閲覧者の間に、どちらの向きにもブロック関係が存在しないことを表す条件式を返す。
+///
+/// `author_expr` には `n.author_id` のような SQL 式を指定する。
+pub(crate) fn not_blocking_or_blocked_expr(viewer_id: UserID, author_expr: &str) -> SimpleExpr {
+    Expr::exists(
+        Query::select()
+            .column(entity::user_block::Column::Id)
+            .from(entity::user_block::Entity)
+            .cond_where(
+                Condition::any()
+                    .add(
+                        Condition::all()
+                            .add(entity::user_block::Column::BlockerId.eq(viewer_id.as_db()))
+                            .add(
+                                Expr::col(entity::user_block::Column::BlockedId)
+                                    .eq(Expr::cust(author_expr)),
+                            ),
+                    )
+                    .add(
+                        Condition::all()
+                            .add(
+                                Expr::col(entity::user_block::Column::BlockerId)
+                                    .eq(Expr::cust(author_expr)),
+                            )
+                            .add(entity::user_block::Column::BlockedId.eq(viewer_id.as_db())),
+                    ),
+            )
+            .take(),
+    )
+    .not()
+}
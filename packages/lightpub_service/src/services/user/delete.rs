@@ -0,0 +1,257 @@
+use activitypub_federation::fetch::object_id::ObjectId;
+use actix_web::http::StatusCode;
+use chrono::{DateTime, Utc};
+use expected_error_derive::ExpectedError;
+use migration::Query;
+use sea_orm::{Condition, IntoActiveModel, Set};
+use thiserror::Error;
+use url::Url;
+
+use crate::{
+    ServiceResult,
+    services::{
+        MapToUnknown, ServiceError,
+        access_token::revoke_all_personal_access_tokens,
+        apub::DeleteActivity,
+        db::{Conn, MaybeTxConn},
+        id::{Identifier, UploadID, UserID},
+        kv::KVObject,
+        oauth::revoke_all_oauth_tokens,
+        queue::QConn,
+        storage::StorageObject,
+        upload::{delete_uploads, remove_upload_files},
+    },
+};
+use sea_orm::prelude::*;
+
+use super::{get_all_known_inboxes, get_apubuser_by_id, invalidate_user_cache};
+
+#[derive(Debug, Error, ExpectedError)]
+pub enum UserDeleteError {
+    #[error("User {0} does not exist")]
+    #[ee(status(StatusCode::NOT_FOUND))]
+    UserDoesNotExist(UserID),
+    #[error("User is not a local user")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    NotLocalUser,
+    #[error("Wrong password")]
+    #[ee(status(StatusCode::FORBIDDEN))]
+    WrongPassword,
+    #[error("User is already deleted")]
+    #[ee(status(StatusCode::GONE))]
+    AlreadyDeleted,
+}
+
+/// ローカルユーザーのアカウントを削除する。
+///
+/// ユーザーの行は Tombstone として残し、ノート・アップロード・フォロー関係・通知を削除する。
+/// 既知の全てのリモートサーバーに Delete(Person) を配送する。
+pub async fn delete_local_user(
+    conn: &Conn,
+    rconn: &KVObject,
+    qconn: &QConn,
+    storage: &StorageObject,
+    user_id: UserID,
+    password: &str,
+    base_url: &Url,
+) -> ServiceResult<()> {
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+
+    let user = find_user(&tx, user_id).await?;
+    if !user.domain.is_empty() {
+        return Err(ServiceError::known(UserDeleteError::NotLocalUser));
+    }
+    if user.deleted_at.is_some() {
+        return Err(ServiceError::known(UserDeleteError::AlreadyDeleted));
+    }
+
+    let hashed_password = user
+        .password
+        .clone()
+        .ok_or(ServiceError::known(UserDeleteError::WrongPassword))?;
+    let password = password.to_string();
+    let verified = tokio::task::spawn_blocking(move || bcrypt::verify(password, &hashed_password))
+        .await
+        .map_err_unknown()?
+        .map_err_unknown()?;
+    if !verified {
+        return Err(ServiceError::known(UserDeleteError::WrongPassword));
+    }
+
+    // 鍵は Delete の署名に必要なので削除前に取得しておく
+    let actor = get_apubuser_by_id(&tx, user_id, base_url)
+        .await?
+        .expect("user should exist");
+    let inboxes = get_all_known_inboxes(&tx).await?;
+
+    let files = purge_user_content(&tx, user_id).await?;
+    revoke_all_personal_access_tokens(&tx, user_id).await?;
+    revoke_all_oauth_tokens(&tx, user_id).await?;
+    // パスキーはパスワードなしでログインできるので残さない
+    entity::webauthn_credential::Entity::delete_many()
+        .filter(entity::webauthn_credential::Column::UserId.eq(user_id.as_db()))
+        .exec(&tx)
+        .await
+        .map_err_unknown()?;
+    let deleted_at = tombstone_user(&tx, user).await?;
+
+    let delete = DeleteActivity::from_user(ObjectId::from(actor.apub.url.clone()), deleted_at);
+    qconn.queue_activity(delete, actor, inboxes).await?;
+
+    tx.commit().await?;
+
+    invalidate_user_cache(rconn, user_id).await?;
+    remove_upload_files(storage, files).await;
+
+    Ok(())
+}
+
+/// リモートから Delete(Person) を受け取ったときに、そのユーザーのデータを削除する。
+pub async fn delete_remote_user(
+    conn: &Conn,
+    rconn: &KVObject,
+    storage: &StorageObject,
+    user_id: UserID,
+) -> ServiceResult<()> {
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+
+    let user = find_user(&tx, user_id).await?;
+    if user.domain.is_empty() {
+        return Err(ServiceError::ise("local user cannot be deleted remotely"));
+    }
+    if user.deleted_at.is_some() {
+        return Ok(());
+    }
+
+    let files = purge_user_content(&tx, user_id).await?;
+    tombstone_user(&tx, user).await?;
+
+    tx.commit().await?;
+
+    invalidate_user_cache(rconn, user_id).await?;
+    remove_upload_files(storage, files).await;
+
+    Ok(())
+}
+
+/// ユーザーが削除済みかどうかを返す。
+pub async fn is_user_deleted(tx: &MaybeTxConn, user_id: UserID) -> ServiceResult<bool> {
+    let user = entity::user::Entity::find_by_id(user_id.as_db())
+        .one(tx)
+        .await
+        .map_err_unknown()?;
+
+    Ok(user.is_some_and(|u| u.deleted_at.is_some()))
+}
+
+async fn find_user(tx: &MaybeTxConn, user_id: UserID) -> ServiceResult<entity::user::Model> {
+    entity::user::Entity::find_by_id(user_id.as_db())
+        .one(tx)
+        .await
+        .map_err_unknown()?
+        .ok_or(ServiceError::known(UserDeleteError::UserDoesNotExist(
+            user_id,
+        )))
+}
+
+/// プロフィールを消去し、削除済みとして記録する。
+/// ユーザー名の再利用を防ぐため、行自体は削除しない。
+async fn tombstone_user(
+    tx: &MaybeTxConn,
+    user: entity::user::Model,
+) -> ServiceResult<DateTime<Utc>> {
+    let now = Utc::now();
+
+    let mut model = user.into_active_model();
+    model.nickname = Set("".to_string());
+    model.bio = Set("".to_string());
+    model.avatar = Set(None);
+    model.password = Set(None);
+    model.auth_expired_at = Set(Some(now.naive_utc()));
+    model.deleted_at = Set(Some(now.naive_utc()));
+    model.update(tx).await.map_err_unknown()?;
+
+    Ok(now)
+}
+
+/// ユーザーのノート・リノート・いいね・ブックマーク・アップロード・フォロー関係・通知を削除する。
+/// 削除すべきローカルファイルのパスを返す。
+async fn purge_user_content(tx: &MaybeTxConn, user_id: UserID) -> ServiceResult<Vec<String>> {
+    let user = user_id.as_db();
+
+    // ノートに添付されたアップロードとアバター
+    let note_uploads = entity::note_upload::Entity::find()
+        .filter(
+            entity::note_upload::Column::NoteId.in_subquery(
+                Query::select()
+                    .column(entity::note::Column::Id)
+                    .from(entity::note::Entity)
+                    .and_where(entity::note::Column::AuthorId.eq(user.clone()))
+                    .to_owned(),
+            ),
+        )
+        .all(tx)
+        .await
+        .map_err_unknown()?;
+    let mut upload_ids = note_uploads
+        .into_iter()
+        .map(|u| UploadID::from_db_trusted(u.upload_id))
+        .collect::<Vec<_>>();
+    let avatar = entity::user::Entity::find_by_id(user.clone())
+        .one(tx)
+        .await
+        .map_err_unknown()?
+        .and_then(|u| u.avatar);
+    if let Some(avatar) = avatar {
+        upload_ids.push(UploadID::from_db_trusted(avatar));
+    }
+
+    // リノートは物理削除し、通常のノートは論理削除する
+    entity::note::Entity::delete_many()
+        .filter(
+            Condition::all()
+                .add(entity::note::Column::AuthorId.eq(user.clone()))
+                .add(entity::note::Column::RenoteOfId.is_not_null())
+                .add(entity::note::Column::Content.is_null()),
+        )
+        .exec(tx)
+        .await
+        .map_err_unknown()?;
+    entity::note::Entity::update_many()
+        .col_expr(
+            entity::note::Column::DeletedAt,
+            Expr::value(Utc::now().naive_utc()),
+        )
+        .filter(
+            Condition::all()
+                .add(entity::note::Column::AuthorId.eq(user.clone()))
+                .add(entity::note::Column::DeletedAt.is_null()),
+        )
+        .exec(tx)
+        .await
+        .map_err_unknown()?;
+
+    entity::note_like::Entity::delete_many()
+        .filter(entity::note_like::Column::UserId.eq(user.clone()))
+        .exec(tx)
+        .await
+        .map_err_unknown()?;
+
+    entity::user_follow::Entity::delete_many()
+        .filter(
+            Condition::any()
+                .add(entity::user_follow::Column::FollowerId.eq(user.clone()))
+                .add(entity::user_follow::Column::FollowedId.eq(user.clone())),
+        )
+        .exec(tx)
+        .await
+        .map_err_unknown()?;
+
+    entity::notification::Entity::delete_many()
+        .filter(entity::notification::Column::UserId.eq(user))
+        .exec(tx)
+        .await
+        .map_err_unknown()?;
+
+    delete_uploads(tx, &upload_ids).await
+}
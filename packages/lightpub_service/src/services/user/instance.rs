@@ -0,0 +1,78 @@
+use activitypub_federation::http_signatures::generate_actor_keypair;
+use chrono::Utc;
+use sea_orm::{ActiveModelTrait, ColumnTrait, Condition, EntityTrait, QueryFilter, Set};
+use url::Url;
+
+use crate::{
+    ServiceResult,
+    services::{
+        MapToUnknown, ServiceError,
+        db::{MaybeTxConn, is_unique_constraint_error},
+        id::{Identifier, UserID},
+    },
+};
+
+use super::{UserWithApubModel, get_apubuser_by_id};
+
+/// サーバー自身を表すアクターのユーザー名。
+/// `.` を含むので、通常のユーザー登録で使われることはない。
+pub const INSTANCE_ACTOR_USERNAME: &str = "instance.actor";
+
+async fn find_instance_actor_id(tx: &MaybeTxConn) -> ServiceResult<Option<UserID>> {
+    let user = entity::user::Entity::find()
+        .filter(
+            Condition::all()
+                .add(entity::user::Column::Username.eq(INSTANCE_ACTOR_USERNAME))
+                .add(entity::user::Column::Domain.eq("")),
+        )
+        .one(tx)
+        .await
+        .map_err_unknown()?;
+    Ok(user.map(|u| UserID::from_db_trusted(u.id)))
+}
+
+/// サーバー自身を表すアクターを取得する。存在しない場合は作成する。
+///
+/// 個々のユーザーではなくサーバーとして送るアクティビティ (通報の転送など) の署名に使う。
+/// パスワードを持たないので、ログインすることはできない。
+pub(crate) async fn get_instance_actor(
+    tx: &MaybeTxConn,
+    base_url: &Url,
+) -> ServiceResult<UserWithApubModel> {
+    let user_id = match find_instance_actor_id(tx).await? {
+        Some(user_id) => user_id,
+        None => {
+            let key_pair = tokio::task::spawn_blocking(generate_actor_keypair)
+                .await
+                .map_err_unknown()?
+                .map_err_unknown()?;
+
+            let user_id = UserID::new_random();
+            let user = entity::user::ActiveModel {
+                id: Set(user_id.as_db()),
+                username: Set(INSTANCE_ACTOR_USERNAME.to_string()),
+                domain: Set("".into()),
+                nickname: Set(base_url.host_str().unwrap_or_default().to_string()),
+                password: Set(None),
+                bio: Set("".into()),
+                private_key: Set(Some(key_pair.private_key)),
+                public_key: Set(Some(key_pair.public_key)),
+                created_at: Set(Some(Utc::now().naive_utc())),
+                is_bot: Set(1),
+                ..Default::default()
+            };
+            match user.insert(tx).await {
+                Ok(_) => user_id,
+                // 同時に作成された場合はそちらを使う
+                Err(e) if is_unique_constraint_error(&e) => find_instance_actor_id(tx)
+                    .await?
+                    .ok_or_else(|| ServiceError::ise("instance actor not found"))?,
+                Err(e) => return Err(ServiceError::unknown(e)),
+            }
+        }
+    };
+
+    get_apubuser_by_id(tx, user_id, base_url)
+        .await?
+        .ok_or_else(|| ServiceError::ise("instance actor not found"))
+}
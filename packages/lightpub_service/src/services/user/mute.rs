@@ -0,0 +1,191 @@
+use std::collections::HashSet;
+
+use actix_web::http::StatusCode;
+use chrono::{DateTime, Utc};
+use expected_error_derive::ExpectedError;
+use sea_orm::{
+    Condition, IntoActiveModel, Set,
+    sea_query::{IntoColumnRef, Query, SimpleExpr},
+};
+use thiserror::Error;
+
+use crate::{
+    ServiceResult,
+    services::{
+        MapToUnknown, ServiceError,
+        db::{Conn, MaybeTxConn},
+        id::{Identifier, UserID},
+        kv::KVObject,
+    },
+};
+use sea_orm::prelude::*;
+
+use super::get_user_by_id;
+
+#[derive(Debug, Error, ExpectedError)]
+pub enum UserMuteError {
+    #[error("User {0} does not exist")]
+    #[ee(status(StatusCode::NOT_FOUND))]
+    UserDoesNotExist(UserID),
+    #[error("Cannot mute self")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    SameUser,
+    #[error("Mute expiry must be in the future")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    ExpiryInPast,
+}
+
+/// `muter_id` のユーザーが `muted_id` のユーザーをミュートする。
+///
+/// `expires_at` が None の場合は無期限のミュートになる。既にミュートしている場合は期限を上書きする。
+/// ブロックと異なり、ミュートは相手に通知されずリモートサーバーにも配送されない。
+pub async fn mute_user(
+    conn: &Conn,
+    rconn: &KVObject,
+    muter_id: UserID,
+    muted_id: UserID,
+    expires_at: Option<DateTime<Utc>>,
+) -> ServiceResult<()> {
+    if muter_id == muted_id {
+        return Err(ServiceError::known(UserMuteError::SameUser));
+    }
+    if expires_at.is_some_and(|e| e <= Utc::now()) {
+        return Err(ServiceError::known(UserMuteError::ExpiryInPast));
+    }
+
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+
+    for user_id in [muter_id, muted_id] {
+        if get_user_by_id(&tx, rconn, user_id).await?.is_none() {
+            return Err(ServiceError::known(UserMuteError::UserDoesNotExist(
+                user_id,
+            )));
+        }
+    }
+
+    let existing = find_mute(&tx, muter_id, muted_id).await?;
+    match existing {
+        Some(model) => {
+            let mut model = model.into_active_model();
+            model.muted_at = Set(Utc::now().naive_utc());
+            model.expires_at = Set(expires_at.map(|e| e.naive_utc()));
+            model.update(&tx).await.map_err_unknown()?;
+        }
+        None => {
+            let model = entity::user_mute::ActiveModel {
+                muter_id: Set(muter_id.as_db()),
+                muted_id: Set(muted_id.as_db()),
+                expires_at: Set(expires_at.map(|e| e.naive_utc())),
+                ..Default::default()
+            };
+            model.insert(&tx).await.map_err_unknown()?;
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// `muter_id` のユーザーによる `muted_id` のユーザーのミュートを解除する。
+pub async fn unmute_user(conn: &Conn, muter_id: UserID, muted_id: UserID) -> ServiceResult<()> {
+    entity::user_mute::Entity::delete_many()
+        .filter(
+            Condition::all()
+                .add(entity::user_mute::Column::MuterId.eq(muter_id.as_db()))
+                .add(entity::user_mute::Column::MutedId.eq(muted_id.as_db())),
+        )
+        .exec(conn.db())
+        .await
+        .map_err_unknown()?;
+
+    Ok(())
+}
+
+async fn find_mute(
+    tx: &MaybeTxConn,
+    muter_id: UserID,
+    muted_id: UserID,
+) -> ServiceResult<Option<entity::user_mute::Model>> {
+    entity::user_mute::Entity::find()
+        .filter(
+            Condition::all()
+                .add(entity::user_mute::Column::MuterId.eq(muter_id.as_db()))
+                .add(entity::user_mute::Column::MutedId.eq(muted_id.as_db())),
+        )
+        .one(tx)
+        .await
+        .map_err_unknown()
+}
+
+/// 期限切れでないミュートのみを対象とする条件
+fn active_mute_condition() -> Condition {
+    Condition::any()
+        .add(entity::user_mute::Column::ExpiresAt.is_null())
+        .add(entity::user_mute::Column::ExpiresAt.gt(Utc::now().naive_utc()))
+}
+
+/// `actor` 列のユーザーを `muter_id` のユーザーが現在ミュートしていないことを表す条件。
+/// `actor` が NULL の場合は真になる。
+pub(crate) fn not_muted_expr(muter_id: UserID, actor: impl IntoColumnRef) -> SimpleExpr {
+    Expr::exists(
+        Query::select()
+            .column(entity::user_mute::Column::Id)
+            .from(entity::user_mute::Entity)
+            .cond_where(
+                Condition::all()
+                    .add(entity::user_mute::Column::MuterId.eq(muter_id.as_db()))
+                    .add(
+                        Expr::col((
+                            entity::user_mute::Entity,
+                            entity::user_mute::Column::MutedId,
+                        ))
+                        .equals(actor),
+                    )
+                    .add(active_mute_condition()),
+            )
+            .to_owned(),
+    )
+    .not()
+}
+
+/// `muter_id` のユーザーが `muted_id` のユーザーを現在ミュートしているかどうかを返す。
+pub async fn is_muting_user(
+    tx: &MaybeTxConn,
+    muter_id: UserID,
+    muted_id: UserID,
+) -> ServiceResult<bool> {
+    let count = entity::user_mute::Entity::find()
+        .filter(
+            Condition::all()
+                .add(entity::user_mute::Column::MuterId.eq(muter_id.as_db()))
+                .add(entity::user_mute::Column::MutedId.eq(muted_id.as_db()))
+                .add(active_mute_condition()),
+        )
+        .count(tx)
+        .await
+        .map_err_unknown()?;
+
+    Ok(count > 0)
+}
+
+/// 対象ユーザーが現在ミュートしているユーザーの ID を全て取得する。
+pub async fn get_muted_user_ids(
+    tx: &MaybeTxConn,
+    muter_id: UserID,
+) -> ServiceResult<HashSet<UserID>> {
+    let mutes = entity::user_mute::Entity::find()
+        .filter(
+            Condition::all()
+                .add(entity::user_mute::Column::MuterId.eq(muter_id.as_db()))
+                .add(active_mute_condition()),
+        )
+        .all(tx)
+        .await
+        .map_err_unknown()?;
+
+    Ok(mutes
+        .into_iter()
+        .map(|m| UserID::from_db_trusted(m.muted_id))
+        .collect())
+}
@@ -18,6 +18,7 @@ along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use activitypub_federation::config::Data;
 use activitypub_federation::traits::Object;
+use chrono::{DateTime, Utc};
 use identicon_rs::Identicon;
 use sea_orm::EntityTrait;
 use sea_orm::entity::*;
@@ -44,6 +45,7 @@ use super::super::{
     id::{Identifier, UserID},
 };
 use super::is_blocking_user;
+use super::is_muting_user;
 use super::{
     SimpleUserModel, UserSpecifier, get_apubuser_by_id, get_follower_inboxes, get_user_by_id,
     get_user_by_spec_with_remote, invalidate_user_cache,
@@ -110,7 +112,7 @@ pub async fn update_user_profile(
     Ok(())
 }
 
-async fn publish_user_profile_update_to_followers(
+pub(super) async fn publish_user_profile_update_to_followers(
     tx: &MaybeTxConn,
     qconn: &QConn,
     user_id: UserID,
@@ -153,16 +155,26 @@ pub struct UserDetailedProfile {
 
     pub is_blocked: Option<bool>,
     pub is_blocking: Option<bool>,
+    pub is_muting: Option<bool>,
 
     pub url: Option<String>,
     pub view_url: Option<String>,
 
     pub auto_follow_accept: bool,
     pub hide_follows: bool,
+
+    /// アカウントの移行先
+    pub moved_to: Option<SimpleUserModel>,
+
+    /// Bot アカウントかどうか
+    pub is_bot: bool,
+    /// アカウントの作成日時。リモートのユーザーの場合は取得した日時。
+    pub created_at: Option<DateTime<Utc>>,
 }
 
 async fn get_user_profile_impl(
     conn: &MaybeTxConn,
+    rconn: &KVObject,
     viewer_id: Option<UserID>,
     user: SimpleUserModel,
 ) -> ServiceResult<Option<UserDetailedProfile>> {
@@ -189,6 +201,11 @@ async fn get_user_profile_impl(
         }
     };
 
+    let is_muting = match viewer_id {
+        None => None,
+        Some(viewer_id) => Some(is_muting_user(conn, viewer_id, user.id).await?),
+    };
+
     let follow_stats = get_follow_stats(conn, *user.id()).await?;
 
     let user_all = entity::user::Entity::find_by_id(user.id().as_db())
@@ -202,12 +219,20 @@ async fn get_user_profile_impl(
 
     let note_count = get_user_note_count(conn, viewer_id, user.id, true).await?;
 
+    let moved_to = match user_all.moved_to_id {
+        None => None,
+        Some(moved_to_id) => {
+            get_user_by_id(conn, rconn, UserID::from_db_trusted(moved_to_id)).await?
+        }
+    };
+
     Ok(Some(UserDetailedProfile {
         basic: user,
         is_following,
         is_followed,
         is_blocked,
         is_blocking,
+        is_muting,
         is_me: is_viewer,
         follow_count: follow_stats.following,
         follower_count: follow_stats.followers,
@@ -217,6 +242,9 @@ async fn get_user_profile_impl(
         view_url: user_all.view_url,
         auto_follow_accept: user_all.auto_follow_accept != 0,
         hide_follows: user_all.hide_follows != 0,
+        moved_to,
+        is_bot: user_all.is_bot != 0,
+        created_at: user_all.created_at.map(|d| d.and_utc()),
     }))
 }
 
@@ -231,7 +259,7 @@ pub async fn get_user_profile_by_id(
 ) -> ServiceResult<Option<UserDetailedProfile>> {
     let user = try_opt_res!(get_user_by_id(conn, rconn, user_id).await?);
 
-    get_user_profile_impl(conn, viewer_id, user).await
+    get_user_profile_impl(conn, rconn, viewer_id, user).await
 }
 
 /// ID を用いてユーザーの詳細なプロフィールを取得する。
@@ -248,7 +276,7 @@ pub async fn get_user_profile(
     let user =
         try_opt_res!(get_user_by_spec_with_remote(conn, rconn, user_spec, my_domain, data).await?);
 
-    get_user_profile_impl(conn, viewer_id, user).await
+    get_user_profile_impl(conn, rconn, viewer_id, user).await
 }
 
 /// ユーザーアバター
@@ -0,0 +1,161 @@
+use actix_web::http::StatusCode;
+use chrono::Utc;
+use entity::sea_orm_active_enums::UserAccountState;
+use expected_error_derive::ExpectedError;
+use sea_orm::{Condition, IntoActiveModel, Set};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use url::Url;
+
+use crate::{
+    ServiceResult,
+    services::{
+        MapToUnknown, ServiceError,
+        db::{Conn, MaybeTxConn},
+        id::{Identifier, UserID},
+        kv::KVObject,
+    },
+};
+use sea_orm::prelude::*;
+
+use super::invalidate_user_cache;
+
+#[derive(Debug, Error, ExpectedError)]
+pub enum UserSuspendError {
+    #[error("User {0} does not exist")]
+    #[ee(status(StatusCode::NOT_FOUND))]
+    UserDoesNotExist(UserID),
+    #[error("Cannot change the state of an admin account")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    TargetIsAdmin,
+    #[error("Account is suspended")]
+    #[ee(status(StatusCode::FORBIDDEN))]
+    Suspended,
+}
+
+/// アカウントの状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UserAccountStateModel {
+    /// 通常の状態
+    Active,
+    /// 公開タイムラインに表示されない
+    Limited,
+    /// ログインできず、ノートも表示されない
+    Suspended,
+}
+
+impl UserAccountStateModel {
+    pub fn from_db(state: UserAccountState) -> Self {
+        match state {
+            UserAccountState::Active => UserAccountStateModel::Active,
+            UserAccountState::Limited => UserAccountStateModel::Limited,
+            UserAccountState::Suspended => UserAccountStateModel::Suspended,
+        }
+    }
+
+    pub fn as_db(self) -> UserAccountState {
+        match self {
+            UserAccountStateModel::Active => UserAccountState::Active,
+            UserAccountStateModel::Limited => UserAccountState::Limited,
+            UserAccountStateModel::Suspended => UserAccountState::Suspended,
+        }
+    }
+}
+
+/// ユーザーのアカウントの状態を変更する。
+///
+/// リモートユーザーを凍結した場合は、キャッシュされているそのユーザーのノートを全て論理削除する。
+/// 凍結を解除しても削除されたノートは元に戻らない。
+pub async fn set_user_account_state(
+    conn: &Conn,
+    rconn: &KVObject,
+    user_id: UserID,
+    state: UserAccountStateModel,
+) -> ServiceResult<()> {
+    let tx: MaybeTxConn = conn.as_tx().await?.into();
+
+    let user = entity::user::Entity::find_by_id(user_id.as_db())
+        .one(&tx)
+        .await
+        .map_err_unknown()?
+        .ok_or(ServiceError::known(UserSuspendError::UserDoesNotExist(
+            user_id,
+        )))?;
+
+    if user.is_admin != 0 {
+        return Err(ServiceError::known(UserSuspendError::TargetIsAdmin));
+    }
+
+    let is_remote = !user.domain.is_empty();
+    let was_suspended = user.account_state == UserAccountState::Suspended;
+
+    let mut model = user.into_active_model();
+    model.account_state = Set(state.as_db());
+    match state {
+        UserAccountStateModel::Suspended if !was_suspended => {
+            model.suspended_at = Set(Some(Utc::now().naive_utc()));
+        }
+        UserAccountStateModel::Suspended => {}
+        _ => {
+            model.suspended_at = Set(None);
+        }
+    }
+    model.update(&tx).await.map_err_unknown()?;
+
+    if state == UserAccountStateModel::Suspended && is_remote {
+        entity::note::Entity::update_many()
+            .col_expr(
+                entity::note::Column::DeletedAt,
+                Expr::value(Utc::now().naive_utc()),
+            )
+            .filter(
+                Condition::all()
+                    .add(entity::note::Column::AuthorId.eq(user_id.as_db()))
+                    .add(entity::note::Column::DeletedAt.is_null()),
+            )
+            .exec(&tx)
+            .await
+            .map_err_unknown()?;
+    }
+
+    tx.commit().await?;
+
+    invalidate_user_cache(rconn, user_id).await?;
+
+    Ok(())
+}
+
+/// ユーザーのアカウントの状態を取得する。ユーザーが存在しない場合は None を返す。
+pub async fn get_user_account_state(
+    tx: &MaybeTxConn,
+    user_id: UserID,
+) -> ServiceResult<Option<UserAccountStateModel>> {
+    let user = entity::user::Entity::find_by_id(user_id.as_db())
+        .one(tx)
+        .await
+        .map_err_unknown()?;
+
+    Ok(user.map(|u| UserAccountStateModel::from_db(u.account_state)))
+}
+
+pub async fn is_user_suspended(tx: &MaybeTxConn, user_id: UserID) -> ServiceResult<bool> {
+    let state = get_user_account_state(tx, user_id).await?;
+    Ok(state == Some(UserAccountStateModel::Suspended))
+}
+
+/// URL で指定されたリモートアクターが凍結されているかどうかを返す。
+/// 未知のアクターの場合は false を返す。
+pub async fn is_actor_url_suspended(tx: &MaybeTxConn, url: &Url) -> ServiceResult<bool> {
+    let count = entity::user::Entity::find()
+        .filter(
+            Condition::all()
+                .add(entity::user::Column::Url.eq(url.as_str()))
+                .add(entity::user::Column::AccountState.eq(UserAccountState::Suspended)),
+        )
+        .count(tx)
+        .await
+        .map_err_unknown()?;
+
+    Ok(count > 0)
+}
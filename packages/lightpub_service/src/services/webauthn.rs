@@ -0,0 +1,755 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! WebAuthn (パスキー)
+//!
+//! ブラウザの navigator.credentials.create() による登録と、
+//! navigator.credentials.get() による認証の結果を検証する。
+//! パスキーはパスワードの代わりにログインに使えるほか、
+//! 二要素認証が有効な場合は認証アプリのコードの代わりにも使える。
+//!
+//! 公開鍵のアルゴリズムは ES256 (P-256) のみに対応し、アテステーションは検証しない。
+
+use std::time::Duration;
+
+use actix_web::http::StatusCode;
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use chrono::{DateTime, Utc};
+use ciborium::Value;
+use expected_error_derive::ExpectedError;
+use p256::ecdsa::{Signature, VerifyingKey, signature::Verifier};
+use rand::Rng;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder,
+    Set,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use url::Url;
+
+use super::{
+    MapToUnknown, ServiceError, ServiceResult,
+    db::{Conn, MaybeTxConn},
+    id::{Identifier, UserID},
+    kv::KVObject,
+    two_factor::{
+        finish_pending_login, get_pending_login_user, verify_password, verify_reauthentication,
+    },
+    user::{UserSuspendError, is_user_deleted, is_user_suspended},
+};
+
+/// 登録・認証を開始してから完了するまでの制限時間
+const CHALLENGE_TTL: Duration = Duration::from_secs(60 * 5);
+
+const CHALLENGE_LENGTH: usize = 32;
+
+/// COSE で ES256 を表す値
+const COSE_ALG_ES256: i64 = -7;
+
+/// 1 ユーザーが登録できるパスキーの数
+const MAX_CREDENTIALS_PER_USER: u64 = 20;
+
+const CREDENTIAL_NAME_MAX_LENGTH: usize = 64;
+
+/// クレデンシャル ID の最大長 (バイト)。
+/// Base64URL でエンコードしてデータベースの列に収まる長さにする。
+const CREDENTIAL_ID_MAX_LENGTH: usize = 384;
+
+/// authenticatorData のフラグ
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+#[derive(Debug, Clone, Error, ExpectedError)]
+pub enum WebauthnError {
+    #[error("invalid passkey name")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    InvalidName,
+    #[error("too many passkeys")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    TooManyCredentials,
+    #[error("passkey already registered")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    AlreadyRegistered,
+    #[error("unsupported public key algorithm")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    UnsupportedAlgorithm,
+    #[error("challenge expired")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    ChallengeExpired,
+    #[error("invalid credential")]
+    #[ee(status(StatusCode::BAD_REQUEST))]
+    InvalidCredential,
+    #[error("passkey verification failed")]
+    #[ee(status(StatusCode::UNAUTHORIZED))]
+    VerificationFailed,
+    #[error("passkey not found")]
+    #[ee(status(StatusCode::NOT_FOUND))]
+    NotFound,
+}
+
+fn invalid_credential<T>() -> ServiceResult<T> {
+    Err(ServiceError::known(WebauthnError::InvalidCredential))
+}
+
+fn verification_failed<T>() -> ServiceResult<T> {
+    Err(ServiceError::known(WebauthnError::VerificationFailed))
+}
+
+/// パスキーを利用するサービス (Relying Party) の情報
+#[derive(Debug, Clone)]
+pub struct RelyingParty {
+    /// RP ID。サーバーのドメイン名。
+    pub id: String,
+    pub name: String,
+    /// clientDataJSON の origin として期待する値
+    pub origin: String,
+}
+
+impl RelyingParty {
+    pub fn from_base_url(base_url: &Url) -> Self {
+        let id = base_url.host_str().unwrap_or_default().to_string();
+        Self {
+            name: id.clone(),
+            id,
+            origin: base_url.origin().ascii_serialization(),
+        }
+    }
+}
+
+fn decode_base64url(s: &str) -> ServiceResult<Vec<u8>> {
+    BASE64_URL_SAFE_NO_PAD
+        .decode(s.trim_end_matches('='))
+        .or_else(|_| invalid_credential())
+}
+
+fn generate_challenge() -> String {
+    let mut challenge = [0u8; CHALLENGE_LENGTH];
+    rand::thread_rng().fill(&mut challenge);
+    BASE64_URL_SAFE_NO_PAD.encode(challenge)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PublicKeyCredentialDescriptor {
+    #[serde(rename = "type")]
+    pub type_: &'static str,
+    /// Base64URL でエンコードしたクレデンシャル ID
+    pub id: String,
+}
+
+impl PublicKeyCredentialDescriptor {
+    fn new(id: String) -> Self {
+        Self {
+            type_: "public-key",
+            id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RelyingPartyEntity {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserEntity {
+    /// Base64URL でエンコードしたユーザー ID
+    pub id: String,
+    pub name: String,
+    pub display_name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PublicKeyCredentialParameters {
+    #[serde(rename = "type")]
+    pub type_: &'static str,
+    pub alg: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorSelection {
+    pub resident_key: &'static str,
+    pub user_verification: &'static str,
+}
+
+/// navigator.credentials.create() に渡すオプション。
+/// バイナリの値は Base64URL でエンコードしているので、クライアント側でデコードする。
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialCreationOptions {
+    pub rp: RelyingPartyEntity,
+    pub user: UserEntity,
+    pub challenge: String,
+    pub pub_key_cred_params: Vec<PublicKeyCredentialParameters>,
+    /// ミリ秒
+    pub timeout: u64,
+    pub exclude_credentials: Vec<PublicKeyCredentialDescriptor>,
+    pub authenticator_selection: AuthenticatorSelection,
+    pub attestation: &'static str,
+}
+
+/// navigator.credentials.get() に渡すオプション
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialRequestOptions {
+    pub challenge: String,
+    pub timeout: u64,
+    pub rp_id: String,
+    /// 空の場合は、ブラウザが端末に保存されたパスキーから選ばせる
+    pub allow_credentials: Vec<PublicKeyCredentialDescriptor>,
+    pub user_verification: &'static str,
+}
+
+/// navigator.credentials.create() の結果
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistrationCredential {
+    pub raw_id: String,
+    pub response: AuthenticatorAttestationResponse,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorAttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub attestation_object: String,
+}
+
+/// navigator.credentials.get() の結果
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticationCredential {
+    pub raw_id: String,
+    pub response: AuthenticatorAssertionResponse,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorAssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+    pub user_handle: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyModel {
+    pub id: i32,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl PasskeyModel {
+    fn from_db(model: entity::webauthn_credential::Model) -> Self {
+        Self {
+            id: model.id,
+            name: model.name,
+            created_at: model.created_at.and_utc(),
+            last_used_at: model.last_used_at.map(|t| t.and_utc()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CollectedClientData {
+    #[serde(rename = "type")]
+    type_: String,
+    challenge: String,
+    origin: String,
+    #[serde(rename = "crossOrigin", default)]
+    cross_origin: bool,
+}
+
+/// clientDataJSON を検証して、含まれるチャレンジを返す。
+fn verify_client_data(
+    rp: &RelyingParty,
+    client_data_json: &[u8],
+    expected_type: &str,
+) -> ServiceResult<String> {
+    let Ok(client_data) = serde_json::from_slice::<CollectedClientData>(client_data_json) else {
+        return invalid_credential();
+    };
+    if client_data.type_ != expected_type
+        || client_data.origin != rp.origin
+        || client_data.cross_origin
+    {
+        return verification_failed();
+    }
+    Ok(client_data.challenge)
+}
+
+struct AuthenticatorData {
+    rp_id_hash: [u8; 32],
+    flags: u8,
+    sign_count: u32,
+    /// 登録時のみ含まれるクレデンシャル ID と公開鍵
+    attested_credential: Option<(Vec<u8>, Value)>,
+}
+
+impl AuthenticatorData {
+    fn parse(data: &[u8]) -> ServiceResult<Self> {
+        if data.len() < 37 {
+            return invalid_credential();
+        }
+        let rp_id_hash = data[..32].try_into().unwrap();
+        let flags = data[32];
+        let sign_count = u32::from_be_bytes(data[33..37].try_into().unwrap());
+
+        let attested_credential = if flags & FLAG_ATTESTED_CREDENTIAL_DATA != 0 {
+            // AAGUID (16 バイト) は使わない
+            let rest = &data[37..];
+            if rest.len() < 18 {
+                return invalid_credential();
+            }
+            let id_len = u16::from_be_bytes([rest[16], rest[17]]) as usize;
+            let rest = &rest[18..];
+            if rest.len() < id_len {
+                return invalid_credential();
+            }
+            let (credential_id, mut rest) = rest.split_at(id_len);
+            let Ok(public_key) = ciborium::from_reader::<Value, _>(&mut rest) else {
+                return invalid_credential();
+            };
+            Some((credential_id.to_vec(), public_key))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            rp_id_hash,
+            flags,
+            sign_count,
+            attested_credential,
+        })
+    }
+
+    fn verify(&self, rp: &RelyingParty, require_user_verification: bool) -> ServiceResult<()> {
+        if self.rp_id_hash[..] != Sha256::digest(rp.id.as_bytes())[..]
+            || self.flags & FLAG_USER_PRESENT == 0
+            || (require_user_verification && self.flags & FLAG_USER_VERIFIED == 0)
+        {
+            return verification_failed();
+        }
+        Ok(())
+    }
+}
+
+fn cbor_map_get<'a>(map: &'a Value, key: &Value) -> Option<&'a Value> {
+    map.as_map()?.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+}
+
+/// COSE 形式の公開鍵を SEC1 の非圧縮形式に変換する。
+fn cose_key_to_sec1(key: &Value) -> ServiceResult<Vec<u8>> {
+    let int = |i: i64| Value::Integer(i.into());
+    let get_int = |label: i64| -> Option<i128> {
+        cbor_map_get(key, &int(label))?.as_integer().map(i128::from)
+    };
+    let get_bytes = |label: i64| cbor_map_get(key, &int(label))?.as_bytes();
+
+    // kty = EC2, crv = P-256
+    if get_int(3) != Some(COSE_ALG_ES256 as i128) || get_int(1) != Some(2) || get_int(-1) != Some(1)
+    {
+        return Err(ServiceError::known(WebauthnError::UnsupportedAlgorithm));
+    }
+    let (Some(x), Some(y)) = (get_bytes(-2), get_bytes(-3)) else {
+        return invalid_credential();
+    };
+    if x.len() != 32 || y.len() != 32 {
+        return invalid_credential();
+    }
+
+    let mut sec1 = Vec::with_capacity(65);
+    sec1.push(0x04);
+    sec1.extend_from_slice(x);
+    sec1.extend_from_slice(y);
+    if VerifyingKey::from_sec1_bytes(&sec1).is_err() {
+        return invalid_credential();
+    }
+    Ok(sec1)
+}
+
+fn registration_challenge_key(user_id: UserID) -> String {
+    format!("webauthn:register:{}", user_id)
+}
+
+/// パスキーの登録を開始する。
+/// パスキーだけでログインできるようになるので、パスワードと、
+/// 二要素認証が有効な場合はそのコードを確認してからチャレンジを発行する。
+/// コードは 1 回しか使えないので、登録の完了時にはパスワードだけを再確認する。
+pub async fn begin_passkey_registration(
+    conn: &Conn,
+    rconn: &KVObject,
+    rp: &RelyingParty,
+    user_id: UserID,
+    password: &str,
+    code: Option<&str>,
+) -> ServiceResult<CredentialCreationOptions> {
+    verify_reauthentication(conn, user_id, password, code).await?;
+
+    let user = entity::user::Entity::find_by_id(user_id.as_db())
+        .one(conn.db())
+        .await
+        .map_err_unknown()?
+        .ok_or_else(|| ServiceError::ise("user not found"))?;
+
+    // 同じ認証器に重複して登録しないように、登録済みのクレデンシャルを渡す
+    let exclude_credentials = entity::webauthn_credential::Entity::find()
+        .filter(entity::webauthn_credential::Column::UserId.eq(user_id.as_db()))
+        .all(conn.db())
+        .await
+        .map_err_unknown()?
+        .into_iter()
+        .map(|c| PublicKeyCredentialDescriptor::new(c.credential_id))
+        .collect();
+
+    let challenge = generate_challenge();
+    rconn
+        .set_ttl(
+            registration_challenge_key(user_id),
+            &challenge,
+            CHALLENGE_TTL,
+        )
+        .await?;
+
+    Ok(CredentialCreationOptions {
+        rp: RelyingPartyEntity {
+            id: rp.id.clone(),
+            name: rp.name.clone(),
+        },
+        user: UserEntity {
+            id: BASE64_URL_SAFE_NO_PAD.encode(user_id.as_db()),
+            name: user.username,
+            display_name: user.nickname,
+        },
+        challenge,
+        pub_key_cred_params: vec![PublicKeyCredentialParameters {
+            type_: "public-key",
+            alg: COSE_ALG_ES256,
+        }],
+        timeout: CHALLENGE_TTL.as_millis() as u64,
+        exclude_credentials,
+        authenticator_selection: AuthenticatorSelection {
+            resident_key: "preferred",
+            user_verification: "preferred",
+        },
+        attestation: "none",
+    })
+}
+
+/// 認証器が作成したクレデンシャルを検証して登録する。
+pub async fn finish_passkey_registration(
+    conn: &Conn,
+    rconn: &KVObject,
+    rp: &RelyingParty,
+    user_id: UserID,
+    password: &str,
+    name: &str,
+    credential: &RegistrationCredential,
+) -> ServiceResult<PasskeyModel> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > CREDENTIAL_NAME_MAX_LENGTH {
+        return Err(ServiceError::known(WebauthnError::InvalidName));
+    }
+
+    verify_password(conn, user_id, password).await?;
+
+    let key = registration_challenge_key(user_id);
+    let expected_challenge: Option<String> = rconn.get(&key).await?;
+    rconn.delete(&key).await?;
+    let Some(expected_challenge) = expected_challenge else {
+        return Err(ServiceError::known(WebauthnError::ChallengeExpired));
+    };
+
+    let client_data_json = decode_base64url(&credential.response.client_data_json)?;
+    let challenge = verify_client_data(rp, &client_data_json, "webauthn.create")?;
+    if challenge != expected_challenge {
+        return verification_failed();
+    }
+
+    let attestation_object = decode_base64url(&credential.response.attestation_object)?;
+    let Ok(attestation_object) = ciborium::from_reader::<Value, _>(attestation_object.as_slice())
+    else {
+        return invalid_credential();
+    };
+    let Some(auth_data) = cbor_map_get(&attestation_object, &Value::Text("authData".into()))
+        .and_then(Value::as_bytes)
+    else {
+        return invalid_credential();
+    };
+    let auth_data = AuthenticatorData::parse(auth_data)?;
+    auth_data.verify(rp, false)?;
+
+    let Some((credential_id, public_key)) = &auth_data.attested_credential else {
+        return invalid_credential();
+    };
+    if credential_id.is_empty()
+        || credential_id.len() > CREDENTIAL_ID_MAX_LENGTH
+        || *credential_id != decode_base64url(&credential.raw_id)?
+    {
+        return invalid_credential();
+    }
+    let public_key = cose_key_to_sec1(public_key)?;
+
+    let count = entity::webauthn_credential::Entity::find()
+        .filter(entity::webauthn_credential::Column::UserId.eq(user_id.as_db()))
+        .count(conn.db())
+        .await
+        .map_err_unknown()?;
+    if count >= MAX_CREDENTIALS_PER_USER {
+        return Err(ServiceError::known(WebauthnError::TooManyCredentials));
+    }
+
+    let model = entity::webauthn_credential::ActiveModel {
+        user_id: Set(user_id.as_db()),
+        name: Set(name.to_string()),
+        credential_id: Set(BASE64_URL_SAFE_NO_PAD.encode(credential_id)),
+        public_key: Set(BASE64_URL_SAFE_NO_PAD.encode(public_key)),
+        sign_count: Set(auth_data.sign_count as i64),
+        created_at: Set(Utc::now().naive_utc()),
+        ..Default::default()
+    };
+    let model = match model.insert(conn.db()).await {
+        Ok(model) => model,
+        Err(e) if super::db::is_unique_constraint_error(&e) => {
+            return Err(ServiceError::known(WebauthnError::AlreadyRegistered));
+        }
+        Err(e) => return Err(ServiceError::unknown(e)),
+    };
+
+    Ok(PasskeyModel::from_db(model))
+}
+
+pub async fn get_passkeys(tx: &MaybeTxConn, user_id: UserID) -> ServiceResult<Vec<PasskeyModel>> {
+    Ok(entity::webauthn_credential::Entity::find()
+        .filter(entity::webauthn_credential::Column::UserId.eq(user_id.as_db()))
+        .order_by_desc(entity::webauthn_credential::Column::CreatedAt)
+        .all(tx)
+        .await
+        .map_err_unknown()?
+        .into_iter()
+        .map(PasskeyModel::from_db)
+        .collect())
+}
+
+/// パスキーを削除する。登録時と同じく、パスワードと二要素認証のコードを確認する。
+pub async fn delete_passkey(
+    conn: &Conn,
+    user_id: UserID,
+    passkey_id: i32,
+    password: &str,
+    code: Option<&str>,
+) -> ServiceResult<()> {
+    verify_reauthentication(conn, user_id, password, code).await?;
+
+    let result = entity::webauthn_credential::Entity::delete_many()
+        .filter(
+            Condition::all()
+                .add(entity::webauthn_credential::Column::Id.eq(passkey_id))
+                .add(entity::webauthn_credential::Column::UserId.eq(user_id.as_db())),
+        )
+        .exec(conn.db())
+        .await
+        .map_err_unknown()?;
+
+    if result.rows_affected == 0 {
+        return Err(ServiceError::known(WebauthnError::NotFound));
+    }
+    Ok(())
+}
+
+/// 認証のチャレンジ。
+/// 二要素認証として使う場合は、パスワードを確認したユーザーのパスキーに限定する。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AuthenticationChallenge {
+    user_id: Option<UserID>,
+}
+
+fn authentication_challenge_key(challenge: &str) -> String {
+    format!("webauthn:authenticate:{}", challenge)
+}
+
+/// パスキーによる認証を開始する。
+/// user_id を指定しない場合は、どのユーザーのパスキーでも認証できる。
+pub async fn begin_passkey_authentication(
+    conn: &Conn,
+    rconn: &KVObject,
+    rp: &RelyingParty,
+    user_id: Option<UserID>,
+) -> ServiceResult<CredentialRequestOptions> {
+    let allow_credentials = match user_id {
+        Some(user_id) => entity::webauthn_credential::Entity::find()
+            .filter(entity::webauthn_credential::Column::UserId.eq(user_id.as_db()))
+            .all(conn.db())
+            .await
+            .map_err_unknown()?
+            .into_iter()
+            .map(|c| PublicKeyCredentialDescriptor::new(c.credential_id))
+            .collect(),
+        None => vec![],
+    };
+
+    let challenge = generate_challenge();
+    rconn
+        .set_ttl(
+            authentication_challenge_key(&challenge),
+            &AuthenticationChallenge { user_id },
+            CHALLENGE_TTL,
+        )
+        .await?;
+
+    Ok(CredentialRequestOptions {
+        challenge,
+        timeout: CHALLENGE_TTL.as_millis() as u64,
+        rp_id: rp.id.clone(),
+        allow_credentials,
+        user_verification: if user_id.is_some() {
+            "discouraged"
+        } else {
+            "required"
+        },
+    })
+}
+
+/// 認証器の署名を検証して、パスキーの持ち主を返す。
+/// 二要素認証として開始したチャレンジの場合は、その持ち主のパスキーでなければ失敗する。
+async fn verify_passkey_assertion(
+    conn: &Conn,
+    rconn: &KVObject,
+    rp: &RelyingParty,
+    credential: &AuthenticationCredential,
+) -> ServiceResult<(UserID, AuthenticationChallenge)> {
+    let client_data_json = decode_base64url(&credential.response.client_data_json)?;
+    let challenge = verify_client_data(rp, &client_data_json, "webauthn.get")?;
+
+    // チャレンジは 1 回しか使えない
+    let key = authentication_challenge_key(&challenge);
+    let expected: Option<AuthenticationChallenge> = rconn.get(&key).await?;
+    rconn.delete(&key).await?;
+    let Some(expected) = expected else {
+        return Err(ServiceError::known(WebauthnError::ChallengeExpired));
+    };
+
+    let credential_id = BASE64_URL_SAFE_NO_PAD.encode(decode_base64url(&credential.raw_id)?);
+    let stored = entity::webauthn_credential::Entity::find()
+        .filter(entity::webauthn_credential::Column::CredentialId.eq(&credential_id))
+        .one(conn.db())
+        .await
+        .map_err_unknown()?;
+    let Some(stored) = stored else {
+        return verification_failed();
+    };
+    let user_id = UserID::from_db_trusted(stored.user_id.clone());
+    if expected.user_id.is_some_and(|expected| expected != user_id) {
+        return verification_failed();
+    }
+    if let Some(user_handle) = &credential.response.user_handle
+        && decode_base64url(user_handle)? != stored.user_id
+    {
+        return verification_failed();
+    }
+
+    let authenticator_data = decode_base64url(&credential.response.authenticator_data)?;
+    let auth_data = AuthenticatorData::parse(&authenticator_data)?;
+    // パスワードの代わりに使う場合は、認証器での本人確認 (生体認証や PIN) を必須にする
+    auth_data.verify(rp, expected.user_id.is_none())?;
+
+    let public_key = decode_base64url(&stored.public_key)?;
+    let Ok(public_key) = VerifyingKey::from_sec1_bytes(&public_key) else {
+        return Err(ServiceError::ise("invalid stored public key"));
+    };
+    let Ok(signature) = Signature::from_der(&decode_base64url(&credential.response.signature)?)
+    else {
+        return verification_failed();
+    };
+    let mut signed_data = authenticator_data.clone();
+    signed_data.extend_from_slice(&Sha256::digest(&client_data_json));
+    if public_key.verify(&signed_data, &signature).is_err() {
+        return verification_failed();
+    }
+
+    // 署名カウンタが増えていない場合は、認証器が複製された可能性がある
+    let sign_count = auth_data.sign_count as i64;
+    if (sign_count != 0 || stored.sign_count != 0) && sign_count <= stored.sign_count {
+        return verification_failed();
+    }
+
+    let mut stored: entity::webauthn_credential::ActiveModel = stored.into();
+    stored.sign_count = Set(sign_count);
+    stored.last_used_at = Set(Some(Utc::now().naive_utc()));
+    stored.update(conn.db()).await.map_err_unknown()?;
+
+    Ok((user_id, expected))
+}
+
+/// パスワードの代わりにパスキーでログインする。
+/// 本人確認を済ませたパスキーは単独で二要素を満たすので、認証アプリのコードは求めない。
+pub async fn authenticate_with_passkey(
+    conn: &Conn,
+    rconn: &KVObject,
+    rp: &RelyingParty,
+    credential: &AuthenticationCredential,
+) -> ServiceResult<UserID> {
+    let (user_id, challenge) = verify_passkey_assertion(conn, rconn, rp, credential).await?;
+    if challenge.user_id.is_some() {
+        // 二要素認証用のチャレンジでは本人確認を求めていない
+        return verification_failed();
+    }
+    let maybe_conn = conn.clone().into();
+    if is_user_deleted(&maybe_conn, user_id).await? {
+        return verification_failed();
+    }
+    if is_user_suspended(&maybe_conn, user_id).await? {
+        return Err(ServiceError::known(UserSuspendError::Suspended));
+    }
+    Ok(user_id)
+}
+
+/// パスワードを確認した後の二要素認証のために、パスキーによる認証を開始する。
+pub async fn begin_pending_login_passkey(
+    conn: &Conn,
+    rconn: &KVObject,
+    rp: &RelyingParty,
+    ticket: &str,
+) -> ServiceResult<CredentialRequestOptions> {
+    let user_id = get_pending_login_user(rconn, ticket).await?;
+    begin_passkey_authentication(conn, rconn, rp, Some(user_id)).await
+}
+
+/// 認証アプリのコードの代わりにパスキーで二要素認証を完了する。
+pub async fn complete_pending_login_with_passkey(
+    conn: &Conn,
+    rconn: &KVObject,
+    rp: &RelyingParty,
+    ticket: &str,
+    credential: &AuthenticationCredential,
+) -> ServiceResult<UserID> {
+    let pending_user_id = get_pending_login_user(rconn, ticket).await?;
+    let (user_id, challenge) = verify_passkey_assertion(conn, rconn, rp, credential).await?;
+    if user_id != pending_user_id || challenge.user_id != Some(pending_user_id) {
+        return verification_failed();
+    }
+    finish_pending_login(conn, rconn, ticket, user_id).await
+}
@@ -28,6 +28,23 @@ impl CleanString {
         CleanString(cleaned)
     }
 
+    /// `clean` と同様にサニタイズするが、`local_prefix` から始まる URL の画像だけは残す。
+    /// サーバーが挿入した絵文字画像を含む HTML をもう一度サニタイズするために使う。
+    pub fn clean_with_local_images(s: &str, local_prefix: &str) -> CleanString {
+        let local_prefix = local_prefix.to_string();
+        let cleaned = Builder::default()
+            .add_tag_attributes("img", ["title", "class"])
+            .attribute_filter(move |element, attribute, value| {
+                if element == "img" && attribute == "src" && !value.starts_with(&local_prefix) {
+                    return None;
+                }
+                Some(value.into())
+            })
+            .clean(s)
+            .to_string();
+        CleanString(cleaned)
+    }
+
     pub fn clean_text(s: &str) -> CleanString {
         CleanString(ammonia::clean_text(s))
     }
@@ -1,13 +1,25 @@
-use actix_web::{post, web, HttpResponse, Responder};
+use actix_multipart::form::{tempfile::TempFile, text::Text as MpText, MultipartForm};
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
 use chrono::{DateTime, Utc};
 use expected_error::StatusCode;
 use lightpub_service::services::create_error_simple;
+use lightpub_service::services::domain_policy::{
+    add_domain_policy, list_domain_policies, remove_domain_policy, DomainPolicyActionModel,
+};
+use lightpub_service::services::emoji::{
+    create_local_emoji, delete_local_emoji, list_local_emojis,
+};
+use lightpub_service::services::id::UserID;
 use lightpub_service::services::note::rebuild_note_fulltext_index;
-use lightpub_service::services::user::is_admin;
+use lightpub_service::services::report::{
+    dismiss_report, list_reports, resolve_report, ReportResolveAction, ReportStatusModel,
+};
+use lightpub_service::services::user::{is_admin, set_user_account_state, UserAccountStateModel};
+use lightpub_service::services::ServiceResult;
 use serde::Deserialize;
 
 use super::auth::AuthedUser;
-use crate::api::auth::middleware_auth_jwt_required;
+use crate::api::auth::{middleware_auth_jwt_required_scoped, SessionOnlyScope};
 use crate::AppState;
 use actix_web::middleware::from_fn;
 
@@ -19,7 +31,7 @@ pub struct AdminApiRebuildNoteFTRequest {
 
 #[post(
     "/admin/ft/rebuild/note",
-    wrap = "from_fn(middleware_auth_jwt_required)"
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
 )]
 pub async fn admin_api_rebuild_note_fulltext(
     st: web::Data<AppState>,
@@ -42,3 +54,215 @@ pub async fn admin_api_rebuild_note_fulltext(
         )
     }
 }
+
+async fn check_admin(st: &AppState, auth: &AuthedUser) -> ServiceResult<()> {
+    let user_id = auth.user_id_unwrap();
+    if !is_admin(&st.maybe_conn(), user_id).await? {
+        return create_error_simple(StatusCode::FORBIDDEN, "You are not an admin");
+    }
+    Ok(())
+}
+
+#[get(
+    "/admin/domain-policy",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn admin_api_list_domain_policies(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<impl Responder> {
+    check_admin(&st, &auth).await?;
+
+    let policies = list_domain_policies(&st.maybe_conn()).await?;
+    Ok(HttpResponse::Ok().json(policies))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminApiAddDomainPolicyRequest {
+    domain: String,
+    action: DomainPolicyActionModel,
+    reason: Option<String>,
+}
+
+#[post(
+    "/admin/domain-policy",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn admin_api_add_domain_policy(
+    st: web::Data<AppState>,
+    params: web::Json<AdminApiAddDomainPolicyRequest>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<impl Responder> {
+    check_admin(&st, &auth).await?;
+
+    let params = params.into_inner();
+    let policy = add_domain_policy(
+        &st.maybe_conn(),
+        &params.domain,
+        params.action,
+        params.reason,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(policy))
+}
+
+#[delete(
+    "/admin/domain-policy/{policy_id}",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn admin_api_remove_domain_policy(
+    st: web::Data<AppState>,
+    policy_id: web::Path<i32>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<impl Responder> {
+    check_admin(&st, &auth).await?;
+
+    remove_domain_policy(&st.maybe_conn(), policy_id.into_inner()).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminApiListReportsQuery {
+    status: Option<ReportStatusModel>,
+    limit: Option<u64>,
+    before_date: Option<DateTime<Utc>>,
+}
+
+#[get(
+    "/admin/report",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn admin_api_list_reports(
+    st: web::Data<AppState>,
+    query: web::Query<AdminApiListReportsQuery>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<impl Responder> {
+    check_admin(&st, &auth).await?;
+
+    let limit = query.limit.unwrap_or(20).min(100);
+    let reports = list_reports(&st.maybe_conn(), query.status, limit, query.before_date).await?;
+    Ok(HttpResponse::Ok().json(reports))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminApiResolveReportRequest {
+    #[serde(default)]
+    action: ReportResolveAction,
+}
+
+#[post(
+    "/admin/report/{report_id}/resolve",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn admin_api_resolve_report(
+    st: web::Data<AppState>,
+    report_id: web::Path<i32>,
+    params: web::Json<AdminApiResolveReportRequest>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<impl Responder> {
+    check_admin(&st, &auth).await?;
+
+    resolve_report(
+        st.conn(),
+        &st.rconn(),
+        st.qconn(),
+        report_id.into_inner(),
+        auth.user_id_unwrap(),
+        params.action,
+        st.base_url(),
+    )
+    .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[post(
+    "/admin/report/{report_id}/dismiss",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn admin_api_dismiss_report(
+    st: web::Data<AppState>,
+    report_id: web::Path<i32>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<impl Responder> {
+    check_admin(&st, &auth).await?;
+
+    dismiss_report(st.conn(), report_id.into_inner(), auth.user_id_unwrap()).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminApiUserStateRequest {
+    state: UserAccountStateModel,
+}
+
+#[post(
+    "/admin/user/{user_id}/state",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn admin_api_set_user_state(
+    st: web::Data<AppState>,
+    user_id: web::Path<UserID>,
+    params: web::Json<AdminApiUserStateRequest>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<impl Responder> {
+    check_admin(&st, &auth).await?;
+
+    set_user_account_state(st.conn(), &st.rconn(), user_id.into_inner(), params.state).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get(
+    "/admin/emoji",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn admin_api_list_emojis(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<impl Responder> {
+    check_admin(&st, &auth).await?;
+
+    let emojis = list_local_emojis(&st.maybe_conn()).await?;
+    Ok(HttpResponse::Ok().json(emojis))
+}
+
+#[derive(Debug, MultipartForm)]
+pub struct AdminApiAddEmojiRequest {
+    shortcode: MpText<String>,
+    #[multipart(limit = "1MB")]
+    file: TempFile,
+}
+
+#[post(
+    "/admin/emoji",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn admin_api_add_emoji(
+    st: web::Data<AppState>,
+    MultipartForm(req): MultipartForm<AdminApiAddEmojiRequest>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<impl Responder> {
+    check_admin(&st, &auth).await?;
+
+    let emoji =
+        create_local_emoji(st.conn(), st.storage(), &req.shortcode.0, req.file.file).await?;
+    Ok(HttpResponse::Ok().json(emoji))
+}
+
+#[delete(
+    "/admin/emoji/{shortcode}",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn admin_api_remove_emoji(
+    st: web::Data<AppState>,
+    shortcode: web::Path<String>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<impl Responder> {
+    check_admin(&st, &auth).await?;
+
+    delete_local_emoji(st.conn(), st.storage(), &shortcode).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -22,17 +22,20 @@ use std::{borrow::Cow, env::VarError};
 use actix_session::Session;
 use actix_web::{
     body::MessageBody,
+    delete,
     dev::{ServiceRequest, ServiceResponse},
-    http::{header::HeaderMap, StatusCode},
+    http::{header::HeaderMap, Method, StatusCode},
     middleware::Next,
-    post, web, HttpMessage, Responder,
+    post, web, HttpMessage, HttpResponse, Responder,
 };
 use chrono::{DateTime, Utc};
 use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use qrcode::{render::svg, QrCode};
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
 use lightpub_service::services::{
+    access_token::{authenticate_personal_access_token, PERSONAL_ACCESS_TOKEN_PREFIX},
     auth::{
         change_password, check_password_user, check_user_login_expiration, logout_all,
         register_user,
@@ -40,6 +43,18 @@ use lightpub_service::services::{
     create_error_simple, create_error_simple_err,
     db::Conn,
     id::{Identifier, UserID},
+    oauth::{authenticate_oauth_token, OAuthScopes},
+    two_factor::{
+        begin_totp_enrollment, complete_pending_login, confirm_totp_enrollment,
+        create_pending_login, disable_two_factor, is_two_factor_enabled, TwoFactorError,
+    },
+    user::{delete_local_user, is_user_suspended, UserSuspendError},
+    webauthn::{
+        authenticate_with_passkey, begin_passkey_authentication, begin_passkey_registration,
+        begin_pending_login_passkey, complete_pending_login_with_passkey, delete_passkey,
+        finish_passkey_registration, AuthenticationCredential, CredentialCreationOptions,
+        CredentialRequestOptions, RegistrationCredential, RelyingParty,
+    },
     MapToUnknown, ServiceError, ServiceResult,
 };
 
@@ -86,15 +101,59 @@ pub async fn api_register_user(
 pub struct LoginRequest {
     username: String,
     password: String,
+    /// ログイン後に移動するパス
+    next: Option<String>,
+}
+
+/// ログイン後の移動先として使えるパスかどうか。
+/// 外部のサイトに移動しないように、同じオリジンのパスのみ許可する。
+pub fn is_safe_redirect_path(path: &str) -> bool {
+    path.starts_with('/') && !path.starts_with("//") && !path.contains('\\')
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct LoginResponse {
     user_id: String,
-    token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+    /// 二要素認証のコードの入力が必要かどうか
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    two_factor_required: bool,
+    /// 二要素認証を完了するためのチケット。
+    /// ブラウザからのログインではセッションにも保存するので、送り返す必要はない。
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ticket: Option<String>,
 }
 
 const TOKEN_SESSION_KEY: &str = "token";
+const LOGIN_TICKET_SESSION_KEY: &str = "login_ticket";
+
+/// ログイン用の JWT を発行して、セッションに保存する。
+/// パスワードと、有効な場合は二要素認証の両方を確認した後にだけ呼び出すこと。
+async fn complete_login(
+    session: &Session,
+    user_id: UserID,
+    next: Option<&str>,
+) -> ServiceResult<APIResponse<LoginResponse>> {
+    let token = generate_jwt(user_id.to_string().as_str(), &get_jwt_secret_key().await?)?;
+    session.remove(LOGIN_TICKET_SESSION_KEY);
+    session
+        .insert(TOKEN_SESSION_KEY, token.clone())
+        .map_err_unknown()?;
+    Ok(APIResponseBuilder::default()
+        .data(LoginResponse {
+            user_id: user_id.to_string(),
+            token: Some(token),
+            two_factor_required: false,
+            ticket: None,
+        })
+        .redirect_to(
+            next.filter(|next| is_safe_redirect_path(next))
+                .unwrap_or("/client/timeline"),
+        )
+        .build()
+        .unwrap())
+}
 
 #[post("/login")]
 pub async fn api_login_user(
@@ -104,31 +163,153 @@ pub async fn api_login_user(
 ) -> ServiceResult<APIResponse<LoginResponse>> {
     let user = check_password_user(st.conn(), &req.username, &req.password).await?;
 
-    match user {
-        None => {
-            session.remove(TOKEN_SESSION_KEY);
-            create_error_simple(StatusCode::UNAUTHORIZED, "bad login")
-        }
-        Some(user) => {
-            let token = generate_jwt(
-                user.user_id.to_string().as_str(),
-                &get_jwt_secret_key().await?,
-            )?;
-            session
-                .insert(TOKEN_SESSION_KEY, token.clone())
-                .map_err_unknown()?;
-            Ok(APIResponseBuilder::default()
-                .data(LoginResponse {
-                    user_id: user.user_id.to_string(),
-                    token,
-                })
-                .redirect_to("/client/timeline")
-                .build()
-                .unwrap())
-        }
+    session.remove(TOKEN_SESSION_KEY);
+    session.remove(LOGIN_TICKET_SESSION_KEY);
+
+    let Some(user) = user else {
+        return create_error_simple(StatusCode::UNAUTHORIZED, "bad login");
+    };
+
+    if !is_two_factor_enabled(&st.maybe_conn(), user.user_id).await? {
+        return complete_login(&session, user.user_id, req.next.as_deref()).await;
+    }
+
+    // 二要素認証が有効な場合は、コードを確認するまでトークンを発行しない
+    let ticket = create_pending_login(&st.rconn(), user.user_id).await?;
+    session
+        .insert(LOGIN_TICKET_SESSION_KEY, ticket.clone())
+        .map_err_unknown()?;
+    Ok(APIResponseBuilder::default()
+        .data(LoginResponse {
+            user_id: user.user_id.to_string(),
+            token: None,
+            two_factor_required: true,
+            ticket: Some(ticket),
+        })
+        .build()
+        .unwrap())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorLoginRequest {
+    /// 認証アプリのコードまたはリカバリーコード
+    code: String,
+    /// 省略した場合はセッションに保存したチケットを使う
+    ticket: Option<String>,
+    next: Option<String>,
+}
+
+/// ログインの 2 段階目。パスワードの確認時に発行したチケットとコードを確認する。
+#[post("/login/2fa")]
+pub async fn api_login_two_factor(
+    st: web::Data<AppState>,
+    session: Session,
+    req: web::Json<TwoFactorLoginRequest>,
+) -> ServiceResult<APIResponse<LoginResponse>> {
+    let ticket = get_login_ticket(&session, req.ticket.as_deref())?;
+
+    let user_id = complete_pending_login(st.conn(), &st.rconn(), &ticket, &req.code).await?;
+
+    complete_login(&session, user_id, req.next.as_deref()).await
+}
+
+/// リクエストで指定されたチケットか、セッションに保存したチケットを返す。
+fn get_login_ticket(session: &Session, ticket: Option<&str>) -> ServiceResult<String> {
+    match ticket {
+        Some(ticket) => Ok(ticket.to_string()),
+        None => session
+            .get::<String>(LOGIN_TICKET_SESSION_KEY)
+            .map_err_unknown()?
+            .ok_or_else(|| ServiceError::known(TwoFactorError::LoginExpired)),
     }
 }
 
+#[derive(Debug, Default, Deserialize)]
+pub struct PasskeyLoginOptionsRequest {
+    ticket: Option<String>,
+}
+
+/// ログインの 2 段階目で、認証アプリのコードの代わりにパスキーを使うためのオプションを返す。
+#[post("/login/2fa/passkey/options")]
+pub async fn api_login_two_factor_passkey_options(
+    st: web::Data<AppState>,
+    session: Session,
+    req: Option<web::Json<PasskeyLoginOptionsRequest>>,
+) -> ServiceResult<web::Json<CredentialRequestOptions>> {
+    let req = req.map(|r| r.into_inner()).unwrap_or_default();
+    let ticket = get_login_ticket(&session, req.ticket.as_deref())?;
+
+    let options = begin_pending_login_passkey(
+        st.conn(),
+        &st.rconn(),
+        &RelyingParty::from_base_url(st.base_url()),
+        &ticket,
+    )
+    .await?;
+    Ok(web::Json(options))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasskeyLoginRequest {
+    credential: AuthenticationCredential,
+    ticket: Option<String>,
+    next: Option<String>,
+}
+
+#[post("/login/2fa/passkey")]
+pub async fn api_login_two_factor_passkey(
+    st: web::Data<AppState>,
+    session: Session,
+    req: web::Json<PasskeyLoginRequest>,
+) -> ServiceResult<APIResponse<LoginResponse>> {
+    let ticket = get_login_ticket(&session, req.ticket.as_deref())?;
+
+    let user_id = complete_pending_login_with_passkey(
+        st.conn(),
+        &st.rconn(),
+        &RelyingParty::from_base_url(st.base_url()),
+        &ticket,
+        &req.credential,
+    )
+    .await?;
+
+    complete_login(&session, user_id, req.next.as_deref()).await
+}
+
+/// パスワードを使わずにパスキーでログインするためのオプションを返す。
+#[post("/passkey/options")]
+pub async fn api_passkey_login_options(
+    st: web::Data<AppState>,
+) -> ServiceResult<web::Json<CredentialRequestOptions>> {
+    let options = begin_passkey_authentication(
+        st.conn(),
+        &st.rconn(),
+        &RelyingParty::from_base_url(st.base_url()),
+        None,
+    )
+    .await?;
+    Ok(web::Json(options))
+}
+
+#[post("/passkey/login")]
+pub async fn api_passkey_login(
+    st: web::Data<AppState>,
+    session: Session,
+    req: web::Json<PasskeyLoginRequest>,
+) -> ServiceResult<APIResponse<LoginResponse>> {
+    session.remove(TOKEN_SESSION_KEY);
+
+    let user_id = authenticate_with_passkey(
+        st.conn(),
+        &st.rconn(),
+        &RelyingParty::from_base_url(st.base_url()),
+        &req.credential,
+    )
+    .await?;
+
+    complete_login(&session, user_id, req.next.as_deref()).await
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct LogoutQuery {
     all: Option<bool>,
@@ -166,7 +347,10 @@ pub struct ChangePasswordRequest {
     new_password: String,
 }
 
-#[post("/changePassword", wrap = "from_fn(middleware_auth_jwt_required)")]
+#[post(
+    "/changePassword",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
 pub async fn api_change_password(
     st: web::Data<AppState>,
     session: Session,
@@ -187,6 +371,212 @@ pub async fn api_change_password(
         .unwrap())
 }
 
+#[derive(Debug, Deserialize)]
+pub struct DeleteAccountRequest {
+    password: String,
+}
+
+#[post(
+    "/deleteAccount",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn api_delete_account(
+    st: web::Data<AppState>,
+    session: Session,
+    auth: web::ReqData<AuthedUser>,
+    req: web::Json<DeleteAccountRequest>,
+) -> ServiceResult<impl Responder> {
+    let user_id = auth.user_id_unwrap();
+
+    delete_local_user(
+        st.conn(),
+        &st.rconn(),
+        st.qconn(),
+        st.storage(),
+        user_id,
+        &req.password,
+        st.base_url(),
+    )
+    .await?;
+
+    session.remove(TOKEN_SESSION_KEY);
+
+    Ok(APIResponseBuilder::default()
+        .data(())
+        .redirect_to("/client/login")
+        .build()
+        .unwrap())
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TotpSetupResponse {
+    secret: String,
+    otpauth_uri: String,
+    /// otpauth_uri を表す QR コード (SVG)
+    qr_svg: String,
+}
+
+/// 二要素認証の設定を開始して、認証アプリに登録する共有鍵を返す。
+#[post(
+    "/2fa/setup",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn api_totp_setup(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<APIResponse<TotpSetupResponse>> {
+    let enrollment =
+        begin_totp_enrollment(st.conn(), auth.user_id_unwrap(), &st.my_domain()).await?;
+
+    let qr_svg = QrCode::new(enrollment.otpauth_uri.as_bytes())
+        .map_err_unknown()?
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build();
+
+    Ok(APIResponseBuilder::default()
+        .data(TotpSetupResponse {
+            secret: enrollment.secret,
+            otpauth_uri: enrollment.otpauth_uri,
+            qr_svg,
+        })
+        .build()
+        .unwrap())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TotpConfirmRequest {
+    code: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TotpConfirmResponse {
+    recovery_codes: Vec<String>,
+}
+
+#[post(
+    "/2fa/confirm",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn api_totp_confirm(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    req: web::Json<TotpConfirmRequest>,
+) -> ServiceResult<APIResponse<TotpConfirmResponse>> {
+    let recovery_codes =
+        confirm_totp_enrollment(st.conn(), auth.user_id_unwrap(), &req.code).await?;
+
+    Ok(APIResponseBuilder::default()
+        .data(TotpConfirmResponse { recovery_codes })
+        .build()
+        .unwrap())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TotpDisableRequest {
+    password: String,
+    code: String,
+}
+
+#[post(
+    "/2fa/disable",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn api_totp_disable(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    req: web::Json<TotpDisableRequest>,
+) -> ServiceResult<APIResponse<()>> {
+    disable_two_factor(st.conn(), auth.user_id_unwrap(), &req.password, &req.code).await?;
+
+    Ok(APIResponseBuilder::default()
+        .data(())
+        .do_refresh(true)
+        .build()
+        .unwrap())
+}
+
+/// パスキーの登録・削除の前に求める再認証
+#[derive(Debug, Deserialize)]
+pub struct ReauthenticationRequest {
+    password: String,
+    /// 二要素認証が有効な場合の、認証アプリのコードまたはリカバリーコード
+    code: Option<String>,
+}
+
+#[post(
+    "/passkeys/options",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn api_passkey_register_options(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    req: web::Json<ReauthenticationRequest>,
+) -> ServiceResult<web::Json<CredentialCreationOptions>> {
+    let options = begin_passkey_registration(
+        st.conn(),
+        &st.rconn(),
+        &RelyingParty::from_base_url(st.base_url()),
+        auth.user_id_unwrap(),
+        &req.password,
+        req.code.as_deref(),
+    )
+    .await?;
+    Ok(web::Json(options))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasskeyRegisterRequest {
+    name: String,
+    password: String,
+    credential: RegistrationCredential,
+}
+
+#[post(
+    "/passkeys",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn api_passkey_register(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    req: web::Json<PasskeyRegisterRequest>,
+) -> ServiceResult<impl Responder> {
+    let passkey = finish_passkey_registration(
+        st.conn(),
+        &st.rconn(),
+        &RelyingParty::from_base_url(st.base_url()),
+        auth.user_id_unwrap(),
+        &req.password,
+        &req.name,
+        &req.credential,
+    )
+    .await?;
+    Ok(HttpResponse::Created().json(passkey))
+}
+
+#[delete(
+    "/passkeys/{passkey_id}",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn api_passkey_delete(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    passkey_id: web::Path<i32>,
+    req: web::Json<ReauthenticationRequest>,
+) -> ServiceResult<impl Responder> {
+    delete_passkey(
+        st.conn(),
+        auth.user_id_unwrap(),
+        passkey_id.into_inner(),
+        &req.password,
+        req.code.as_deref(),
+    )
+    .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
 async fn get_cookie_from_req<'a>(
     headers: &'a HeaderMap,
     session: &'a Session,
@@ -218,10 +608,103 @@ async fn check_auth_expired(conn: &Conn, user_id: &UserID, claims: &Claims) -> S
     }
 }
 
-pub async fn middleware_auth_jwt_required(
+/// トークンを検証して、ユーザーの ID とトークンのスコープを返す。
+///
+/// ログイン時に発行する JWT と、OAuth のアクセストークン、パーソナルアクセストークンを受け付ける。
+/// JWT の場合はスコープの制限がないので None を返す。
+async fn verify_token(st: &AppState, token: &str) -> ServiceResult<(UserID, Option<OAuthScopes>)> {
+    if token.starts_with(PERSONAL_ACCESS_TOKEN_PREFIX) {
+        return match authenticate_personal_access_token(st.conn(), token).await? {
+            Some(t) => Ok((t.user_id, Some(t.scopes))),
+            None => create_error_simple(StatusCode::UNAUTHORIZED, "bad token"),
+        };
+    }
+
+    // OAuth のアクセストークンには "." が含まれない
+    if !token.contains('.') {
+        return match authenticate_oauth_token(st.conn(), token).await? {
+            Some(t) => Ok((t.user_id, Some(t.scopes))),
+            None => create_error_simple(StatusCode::UNAUTHORIZED, "bad token"),
+        };
+    }
+
+    let secret_key = get_jwt_decoding_key().await?;
+    let claims = verify_jwt(token, &secret_key).await?;
+    let user_id = UserID::from_string(claims.sub.as_str())
+        .ok_or_else(|| create_error_simple_err(StatusCode::UNAUTHORIZED, "bad token"))?;
+    check_auth_expired(st.conn(), &user_id, &claims).await?;
+    Ok((user_id, None))
+}
+
+/// ルートを利用するために、トークンに必要なスコープ
+#[derive(Debug, Clone, Copy)]
+pub enum ScopeRequirement {
+    /// 読み取りのリクエストには read が、それ以外には write が必要
+    Default,
+    /// 読み取りのリクエストには "read:<対象>" が、それ以外には "write:<対象>" が必要
+    Resource(&'static str),
+    /// トークンでは利用できず、ログインが必要
+    SessionOnly,
+}
+
+/// `middleware_auth_jwt_required_scoped` に渡すスコープの種類
+pub trait RouteScope {
+    const REQUIREMENT: ScopeRequirement;
+}
+
+/// ノートの作成や削除、いいねなど
+pub struct NotesScope;
+impl RouteScope for NotesScope {
+    const REQUIREMENT: ScopeRequirement = ScopeRequirement::Resource("notes");
+}
+
+/// フォローやブロック、ミュート
+pub struct FollowsScope;
+impl RouteScope for FollowsScope {
+    const REQUIREMENT: ScopeRequirement = ScopeRequirement::Resource("follows");
+}
+
+/// 通知の取得や既読
+pub struct NotificationsScope;
+impl RouteScope for NotificationsScope {
+    const REQUIREMENT: ScopeRequirement = ScopeRequirement::Resource("notifications");
+}
+
+/// パスワードの変更やトークンの発行、管理者の操作、アカウントのエクスポートなど、
+/// トークンでは利用できない操作
+pub struct SessionOnlyScope;
+impl RouteScope for SessionOnlyScope {
+    const REQUIREMENT: ScopeRequirement = ScopeRequirement::SessionOnly;
+}
+
+/// トークンのスコープでリクエストが許可されているかを確認する。
+/// ログイン時に発行したトークンの場合は常に許可する。
+fn check_token_scope(
+    method: &Method,
+    scopes: Option<&OAuthScopes>,
+    requirement: ScopeRequirement,
+) -> ServiceResult<()> {
+    let Some(scopes) = scopes else {
+        return Ok(());
+    };
+    let action = if method.is_safe() { "read" } else { "write" };
+    let allowed = match requirement {
+        ScopeRequirement::Default => scopes.allows(action),
+        ScopeRequirement::Resource(resource) => scopes.allows(&format!("{action}:{resource}")),
+        ScopeRequirement::SessionOnly => false,
+    };
+    if allowed {
+        Ok(())
+    } else {
+        create_error_simple(StatusCode::FORBIDDEN, "insufficient scope")
+    }
+}
+
+async fn auth_jwt_required<B: MessageBody>(
     mut req: ServiceRequest,
-    next: Next<impl MessageBody>,
-) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    next: Next<B>,
+    requirement: ScopeRequirement,
+) -> Result<ServiceResponse<B>, actix_web::Error> {
     let headers = req.headers().clone();
     let session = req.extract::<Session>().await.unwrap();
     let st = req.extract::<web::Data<AppState>>().await.unwrap();
@@ -232,20 +715,35 @@ pub async fn middleware_auth_jwt_required(
             return Err(create_error_simple_err(StatusCode::UNAUTHORIZED, "no token").into());
         }
         Some(token) => {
-            let token = &token;
-            let secret_key = get_jwt_decoding_key().await?;
-            let claims = verify_jwt(token, &secret_key).await?;
-            let user_id = UserID::from_string(claims.sub.as_str())
-                .ok_or_else(|| create_error_simple_err(StatusCode::UNAUTHORIZED, "bad token"))?;
-            check_auth_expired(st.conn(), &user_id, &claims).await?;
+            let (user_id, scopes) = verify_token(&st, &token).await?;
+            check_token_scope(req.method(), scopes.as_ref(), requirement)?;
+            if is_user_suspended(&st.maybe_conn(), user_id).await? {
+                return Err(ServiceError::known(UserSuspendError::Suspended).into());
+            }
             req.extensions_mut().insert(AuthedUser {
                 user_id: Some(user_id),
+                scopes,
             });
             next.call(req).await
         }
     }
 }
 
+pub async fn middleware_auth_jwt_required(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    auth_jwt_required(req, next, ScopeRequirement::Default).await
+}
+
+/// middleware_auth_jwt_required と同じだが、トークンに S のスコープを要求する。
+pub async fn middleware_auth_jwt_required_scoped<S: RouteScope>(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    auth_jwt_required(req, next, S::REQUIREMENT).await
+}
+
 pub async fn middleware_auth_jwt_optional(
     mut req: ServiceRequest,
     next: Next<impl MessageBody>,
@@ -257,20 +755,22 @@ pub async fn middleware_auth_jwt_optional(
     let token = get_cookie_from_req(&headers, &session).await?;
     match token {
         None => {
-            req.extensions_mut().insert(AuthedUser { user_id: None });
+            req.extensions_mut().insert(AuthedUser {
+                user_id: None,
+                scopes: None,
+            });
             next.call(req).await
         }
         Some(token) => {
-            let token = &token;
-            let secret_key = get_jwt_decoding_key().await?;
-            let claims = verify_jwt(token, &secret_key).await?;
-
-            let user_id = UserID::from_string(claims.sub.as_str())
-                .ok_or_else(|| create_error_simple_err(StatusCode::UNAUTHORIZED, "bad token"))?;
-            check_auth_expired(st.conn(), &user_id, &claims).await?;
-            req.extensions_mut().insert(AuthedUser {
-                user_id: Some(user_id),
-            });
+            let (user_id, scopes) = verify_token(&st, &token).await?;
+            check_token_scope(req.method(), scopes.as_ref(), ScopeRequirement::Default)?;
+            // 凍結されたアカウントは未ログインとして扱う
+            let user_id = if is_user_suspended(&st.maybe_conn(), user_id).await? {
+                None
+            } else {
+                Some(user_id)
+            };
+            req.extensions_mut().insert(AuthedUser { user_id, scopes });
             next.call(req).await
         }
     }
@@ -279,6 +779,8 @@ pub async fn middleware_auth_jwt_optional(
 #[derive(Debug, Clone)]
 pub struct AuthedUser {
     user_id: Option<UserID>,
+    /// OAuth のアクセストークンやパーソナルアクセストークンで認証された場合のスコープ
+    scopes: Option<OAuthScopes>,
 }
 
 impl AuthedUser {
@@ -300,6 +802,12 @@ impl AuthedUser {
     pub fn user_id_unwrap(&self) -> UserID {
         self.user_id.clone().unwrap()
     }
+
+    /// トークンで認証された場合のスコープを取得する。
+    /// ログイン時に発行したトークンで認証された場合は None を返す。
+    pub fn scopes(&self) -> Option<&OAuthScopes> {
+        self.scopes.as_ref()
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
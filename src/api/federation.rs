@@ -30,14 +30,16 @@ use tracing::{debug, warn};
 use url::Url;
 
 use lightpub_service::services::apub::{
-    report_apub_error, AcceptActivity, AnnounceActivity, CreateActivity, DeleteActivity,
-    LikeActivity, RejectActivity, UndoActivity, UpdateActivity,
+    report_apub_error, AcceptActivity, AnnounceActivity, BlockActivity, CreateActivity,
+    DeleteActivity, FlagActivity, LikeActivity, MoveActivity, RejectActivity, UndoActivity,
+    UpdateActivity,
 };
 use lightpub_service::services::FederationServiceError;
 use lightpub_service::services::{
     apub::FollowActivity,
+    domain_policy::get_domain_policy_for_url,
     id::Identifier,
-    user::{get_user_by_spec, UserSpecifier, UserWithApubModel},
+    user::{get_user_by_spec, is_actor_url_suspended, UserSpecifier, UserWithApubModel},
     ServiceError, ServiceResult,
 };
 
@@ -101,6 +103,9 @@ pub enum SharedInboxActivity {
     Delete(DeleteActivity),
     Announce(AnnounceActivity),
     Update(UpdateActivity),
+    Block(BlockActivity),
+    Flag(FlagActivity),
+    Move(MoveActivity),
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -116,6 +121,9 @@ pub enum UserInboxActivity {
     Delete(DeleteActivity),
     Announce(AnnounceActivity),
     Update(UpdateActivity),
+    Block(BlockActivity),
+    Flag(FlagActivity),
+    Move(MoveActivity),
 }
 
 #[post("/inbox")]
@@ -135,6 +143,20 @@ pub(crate) async fn handle_inbox(
 ) -> ServiceResult<impl Responder> {
     let data = st.request_data();
     debug!("incoming inbox: {body:?}");
+
+    // 拒否されたドメインや凍結されたアクターからのアクティビティは検証前に破棄する
+    if let Some(actor) = activity_actor_url(&body) {
+        let policy = get_domain_policy_for_url(&st.maybe_conn(), &actor).await?;
+        if policy.rejects_activities() {
+            debug!("rejected activity from {actor}");
+            return Ok(HttpResponse::Forbidden().finish());
+        }
+        if is_actor_url_suspended(&st.maybe_conn(), &actor).await? {
+            debug!("rejected activity from suspended actor {actor}");
+            return Ok(HttpResponse::Forbidden().finish());
+        }
+    }
+
     let body_copy = body.clone();
     let result = if is_user {
         receive_activity::<UserInboxActivity, UserWithApubModel, _>(req, body, &data).await
@@ -174,6 +196,17 @@ pub(crate) async fn handle_inbox(
     }
 }
 
+/// 受信したアクティビティの actor の URL を取り出す。
+fn activity_actor_url(body: &[u8]) -> Option<Url> {
+    let value: serde_json::Value = serde_json::from_slice(body).ok()?;
+    let actor = match value.get("actor")? {
+        serde_json::Value::String(s) => s.as_str(),
+        serde_json::Value::Object(o) => o.get("id")?.as_str()?,
+        _ => return None,
+    };
+    Url::parse(actor).ok()
+}
+
 pub async fn apub_auth(
     req: &HttpRequest,
     body: web::Bytes,
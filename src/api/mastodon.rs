@@ -0,0 +1,494 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Mastodon 互換のクライアント API (/api/v1, /api/v2)
+//!
+//! 既存の Mastodon クライアントアプリから利用できるように、
+//! lightpub_service の機能を Mastodon のエンティティに変換して JSON で返す。
+//! 認証は `Authorization: Bearer` ヘッダーで行い、ログイン時の JWT のほか、
+//! OAuth のアクセストークンとパーソナルアクセストークンも受け付ける
+//! (トークンの検証は [`crate::api::auth`] の `verify_token` を参照)。
+//! OAuth のアクセストークンとパーソナルアクセストークンは、スコープで許可された API のみ利用できる。
+
+use std::{collections::HashMap, future::Future, pin::Pin};
+
+use actix_web::{
+    dev::Payload,
+    http::{header, StatusCode},
+    web, FromRequest, HttpRequest, HttpResponse,
+};
+use chrono::{DateTime, Utc};
+use lightpub_service::services::{
+    create_error_simple_err,
+    id::{Identifier, NoteID, UserID},
+    note::{get_note_by_id_visibility_check, DetailedNoteModel},
+    notification::{get_related_notification_data, Notification, NotificationBodyData},
+    user::{get_user_profile_by_id, UserDetailedProfile},
+    ServiceError, ServiceResult,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::AppState;
+
+use entities::{Account, MastodonNotification, Status};
+
+pub mod accounts;
+pub mod apps;
+pub mod entities;
+pub mod favourites;
+pub mod instance;
+pub mod media;
+pub mod notifications;
+pub mod search;
+pub mod statuses;
+pub mod timelines;
+
+/// 1 ページあたりの件数の既定値
+const DEFAULT_PAGE_LIMIT: u64 = 20;
+/// 1 ページあたりの件数の上限
+const MAX_PAGE_LIMIT: u64 = 40;
+
+fn qs_config() -> serde_qs::Config {
+    // `media_ids%5B%5D=...` のようにブラケットがエンコードされていても受け付ける
+    serde_qs::Config::new(5, false)
+}
+
+/// Mastodon API のパラメータ。
+/// クライアントによって送り方が異なるため、クエリ文字列、JSON、
+/// application/x-www-form-urlencoded のいずれでも受け付ける。
+#[derive(Debug)]
+pub struct MastodonParams<T>(pub T);
+
+impl<T> MastodonParams<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> std::ops::Deref for MastodonParams<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: DeserializeOwned + 'static> FromRequest for MastodonParams<T> {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let is_json = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|c| c.to_str().ok())
+            .is_some_and(|c| c.starts_with("application/json"));
+        let query = req.query_string().to_string();
+        let body = web::Bytes::from_request(req, payload);
+
+        Box::pin(async move {
+            let body = body.await?;
+            let result = if body.is_empty() {
+                qs_config()
+                    .deserialize_str(&query)
+                    .map_err(|e| e.to_string())
+            } else if is_json {
+                serde_json::from_slice(&body).map_err(|e| e.to_string())
+            } else {
+                qs_config()
+                    .deserialize_bytes(&body)
+                    .map_err(|e| e.to_string())
+            };
+
+            result
+                .map(MastodonParams)
+                .map_err(|e| create_error_simple_err(StatusCode::UNPROCESSABLE_ENTITY, e).into())
+        })
+    }
+}
+
+/// ページネーションのパラメータ
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PageParams {
+    /// この ID より古いものを返す
+    pub max_id: Option<String>,
+    /// この ID より新しいものを返す
+    pub since_id: Option<String>,
+    /// この ID より新しいものを、この ID に近い順に選んで返す
+    pub min_id: Option<String>,
+    pub limit: Option<u64>,
+}
+
+impl PageParams {
+    pub fn limit(&self) -> u64 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_LIMIT)
+            .clamp(1, MAX_PAGE_LIMIT)
+    }
+
+    /// since_id または min_id
+    pub fn newer_than(&self) -> Option<&str> {
+        self.min_id.as_deref().or(self.since_id.as_deref())
+    }
+}
+
+/// Link ヘッダーでページネーションするレスポンスを作成する。
+/// `next_max_id` と `prev_min_id` はそれぞれ次のページ (古い方) と前のページ (新しい方) の境界の ID。
+pub fn paginated_response<T: Serialize>(
+    st: &AppState,
+    req: &HttpRequest,
+    limit: u64,
+    items: &[T],
+    next_max_id: Option<String>,
+    prev_min_id: Option<String>,
+) -> HttpResponse {
+    let mut res = HttpResponse::Ok();
+
+    let mut links = vec![];
+    if let Some(max_id) = next_max_id {
+        links.push(page_link(st, req, limit, "max_id", &max_id, "next"));
+    }
+    if let Some(min_id) = prev_min_id {
+        links.push(page_link(st, req, limit, "min_id", &min_id, "prev"));
+    }
+    if !links.is_empty() {
+        res.insert_header((header::LINK, links.join(", ")));
+    }
+
+    res.json(items)
+}
+
+fn page_link(
+    st: &AppState,
+    req: &HttpRequest,
+    limit: u64,
+    key: &str,
+    id: &str,
+    rel: &str,
+) -> String {
+    let mut url = st.base_url().join(req.path()).unwrap();
+    {
+        let mut pairs = url.query_pairs_mut();
+        // ページネーション以外のパラメータはそのまま引き継ぐ
+        for (k, v) in url::form_urlencoded::parse(req.query_string().as_bytes()) {
+            if !matches!(k.as_ref(), "max_id" | "since_id" | "min_id" | "limit") {
+                pairs.append_pair(&k, &v);
+            }
+        }
+        pairs.append_pair("limit", &limit.to_string());
+        pairs.append_pair(key, id);
+    }
+    format!("<{}>; rel=\"{}\"", url, rel)
+}
+
+/// ページネーションの ID として指定されたノートの作成日時を取得する。
+/// ノートが見つからない場合は None を返す。
+pub async fn note_page_boundary(
+    st: &AppState,
+    viewer_id: Option<UserID>,
+    id: Option<&str>,
+) -> ServiceResult<Option<DateTime<Utc>>> {
+    let Some(note_id) = id.and_then(NoteID::from_string) else {
+        return Ok(None);
+    };
+    let note =
+        get_note_by_id_visibility_check(&st.maybe_conn(), &st.rconn(), note_id, viewer_id, true)
+            .await?;
+    Ok(note.map(|n| n.basic.created_at))
+}
+
+/// ノートのページを取得する。
+/// `fetch(limit, before_date, after_date)` は、after_date が None の場合は before_date 以前
+/// (境界を含む) のノートを新しい順に、Some の場合はさらに after_date より新しいノートを
+/// 古い順に返すこと。
+/// max_id のノート自体と、since_id より古いノートは取り除く。
+/// min_id が指定された場合は min_id の直後から古い順に取得し、新しい順に並べ直して返す。
+pub async fn fetch_note_page<F, Fut>(
+    st: &AppState,
+    viewer_id: Option<UserID>,
+    page: &PageParams,
+    fetch: F,
+) -> ServiceResult<Vec<DetailedNoteModel>>
+where
+    F: FnOnce(u64, Option<DateTime<Utc>>, Option<DateTime<Utc>>) -> Fut,
+    Fut: Future<Output = ServiceResult<Vec<DetailedNoteModel>>>,
+{
+    let limit = page.limit();
+
+    let before_date = match page.max_id.as_deref() {
+        None => None,
+        Some(max_id) => match note_page_boundary(st, viewer_id, Some(max_id)).await? {
+            Some(d) => Some(d),
+            None => return Ok(vec![]),
+        },
+    };
+    let not_max_id =
+        |n: &DetailedNoteModel| page.max_id.as_deref() != Some(n.basic.id.to_string().as_str());
+
+    if let Some(min_date) = note_page_boundary(st, viewer_id, page.min_id.as_deref()).await? {
+        // before_date は境界を含むので、max_id のノートの分だけ多めに取得する
+        let mut notes: Vec<_> = fetch(limit + 1, before_date, Some(min_date))
+            .await?
+            .into_iter()
+            .filter(not_max_id)
+            .take(limit as usize)
+            .collect();
+        notes.reverse();
+        return Ok(notes);
+    }
+
+    let after_date = note_page_boundary(st, viewer_id, page.since_id.as_deref()).await?;
+
+    // before_date は境界を含むので、max_id のノートの分だけ多めに取得する
+    let notes = fetch(limit + 1, before_date, None).await?;
+    let notes = notes
+        .into_iter()
+        .filter(not_max_id)
+        .filter(|n| after_date.is_none_or(|d| n.basic.created_at > d))
+        .take(limit as usize)
+        .collect();
+    Ok(notes)
+}
+
+/// ステータスのリストを Link ヘッダー付きで返す。
+pub fn statuses_response(
+    st: &AppState,
+    req: &HttpRequest,
+    limit: u64,
+    statuses: &[Status],
+) -> HttpResponse {
+    let next = if statuses.len() as u64 >= limit {
+        statuses.last().map(|s| s.id.clone())
+    } else {
+        None
+    };
+    let prev = statuses.first().map(|s| s.id.clone());
+    paginated_response(st, req, limit, statuses, next, prev)
+}
+
+/// サービス層のモデルを Mastodon のエンティティに変換する。
+/// 同じリクエストの中で同じユーザーを何度も取得しないように、アカウントをキャッシュする。
+pub struct Renderer<'a> {
+    st: &'a AppState,
+    viewer_id: Option<UserID>,
+    accounts: HashMap<UserID, Option<Account>>,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(st: &'a AppState, viewer_id: Option<UserID>) -> Self {
+        Self {
+            st,
+            viewer_id,
+            accounts: HashMap::new(),
+        }
+    }
+
+    pub fn profile(&mut self, profile: &UserDetailedProfile) -> Account {
+        let account = Account::from_profile(profile, self.st.base_url());
+        self.accounts
+            .insert(profile.basic.id, Some(account.clone()));
+        account
+    }
+
+    pub async fn account(&mut self, user_id: UserID) -> ServiceResult<Option<Account>> {
+        if let Some(account) = self.accounts.get(&user_id) {
+            return Ok(account.clone());
+        }
+
+        // 関係はアカウントのエンティティに含まれないので、閲覧者は指定しない
+        let profile =
+            get_user_profile_by_id(&self.st.maybe_conn(), &self.st.rconn(), None, user_id).await?;
+        let account = profile.map(|p| Account::from_profile(&p, self.st.base_url()));
+        self.accounts.insert(user_id, account.clone());
+        Ok(account)
+    }
+
+    async fn account_required(&mut self, user_id: UserID) -> ServiceResult<Account> {
+        self.account(user_id)
+            .await?
+            .ok_or_else(|| ServiceError::ise("account not found while rendering"))
+    }
+
+    pub async fn status(&mut self, note: &DetailedNoteModel) -> ServiceResult<Status> {
+        let account = self.account_required(note.basic.author.id).await?;
+
+        // 本文のないリノートは、リノート先を reblog に入れる
+        let reblog = match (note.basic.content.as_ref(), note.basic.renote_of_id) {
+            (None, Some(renote_of_id)) => {
+                match get_note_by_id_visibility_check(
+                    &self.st.maybe_conn(),
+                    &self.st.rconn(),
+                    renote_of_id,
+                    self.viewer_id,
+                    false,
+                )
+                .await?
+                {
+                    Some(target) => Some(Box::pin(self.status(&target)).await?),
+                    None => None,
+                }
+            }
+            _ => None,
+        };
+
+        let in_reply_to_account_id = match note.basic.reply_to_id {
+            None => None,
+            Some(reply_to_id) => get_note_by_id_visibility_check(
+                &self.st.maybe_conn(),
+                &self.st.rconn(),
+                reply_to_id,
+                None,
+                true,
+            )
+            .await?
+            .map(|n| n.basic.author.id.to_string()),
+        };
+
+        let content = match note.basic.content.as_ref() {
+            None => String::new(),
+            Some(content) => content
+                .render_to_html_with_emojis(
+                    self.st.qconn(),
+                    &note.details.emojis,
+                    self.st.base_url(),
+                )
+                .await?
+                .into_inner(),
+        };
+
+        Ok(Status::from_model(
+            note,
+            account,
+            content,
+            reblog.map(Box::new),
+            in_reply_to_account_id,
+            self.st.base_url(),
+        ))
+    }
+
+    pub async fn statuses(&mut self, notes: &[DetailedNoteModel]) -> ServiceResult<Vec<Status>> {
+        let mut statuses = Vec::with_capacity(notes.len());
+        for note in notes {
+            statuses.push(self.status(note).await?);
+        }
+        Ok(statuses)
+    }
+
+    async fn status_by_id(&mut self, note_id: NoteID) -> ServiceResult<Option<Status>> {
+        let note = get_note_by_id_visibility_check(
+            &self.st.maybe_conn(),
+            &self.st.rconn(),
+            note_id,
+            self.viewer_id,
+            false,
+        )
+        .await?;
+        match note {
+            None => Ok(None),
+            Some(note) => self.status(&note).await.map(Some),
+        }
+    }
+
+    /// 通知を変換する。Mastodon に対応する種類がない通知や、
+    /// 関連するユーザー・ノートが削除されている通知は None を返す。
+    pub async fn notification(
+        &mut self,
+        notification: &Notification,
+    ) -> ServiceResult<Option<MastodonNotification>> {
+        let data = get_related_notification_data(
+            &self.st.maybe_conn(),
+            &self.st.rconn(),
+            self.st.base_url(),
+            &notification.body,
+        )
+        .await?;
+
+        let (kind, user, note_id) = match data {
+            None => return Ok(None),
+            Some(NotificationBodyData::Followed(user)) => ("follow", user, None),
+            Some(NotificationBodyData::FollowRequested(user)) => ("follow_request", user, None),
+            // フォローが承認されたことを表す通知は Mastodon にはない
+            Some(NotificationBodyData::FollowAccepted(_)) => return Ok(None),
+            Some(NotificationBodyData::Replied {
+                author, reply_note, ..
+            }) => ("mention", author, Some(reply_note.id)),
+            Some(NotificationBodyData::Mentioned { user, note }) => {
+                ("mention", user, Some(note.id))
+            }
+            Some(NotificationBodyData::Renoted { user, renoted_note }) => {
+                ("reblog", user, Some(renoted_note.id))
+            }
+            Some(NotificationBodyData::Quoted {
+                user, quote_note, ..
+            }) => ("quote", user, Some(quote_note.id)),
+        };
+
+        let status = match note_id {
+            None => None,
+            Some(note_id) => match self.status_by_id(note_id).await? {
+                Some(status) => Some(status),
+                None => return Ok(None),
+            },
+        };
+
+        Ok(Some(MastodonNotification {
+            id: notification.id.to_string(),
+            kind,
+            created_at: notification.created_at,
+            account: self.profile(&user),
+            status,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{qs_config, PageParams};
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    struct StatusForm {
+        status: String,
+        #[serde(default)]
+        media_ids: Vec<String>,
+    }
+
+    #[test]
+    fn test_parse_array_params() {
+        let form: StatusForm = qs_config()
+            .deserialize_str("status=hello&media_ids%5B%5D=a&media_ids%5B%5D=b")
+            .unwrap();
+        assert_eq!(form.status, "hello");
+        assert_eq!(form.media_ids, vec!["a", "b"]);
+
+        let form: StatusForm = qs_config().deserialize_str("status=hello").unwrap();
+        assert!(form.media_ids.is_empty());
+    }
+
+    #[test]
+    fn test_page_limit() {
+        let page: PageParams = qs_config().deserialize_str("").unwrap();
+        assert_eq!(page.limit(), 20);
+
+        let page: PageParams = qs_config()
+            .deserialize_str("limit=100&since_id=a&min_id=b")
+            .unwrap();
+        assert_eq!(page.limit(), 40);
+        assert_eq!(page.newer_than(), Some("b"));
+    }
+}
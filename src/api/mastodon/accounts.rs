@@ -0,0 +1,487 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+/// Route handlers for /api/v1/accounts
+use actix_web::{get, http::StatusCode, middleware::from_fn, post, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use lightpub_service::services::{
+    create_error_simple,
+    follow::{follow_user, unfollow_user},
+    id::{Identifier, UserID},
+    note::{get_user_notes, get_user_notes_after},
+    user::{
+        block_user, get_user_by_spec, get_user_followers, get_user_followings,
+        get_user_profile_by_id, mute_user, unblock_user, unmute_user, UserDetailedProfile,
+        UserFollow, UserSpecifier,
+    },
+    ServiceResult,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    api::auth::{
+        middleware_auth_jwt_optional, middleware_auth_jwt_required,
+        middleware_auth_jwt_required_scoped, AuthedUser, FollowsScope,
+    },
+    AppState,
+};
+
+use super::{
+    entities::{Account, Relationship},
+    fetch_note_page, paginated_response, MastodonParams, PageParams, Renderer,
+};
+
+async fn get_profile(
+    st: &AppState,
+    user_id: UserID,
+    viewer_id: Option<UserID>,
+) -> ServiceResult<UserDetailedProfile> {
+    let profile = get_user_profile_by_id(&st.maybe_conn(), &st.rconn(), viewer_id, user_id).await?;
+    match profile {
+        Some(profile) => Ok(profile),
+        None => create_error_simple(StatusCode::NOT_FOUND, "Record not found"),
+    }
+}
+
+/// ログイン中のユーザー自身のアカウント。編集用の情報を含む。
+#[derive(Debug, Clone, Serialize)]
+pub struct CredentialAccount {
+    #[serde(flatten)]
+    account: Account,
+    source: serde_json::Value,
+}
+
+#[get(
+    "/accounts/verify_credentials",
+    wrap = "from_fn(middleware_auth_jwt_required)"
+)]
+pub async fn mastodon_verify_credentials(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<web::Json<CredentialAccount>> {
+    let profile = get_profile(&st, auth.user_id_unwrap(), None).await?;
+
+    Ok(web::Json(CredentialAccount {
+        account: Account::from_profile(&profile, st.base_url()),
+        source: json!({
+            "privacy": "public",
+            "sensitive": false,
+            "language": null,
+            "note": profile.basic.bio,
+            "fields": [],
+            "follow_requests_count": 0,
+        }),
+    }))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LookupParams {
+    acct: String,
+}
+
+#[get("/accounts/lookup", wrap = "from_fn(middleware_auth_jwt_optional)")]
+pub async fn mastodon_lookup_account(
+    st: web::Data<AppState>,
+    params: MastodonParams<LookupParams>,
+) -> ServiceResult<web::Json<Account>> {
+    let acct = params.acct.trim_start_matches('@');
+    let spec = match UserSpecifier::from_str(&format!("@{}", acct), &st.my_domain()) {
+        Some(spec) => spec,
+        None => return create_error_simple(StatusCode::NOT_FOUND, "Record not found"),
+    };
+    let user = get_user_by_spec(&st.maybe_conn(), &st.rconn(), &spec, &st.my_domain()).await?;
+    let user = match user {
+        Some(user) => user,
+        None => return create_error_simple(StatusCode::NOT_FOUND, "Record not found"),
+    };
+
+    let profile = get_profile(&st, user.id, None).await?;
+    Ok(web::Json(Account::from_profile(&profile, st.base_url())))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RelationshipsParams {
+    #[serde(default)]
+    id: Vec<String>,
+}
+
+#[get(
+    "/accounts/relationships",
+    wrap = "from_fn(middleware_auth_jwt_required)"
+)]
+pub async fn mastodon_get_relationships(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    params: MastodonParams<RelationshipsParams>,
+) -> ServiceResult<web::Json<Vec<Relationship>>> {
+    let viewer_id = auth.user_id_unwrap();
+
+    let mut relationships = vec![];
+    for id in &params.id {
+        let Some(user_id) = UserID::from_string(id) else {
+            continue;
+        };
+        let profile =
+            get_user_profile_by_id(&st.maybe_conn(), &st.rconn(), Some(viewer_id), user_id).await?;
+        if let Some(profile) = profile {
+            relationships.push(Relationship::from_profile(&profile));
+        }
+    }
+
+    Ok(web::Json(relationships))
+}
+
+#[get("/accounts/{user_id}", wrap = "from_fn(middleware_auth_jwt_optional)")]
+pub async fn mastodon_get_account(
+    st: web::Data<AppState>,
+    user_id: web::Path<UserID>,
+) -> ServiceResult<web::Json<Account>> {
+    let profile = get_profile(&st, user_id.into_inner(), None).await?;
+    Ok(web::Json(Account::from_profile(&profile, st.base_url())))
+}
+
+/// serde_qs では `#[serde(flatten)]` した数値を読めないので、ページネーションのパラメータも並べる
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AccountStatusesParams {
+    max_id: Option<String>,
+    since_id: Option<String>,
+    min_id: Option<String>,
+    limit: Option<u64>,
+    #[serde(default)]
+    exclude_replies: bool,
+    #[serde(default)]
+    exclude_reblogs: bool,
+    #[serde(default)]
+    only_media: bool,
+    #[serde(default)]
+    pinned: bool,
+}
+
+impl AccountStatusesParams {
+    fn page(&self) -> PageParams {
+        PageParams {
+            max_id: self.max_id.clone(),
+            since_id: self.since_id.clone(),
+            min_id: self.min_id.clone(),
+            limit: self.limit,
+        }
+    }
+}
+
+#[get(
+    "/accounts/{user_id}/statuses",
+    wrap = "from_fn(middleware_auth_jwt_optional)"
+)]
+pub async fn mastodon_get_account_statuses(
+    st: web::Data<AppState>,
+    req: HttpRequest,
+    auth: web::ReqData<AuthedUser>,
+    user_id: web::Path<UserID>,
+    params: MastodonParams<AccountStatusesParams>,
+) -> ServiceResult<HttpResponse> {
+    let viewer_id = auth.user_id();
+    let user_id = user_id.into_inner();
+    let page = params.page();
+    let limit = page.limit();
+
+    // ピン留めには対応していない
+    if params.pinned {
+        return Ok(paginated_response::<()>(&st, &req, limit, &[], None, None));
+    }
+
+    let notes = fetch_note_page(&st, viewer_id, &page, |limit, before_date, after_date| {
+        let conn = st.maybe_conn();
+        let rconn = st.rconn();
+        async move {
+            match after_date {
+                Some(after_date) => {
+                    get_user_notes_after(
+                        &conn,
+                        &rconn,
+                        viewer_id,
+                        user_id,
+                        limit,
+                        before_date,
+                        after_date,
+                    )
+                    .await
+                }
+                None => get_user_notes(&conn, &rconn, viewer_id, user_id, limit, before_date).await,
+            }
+        }
+    })
+    .await?;
+
+    // 絞り込みで件数が減っても続きを取得できるように、境界は絞り込む前のノートで決める
+    let next = if notes.len() as u64 >= limit {
+        notes.last().map(|n| n.basic.id.to_string())
+    } else {
+        None
+    };
+    let prev = notes.first().map(|n| n.basic.id.to_string());
+
+    let notes: Vec<_> = notes
+        .into_iter()
+        .filter(|n| !params.exclude_replies || n.basic.reply_to_id.is_none())
+        .filter(|n| !params.exclude_reblogs || n.basic.content.is_some())
+        .filter(|n| !params.only_media || !n.basic.uploads.is_empty())
+        .collect();
+    let statuses = Renderer::new(&st, viewer_id).statuses(&notes).await?;
+
+    Ok(paginated_response(&st, &req, limit, &statuses, next, prev))
+}
+
+/// フォロー一覧のページネーションの ID はフォローした日時 (ミリ秒)
+fn follow_page_boundary(id: Option<&str>) -> Option<DateTime<Utc>> {
+    id.and_then(|id| id.parse().ok())
+        .and_then(DateTime::<Utc>::from_timestamp_millis)
+}
+
+async fn follows_response(
+    st: &AppState,
+    req: &HttpRequest,
+    user_id: UserID,
+    viewer_id: Option<UserID>,
+    page: &PageParams,
+    followers: bool,
+) -> ServiceResult<HttpResponse> {
+    let limit = page.limit();
+
+    // フォロー一覧を隠しているユーザーは、本人以外には空の一覧を返す
+    let profile = get_profile(st, user_id, None).await?;
+    if profile.hide_follows && viewer_id != Some(user_id) {
+        return Ok(paginated_response::<()>(st, req, limit, &[], None, None));
+    }
+
+    // before_date は境界を含むので、1 ミリ秒前にずらす
+    let before_date = follow_page_boundary(page.max_id.as_deref())
+        .map(|d| d - chrono::TimeDelta::milliseconds(1));
+    let after_date = follow_page_boundary(page.newer_than());
+
+    let follows: Vec<UserFollow> = if followers {
+        get_user_followers(&st.maybe_conn(), &st.rconn(), &user_id, limit, before_date).await?
+    } else {
+        get_user_followings(&st.maybe_conn(), &st.rconn(), &user_id, limit, before_date).await?
+    };
+    let follows: Vec<_> = follows
+        .into_iter()
+        .filter(|f| after_date.is_none_or(|d| f.created_at > d))
+        .collect();
+
+    let next = if follows.len() as u64 >= limit {
+        follows
+            .last()
+            .map(|f| f.created_at.timestamp_millis().to_string())
+    } else {
+        None
+    };
+    let prev = follows
+        .first()
+        .map(|f| f.created_at.timestamp_millis().to_string());
+
+    let mut renderer = Renderer::new(st, viewer_id);
+    let mut accounts = Vec::with_capacity(follows.len());
+    for follow in &follows {
+        accounts.push(match renderer.account(follow.user.id).await? {
+            Some(account) => account,
+            None => Account::from_simple(&follow.user, st.base_url()),
+        });
+    }
+
+    Ok(paginated_response(st, req, limit, &accounts, next, prev))
+}
+
+#[get(
+    "/accounts/{user_id}/followers",
+    wrap = "from_fn(middleware_auth_jwt_optional)"
+)]
+pub async fn mastodon_get_account_followers(
+    st: web::Data<AppState>,
+    req: HttpRequest,
+    auth: web::ReqData<AuthedUser>,
+    user_id: web::Path<UserID>,
+    page: MastodonParams<PageParams>,
+) -> ServiceResult<HttpResponse> {
+    follows_response(&st, &req, user_id.into_inner(), auth.user_id(), &page, true).await
+}
+
+#[get(
+    "/accounts/{user_id}/following",
+    wrap = "from_fn(middleware_auth_jwt_optional)"
+)]
+pub async fn mastodon_get_account_following(
+    st: web::Data<AppState>,
+    req: HttpRequest,
+    auth: web::ReqData<AuthedUser>,
+    user_id: web::Path<UserID>,
+    page: MastodonParams<PageParams>,
+) -> ServiceResult<HttpResponse> {
+    follows_response(
+        &st,
+        &req,
+        user_id.into_inner(),
+        auth.user_id(),
+        &page,
+        false,
+    )
+    .await
+}
+
+async fn relationship_response(
+    st: &AppState,
+    viewer_id: UserID,
+    user_id: UserID,
+) -> ServiceResult<web::Json<Relationship>> {
+    let profile = get_profile(st, user_id, Some(viewer_id)).await?;
+    Ok(web::Json(Relationship::from_profile(&profile)))
+}
+
+#[post(
+    "/accounts/{user_id}/follow",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<FollowsScope>)"
+)]
+pub async fn mastodon_follow_account(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    user_id: web::Path<UserID>,
+) -> ServiceResult<web::Json<Relationship>> {
+    let my_id = auth.user_id_unwrap();
+    let user_id = user_id.into_inner();
+    follow_user(
+        st.conn(),
+        &st.rconn(),
+        st.qconn(),
+        st.wp(),
+        my_id,
+        user_id,
+        st.base_url(),
+    )
+    .await?;
+    relationship_response(&st, my_id, user_id).await
+}
+
+#[post(
+    "/accounts/{user_id}/unfollow",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<FollowsScope>)"
+)]
+pub async fn mastodon_unfollow_account(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    user_id: web::Path<UserID>,
+) -> ServiceResult<web::Json<Relationship>> {
+    let my_id = auth.user_id_unwrap();
+    let user_id = user_id.into_inner();
+    unfollow_user(
+        st.conn(),
+        &st.rconn(),
+        st.qconn(),
+        my_id,
+        user_id,
+        st.base_url(),
+    )
+    .await?;
+    relationship_response(&st, my_id, user_id).await
+}
+
+#[post(
+    "/accounts/{user_id}/block",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<FollowsScope>)"
+)]
+pub async fn mastodon_block_account(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    user_id: web::Path<UserID>,
+) -> ServiceResult<web::Json<Relationship>> {
+    let my_id = auth.user_id_unwrap();
+    let user_id = user_id.into_inner();
+    block_user(
+        st.conn(),
+        &st.rconn(),
+        st.qconn(),
+        my_id,
+        user_id,
+        st.base_url(),
+    )
+    .await?;
+    relationship_response(&st, my_id, user_id).await
+}
+
+#[post(
+    "/accounts/{user_id}/unblock",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<FollowsScope>)"
+)]
+pub async fn mastodon_unblock_account(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    user_id: web::Path<UserID>,
+) -> ServiceResult<web::Json<Relationship>> {
+    let my_id = auth.user_id_unwrap();
+    let user_id = user_id.into_inner();
+    unblock_user(
+        st.conn(),
+        &st.rconn(),
+        st.qconn(),
+        my_id,
+        user_id,
+        st.base_url(),
+    )
+    .await?;
+    relationship_response(&st, my_id, user_id).await
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MuteParams {
+    /// ミュートする秒数。0 の場合は無期限。
+    #[serde(default)]
+    duration: i64,
+}
+
+#[post(
+    "/accounts/{user_id}/mute",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<FollowsScope>)"
+)]
+pub async fn mastodon_mute_account(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    user_id: web::Path<UserID>,
+    params: MastodonParams<MuteParams>,
+) -> ServiceResult<web::Json<Relationship>> {
+    let my_id = auth.user_id_unwrap();
+    let user_id = user_id.into_inner();
+    let expires_at = Some(params.duration)
+        .filter(|d| *d > 0)
+        .map(|d| Utc::now() + chrono::TimeDelta::seconds(d));
+    mute_user(st.conn(), &st.rconn(), my_id, user_id, expires_at).await?;
+    relationship_response(&st, my_id, user_id).await
+}
+
+#[post(
+    "/accounts/{user_id}/unmute",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<FollowsScope>)"
+)]
+pub async fn mastodon_unmute_account(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    user_id: web::Path<UserID>,
+) -> ServiceResult<web::Json<Relationship>> {
+    let my_id = auth.user_id_unwrap();
+    let user_id = user_id.into_inner();
+    unmute_user(st.conn(), my_id, user_id).await?;
+    relationship_response(&st, my_id, user_id).await
+}
@@ -0,0 +1,59 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+/// Route handlers for /api/v1/apps
+use actix_web::{post, web};
+use lightpub_service::services::{
+    oauth::{create_oauth_app, OAuthScopes},
+    ServiceResult,
+};
+use serde::Deserialize;
+
+use crate::AppState;
+
+use super::{entities::Application, MastodonParams};
+
+#[derive(Debug, Deserialize)]
+pub struct CreateAppParams {
+    client_name: String,
+    /// スペースまたは改行区切り
+    redirect_uris: String,
+    /// スペース区切り
+    scopes: Option<String>,
+    website: Option<String>,
+}
+
+#[post("/apps")]
+pub async fn mastodon_create_app(
+    st: web::Data<AppState>,
+    params: MastodonParams<CreateAppParams>,
+) -> ServiceResult<web::Json<Application>> {
+    let redirect_uris = params.redirect_uris.split_whitespace().collect::<Vec<_>>();
+    let scopes = OAuthScopes::parse(params.scopes.as_deref().unwrap_or_default())?;
+
+    let app = create_oauth_app(
+        st.conn(),
+        &params.client_name,
+        &redirect_uris,
+        &scopes,
+        params.website.as_deref(),
+    )
+    .await?;
+
+    Ok(web::Json(Application::from_model(&app)))
+}
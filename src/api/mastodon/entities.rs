@@ -0,0 +1,535 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Mastodon API のエンティティ
+//!
+//! <https://docs.joinmastodon.org/entities/>
+
+use chrono::{DateTime, Utc};
+use lightpub_service::{
+    services::{
+        emoji::EmojiModel,
+        follow::FollowState,
+        id::{NoteID, UploadID},
+        note::{DetailedNoteModel, NotePollModel, NoteUploadModelData, VisibilityModel},
+        oauth::OAuthAppModel,
+        upload::{UploadKind, UploadMetadata},
+        user::{SimpleUserModel, UserDetailedProfile},
+    },
+    utils::sanitize::CleanString,
+};
+use serde::Serialize;
+use serde_json::json;
+use url::Url;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Account {
+    pub id: String,
+    pub username: String,
+    pub acct: String,
+    pub display_name: String,
+    pub locked: bool,
+    pub bot: bool,
+    pub discoverable: bool,
+    pub group: bool,
+    pub created_at: DateTime<Utc>,
+    pub note: String,
+    pub url: String,
+    pub uri: String,
+    pub avatar: String,
+    pub avatar_static: String,
+    /// ヘッダー画像には対応していないので常に空文字列
+    pub header: String,
+    pub header_static: String,
+    pub followers_count: u64,
+    pub following_count: u64,
+    pub statuses_count: u64,
+    pub last_status_at: Option<String>,
+    pub emojis: Vec<CustomEmoji>,
+    pub fields: Vec<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub moved: Option<Box<Account>>,
+}
+
+impl Account {
+    /// 統計情報を含まないアカウントを作成する。
+    pub fn from_simple(user: &SimpleUserModel, base_url: &Url) -> Self {
+        let id = user.id.to_string();
+        let acct = match &user.domain {
+            None => user.username.clone(),
+            Some(domain) => format!("{}@{}", user.username, domain),
+        };
+        let avatar = base_url
+            .join(&format!("/user/{}/avatar", id))
+            .unwrap()
+            .to_string();
+        let local_url = base_url.join(&format!("/user/{}", id)).unwrap().to_string();
+
+        Self {
+            username: user.username.clone(),
+            acct,
+            display_name: user.nickname.clone(),
+            locked: false,
+            bot: false,
+            discoverable: true,
+            group: false,
+            created_at: DateTime::UNIX_EPOCH,
+            note: CleanString::clean(&user.bio).into_inner(),
+            url: base_url
+                .join(&format!("/client/user/{}", id))
+                .unwrap()
+                .to_string(),
+            uri: local_url,
+            avatar_static: avatar.clone(),
+            avatar,
+            header: String::new(),
+            header_static: String::new(),
+            followers_count: 0,
+            following_count: 0,
+            statuses_count: 0,
+            last_status_at: None,
+            emojis: vec![],
+            fields: vec![],
+            moved: None,
+            id,
+        }
+    }
+
+    pub fn from_profile(profile: &UserDetailedProfile, base_url: &Url) -> Self {
+        let mut account = Self::from_simple(&profile.basic, base_url);
+        account.locked = !profile.auto_follow_accept;
+        account.bot = profile.is_bot;
+        if let Some(created_at) = profile.created_at {
+            account.created_at = created_at;
+        }
+        if let Some(url) = &profile.url {
+            account.uri = url.clone();
+        }
+        if let Some(url) = profile.view_url.as_ref().or(profile.url.as_ref()) {
+            account.url = url.clone();
+        }
+        account.followers_count = profile.follower_count;
+        account.following_count = profile.follow_count;
+        account.statuses_count = profile.note_count;
+        account.moved = profile
+            .moved_to
+            .as_ref()
+            .map(|m| Box::new(Self::from_simple(m, base_url)));
+        account
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Relationship {
+    pub id: String,
+    pub following: bool,
+    pub showing_reblogs: bool,
+    pub notifying: bool,
+    pub followed_by: bool,
+    pub blocking: bool,
+    pub blocked_by: bool,
+    pub muting: bool,
+    pub muting_notifications: bool,
+    pub requested: bool,
+    pub requested_by: bool,
+    pub domain_blocking: bool,
+    pub endorsed: bool,
+    pub note: String,
+}
+
+impl Relationship {
+    /// 閲覧者を指定して取得したプロフィールから関係を作成する。
+    pub fn from_profile(profile: &UserDetailedProfile) -> Self {
+        let following = profile.is_following == Some(FollowState::Yes);
+        let muting = profile.is_muting.unwrap_or(false);
+        Self {
+            id: profile.basic.id.to_string(),
+            following,
+            showing_reblogs: following,
+            notifying: false,
+            followed_by: profile.is_followed == Some(FollowState::Yes),
+            blocking: profile.is_blocking.unwrap_or(false),
+            blocked_by: profile.is_blocked.unwrap_or(false),
+            muting,
+            muting_notifications: muting,
+            requested: profile.is_following == Some(FollowState::Pending),
+            requested_by: profile.is_followed == Some(FollowState::Pending),
+            domain_blocking: false,
+            endorsed: false,
+            note: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CustomEmoji {
+    pub shortcode: String,
+    pub url: String,
+    pub static_url: String,
+    pub visible_in_picker: bool,
+}
+
+impl CustomEmoji {
+    pub fn from_model(emoji: &EmojiModel, base_url: &Url) -> Self {
+        let url = emoji.image_url(base_url).to_string();
+        Self {
+            shortcode: emoji.shortcode.clone(),
+            static_url: url.clone(),
+            url,
+            visible_in_picker: emoji.domain.is_none(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MediaAttachment {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub url: String,
+    pub preview_url: String,
+    pub remote_url: Option<String>,
+    pub meta: serde_json::Value,
+    pub description: Option<String>,
+    pub blurhash: Option<String>,
+}
+
+impl MediaAttachment {
+    pub fn new(
+        upload_id: UploadID,
+        mime_type: &str,
+        metadata: &UploadMetadata,
+        remote_url: Option<&Url>,
+        base_url: &Url,
+    ) -> Self {
+        let url = base_url.join(&format!("/upload/{}", upload_id)).unwrap();
+        let kind = UploadKind::from_mime_type(mime_type);
+        let preview_url = match (kind, metadata.thumbnail_id) {
+            (Some(UploadKind::Video), Some(thumbnail_id)) => base_url
+                .join(&format!("/upload/{}", thumbnail_id))
+                .unwrap()
+                .to_string(),
+            _ if metadata.has_variants => format!("{}?variant=preview", url),
+            _ => url.to_string(),
+        };
+
+        let mut meta = json!({});
+        if let (Some(width), Some(height)) = (metadata.width, metadata.height) {
+            let original = json!({
+                "width": width,
+                "height": height,
+                "size": format!("{}x{}", width, height),
+                "aspect": width as f64 / height.max(1) as f64,
+            });
+            meta["original"] = original.clone();
+            meta["small"] = original;
+        }
+        if let Some((x, y)) = metadata.focal_point {
+            meta["focus"] = json!({ "x": x, "y": y });
+        }
+        if let Some(duration) = metadata.duration {
+            meta["duration"] = json!(duration);
+        }
+
+        Self {
+            id: upload_id.to_string(),
+            kind: match kind {
+                Some(UploadKind::Image) => "image",
+                Some(UploadKind::Video) => "video",
+                Some(UploadKind::Audio) => "audio",
+                _ => "unknown",
+            },
+            url: url.to_string(),
+            preview_url,
+            remote_url: remote_url.map(|u| u.to_string()),
+            meta,
+            description: metadata.description.clone(),
+            blurhash: metadata.blurhash.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Mention {
+    pub id: String,
+    pub username: String,
+    pub url: String,
+    pub acct: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Tag {
+    pub name: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PollOption {
+    pub title: String,
+    pub votes_count: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Poll {
+    pub id: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub expired: bool,
+    pub multiple: bool,
+    pub votes_count: u64,
+    pub voters_count: Option<u64>,
+    pub options: Vec<PollOption>,
+    pub emojis: Vec<CustomEmoji>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub voted: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub own_votes: Option<Vec<usize>>,
+}
+
+impl Poll {
+    /// 投票の ID はノートの ID と同じ
+    pub fn from_model(note_id: NoteID, poll: &NotePollModel) -> Self {
+        Self {
+            id: note_id.to_string(),
+            expires_at: poll.expires_at,
+            expired: poll.closed,
+            multiple: poll.multiple,
+            votes_count: poll.total_votes(),
+            voters_count: Some(poll.voters_count),
+            options: poll
+                .options
+                .iter()
+                .map(|o| PollOption {
+                    title: o.title.clone(),
+                    votes_count: Some(o.votes_count),
+                })
+                .collect(),
+            emojis: vec![],
+            voted: poll.voted.as_ref().map(|v| !v.is_empty()),
+            own_votes: poll.voted.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Status {
+    pub id: String,
+    pub uri: String,
+    pub url: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub edited_at: Option<DateTime<Utc>>,
+    pub account: Account,
+    pub content: String,
+    pub visibility: &'static str,
+    pub sensitive: bool,
+    pub spoiler_text: String,
+    pub media_attachments: Vec<MediaAttachment>,
+    pub mentions: Vec<Mention>,
+    pub tags: Vec<Tag>,
+    pub emojis: Vec<CustomEmoji>,
+    pub reblogs_count: u64,
+    pub favourites_count: u64,
+    pub replies_count: u64,
+    pub in_reply_to_id: Option<String>,
+    pub in_reply_to_account_id: Option<String>,
+    pub reblog: Option<Box<Status>>,
+    pub poll: Option<Poll>,
+    pub card: Option<serde_json::Value>,
+    pub language: Option<String>,
+    /// 削除時に返す、投稿時のテキスト
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub favourited: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reblogged: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bookmarked: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub muted: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pinned: Option<bool>,
+}
+
+pub fn visibility_to_str(visibility: VisibilityModel) -> &'static str {
+    match visibility {
+        VisibilityModel::Public => "public",
+        VisibilityModel::Unlisted => "unlisted",
+        VisibilityModel::Follower => "private",
+        VisibilityModel::Private => "direct",
+    }
+}
+
+pub fn visibility_from_str(visibility: &str) -> Option<VisibilityModel> {
+    match visibility {
+        "public" => Some(VisibilityModel::Public),
+        "unlisted" => Some(VisibilityModel::Unlisted),
+        "private" => Some(VisibilityModel::Follower),
+        "direct" => Some(VisibilityModel::Private),
+        _ => None,
+    }
+}
+
+impl Status {
+    /// `content` は HTML に変換済みの本文。
+    pub fn from_model(
+        dnote: &DetailedNoteModel,
+        account: Account,
+        content: String,
+        reblog: Option<Box<Status>>,
+        in_reply_to_account_id: Option<String>,
+        base_url: &Url,
+    ) -> Self {
+        let note = &dnote.basic;
+        let details = &dnote.details;
+        let id = note.id.to_string();
+
+        let uri = match &note.url {
+            Some(url) => url.to_string(),
+            None => base_url.join(&format!("/note/{}", id)).unwrap().to_string(),
+        };
+        let url = match note.view_url.as_ref().or(note.url.as_ref()) {
+            Some(url) => url.to_string(),
+            None => base_url
+                .join(&format!("/client/note/{}", id))
+                .unwrap()
+                .to_string(),
+        };
+
+        let media_attachments = note
+            .uploads
+            .iter()
+            .map(|u| {
+                let remote_url = match u.data() {
+                    NoteUploadModelData::File(_) => None,
+                    NoteUploadModelData::Remote(_, url) => Some(url),
+                };
+                MediaAttachment::new(
+                    u.data().upload_id(),
+                    u.mime_type(),
+                    u.metadata(),
+                    remote_url,
+                    base_url,
+                )
+            })
+            .collect();
+
+        let mentions = details
+            .mentions
+            .iter()
+            .map(|m| Mention {
+                id: m.id.to_string(),
+                username: m.username.clone(),
+                url: base_url
+                    .join(&format!("/client/user/{}", m.id))
+                    .unwrap()
+                    .to_string(),
+                acct: match &m.domain {
+                    None => m.username.clone(),
+                    Some(domain) => format!("{}@{}", m.username, domain),
+                },
+            })
+            .collect();
+
+        let tags = details
+            .hashtags
+            .iter()
+            .map(|h| {
+                let mut url = base_url.join("/client/search").unwrap();
+                url.query_pairs_mut().append_pair("q", &format!("#{}", h));
+                Tag {
+                    name: h.clone(),
+                    url: url.to_string(),
+                }
+            })
+            .collect();
+
+        Self {
+            uri,
+            url: Some(url),
+            created_at: note.created_at,
+            edited_at: note.updated_at,
+            account,
+            content,
+            visibility: visibility_to_str(note.visibility),
+            sensitive: note.sensitive,
+            spoiler_text: note.summary.clone().unwrap_or_default(),
+            media_attachments,
+            mentions,
+            tags,
+            emojis: details
+                .emojis
+                .iter()
+                .map(|e| CustomEmoji::from_model(e, base_url))
+                .collect(),
+            reblogs_count: details.renote_count,
+            favourites_count: details.like_count,
+            replies_count: details.reply_count,
+            in_reply_to_id: note.reply_to_id.map(|r| r.to_string()),
+            in_reply_to_account_id,
+            reblog,
+            poll: details.poll.as_ref().map(|p| Poll::from_model(note.id, p)),
+            card: None,
+            language: None,
+            text: None,
+            favourited: details.liked,
+            reblogged: details.renoted,
+            bookmarked: details.bookmarked,
+            muted: details.liked.map(|_| false),
+            pinned: details.liked.map(|_| false),
+            id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MastodonNotification {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub created_at: DateTime<Utc>,
+    pub account: Account,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<Status>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Application {
+    pub id: String,
+    pub name: String,
+    pub website: Option<String>,
+    pub scopes: Vec<String>,
+    /// 古いクライアントのための改行区切りの redirect_uris
+    pub redirect_uri: String,
+    pub redirect_uris: Vec<String>,
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+impl Application {
+    pub fn from_model(app: &OAuthAppModel) -> Self {
+        Self {
+            id: app.id.to_string(),
+            name: app.name.clone(),
+            website: app.website.clone(),
+            scopes: app.scopes.iter().map(|s| s.to_string()).collect(),
+            redirect_uri: app.redirect_uris.join("\n"),
+            redirect_uris: app.redirect_uris.clone(),
+            client_id: app.client_id.clone(),
+            client_secret: app.client_secret.clone(),
+        }
+    }
+}
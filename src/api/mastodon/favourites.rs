@@ -0,0 +1,90 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+/// Route handlers for /api/v1/favourites and /api/v1/bookmarks
+use actix_web::{get, middleware::from_fn, web, HttpRequest, HttpResponse};
+use lightpub_service::services::{id::UserID, note::get_liked_notes, ServiceResult};
+
+use crate::{
+    api::auth::{middleware_auth_jwt_required, AuthedUser},
+    AppState,
+};
+
+use super::{paginated_response, MastodonParams, PageParams, Renderer};
+
+/// ページネーションの ID はいいね・ブックマークの ID
+async fn liked_notes_response(
+    st: &AppState,
+    req: &HttpRequest,
+    user_id: UserID,
+    is_private: bool,
+    page: &PageParams,
+) -> ServiceResult<HttpResponse> {
+    let limit = page.limit();
+    let before_id = page.max_id.as_deref().and_then(|id| id.parse().ok());
+    let after_id: Option<i32> = page.newer_than().and_then(|id| id.parse().ok());
+
+    let liked = get_liked_notes(
+        &st.maybe_conn(),
+        &st.rconn(),
+        user_id,
+        is_private,
+        limit,
+        before_id,
+    )
+    .await?;
+    let liked: Vec<_> = liked
+        .into_iter()
+        .filter(|l| after_id.is_none_or(|id| l.like_id > id))
+        .collect();
+
+    let next = if liked.len() as u64 >= limit {
+        liked.last().map(|l| l.like_id.to_string())
+    } else {
+        None
+    };
+    let prev = liked.first().map(|l| l.like_id.to_string());
+
+    let mut renderer = Renderer::new(st, Some(user_id));
+    let mut statuses = Vec::with_capacity(liked.len());
+    for l in &liked {
+        statuses.push(renderer.status(&l.note).await?);
+    }
+
+    Ok(paginated_response(st, req, limit, &statuses, next, prev))
+}
+
+#[get("/favourites", wrap = "from_fn(middleware_auth_jwt_required)")]
+pub async fn mastodon_get_favourites(
+    st: web::Data<AppState>,
+    req: HttpRequest,
+    auth: web::ReqData<AuthedUser>,
+    page: MastodonParams<PageParams>,
+) -> ServiceResult<HttpResponse> {
+    liked_notes_response(&st, &req, auth.user_id_unwrap(), false, &page).await
+}
+
+#[get("/bookmarks", wrap = "from_fn(middleware_auth_jwt_required)")]
+pub async fn mastodon_get_bookmarks(
+    st: web::Data<AppState>,
+    req: HttpRequest,
+    auth: web::ReqData<AuthedUser>,
+    page: MastodonParams<PageParams>,
+) -> ServiceResult<HttpResponse> {
+    liked_notes_response(&st, &req, auth.user_id_unwrap(), true, &page).await
+}
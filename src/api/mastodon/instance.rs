@@ -0,0 +1,147 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+/// Route handlers for /api/v1/instance, /api/v2/instance and /api/v1/custom_emojis
+use actix_web::{get, web, HttpResponse, Responder};
+use lightpub_service::services::{
+    emoji::list_local_emojis, note::count_local_notes, upload::UploadKind,
+    user::get_total_users_count, ServiceResult,
+};
+use serde_json::json;
+
+use crate::AppState;
+
+use super::entities::CustomEmoji;
+
+/// クライアントが対応している機能を判定できるように、互換性のある Mastodon のバージョンを含める
+fn version() -> String {
+    format!("4.2.0 (compatible; Lightpub {})", env!("CARGO_PKG_VERSION"))
+}
+
+/// 本文の文字数や添付ファイルの数に上限はないが、クライアントが入力欄の制限に使うので値を返す
+const MAX_CHARACTERS: usize = 10000;
+const MAX_MEDIA_ATTACHMENTS: usize = 16;
+
+fn configuration() -> serde_json::Value {
+    json!({
+        "statuses": {
+            "max_characters": MAX_CHARACTERS,
+            "max_media_attachments": MAX_MEDIA_ATTACHMENTS,
+            "characters_reserved_per_url": 23,
+        },
+        "media_attachments": {
+            "supported_mime_types": [
+                "image/jpeg", "image/png", "image/gif", "image/webp", "image/avif",
+                "video/mp4", "video/webm",
+                "audio/mpeg", "audio/ogg", "audio/opus",
+            ],
+            "image_size_limit": UploadKind::Image.max_size(),
+            "image_matrix_limit": 16777216,
+            "video_size_limit": UploadKind::Video.max_size(),
+            "video_frame_rate_limit": 60,
+            "video_matrix_limit": 2304000,
+        },
+        "polls": {
+            "max_options": 10,
+            "max_characters_per_option": 100,
+            "min_expiration": 300,
+            "max_expiration": 30 * 24 * 60 * 60,
+        },
+    })
+}
+
+#[get("/instance")]
+pub async fn mastodon_get_instance_v1(st: web::Data<AppState>) -> ServiceResult<impl Responder> {
+    let user_count = get_total_users_count(&st.maybe_conn()).await?;
+    let status_count = count_local_notes(&st.maybe_conn()).await?;
+
+    let data = json!({
+        "uri": st.my_domain(),
+        "title": st.nodeinfo().name(),
+        "short_description": st.nodeinfo().description(),
+        "description": st.nodeinfo().description(),
+        "email": "",
+        "version": version(),
+        "urls": {},
+        "stats": {
+            "user_count": user_count,
+            "status_count": status_count,
+            "domain_count": 0,
+        },
+        "thumbnail": null,
+        "languages": [],
+        "registrations": st.is_registration_open(),
+        "approval_required": false,
+        "invites_enabled": false,
+        "configuration": configuration(),
+        "contact_account": null,
+        "rules": [],
+    });
+
+    Ok(HttpResponse::Ok().json(data))
+}
+
+#[get("/instance")]
+pub async fn mastodon_get_instance_v2(st: web::Data<AppState>) -> ServiceResult<impl Responder> {
+    let mut configuration = configuration();
+    configuration["urls"] = json!({});
+    configuration["accounts"] = json!({ "max_featured_tags": 0 });
+    configuration["translation"] = json!({ "enabled": false });
+
+    let data = json!({
+        "domain": st.my_domain(),
+        "title": st.nodeinfo().name(),
+        "version": version(),
+        "description": st.nodeinfo().description(),
+        "usage": {
+            "users": {
+                "active_month": 0,
+            },
+        },
+        "thumbnail": {
+            "url": st.base_url().join("/static/icons/512x512.png").unwrap(),
+        },
+        "languages": [],
+        "configuration": configuration,
+        "registrations": {
+            "enabled": st.is_registration_open(),
+            "approval_required": false,
+            "message": null,
+        },
+        "contact": {
+            "email": "",
+            "account": null,
+        },
+        "rules": [],
+    });
+
+    Ok(HttpResponse::Ok().json(data))
+}
+
+#[get("/custom_emojis")]
+pub async fn mastodon_get_custom_emojis(
+    st: web::Data<AppState>,
+) -> ServiceResult<web::Json<Vec<CustomEmoji>>> {
+    let emojis = list_local_emojis(&st.maybe_conn()).await?;
+    Ok(web::Json(
+        emojis
+            .iter()
+            .map(|e| CustomEmoji::from_model(e, st.base_url()))
+            .collect(),
+    ))
+}
@@ -0,0 +1,213 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+/// Route handlers for /api/v1/media and /api/v2/media
+///
+/// Mastodon ではファイルを先にアップロードし、投稿時に ID で指定する。
+/// アップロードされたファイルは投稿されるまでの間、添付前のアップロードとして
+/// データベースに登録しておく。
+use std::str::FromStr;
+
+use actix_multipart::form::{tempfile::TempFile, text::Text as MpText, MultipartForm};
+use actix_web::{get, http::StatusCode, middleware::from_fn, post, put, web};
+use lightpub_service::services::{
+    create_error_simple,
+    id::{UploadID, UserID},
+    note::NoteUpload,
+    pending_upload::{
+        get_pending_upload, register_pending_upload, update_pending_upload_metadata,
+        PendingUploadModel,
+    },
+    upload::{save_upload_file, validate_upload_description, UploadKind},
+    ServiceResult,
+};
+use serde::Deserialize;
+
+use crate::{
+    api::auth::{middleware_auth_jwt_required_scoped, AuthedUser, NotesScope},
+    AppState,
+};
+
+use super::{entities::MediaAttachment, MastodonParams};
+
+fn to_attachment(media: &PendingUploadModel, st: &AppState) -> MediaAttachment {
+    MediaAttachment::new(
+        media.upload_id,
+        &media.mime_type,
+        &media.metadata,
+        None,
+        st.base_url(),
+    )
+}
+
+async fn get_pending_media(
+    st: &AppState,
+    user_id: UserID,
+    id: &str,
+) -> ServiceResult<PendingUploadModel> {
+    let upload_id = match UploadID::from_str(id) {
+        Ok(upload_id) => upload_id,
+        Err(_) => return create_error_simple(StatusCode::NOT_FOUND, "Record not found"),
+    };
+    match get_pending_upload(&st.maybe_conn(), user_id, &upload_id).await? {
+        Some(media) => Ok(media),
+        None => create_error_simple(StatusCode::NOT_FOUND, "Record not found"),
+    }
+}
+
+/// 投稿に添付するファイルを取得する。
+pub async fn get_pending_uploads(
+    st: &AppState,
+    user_id: UserID,
+    media_ids: &[String],
+) -> ServiceResult<Vec<NoteUpload>> {
+    let mut uploads = Vec::with_capacity(media_ids.len());
+    for id in media_ids {
+        let media = match get_pending_media(st, user_id, id).await {
+            Ok(media) => media,
+            Err(_) => {
+                return create_error_simple(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "media not found or already attached",
+                )
+            }
+        };
+        uploads.push(NoteUpload::Pending(media.upload_id));
+    }
+    Ok(uploads)
+}
+
+fn parse_focus(focus: &str) -> Option<(f32, f32)> {
+    let (x, y) = focus.split_once(',')?;
+    Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
+
+#[derive(Debug, MultipartForm)]
+pub struct UploadMediaForm {
+    #[multipart(limit = "100MB")]
+    file: TempFile,
+    description: Option<MpText<String>>,
+    /// フォーカルポイント。"x,y" の形式。
+    focus: Option<MpText<String>>,
+}
+
+async fn upload_media(
+    st: &AppState,
+    user_id: UserID,
+    form: UploadMediaForm,
+) -> ServiceResult<web::Json<MediaAttachment>> {
+    let description = form
+        .description
+        .map(|d| d.0.trim().to_string())
+        .filter(|d| !d.is_empty());
+    let focal_point = form.focus.as_ref().and_then(|f| parse_focus(&f.0));
+    validate_upload_description(description.as_deref(), focal_point)?;
+
+    let (upload_id, filename, mime_type, mut metadata) = save_upload_file(
+        st.storage(),
+        form.file.file,
+        &[UploadKind::Image, UploadKind::Video, UploadKind::Audio],
+    )
+    .await?;
+    metadata.description = description;
+    metadata.focal_point = focal_point;
+
+    register_pending_upload(
+        st.conn(),
+        user_id,
+        &upload_id,
+        &filename,
+        mime_type.mime_type(),
+        &metadata,
+    )
+    .await?;
+
+    let media = PendingUploadModel {
+        upload_id,
+        mime_type: mime_type.to_string(),
+        metadata,
+    };
+    Ok(web::Json(to_attachment(&media, st)))
+}
+
+#[post(
+    "/media",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
+pub async fn mastodon_upload_media(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    MultipartForm(form): MultipartForm<UploadMediaForm>,
+) -> ServiceResult<web::Json<MediaAttachment>> {
+    upload_media(&st, auth.user_id_unwrap(), form).await
+}
+
+/// v2 ではサムネイルなどを非同期に処理するが、Lightpub ではアップロード時に処理が完了する
+#[post(
+    "/media",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
+pub async fn mastodon_upload_media_v2(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    MultipartForm(form): MultipartForm<UploadMediaForm>,
+) -> ServiceResult<web::Json<MediaAttachment>> {
+    upload_media(&st, auth.user_id_unwrap(), form).await
+}
+
+#[get(
+    "/media/{upload_id}",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
+pub async fn mastodon_get_media(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    upload_id: web::Path<String>,
+) -> ServiceResult<web::Json<MediaAttachment>> {
+    let media = get_pending_media(&st, auth.user_id_unwrap(), &upload_id).await?;
+    Ok(web::Json(to_attachment(&media, &st)))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UpdateMediaParams {
+    description: Option<String>,
+    focus: Option<String>,
+}
+
+#[put(
+    "/media/{upload_id}",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
+pub async fn mastodon_update_media(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    upload_id: web::Path<String>,
+    params: MastodonParams<UpdateMediaParams>,
+) -> ServiceResult<web::Json<MediaAttachment>> {
+    let media = get_pending_media(&st, auth.user_id_unwrap(), &upload_id).await?;
+    let media = update_pending_upload_metadata(
+        &st.maybe_conn(),
+        auth.user_id_unwrap(),
+        &media.upload_id,
+        params.description.clone(),
+        params.focus.as_deref().and_then(parse_focus),
+    )
+    .await?;
+
+    Ok(web::Json(to_attachment(&media, &st)))
+}
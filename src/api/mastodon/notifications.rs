@@ -0,0 +1,134 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+/// Route handlers for /api/v1/notifications
+use actix_web::{get, http::StatusCode, middleware::from_fn, web, HttpRequest, HttpResponse};
+use lightpub_service::services::{
+    create_error_simple, id::Identifier, notification::get_notifications, ServiceResult,
+};
+use serde::Deserialize;
+
+use crate::{
+    api::auth::{middleware_auth_jwt_required_scoped, AuthedUser, NotificationsScope},
+    AppState,
+};
+
+use super::{
+    entities::MastodonNotification, paginated_response, MastodonParams, PageParams, Renderer,
+};
+
+/// serde_qs では `#[serde(flatten)]` した数値を読めないので、ページネーションのパラメータも並べる
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct NotificationsParams {
+    max_id: Option<String>,
+    since_id: Option<String>,
+    min_id: Option<String>,
+    limit: Option<u64>,
+    #[serde(default)]
+    types: Vec<String>,
+    #[serde(default)]
+    exclude_types: Vec<String>,
+}
+
+/// ページネーションの ID は通知の ID
+#[get(
+    "/notifications",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotificationsScope>)"
+)]
+pub async fn mastodon_get_notifications(
+    st: web::Data<AppState>,
+    req: HttpRequest,
+    auth: web::ReqData<AuthedUser>,
+    params: MastodonParams<NotificationsParams>,
+) -> ServiceResult<HttpResponse> {
+    let user_id = auth.user_id_unwrap();
+    let page = PageParams {
+        max_id: params.max_id.clone(),
+        since_id: params.since_id.clone(),
+        min_id: params.min_id.clone(),
+        limit: params.limit,
+    };
+    let limit = page.limit();
+    let before_id: Option<i32> = page.max_id.as_deref().and_then(|id| id.parse().ok());
+    let after_id: Option<i32> = page.newer_than().and_then(|id| id.parse().ok());
+
+    // 通知は新しい順に並んでいる
+    let notifications = get_notifications(st.conn(), user_id).await?;
+
+    let mut renderer = Renderer::new(&st, Some(user_id));
+    let mut result: Vec<MastodonNotification> = vec![];
+    for notification in notifications {
+        if result.len() as u64 >= limit {
+            break;
+        }
+        let id = notification.id.as_db();
+        if before_id.is_some_and(|b| id >= b) || after_id.is_some_and(|a| id <= a) {
+            continue;
+        }
+        let Some(rendered) = renderer.notification(&notification).await? else {
+            continue;
+        };
+        if !params.types.is_empty() && !params.types.iter().any(|t| t == rendered.kind) {
+            continue;
+        }
+        if params.exclude_types.iter().any(|t| t == rendered.kind) {
+            continue;
+        }
+        result.push(rendered);
+    }
+
+    let next = if result.len() as u64 >= limit {
+        result.last().map(|n| n.id.clone())
+    } else {
+        None
+    };
+    let prev = result.first().map(|n| n.id.clone());
+
+    Ok(paginated_response(&st, &req, limit, &result, next, prev))
+}
+
+#[get(
+    "/notifications/{notification_id}",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotificationsScope>)"
+)]
+pub async fn mastodon_get_notification(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    notification_id: web::Path<i32>,
+) -> ServiceResult<web::Json<MastodonNotification>> {
+    let user_id = auth.user_id_unwrap();
+    let notification_id = notification_id.into_inner();
+
+    let notification = get_notifications(st.conn(), user_id)
+        .await?
+        .into_iter()
+        .find(|n| n.id.as_db() == notification_id);
+    let rendered = match notification {
+        Some(notification) => {
+            Renderer::new(&st, Some(user_id))
+                .notification(&notification)
+                .await?
+        }
+        None => None,
+    };
+
+    match rendered {
+        Some(rendered) => Ok(web::Json(rendered)),
+        None => create_error_simple(StatusCode::NOT_FOUND, "Record not found"),
+    }
+}
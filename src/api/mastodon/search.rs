@@ -0,0 +1,104 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+/// Route handlers for /api/v2/search
+use actix_web::{get, middleware::from_fn, web};
+use lightpub_service::services::{
+    search::{search_note_by_content, search_user_by_text},
+    ServiceResult,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::auth::{middleware_auth_jwt_optional, AuthedUser},
+    AppState,
+};
+
+use super::{
+    entities::{Account, Status, Tag},
+    MastodonParams, Renderer,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchParams {
+    q: String,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    /// リモートのユーザーを WebFinger で検索するかどうか
+    #[serde(default)]
+    resolve: bool,
+    limit: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResult {
+    accounts: Vec<Account>,
+    statuses: Vec<Status>,
+    hashtags: Vec<Tag>,
+}
+
+#[get("/search", wrap = "from_fn(middleware_auth_jwt_optional)")]
+pub async fn mastodon_search(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    params: MastodonParams<SearchParams>,
+) -> ServiceResult<web::Json<SearchResult>> {
+    let viewer_id = auth.user_id();
+    let limit = params.limit.unwrap_or(20).clamp(1, 40) as usize;
+    let wants = |kind: &str| params.kind.as_deref().is_none_or(|k| k == kind);
+    let mut renderer = Renderer::new(&st, viewer_id);
+
+    let mut accounts = vec![];
+    if wants("accounts") {
+        let data = st.request_data();
+        // 未ログインのユーザーにはリモートの検索をさせない
+        let resolve = params.resolve && viewer_id.is_some();
+        let users = search_user_by_text(
+            &st.maybe_conn(),
+            &st.rconn(),
+            &params.q,
+            !resolve,
+            resolve,
+            st.dev_mode(),
+            &st.my_domain(),
+            &data,
+        )
+        .await?;
+        for user in users.into_iter().take(limit) {
+            accounts.push(match renderer.account(user.id).await? {
+                Some(account) => account,
+                None => Account::from_simple(&user, st.base_url()),
+            });
+        }
+    }
+
+    // 全文検索が無効な場合はノートを検索しない
+    let mut statuses = vec![];
+    if let Some(ft) = st.ft().filter(|_| wants("statuses")) {
+        let notes =
+            search_note_by_content(&st.maybe_conn(), &st.rconn(), ft, &params.q, viewer_id).await?;
+        let notes: Vec<_> = notes.into_iter().take(limit).collect();
+        statuses = renderer.statuses(&notes).await?;
+    }
+
+    Ok(web::Json(SearchResult {
+        accounts,
+        statuses,
+        hashtags: vec![],
+    }))
+}
@@ -0,0 +1,540 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+/// Route handlers for /api/v1/statuses and /api/v1/polls
+use actix_web::{delete, get, http::StatusCode, middleware::from_fn, post, web};
+use chrono::Utc;
+use lightpub_service::services::{
+    create_error_simple,
+    id::{NoteID, UserID},
+    note::{
+        create_note, create_renote, delete_note_by_id, delete_renote_by_id, get_liked_users,
+        get_note_by_id_visibility_check, get_note_poll, get_note_replies, get_renoted_users,
+        note_like_add, note_like_remove, vote_note_poll, ContentType, DetailedNoteModel,
+        NotePollCreate, PostCreateOptionsBuilder, VisibilityModel,
+    },
+    user::{get_user_profile_by_id, SimpleUserModel},
+    ServiceResult, UpsertOperation,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::auth::{
+        middleware_auth_jwt_optional, middleware_auth_jwt_required_scoped, AuthedUser, NotesScope,
+    },
+    AppState,
+};
+
+use super::{
+    entities::{visibility_from_str, Account, Poll, Status},
+    media::get_pending_uploads,
+    MastodonParams, PageParams, Renderer,
+};
+
+/// 返信ツリーを取得するときの上限
+const MAX_CONTEXT_NOTES: usize = 100;
+
+async fn get_visible_note(
+    st: &AppState,
+    note_id: NoteID,
+    viewer_id: Option<UserID>,
+) -> ServiceResult<DetailedNoteModel> {
+    let note =
+        get_note_by_id_visibility_check(&st.maybe_conn(), &st.rconn(), note_id, viewer_id, false)
+            .await?;
+    match note {
+        Some(note) => Ok(note),
+        None => create_error_simple(StatusCode::NOT_FOUND, "Record not found"),
+    }
+}
+
+async fn render_status_by_id(
+    st: &AppState,
+    note_id: NoteID,
+    viewer_id: Option<UserID>,
+) -> ServiceResult<web::Json<Status>> {
+    let note = get_visible_note(st, note_id, viewer_id).await?;
+    let status = Renderer::new(st, viewer_id).status(&note).await?;
+    Ok(web::Json(status))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreatePollParams {
+    options: Vec<String>,
+    expires_in: i64,
+    #[serde(default)]
+    multiple: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateStatusParams {
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    media_ids: Vec<String>,
+    poll: Option<CreatePollParams>,
+    in_reply_to_id: Option<NoteID>,
+    #[serde(default)]
+    sensitive: bool,
+    spoiler_text: Option<String>,
+    visibility: Option<String>,
+    quote_id: Option<NoteID>,
+}
+
+#[post(
+    "/statuses",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
+pub async fn mastodon_create_status(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    params: MastodonParams<CreateStatusParams>,
+) -> ServiceResult<web::Json<Status>> {
+    let user_id = auth.user_id_unwrap();
+    let params = params.into_inner();
+
+    let visibility = match params.visibility.as_deref() {
+        None => VisibilityModel::Public,
+        Some(v) => match visibility_from_str(v) {
+            Some(v) => v,
+            None => return create_error_simple(StatusCode::UNPROCESSABLE_ENTITY, "bad visibility"),
+        },
+    };
+
+    let uploads = get_pending_uploads(&st, user_id, &params.media_ids).await?;
+
+    let poll = match params.poll {
+        None => None,
+        Some(poll) => Some(NotePollCreate {
+            options: poll
+                .options
+                .into_iter()
+                .map(|o| o.trim().to_string())
+                .collect(),
+            multiple: poll.multiple,
+            expires_at: Some(poll.expires_in)
+                .filter(|e| *e > 0)
+                .map(|e| Utc::now() + chrono::Duration::seconds(e)),
+        }),
+    };
+
+    let data = st.request_data();
+    let note_id = create_note(
+        st.conn(),
+        &st.rconn(),
+        st.qconn(),
+        st.ft(),
+        st.wp(),
+        user_id,
+        &params.status,
+        ContentType::Plain,
+        visibility,
+        &PostCreateOptionsBuilder::default()
+            .sensitive(UpsertOperation::Set(params.sensitive))
+            .summary(UpsertOperation::Set(
+                params.spoiler_text.filter(|s| !s.trim().is_empty()),
+            ))
+            .reply_to_id(UpsertOperation::Set(params.in_reply_to_id))
+            .uploads(UpsertOperation::Set(uploads))
+            .poll(poll)
+            .quote_of_id(params.quote_id)
+            .build()
+            .unwrap(),
+        &st.my_domain(),
+        st.base_url(),
+        &data,
+    )
+    .await?;
+
+    render_status_by_id(&st, note_id, Some(user_id)).await
+}
+
+#[get("/statuses/{note_id}", wrap = "from_fn(middleware_auth_jwt_optional)")]
+pub async fn mastodon_get_status(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    note_id: web::Path<NoteID>,
+) -> ServiceResult<web::Json<Status>> {
+    render_status_by_id(&st, note_id.into_inner(), auth.user_id()).await
+}
+
+#[delete(
+    "/statuses/{note_id}",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
+pub async fn mastodon_delete_status(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    note_id: web::Path<NoteID>,
+) -> ServiceResult<web::Json<Status>> {
+    let user_id = auth.user_id_unwrap();
+    let note_id = note_id.into_inner();
+
+    let note = get_visible_note(&st, note_id, Some(user_id)).await?;
+    let mut status = Renderer::new(&st, Some(user_id)).status(&note).await?;
+    // 削除して書き直すために、元のテキストを返す
+    status.text = note
+        .basic
+        .content
+        .as_ref()
+        .map(|c| c.as_raw_text().to_string());
+
+    delete_note_by_id(st.conn(), st.qconn(), note_id, user_id, st.base_url()).await?;
+
+    Ok(web::Json(status))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusContext {
+    ancestors: Vec<Status>,
+    descendants: Vec<Status>,
+}
+
+#[get(
+    "/statuses/{note_id}/context",
+    wrap = "from_fn(middleware_auth_jwt_optional)"
+)]
+pub async fn mastodon_get_status_context(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    note_id: web::Path<NoteID>,
+) -> ServiceResult<web::Json<StatusContext>> {
+    let viewer_id = auth.user_id();
+    let note = get_visible_note(&st, note_id.into_inner(), viewer_id).await?;
+
+    let mut ancestors = vec![];
+    let mut reply_to_id = note.basic.reply_to_id;
+    while let Some(id) = reply_to_id {
+        if ancestors.len() >= MAX_CONTEXT_NOTES {
+            break;
+        }
+        let parent =
+            get_note_by_id_visibility_check(&st.maybe_conn(), &st.rconn(), id, viewer_id, false)
+                .await?;
+        match parent {
+            None => break,
+            Some(parent) => {
+                reply_to_id = parent.basic.reply_to_id;
+                ancestors.push(parent);
+            }
+        }
+    }
+    ancestors.reverse();
+
+    // 返信ツリーを深さ優先でたどり、それぞれの返信の直後にその返信への返信が来るように並べる
+    let root_id = note.basic.id;
+    let mut descendants = vec![];
+    let mut stack = vec![note];
+    while let Some(current) = stack.pop() {
+        let current_id = current.basic.id;
+        if current_id != root_id {
+            descendants.push(current);
+        }
+        if descendants.len() >= MAX_CONTEXT_NOTES {
+            break;
+        }
+        // 新しい順に返されるので、そのまま積むと古いものから取り出される
+        let replies = get_note_replies(
+            &st.maybe_conn(),
+            &st.rconn(),
+            viewer_id,
+            current_id,
+            MAX_CONTEXT_NOTES as u64,
+            None,
+        )
+        .await?;
+        stack.extend(replies);
+    }
+
+    let mut renderer = Renderer::new(&st, viewer_id);
+    Ok(web::Json(StatusContext {
+        ancestors: renderer.statuses(&ancestors).await?,
+        descendants: renderer.statuses(&descendants).await?,
+    }))
+}
+
+#[post(
+    "/statuses/{note_id}/favourite",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
+pub async fn mastodon_favourite_status(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    note_id: web::Path<NoteID>,
+) -> ServiceResult<web::Json<Status>> {
+    change_like(
+        &st,
+        auth.user_id_unwrap(),
+        note_id.into_inner(),
+        false,
+        true,
+    )
+    .await
+}
+
+#[post(
+    "/statuses/{note_id}/unfavourite",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
+pub async fn mastodon_unfavourite_status(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    note_id: web::Path<NoteID>,
+) -> ServiceResult<web::Json<Status>> {
+    change_like(
+        &st,
+        auth.user_id_unwrap(),
+        note_id.into_inner(),
+        false,
+        false,
+    )
+    .await
+}
+
+#[post(
+    "/statuses/{note_id}/bookmark",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
+pub async fn mastodon_bookmark_status(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    note_id: web::Path<NoteID>,
+) -> ServiceResult<web::Json<Status>> {
+    change_like(&st, auth.user_id_unwrap(), note_id.into_inner(), true, true).await
+}
+
+#[post(
+    "/statuses/{note_id}/unbookmark",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
+pub async fn mastodon_unbookmark_status(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    note_id: web::Path<NoteID>,
+) -> ServiceResult<web::Json<Status>> {
+    change_like(
+        &st,
+        auth.user_id_unwrap(),
+        note_id.into_inner(),
+        true,
+        false,
+    )
+    .await
+}
+
+/// いいね (`is_private` が true の場合はブックマーク) を追加または削除して、ステータスを返す。
+async fn change_like(
+    st: &AppState,
+    user_id: UserID,
+    note_id: NoteID,
+    is_private: bool,
+    add: bool,
+) -> ServiceResult<web::Json<Status>> {
+    if add {
+        note_like_add(
+            st.conn(),
+            st.qconn(),
+            user_id,
+            note_id,
+            is_private,
+            None,
+            &st.my_domain(),
+            st.base_url(),
+        )
+        .await?;
+    } else {
+        note_like_remove(
+            st.conn(),
+            st.qconn(),
+            user_id,
+            note_id,
+            is_private,
+            st.base_url(),
+        )
+        .await?;
+    }
+
+    render_status_by_id(st, note_id, Some(user_id)).await
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ReblogParams {
+    visibility: Option<String>,
+}
+
+#[post(
+    "/statuses/{note_id}/reblog",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
+pub async fn mastodon_reblog_status(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    note_id: web::Path<NoteID>,
+    params: MastodonParams<ReblogParams>,
+) -> ServiceResult<web::Json<Status>> {
+    let user_id = auth.user_id_unwrap();
+    // リノートは public か unlisted のみ
+    let visibility = match params.visibility.as_deref() {
+        Some("unlisted") => VisibilityModel::Unlisted,
+        _ => VisibilityModel::Public,
+    };
+
+    let renote_id = create_renote(
+        st.conn(),
+        &st.rconn(),
+        st.qconn(),
+        st.wp(),
+        user_id,
+        note_id.into_inner(),
+        visibility,
+        st.base_url(),
+    )
+    .await?;
+
+    render_status_by_id(&st, renote_id, Some(user_id)).await
+}
+
+#[post(
+    "/statuses/{note_id}/unreblog",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
+pub async fn mastodon_unreblog_status(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    note_id: web::Path<NoteID>,
+) -> ServiceResult<web::Json<Status>> {
+    let user_id = auth.user_id_unwrap();
+    let note_id = note_id.into_inner();
+
+    delete_renote_by_id(st.conn(), st.qconn(), note_id, user_id, st.base_url()).await?;
+
+    render_status_by_id(&st, note_id, Some(user_id)).await
+}
+
+async fn render_accounts(
+    st: &AppState,
+    users: Vec<(SimpleUserModel, chrono::DateTime<Utc>)>,
+) -> ServiceResult<Vec<Account>> {
+    let mut accounts = Vec::with_capacity(users.len());
+    for (user, _) in users {
+        let profile = get_user_profile_by_id(&st.maybe_conn(), &st.rconn(), None, user.id).await?;
+        accounts.push(match profile {
+            Some(profile) => Account::from_profile(&profile, st.base_url()),
+            None => Account::from_simple(&user, st.base_url()),
+        });
+    }
+    Ok(accounts)
+}
+
+#[get(
+    "/statuses/{note_id}/favourited_by",
+    wrap = "from_fn(middleware_auth_jwt_optional)"
+)]
+pub async fn mastodon_get_status_favourited_by(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    note_id: web::Path<NoteID>,
+    page: MastodonParams<PageParams>,
+) -> ServiceResult<web::Json<Vec<Account>>> {
+    let users = get_liked_users(
+        &st.maybe_conn(),
+        &st.rconn(),
+        auth.user_id(),
+        note_id.into_inner(),
+        page.limit(),
+        None,
+    )
+    .await?;
+    Ok(web::Json(render_accounts(&st, users).await?))
+}
+
+#[get(
+    "/statuses/{note_id}/reblogged_by",
+    wrap = "from_fn(middleware_auth_jwt_optional)"
+)]
+pub async fn mastodon_get_status_reblogged_by(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    note_id: web::Path<NoteID>,
+    page: MastodonParams<PageParams>,
+) -> ServiceResult<web::Json<Vec<Account>>> {
+    let users = get_renoted_users(
+        &st.maybe_conn(),
+        &st.rconn(),
+        auth.user_id(),
+        note_id.into_inner(),
+        page.limit(),
+        None,
+    )
+    .await?;
+    Ok(web::Json(render_accounts(&st, users).await?))
+}
+
+async fn render_poll(
+    st: &AppState,
+    note_id: NoteID,
+    viewer_id: Option<UserID>,
+) -> ServiceResult<web::Json<Poll>> {
+    // 投票の閲覧権限はノートと同じ
+    get_visible_note(st, note_id, viewer_id).await?;
+    let poll = get_note_poll(&st.maybe_conn(), note_id, viewer_id).await?;
+    match poll {
+        Some(poll) => Ok(web::Json(Poll::from_model(note_id, &poll))),
+        None => create_error_simple(StatusCode::NOT_FOUND, "Record not found"),
+    }
+}
+
+#[get("/polls/{note_id}", wrap = "from_fn(middleware_auth_jwt_optional)")]
+pub async fn mastodon_get_poll(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    note_id: web::Path<NoteID>,
+) -> ServiceResult<web::Json<Poll>> {
+    render_poll(&st, note_id.into_inner(), auth.user_id()).await
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PollVoteParams {
+    choices: Vec<usize>,
+}
+
+#[post(
+    "/polls/{note_id}/votes",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
+pub async fn mastodon_vote_poll(
+    st: web::Data<AppState>,
+    auth: web::ReqData<AuthedUser>,
+    note_id: web::Path<NoteID>,
+    params: MastodonParams<PollVoteParams>,
+) -> ServiceResult<web::Json<Poll>> {
+    let user_id = auth.user_id_unwrap();
+    let note_id = note_id.into_inner();
+
+    vote_note_poll(
+        st.conn(),
+        st.qconn(),
+        user_id,
+        note_id,
+        &params.choices,
+        st.base_url(),
+    )
+    .await?;
+
+    render_poll(&st, note_id, Some(user_id)).await
+}
@@ -0,0 +1,90 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+/// Route handlers for /api/v1/timelines
+use actix_web::{get, middleware::from_fn, web, HttpRequest, HttpResponse};
+use lightpub_service::services::{
+    id::UserID,
+    note::{get_timeline_notes, get_timeline_notes_after},
+    ServiceResult,
+};
+
+use crate::{
+    api::auth::{middleware_auth_jwt_optional, middleware_auth_jwt_required, AuthedUser},
+    AppState,
+};
+
+use super::{fetch_note_page, statuses_response, MastodonParams, PageParams, Renderer};
+
+async fn timeline_response(
+    st: &AppState,
+    req: &HttpRequest,
+    viewer_id: Option<UserID>,
+    include_public: bool,
+    page: &PageParams,
+) -> ServiceResult<HttpResponse> {
+    let notes = fetch_note_page(st, viewer_id, page, |limit, before_date, after_date| {
+        let conn = st.maybe_conn();
+        let rconn = st.rconn();
+        async move {
+            match after_date {
+                Some(after_date) => {
+                    get_timeline_notes_after(
+                        &conn,
+                        &rconn,
+                        viewer_id,
+                        include_public,
+                        limit,
+                        before_date,
+                        after_date,
+                    )
+                    .await
+                }
+                None => {
+                    get_timeline_notes(&conn, &rconn, viewer_id, include_public, limit, before_date)
+                        .await
+                }
+            }
+        }
+    })
+    .await?;
+
+    let statuses = Renderer::new(st, viewer_id).statuses(&notes).await?;
+    Ok(statuses_response(st, req, page.limit(), &statuses))
+}
+
+#[get("/timelines/home", wrap = "from_fn(middleware_auth_jwt_required)")]
+pub async fn mastodon_home_timeline(
+    st: web::Data<AppState>,
+    req: HttpRequest,
+    auth: web::ReqData<AuthedUser>,
+    page: MastodonParams<PageParams>,
+) -> ServiceResult<HttpResponse> {
+    timeline_response(&st, &req, auth.user_id(), false, &page).await
+}
+
+/// 連合タイムライン。`local` などの絞り込みには対応していない。
+#[get("/timelines/public", wrap = "from_fn(middleware_auth_jwt_optional)")]
+pub async fn mastodon_public_timeline(
+    st: web::Data<AppState>,
+    req: HttpRequest,
+    auth: web::ReqData<AuthedUser>,
+    page: MastodonParams<PageParams>,
+) -> ServiceResult<HttpResponse> {
+    timeline_response(&st, &req, auth.user_id(), true, &page).await
+}
@@ -35,8 +35,10 @@ use tracing::debug;
 pub mod admin;
 pub mod auth;
 pub mod federation;
+pub mod mastodon;
 pub mod note;
 pub mod notifications;
+pub mod oauth;
 pub mod pagination;
 pub mod search;
 pub mod timeline;
@@ -18,6 +18,7 @@ along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use crate::api::auth::middleware_auth_jwt_optional;
 use crate::api::auth::middleware_auth_jwt_required;
+use crate::api::auth::{middleware_auth_jwt_required_scoped, NotesScope};
 use activitypub_federation::protocol::context::WithContext;
 use activitypub_federation::traits::Object;
 use actix_multipart::form::{tempfile::TempFile, text::Text as MpText, MultipartForm};
@@ -35,21 +36,24 @@ use crate::{
     api::{pagination::Paginator, APIResponseBuilder},
     template::{
         render_template, EditNoteContentData, EditNoteData, NoteAuthorData, NoteContentData,
-        PartsEditNote, PartsNote, PartsNoteNote, PartsNotes, PartsUserList, PartsUserListData,
-        PartsUserListDataUser, RenoteInfo, RenoteInfoUser, Template,
+        PartsEditNote, PartsNote, PartsNoteNote, PartsNotePoll, PartsNotePollOption,
+        PartsNoteQuote, PartsNoteReaction, PartsNoteUpload, PartsNotes, PartsUserList,
+        PartsUserListData, PartsUserListDataUser, RenoteInfo, RenoteInfoUser, Template,
     },
     AppState,
 };
 use lightpub_service::services::{
     create_error_simple,
-    id::{NoteID, UserID},
+    id::{NoteID, UploadID, UserID},
     note::{
         create_note, create_renote, delete_note_by_id, delete_renote_by_id, edit_note,
         get_apubnote_by_id_visibility_check, get_liked_users, get_note_by_id_visibility_check,
-        get_note_replies, get_renoted_users, note_like_add, note_like_remove, ContentType,
-        DetailedNoteModel, NoteUpload, PostCreateOptionsBuilder, VisibilityModel,
+        get_note_replies, get_reacted_users, get_renoted_users, note_like_add, note_like_remove,
+        note_reaction_add, update_note_upload_metadata, vote_note_poll, ContentType,
+        DetailedNoteModel, NotePollCreate, NoteUpload, NoteUploadModel, PostCreateOptionsBuilder,
+        VisibilityModel,
     },
-    upload::save_upload_file,
+    upload::{save_upload_file, validate_upload_description, UploadKind},
     user::{get_user_by_id, SimpleUserModel},
     ServiceResult, UpsertOperation,
 };
@@ -59,6 +63,9 @@ use super::{
     APIResponse, FormBool, RequestType,
 };
 
+/// ノートのツールバーに表示するクイックリアクション
+const QUICK_REACTIONS: &[&str] = &["👍", "❤️", "😆", "🎉", "🤔", "😢"];
+
 #[derive(Debug, Deserialize, Clone, Copy)]
 #[serde(rename_all = "snake_case")]
 pub enum CreatableNoteContentType {
@@ -105,11 +112,25 @@ pub struct CreateNoteRequest {
     #[multipart(rename = "contentType")]
     content_type: MpText<CreatableNoteContentType>,
     sensitive: Option<MpText<FormBool>>,
+    /// コンテンツ警告 (CW) の文言
+    summary: Option<MpText<String>>,
     visibility: MpText<CreatableNoteVisibility>,
     #[multipart(rename = "replyToId")]
     reply_to_id: Option<MpText<NoteID>>,
-    #[multipart(limit = "10MB")]
+    #[multipart(rename = "quoteOfId")]
+    quote_of_id: Option<MpText<NoteID>>,
+    #[multipart(limit = "100MB")]
     file: Vec<TempFile>,
+    /// 添付ファイルの説明文。`file` と同じ順序で指定する。
+    #[multipart(rename = "fileDescription")]
+    file_description: Vec<MpText<String>>,
+    #[multipart(rename = "pollOption")]
+    poll_option: Vec<MpText<String>>,
+    #[multipart(rename = "pollMultiple")]
+    poll_multiple: Option<MpText<FormBool>>,
+    /// 投票の締め切りまでの秒数
+    #[multipart(rename = "pollExpiresIn")]
+    poll_expires_in: Option<MpText<i64>>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -118,7 +139,10 @@ pub struct CreateNoteResponse {
     note_id: NoteID,
 }
 
-#[post("", wrap = "from_fn(middleware_auth_jwt_required)")]
+#[post(
+    "",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
 pub async fn api_create_note(
     st: web::Data<AppState>,
     auth: web::ReqData<AuthedUser>,
@@ -126,11 +150,52 @@ pub async fn api_create_note(
 ) -> ServiceResult<APIResponse<CreateNoteResponse>> {
     let mut uploads = vec![];
     let req_files = std::mem::replace(&mut req.file, vec![]);
+    let descriptions: Vec<Option<String>> = req
+        .file_description
+        .iter()
+        .map(|d| Some(d.0.trim().to_string()).filter(|d| !d.is_empty()))
+        .collect();
+    for description in &descriptions {
+        validate_upload_description(description.as_deref(), None)?;
+    }
+    let mut descriptions = descriptions.into_iter();
     for upload in req_files {
-        let (upload_id, filename, mime_type) = save_upload_file(upload.file)?;
-        uploads.push(NoteUpload::File(upload_id, filename, mime_type.to_string()));
+        let description = descriptions.next().flatten();
+        let (upload_id, filename, mime_type, mut metadata) = save_upload_file(
+            st.storage(),
+            upload.file,
+            &[UploadKind::Image, UploadKind::Video, UploadKind::Audio],
+        )
+        .await?;
+        metadata.description = description;
+        uploads.push(NoteUpload::File(
+            upload_id,
+            filename,
+            mime_type.to_string(),
+            metadata,
+        ));
     }
 
+    let poll_options: Vec<String> = req
+        .poll_option
+        .iter()
+        .map(|o| o.0.trim().to_string())
+        .filter(|o| !o.is_empty())
+        .collect();
+    let poll = if poll_options.is_empty() {
+        None
+    } else {
+        Some(NotePollCreate {
+            options: poll_options,
+            multiple: req.poll_multiple.as_ref().map(|m| m.0 .0).unwrap_or(false),
+            expires_at: req
+                .poll_expires_in
+                .as_ref()
+                .filter(|e| e.0 > 0)
+                .map(|e| Utc::now() + chrono::Duration::seconds(e.0)),
+        })
+    };
+
     let data = st.request_data();
     let result = create_note(
         st.conn(),
@@ -146,8 +211,11 @@ pub async fn api_create_note(
             .sensitive(UpsertOperation::Set(
                 req.sensitive.map(|s| s.0 .0).unwrap_or(false),
             ))
+            .summary(UpsertOperation::Set(req.summary.map(|s| s.0)))
             .reply_to_id(UpsertOperation::Set(req.reply_to_id.map(|r| r.0)))
             .uploads(UpsertOperation::Set(uploads))
+            .poll(poll)
+            .quote_of_id(req.quote_of_id.map(|q| q.0))
             .build()
             .unwrap(),
         &st.my_domain(),
@@ -168,7 +236,10 @@ pub struct CreateRenoteRequest {
     visibility: CreatableNoteVisibility,
 }
 
-#[post("/{note_id}/renote", wrap = "from_fn(middleware_auth_jwt_required)")]
+#[post(
+    "/{note_id}/renote",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
 pub async fn api_create_renote(
     st: web::Data<AppState>,
     auth: web::ReqData<AuthedUser>,
@@ -196,6 +267,49 @@ pub async fn api_create_renote(
         .unwrap())
 }
 
+fn create_parts_note_upload(upload: &NoteUploadModel) -> PartsNoteUpload {
+    let upload_id = upload.data().upload_id();
+    let metadata = upload.metadata();
+    let (focal_x, focal_y) = metadata.focal_point.unwrap_or((0.0, 0.0));
+    let url = format!("/upload/{}", upload_id);
+    let variant_url = |variant: &str| {
+        if metadata.has_variants {
+            format!("{}?variant={}", url, variant)
+        } else {
+            url.clone()
+        }
+    };
+    PartsNoteUpload {
+        id: upload_id,
+        preview_url: variant_url("preview"),
+        thumbnail_url: variant_url("thumbnail"),
+        url,
+        mime_type: upload.mime_type().clone(),
+        kind: match UploadKind::from_mime_type(upload.mime_type()) {
+            Some(UploadKind::Video) => "video",
+            Some(UploadKind::Audio) => "audio",
+            _ => "image",
+        },
+        poster_url: metadata
+            .thumbnail_id
+            .map(|thumbnail_id| format!("/upload/{}", thumbnail_id)),
+        duration: metadata.duration.map(|d| {
+            let secs = d.round() as u64;
+            format!("{}:{:02}", secs / 60, secs % 60)
+        }),
+        description: metadata.description.clone(),
+        width: metadata.width,
+        height: metadata.height,
+        blurhash: metadata.blurhash.clone(),
+        focal_x,
+        focal_y,
+        // フォーカルポイントは y 軸が上向きなので反転する
+        object_position: metadata
+            .focal_point
+            .map(|(x, y)| format!("{:.1}% {:.1}%", (x + 1.0) * 50.0, (1.0 - y) * 50.0)),
+    }
+}
+
 pub async fn create_parts_note_from_model(
     st: &AppState,
     dnote: &DetailedNoteModel,
@@ -206,7 +320,9 @@ pub async fn create_parts_note_from_model(
     let content = match note.content.as_ref() {
         None => None,
         Some(content) => Some(NoteContentData {
-            content: content.render_to_html(st.qconn()).await?,
+            content: content
+                .render_to_html_with_emojis(st.qconn(), &dnote.details.emojis, st.base_url())
+                .await?,
         }),
     };
 
@@ -225,6 +341,82 @@ pub async fn create_parts_note_from_model(
         None
     };
 
+    let quote = match note.quote_of_id() {
+        None => None,
+        Some(quote_of_id) => {
+            let quoted = get_note_by_id_visibility_check(
+                &st.maybe_conn(),
+                &st.rconn(),
+                quote_of_id,
+                viewer_id,
+                false,
+            )
+            .await?;
+            match quoted {
+                None => None,
+                Some(quoted) => {
+                    let content = match quoted.basic.content.as_ref() {
+                        None => None,
+                        Some(content) => Some(NoteContentData {
+                            content: content
+                                .render_to_html_with_emojis(
+                                    st.qconn(),
+                                    &quoted.details.emojis,
+                                    st.base_url(),
+                                )
+                                .await?,
+                        }),
+                    };
+                    Some(PartsNoteQuote {
+                        id: quoted.basic.id,
+                        author: NoteAuthorData {
+                            id: quoted.basic.author.id,
+                            nickname: quoted.basic.author.nickname.clone(),
+                            specifier: quoted.basic.author.specifier(),
+                        },
+                        content,
+                        created_at: quoted.basic.created_at,
+                    })
+                }
+            }
+        }
+    };
+
+    let is_my_note = viewer_id.map(|v| v == note.author.id).unwrap_or(false);
+    let poll = dnote.details.poll.as_ref().map(|poll| {
+        let voted = poll.voted.clone().unwrap_or_default();
+        PartsNotePoll {
+            multiple: poll.multiple,
+            expires_at: poll.expires_at,
+            closed: poll.closed,
+            voters_count: poll.voters_count,
+            show_results: poll.closed || viewer_id.is_none() || is_my_note || !voted.is_empty(),
+            options: poll
+                .options
+                .iter()
+                .enumerate()
+                .map(|(i, o)| PartsNotePollOption {
+                    index: i,
+                    title: o.title.clone(),
+                    votes_count: o.votes_count,
+                    voted: voted.contains(&i),
+                })
+                .collect(),
+        }
+    });
+
+    let reactions = dnote
+        .details
+        .reactions
+        .iter()
+        .map(|r| PartsNoteReaction {
+            reaction: r.reaction.clone(),
+            count: r.count,
+            image_url: r.emoji.as_ref().map(|e| e.image_url(st.base_url())),
+            reacted: dnote.details.my_reaction.as_ref() == Some(&r.reaction),
+        })
+        .collect();
+
     Ok(PartsNote {
         renote_info,
         authed: viewer_id.is_some().into(),
@@ -239,30 +431,28 @@ pub async fn create_parts_note_from_model(
             visibility: note.visibility,
             created_at: note.created_at,
             sensitive: note.sensitive,
+            summary: note.summary.clone(),
             reply_to_id: note.reply_to_id,
-            renote_of_id: note.renote_of_id,
+            // 引用ノートはリノートとして表示しない
+            renote_of_id: note.renote_of_id.filter(|_| note.content.is_none()),
             view_url: None,
             liked: dnote.details.liked.unwrap_or(false),
             bookmarked: dnote.details.bookmarked.unwrap_or(false),
             uploads: if note.uploads.len() == 0 {
                 None
             } else {
-                Some(
-                    note.uploads
-                        .iter()
-                        .map(|u| {
-                            let upload_id = u.data().upload_id();
-                            format!("/upload/{}", upload_id)
-                        })
-                        .collect(),
-                )
+                Some(note.uploads.iter().map(create_parts_note_upload).collect())
             },
-            is_my_note: viewer_id.map(|v| v == note.author.id).unwrap_or(false),
+            is_my_note,
             renotable: note.is_renotable(),
             renoted: dnote.details.renoted,
             reply_count: dnote.details.reply_count,
             renote_count: dnote.details.renote_count,
             like_count: dnote.details.like_count,
+            poll,
+            reactions,
+            quick_reactions: QUICK_REACTIONS.to_vec(),
+            quote,
         },
     })
 }
@@ -376,10 +566,18 @@ pub async fn api_edit_note_view(
                 },
             };
 
+            let uploads = note
+                .basic
+                .uploads
+                .iter()
+                .map(create_parts_note_upload)
+                .collect();
+
             let edit_data = Template::PartsEditNote(PartsEditNote {
                 note: EditNoteData {
                     content,
                     id: note.basic.id,
+                    uploads,
                 },
             });
 
@@ -464,7 +662,10 @@ pub async fn api_get_replies(
     )
 }
 
-#[put("/{note_id}/like", wrap = "from_fn(middleware_auth_jwt_required)")]
+#[put(
+    "/{note_id}/like",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
 pub async fn api_note_add_like(
     st: web::Data<AppState>,
     note_id: web::Path<NoteID>,
@@ -480,6 +681,7 @@ pub async fn api_note_add_like(
         user_id,
         note_id,
         false,
+        None,
         &st.my_domain(),
         st.base_url(),
     )
@@ -492,7 +694,155 @@ pub async fn api_note_add_like(
         .unwrap())
 }
 
-#[put("/{note_id}/bookmark", wrap = "from_fn(middleware_auth_jwt_required)")]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateNoteUploadRequest {
+    description: Option<String>,
+    focal_x: Option<f32>,
+    focal_y: Option<f32>,
+}
+
+#[patch(
+    "/{note_id}/uploads/{upload_id}",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
+pub async fn api_note_update_upload(
+    st: web::Data<AppState>,
+    path: web::Path<(NoteID, UploadID)>,
+    auth: web::ReqData<AuthedUser>,
+    req: web::Form<UpdateNoteUploadRequest>,
+) -> ServiceResult<impl Responder> {
+    let user_id = auth.user_id_unwrap();
+    let (note_id, upload_id) = path.into_inner();
+    let req = req.into_inner();
+
+    let data = st.request_data();
+    update_note_upload_metadata(
+        st.conn(),
+        &st.rconn(),
+        st.qconn(),
+        user_id,
+        note_id,
+        upload_id,
+        req.description,
+        req.focal_x.zip(req.focal_y),
+        st.base_url(),
+        &data,
+    )
+    .await?;
+
+    Ok(APIResponseBuilder::default()
+        .data(())
+        .trigger_event("note-refresh")
+        .build()
+        .unwrap())
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NoteReactionRequest {
+    reaction: String,
+}
+
+#[put(
+    "/{note_id}/reaction",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
+pub async fn api_note_add_reaction(
+    st: web::Data<AppState>,
+    note_id: web::Path<NoteID>,
+    auth: web::ReqData<AuthedUser>,
+    req: web::Json<NoteReactionRequest>,
+) -> ServiceResult<impl Responder> {
+    let user_id = auth.user_id_unwrap();
+    let note_id = note_id.into_inner();
+
+    note_reaction_add(
+        st.conn(),
+        st.qconn(),
+        user_id,
+        note_id,
+        &req.reaction,
+        &st.my_domain(),
+        st.base_url(),
+    )
+    .await?;
+
+    Ok(APIResponseBuilder::default()
+        .data(())
+        .trigger_event("note-refresh")
+        .build()
+        .unwrap())
+}
+
+#[delete(
+    "/{note_id}/reaction",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
+pub async fn api_note_remove_reaction(
+    st: web::Data<AppState>,
+    note_id: web::Path<NoteID>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<impl Responder> {
+    let user_id = auth.user_id_unwrap();
+    let note_id = note_id.into_inner();
+
+    note_like_remove(
+        st.conn(),
+        st.qconn(),
+        user_id,
+        note_id,
+        false,
+        st.base_url(),
+    )
+    .await?;
+
+    Ok(APIResponseBuilder::default()
+        .data(())
+        .trigger_event("note-refresh")
+        .build()
+        .unwrap())
+}
+
+#[derive(Debug, MultipartForm)]
+pub struct PollVoteRequest {
+    choices: Vec<MpText<usize>>,
+}
+
+#[post(
+    "/{note_id}/poll/votes",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
+pub async fn api_note_poll_vote(
+    st: web::Data<AppState>,
+    note_id: web::Path<NoteID>,
+    auth: web::ReqData<AuthedUser>,
+    MultipartForm(req): MultipartForm<PollVoteRequest>,
+) -> ServiceResult<impl Responder> {
+    let user_id = auth.user_id_unwrap();
+    let note_id = note_id.into_inner();
+
+    let choices: Vec<usize> = req.choices.iter().map(|c| c.0).collect();
+    vote_note_poll(
+        st.conn(),
+        st.qconn(),
+        user_id,
+        note_id,
+        &choices,
+        st.base_url(),
+    )
+    .await?;
+
+    Ok(APIResponseBuilder::default()
+        .data(())
+        .trigger_event("note-refresh")
+        .build()
+        .unwrap())
+}
+
+#[put(
+    "/{note_id}/bookmark",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
 pub async fn api_note_add_bookmark(
     st: web::Data<AppState>,
     note_id: web::Path<NoteID>,
@@ -508,6 +858,7 @@ pub async fn api_note_add_bookmark(
         user_id,
         note_id,
         true,
+        None,
         &st.my_domain(),
         st.base_url(),
     )
@@ -520,7 +871,10 @@ pub async fn api_note_add_bookmark(
         .unwrap())
 }
 
-#[delete("/{note_id}/like", wrap = "from_fn(middleware_auth_jwt_required)")]
+#[delete(
+    "/{note_id}/like",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
 pub async fn api_note_remove_like(
     st: web::Data<AppState>,
     note_id: web::Path<NoteID>,
@@ -547,7 +901,10 @@ pub async fn api_note_remove_like(
         .unwrap())
 }
 
-#[delete("/{note_id}/bookmark", wrap = "from_fn(middleware_auth_jwt_required)")]
+#[delete(
+    "/{note_id}/bookmark",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
 pub async fn api_note_remove_bookmark(
     st: web::Data<AppState>,
     note_id: web::Path<NoteID>,
@@ -566,7 +923,10 @@ pub async fn api_note_remove_bookmark(
         .unwrap())
 }
 
-#[delete("/{note_id}", wrap = "from_fn(middleware_auth_jwt_required)")]
+#[delete(
+    "/{note_id}",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
 pub async fn api_note_delete(
     st: web::Data<AppState>,
     note_id: web::Path<NoteID>,
@@ -583,7 +943,10 @@ pub async fn api_note_delete(
         .finish())
 }
 
-#[delete("/{note_id}/renote", wrap = "from_fn(middleware_auth_jwt_required)")]
+#[delete(
+    "/{note_id}/renote",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
 pub async fn api_note_delete_by_renote_target_id(
     st: web::Data<AppState>,
     note_id: web::Path<NoteID>,
@@ -607,7 +970,10 @@ pub struct EditNoteRequest {
     content_type: MpText<CreatableNoteContentType>,
 }
 
-#[patch("/{note_id}", wrap = "from_fn(middleware_auth_jwt_required)")]
+#[patch(
+    "/{note_id}",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotesScope>)"
+)]
 pub async fn api_note_patch(
     st: web::Data<AppState>,
     note_id: web::Path<NoteID>,
@@ -755,6 +1121,66 @@ pub async fn api_get_note_like_users(
     render_template(st.template(), &Template::PartsUserList(user_list))
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteReactionListQuery {
+    key: Option<String>,
+    reaction: Option<String>,
+}
+
+#[get("/{note_id}/reactions", wrap = "from_fn(middleware_auth_jwt_optional)")]
+pub async fn api_get_note_reaction_users(
+    st: web::Data<AppState>,
+    note_id: web::Path<NoteID>,
+    query: web::Query<NoteReactionListQuery>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<impl Responder> {
+    let note_id = note_id.into_inner();
+    let viewer_id = auth.user_id();
+
+    let paginator = Paginator::new(
+        20,
+        |limit, key| {
+            let conn = st.maybe_conn();
+            let rconn = st.rconn();
+            let reaction = query.reaction.clone();
+            Box::pin(async move {
+                let result = get_reacted_users(
+                    &conn,
+                    &rconn,
+                    viewer_id,
+                    note_id,
+                    reaction.as_deref(),
+                    limit as u64,
+                    key,
+                )
+                .await?;
+                let result = create_user_list_from_models(result)?;
+                Ok(result)
+            })
+        },
+        |last| last.created_at.unwrap(),
+    );
+
+    let result = paginator.fetch_page(query.key.clone()).await?;
+
+    let result = URLBasedPaginatedResponse::from_paginated(result, |key| {
+        let mut new_query = query.clone();
+        new_query.key = Some(key);
+        format!(
+            "/note/{}/reactions?{}",
+            note_id,
+            serde_qs::to_string(&new_query.0).unwrap()
+        )
+    });
+
+    let user_list = PartsUserList {
+        data: result.data,
+        next_url: result.next_url,
+    };
+
+    render_template(st.template(), &Template::PartsUserList(user_list))
+}
+
 #[get("/{note_id}/mentions", wrap = "from_fn(middleware_auth_jwt_optional)")]
 pub async fn api_get_note_mentions_users(
     st: web::Data<AppState>,
@@ -34,13 +34,14 @@ use url::Url;
 use web_push::SubscriptionInfo;
 
 use super::auth::AuthedUser;
-use crate::api::auth::middleware_auth_jwt_required;
+use crate::api::auth::{middleware_auth_jwt_required_scoped, NotificationsScope};
 use crate::{
     template::{
         render_template, NotifyFollowRequestedBody, NotifyFollowRequestedBodyData,
         NotifyFollowedBody, NotifyFollowedBodyData, NotifyMentionedBody, NotifyMentionedBodyData,
-        NotifyNotifyBase, NotifyRenotedBody, NotifyRenotedBodyData, NotifyRepliedBody,
-        NotifyRepliedBodyData, PartsNotifyList, PartsNotifyListEntry, Template,
+        NotifyNotifyBase, NotifyQuotedBody, NotifyQuotedBodyData, NotifyRenotedBody,
+        NotifyRenotedBodyData, NotifyRepliedBody, NotifyRepliedBodyData, PartsNotifyList,
+        PartsNotifyListEntry, Template,
     },
     AppState,
 };
@@ -56,7 +57,10 @@ use lightpub_service::services::{
     ServiceResult,
 };
 use lightpub_service::try_opt_res;
-#[post("/all/read", wrap = "from_fn(middleware_auth_jwt_required)")]
+#[post(
+    "/all/read",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotificationsScope>)"
+)]
 pub async fn api_read_all_notifications(
     st: web::Data<AppState>,
     auth: web::ReqData<AuthedUser>,
@@ -72,7 +76,7 @@ pub async fn api_read_all_notifications(
 
 #[post(
     "/{notification_id}/read",
-    wrap = "from_fn(middleware_auth_jwt_required)"
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotificationsScope>)"
 )]
 pub async fn api_read_notification(
     st: web::Data<AppState>,
@@ -88,7 +92,10 @@ pub async fn api_read_notification(
         .finish())
 }
 
-#[get("/all", wrap = "from_fn(middleware_auth_jwt_required)")]
+#[get(
+    "/all",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotificationsScope>)"
+)]
 pub async fn api_get_notifications(
     st: web::Data<AppState>,
     auth: web::ReqData<AuthedUser>,
@@ -195,11 +202,27 @@ async fn get_notification_data(
                 },
             })))
         }
+        NotificationBodyData::Quoted {
+            user,
+            quote_note,
+            quoted_note,
+        } => Ok(Some(PartsNotifyListEntry::Quoted(NotifyQuotedBody {
+            base: base_func("parts/notify/quoted".to_owned()),
+            data: NotifyQuotedBodyData {
+                author_url: format!("/client/user/{}", user.basic.id),
+                author_nickname: user.basic.nickname,
+                quote_note_url: quote_note.view_url.to_string(),
+                quoted_note_url: quoted_note.view_url.to_string(),
+            },
+        }))),
         _ => todo!(),
     }
 }
 
-#[get("/unread-count", wrap = "from_fn(middleware_auth_jwt_required)")]
+#[get(
+    "/unread-count",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotificationsScope>)"
+)]
 pub async fn api_unread_notification_count(
     st: web::Data<AppState>,
     auth: web::ReqData<AuthedUser>,
@@ -261,7 +284,10 @@ nest! {
 
 }
 
-#[post("/push/subscribe", wrap = "from_fn(middleware_auth_jwt_required)")]
+#[post(
+    "/push/subscribe",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<NotificationsScope>)"
+)]
 pub async fn api_wp_subscribe(
     st: web::Data<AppState>,
     subscription: web::Json<SubscriptionInfo>,
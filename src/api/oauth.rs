@@ -0,0 +1,350 @@
+/*
+Lightpub: a simple ActivityPub server
+Copyright (C) 2025 tinaxd
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published
+by the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+/// Route handlers for /oauth
+use actix_session::Session;
+use actix_web::{
+    get,
+    http::{header, StatusCode},
+    middleware::from_fn,
+    post, web, HttpRequest, HttpResponse,
+};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use lightpub_service::services::{
+    create_error_simple,
+    oauth::{
+        create_authorization_code, exchange_authorization_code, refresh_oauth_token,
+        revoke_oauth_token, validate_authorization_request, IssuedOAuthToken, PkceChallenge,
+        OOB_REDIRECT_URI,
+    },
+    MapToUnknown, ServiceResult,
+};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    api::auth::{
+        middleware_auth_jwt_optional, middleware_auth_jwt_required_scoped, AuthedUser,
+        SessionOnlyScope,
+    },
+    template::{render_template, OAuthAuthorize, OAuthCode, Template},
+    AppState,
+};
+
+use super::mastodon::MastodonParams;
+
+/// 認可画面で発行した値をセッションに保存するキー
+const CONSENT_NONCE_SESSION_KEY: &str = "oauth_consent_nonce";
+
+#[derive(Debug, Deserialize)]
+pub struct AuthorizeQuery {
+    response_type: String,
+    client_id: String,
+    redirect_uri: String,
+    scope: Option<String>,
+    state: Option<String>,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+}
+
+#[get("/authorize", wrap = "from_fn(middleware_auth_jwt_optional)")]
+pub async fn oauth_authorize_get(
+    st: web::Data<AppState>,
+    session: Session,
+    auth: web::ReqData<AuthedUser>,
+    req: HttpRequest,
+    query: web::Query<AuthorizeQuery>,
+) -> ServiceResult<HttpResponse> {
+    if query.response_type != "code" {
+        return create_error_simple(StatusCode::BAD_REQUEST, "unsupported_response_type");
+    }
+    let (app, scopes) = validate_authorization_request(
+        st.conn(),
+        &query.client_id,
+        &query.redirect_uri,
+        query.scope.as_deref(),
+    )
+    .await?;
+    if let Some(challenge) = &query.code_challenge {
+        PkceChallenge::new(challenge, query.code_challenge_method.as_deref())?;
+    }
+
+    // ログイン後に認可画面に戻ってくる
+    if !auth.is_authed() {
+        let url = format!(
+            "/client/login?next={}",
+            urlencoding::encode(&req.uri().to_string())
+        );
+        return Ok(HttpResponse::Found()
+            .insert_header((header::LOCATION, url))
+            .finish());
+    }
+
+    let nonce = uuid::Uuid::new_v4().simple().to_string();
+    session
+        .insert(CONSENT_NONCE_SESSION_KEY, &nonce)
+        .map_err_unknown()?;
+
+    let query = query.into_inner();
+    render_template(
+        st.template(),
+        &Template::OAuthAuthorize(OAuthAuthorize {
+            app_name: app.name,
+            app_website: app.website,
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            client_id: query.client_id,
+            redirect_uri: query.redirect_uri,
+            scope: scopes.to_string(),
+            state: query.state,
+            code_challenge: query.code_challenge,
+            code_challenge_method: query.code_challenge_method,
+            nonce,
+        }),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuthorizeForm {
+    client_id: String,
+    redirect_uri: String,
+    scope: String,
+    state: Option<String>,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+    nonce: String,
+    /// "approve" または "deny"
+    decision: String,
+}
+
+fn redirect_with_params(
+    redirect_uri: &str,
+    params: &[(&str, &str)],
+) -> ServiceResult<HttpResponse> {
+    let mut url = Url::parse(redirect_uri).map_err_unknown()?;
+    url.query_pairs_mut().extend_pairs(params);
+    Ok(HttpResponse::Found()
+        .insert_header((header::LOCATION, url.as_str()))
+        .finish())
+}
+
+#[post(
+    "/authorize",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn oauth_authorize_post(
+    st: web::Data<AppState>,
+    session: Session,
+    auth: web::ReqData<AuthedUser>,
+    form: web::Form<AuthorizeForm>,
+) -> ServiceResult<HttpResponse> {
+    // 他のサイトから送信されたフォームを拒否する
+    let nonce = session
+        .get::<String>(CONSENT_NONCE_SESSION_KEY)
+        .map_err_unknown()?;
+    session.remove(CONSENT_NONCE_SESSION_KEY);
+    if nonce.as_deref() != Some(form.nonce.as_str()) {
+        return create_error_simple(StatusCode::FORBIDDEN, "invalid nonce");
+    }
+
+    let (app, scopes) = validate_authorization_request(
+        st.conn(),
+        &form.client_id,
+        &form.redirect_uri,
+        Some(&form.scope),
+    )
+    .await?;
+    let challenge = form
+        .code_challenge
+        .as_deref()
+        .map(|c| PkceChallenge::new(c, form.code_challenge_method.as_deref()))
+        .transpose()?;
+    let is_oob = form.redirect_uri == OOB_REDIRECT_URI;
+
+    if form.decision != "approve" {
+        if is_oob {
+            return create_error_simple(StatusCode::FORBIDDEN, "access_denied");
+        }
+        let mut params = vec![("error", "access_denied")];
+        if let Some(state) = &form.state {
+            params.push(("state", state));
+        }
+        return redirect_with_params(&form.redirect_uri, &params);
+    }
+
+    let code = create_authorization_code(
+        &st.rconn(),
+        &app,
+        auth.user_id_unwrap(),
+        &form.redirect_uri,
+        &scopes,
+        challenge,
+    )
+    .await?;
+
+    if is_oob {
+        return render_template(
+            st.template(),
+            &Template::OAuthCode(OAuthCode {
+                app_name: app.name,
+                code,
+            }),
+        );
+    }
+
+    let mut params = vec![("code", code.as_str())];
+    if let Some(state) = &form.state {
+        params.push(("state", state));
+    }
+    redirect_with_params(&form.redirect_uri, &params)
+}
+
+/// クライアントの ID とシークレット。
+/// パラメータの代わりに Basic 認証で送ってくるクライアントもある。
+fn client_credentials(
+    req: &HttpRequest,
+    client_id: Option<&str>,
+    client_secret: Option<&str>,
+) -> ServiceResult<(String, Option<String>)> {
+    if let Some(client_id) = client_id {
+        return Ok((client_id.to_string(), client_secret.map(|s| s.to_string())));
+    }
+
+    let basic = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Basic "))
+        .and_then(|b| BASE64_STANDARD.decode(b).ok())
+        .and_then(|b| String::from_utf8(b).ok());
+    match basic.as_deref().and_then(|b| b.split_once(':')) {
+        Some((id, secret)) => Ok((id.to_string(), Some(secret.to_string()))),
+        None => create_error_simple(StatusCode::UNAUTHORIZED, "invalid_client"),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenParams {
+    grant_type: String,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    code: Option<String>,
+    redirect_uri: Option<String>,
+    code_verifier: Option<String>,
+    refresh_token: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenResponse {
+    access_token: String,
+    token_type: &'static str,
+    scope: String,
+    created_at: i64,
+    expires_in: i64,
+    refresh_token: String,
+}
+
+impl From<IssuedOAuthToken> for TokenResponse {
+    fn from(token: IssuedOAuthToken) -> Self {
+        Self {
+            access_token: token.access_token,
+            token_type: "Bearer",
+            scope: token.scopes.to_string(),
+            created_at: token.created_at.timestamp(),
+            expires_in: token.expires_in,
+            refresh_token: token.refresh_token,
+        }
+    }
+}
+
+#[post("/token")]
+pub async fn oauth_token(
+    st: web::Data<AppState>,
+    req: HttpRequest,
+    params: MastodonParams<TokenParams>,
+) -> ServiceResult<HttpResponse> {
+    let (client_id, client_secret) = client_credentials(
+        &req,
+        params.client_id.as_deref(),
+        params.client_secret.as_deref(),
+    )?;
+
+    let token = match params.grant_type.as_str() {
+        "authorization_code" => {
+            let (Some(code), Some(redirect_uri)) = (&params.code, &params.redirect_uri) else {
+                return create_error_simple(StatusCode::BAD_REQUEST, "invalid_request");
+            };
+            exchange_authorization_code(
+                st.conn(),
+                &st.rconn(),
+                &client_id,
+                client_secret.as_deref(),
+                code,
+                redirect_uri,
+                params.code_verifier.as_deref(),
+            )
+            .await?
+        }
+        "refresh_token" => {
+            let Some(refresh_token) = &params.refresh_token else {
+                return create_error_simple(StatusCode::BAD_REQUEST, "invalid_request");
+            };
+            refresh_oauth_token(
+                st.conn(),
+                &client_id,
+                client_secret.as_deref(),
+                refresh_token,
+            )
+            .await?
+        }
+        _ => return create_error_simple(StatusCode::BAD_REQUEST, "unsupported_grant_type"),
+    };
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(TokenResponse::from(token)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevokeParams {
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    token: String,
+}
+
+#[post("/revoke")]
+pub async fn oauth_revoke(
+    st: web::Data<AppState>,
+    req: HttpRequest,
+    params: MastodonParams<RevokeParams>,
+) -> ServiceResult<HttpResponse> {
+    let (client_id, client_secret) = client_credentials(
+        &req,
+        params.client_id.as_deref(),
+        params.client_secret.as_deref(),
+    )?;
+
+    revoke_oauth_token(
+        st.conn(),
+        &client_id,
+        client_secret.as_deref(),
+        &params.token,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({})))
+}
@@ -18,7 +18,7 @@ along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use crate::middleware::strip_body;
 use crate::AppState;
-use actix_middleware_etag::Etag;
+use actix_files::NamedFile;
 use actix_web::middleware::from_fn;
 use actix_web::route;
 /// Route handlers for /upload
@@ -27,29 +27,61 @@ use actix_web::{
         header::{self, CacheControl, CacheDirective},
         StatusCode,
     },
-    web, Either, HttpResponse, Responder,
+    web, Either, HttpRequest, HttpResponse, Responder,
 };
 use lightpub_service::services::{
     id::UploadID,
-    upload::{get_upload, get_uploads_dir, GetUpload, ProxyResult},
+    storage::StorageServe,
+    upload::{get_upload, GetUpload, ProxyResult, UploadVariant},
     MapToUnknown, ServiceResult,
 };
+use serde::Deserialize;
 use tracing::debug;
+
+#[derive(Debug, Deserialize)]
+pub struct GetUploadQuery {
+    /// 縮小した画像を取得する場合に指定する
+    variant: Option<UploadVariant>,
+}
+
+/// リモートからのレスポンスのうち、そのままクライアントに返すヘッダー
+const PROXY_PASSTHROUGH_HEADERS: [header::HeaderName; 5] = [
+    header::CONTENT_LENGTH,
+    header::CONTENT_RANGE,
+    header::ACCEPT_RANGES,
+    header::ETAG,
+    header::LAST_MODIFIED,
+];
+
 #[route(
     "/upload/{upload_id}",
     method = "GET",
     method = "HEAD",
-    wrap = "from_fn(strip_body)",
-    wrap = "Etag::default()"
+    wrap = "from_fn(strip_body)"
 )]
 pub async fn api_get_upload(
     st: web::Data<AppState>,
     upload_id: web::Path<UploadID>,
+    query: web::Query<GetUploadQuery>,
+    req: HttpRequest,
 ) -> ServiceResult<Either<impl Responder, impl Responder>> {
     let upload_id = upload_id.into_inner();
     let client = st.proxy_client();
+    let range = req
+        .headers()
+        .get(header::RANGE)
+        .and_then(|r| r.to_str().ok());
 
-    let upload = get_upload(&st.maybe_conn(), upload_id, client).await?;
+    let upload = get_upload(
+        &st.maybe_conn(),
+        st.storage(),
+        st.media_cache(),
+        upload_id,
+        client,
+        query.variant,
+        range,
+    )
+    .await?;
 
     match upload {
         GetUpload::Proxy { res, cache_control } => match res {
@@ -61,34 +93,65 @@ pub async fn api_get_upload(
                 res.finish()
             })),
             ProxyResult::Success { res, content_type } => {
-                let mut r = HttpResponse::build(StatusCode::OK);
+                let mut r = HttpResponse::build(
+                    StatusCode::from_u16(res.status().as_u16()).unwrap_or(StatusCode::OK),
+                );
                 r.content_type(content_type);
                 r.insert_header(CacheControl(vec![
                     CacheDirective::Public,
                     CacheDirective::MaxAge(UPLOAD_MAX_AGE),
                 ]));
-                Ok(Either::Left(r.body(res.bytes().await.map_err_unknown()?)))
+                for name in PROXY_PASSTHROUGH_HEADERS {
+                    if let Some(value) = res
+                        .headers()
+                        .get(name.as_str())
+                        .and_then(|v| header::HeaderValue::from_bytes(v.as_bytes()).ok())
+                    {
+                        r.insert_header((name, value));
+                    }
+                }
+                Ok(Either::Left(r.streaming(res.bytes_stream())))
             }
         },
-        GetUpload::Local {
-            relative_path,
-            mime_type,
-        } => {
-            let filepath = get_uploads_dir().join(&relative_path);
-            debug!("Serving local file: {:?}", filepath);
-            let f = tokio::fs::read(filepath).await.map_err_unknown()?;
-            Ok(Either::Right(
+        GetUpload::Stored { serve, mime_type } => match serve {
+            StorageServe::File(filepath) => {
+                debug!("Serving local file: {:?}", filepath);
+                // Range リクエストや ETag は NamedFile が処理する
+                let file = NamedFile::open_async(filepath).await.map_err_unknown()?;
+                let file = match mime_type.parse() {
+                    Ok(mime) => file.set_content_type(mime),
+                    Err(_) => file,
+                };
+                Ok(Either::Right(file.customize().insert_header(CacheControl(
+                    vec![
+                        CacheDirective::Public,
+                        CacheDirective::MaxAge(UPLOAD_MAX_AGE),
+                        CacheDirective::Extension("immutable".to_string(), None),
+                    ],
+                ))))
+            }
+            StorageServe::Redirect(url) => Ok(Either::Left(
+                HttpResponse::Found()
+                    .insert_header((header::LOCATION, url.to_string()))
+                    // 署名付き URL は期限があるので長くキャッシュしない
+                    .insert_header(CacheControl(vec![
+                        CacheDirective::Public,
+                        CacheDirective::MaxAge(UPLOAD_REDIRECT_MAX_AGE),
+                    ]))
+                    .finish(),
+            )),
+            StorageServe::Data(data) => Ok(Either::Left(
                 HttpResponse::Ok()
                     .content_type(mime_type)
                     .insert_header(CacheControl(vec![
                         CacheDirective::Public,
                         CacheDirective::MaxAge(UPLOAD_MAX_AGE),
-                        CacheDirective::Extension("immutable".to_string(), None),
                     ]))
-                    .body(f),
-            ))
-        }
+                    .body(data),
+            )),
+        },
     }
 }
 
 const UPLOAD_MAX_AGE: u32 = 60 * 60 * 24 * 7;
+const UPLOAD_REDIRECT_MAX_AGE: u32 = 60 * 5;
@@ -23,12 +23,16 @@ use crate::api::pagination::{ApubCollectionQuery, ApubPaginator, URLBasedPaginat
 use crate::template::{render_template, PartsNotes, PartsUserList, Template};
 use crate::AppState;
 use activitypub_federation::{protocol::context::WithContext, traits::Object};
+use actix_files::NamedFile;
 use actix_multipart::form::text::Text as MpText;
 use actix_multipart::form::{tempfile::TempFile, MultipartForm};
-use actix_web::{get, patch, post, HttpRequest};
+use actix_web::{delete, get, patch, post, HttpRequest};
 use actix_web::{
     http::{
-        header::{CacheControl, CacheDirective, LOCATION},
+        header::{
+            CacheControl, CacheDirective, ContentDisposition, DispositionParam, DispositionType,
+            LOCATION,
+        },
         StatusCode,
     },
     web::{self, Path},
@@ -36,17 +40,26 @@ use actix_web::{
 };
 use chrono::{DateTime, Utc};
 use lightpub_service::services::note::{get_user_apub_outbox, get_user_note_count, get_user_notes};
-use lightpub_service::services::user::{block_user, unblock_user};
+use lightpub_service::services::user::{block_user, mute_user, unblock_user, unmute_user};
 use lightpub_service::services::{
+    access_token::{
+        create_personal_access_token, get_personal_access_tokens, revoke_personal_access_token,
+        PersonalAccessTokenModel,
+    },
     create_error_simple,
+    export::{get_user_export_file, get_user_exports, request_user_export},
     follow::{accept_pending_follow, follow_user, reject_pending_follow, unfollow_user},
-    id::UserID,
-    upload::{save_upload_file, save_upload_file_info},
+    id::{NoteID, UserID},
+    import::{get_user_import, get_user_imports, request_user_import, UserImportKindModel},
+    oauth::OAuthScopes,
+    report::create_report,
+    upload::{save_upload_file, save_upload_file_info, UploadKind},
     user::{
-        get_apubuser_by_id, get_user_avatar, get_user_followers, get_user_followings,
+        get_apubuser_by_id, get_user_avatar, get_user_by_id, get_user_followers,
+        get_user_followings, is_user_deleted, is_user_suspended, move_user, set_user_aliases,
         update_user_profile, UserAvatar, UserProfileUpdate,
     },
-    ServiceResult,
+    MapToUnknown, ServiceResult,
 };
 use percent_encoding::CONTROLS;
 use serde::{Deserialize, Serialize};
@@ -163,6 +176,9 @@ pub async fn api_get_user_avatar(
 pub struct UserInteractionRequest {
     #[serde(rename = "type")]
     ty: UserInteractionType,
+    /// ミュートの期限。None の場合は無期限。Mute 以外では無視される。
+    #[serde(default, rename = "expiresAt")]
+    expires_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -174,11 +190,14 @@ pub enum UserInteractionType {
     RejectFollow,
     Block,
     Unblock,
+    Mute,
+    Unmute,
 }
 use crate::api::auth::middleware_auth_jwt_required;
+use crate::api::auth::{middleware_auth_jwt_required_scoped, FollowsScope, SessionOnlyScope};
 #[post(
     "/{user_id}/interaction",
-    wrap = "from_fn(middleware_auth_jwt_required)"
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<FollowsScope>)"
 )]
 pub async fn api_user_interaction(
     st: web::Data<AppState>,
@@ -238,10 +257,32 @@ pub async fn api_user_interaction(
             .await?;
         }
         Block => {
-            block_user(&st.maybe_conn(), my_id, target_id).await?;
+            block_user(
+                st.conn(),
+                &st.rconn(),
+                st.qconn(),
+                my_id,
+                target_id,
+                st.base_url(),
+            )
+            .await?;
         }
         Unblock => {
-            unblock_user(&st.maybe_conn(), my_id, target_id).await?;
+            unblock_user(
+                st.conn(),
+                &st.rconn(),
+                st.qconn(),
+                my_id,
+                target_id,
+                st.base_url(),
+            )
+            .await?;
+        }
+        Mute => {
+            mute_user(st.conn(), &st.rconn(), my_id, target_id, req.expires_at).await?;
+        }
+        Unmute => {
+            unmute_user(st.conn(), my_id, target_id).await?;
         }
     }
 
@@ -250,6 +291,375 @@ pub async fn api_user_interaction(
         .finish())
 }
 
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserReportRequest {
+    #[serde(default)]
+    note_ids: Vec<NoteID>,
+    #[serde(default)]
+    comment: String,
+    /// リモートユーザーへの通報の場合、相手のサーバーにも転送するかどうか
+    #[serde(default)]
+    forward: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserReportResponse {
+    report_id: i32,
+}
+
+#[post("/{user_id}/report", wrap = "from_fn(middleware_auth_jwt_required)")]
+pub async fn api_user_report(
+    st: web::Data<AppState>,
+    req: web::Json<UserReportRequest>,
+    user_id: web::Path<UserID>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<impl Responder> {
+    let my_id = auth.user_id_unwrap();
+    let req = req.into_inner();
+
+    let report_id = create_report(
+        st.conn(),
+        &st.rconn(),
+        st.qconn(),
+        my_id,
+        user_id.into_inner(),
+        &req.note_ids,
+        &req.comment,
+        req.forward,
+        st.base_url(),
+    )
+    .await?;
+
+    Ok(HttpResponse::Created().json(UserReportResponse { report_id }))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserAliasesRequest {
+    /// `@user@domain` 形式または URL
+    aliases: Vec<String>,
+}
+
+#[post(
+    "/{user_id}/aliases",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn api_user_set_aliases(
+    st: web::Data<AppState>,
+    req: web::Json<UserAliasesRequest>,
+    user_id: web::Path<UserID>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<impl Responder> {
+    let my_id = auth.user_id_unwrap();
+    if my_id != user_id.into_inner() {
+        return create_error_simple(StatusCode::FORBIDDEN, "not your account");
+    }
+
+    let data = st.request_data();
+    set_user_aliases(
+        st.conn(),
+        &st.rconn(),
+        st.qconn(),
+        my_id,
+        &req.aliases,
+        &st.my_domain(),
+        st.base_url(),
+        &data,
+    )
+    .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserMoveRequest {
+    /// 移行先のアカウント。`@user@domain` 形式または URL
+    target: String,
+}
+
+#[post(
+    "/{user_id}/move",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn api_user_move(
+    st: web::Data<AppState>,
+    req: web::Json<UserMoveRequest>,
+    user_id: web::Path<UserID>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<impl Responder> {
+    let my_id = auth.user_id_unwrap();
+    if my_id != user_id.into_inner() {
+        return create_error_simple(StatusCode::FORBIDDEN, "not your account");
+    }
+
+    let data = st.request_data();
+    move_user(
+        st.conn(),
+        &st.rconn(),
+        st.qconn(),
+        st.wp(),
+        my_id,
+        &req.target,
+        &st.my_domain(),
+        st.base_url(),
+        &data,
+    )
+    .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserExportResponse {
+    export_id: i32,
+}
+
+#[post(
+    "/{user_id}/export",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn api_user_request_export(
+    st: web::Data<AppState>,
+    user_id: web::Path<UserID>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<impl Responder> {
+    let my_id = auth.user_id_unwrap();
+    if my_id != user_id.into_inner() {
+        return create_error_simple(StatusCode::FORBIDDEN, "not your account");
+    }
+
+    let export_id = request_user_export(st.conn(), st.qconn(), my_id).await?;
+
+    Ok(HttpResponse::Accepted().json(UserExportResponse { export_id }))
+}
+
+#[get(
+    "/{user_id}/export",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn api_user_list_exports(
+    st: web::Data<AppState>,
+    user_id: web::Path<UserID>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<impl Responder> {
+    let my_id = auth.user_id_unwrap();
+    if my_id != user_id.into_inner() {
+        return create_error_simple(StatusCode::FORBIDDEN, "not your account");
+    }
+
+    let exports = get_user_exports(&st.maybe_conn(), my_id).await?;
+
+    Ok(HttpResponse::Ok().json(exports))
+}
+
+#[get(
+    "/{user_id}/export/{export_id}",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn api_user_download_export(
+    st: web::Data<AppState>,
+    path: web::Path<(UserID, i32)>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<impl Responder> {
+    let (user_id, export_id) = path.into_inner();
+    let my_id = auth.user_id_unwrap();
+    if my_id != user_id {
+        return create_error_simple(StatusCode::FORBIDDEN, "not your account");
+    }
+
+    let path = get_user_export_file(&st.maybe_conn(), my_id, export_id).await?;
+    let file = NamedFile::open_async(path)
+        .await
+        .map_err_unknown()?
+        .set_content_type("application/gzip".parse().unwrap())
+        .set_content_disposition(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "lightpub-export-{}.tar.gz",
+                export_id
+            ))],
+        });
+
+    Ok(file)
+}
+
+#[derive(Debug, MultipartForm)]
+pub struct UserImportRequest {
+    kind: MpText<UserImportKindModel>,
+    #[multipart(limit = "2MB")]
+    file: TempFile,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserImportResponse {
+    import_id: i32,
+}
+
+#[post(
+    "/{user_id}/import",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn api_user_request_import(
+    st: web::Data<AppState>,
+    user_id: web::Path<UserID>,
+    auth: web::ReqData<AuthedUser>,
+    req: MultipartForm<UserImportRequest>,
+) -> ServiceResult<impl Responder> {
+    let my_id = auth.user_id_unwrap();
+    if my_id != user_id.into_inner() {
+        return create_error_simple(StatusCode::FORBIDDEN, "not your account");
+    }
+
+    let content = match std::fs::read_to_string(req.file.file.path()) {
+        Ok(c) => c,
+        Err(_) => return create_error_simple(StatusCode::BAD_REQUEST, "file is not valid UTF-8"),
+    };
+
+    let import_id = request_user_import(st.conn(), st.qconn(), my_id, req.kind.0, &content).await?;
+
+    Ok(HttpResponse::Accepted().json(UserImportResponse { import_id }))
+}
+
+#[get(
+    "/{user_id}/import",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn api_user_list_imports(
+    st: web::Data<AppState>,
+    user_id: web::Path<UserID>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<impl Responder> {
+    let my_id = auth.user_id_unwrap();
+    if my_id != user_id.into_inner() {
+        return create_error_simple(StatusCode::FORBIDDEN, "not your account");
+    }
+
+    let imports = get_user_imports(&st.maybe_conn(), my_id).await?;
+
+    Ok(HttpResponse::Ok().json(imports))
+}
+
+#[get(
+    "/{user_id}/import/{import_id}",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn api_user_get_import(
+    st: web::Data<AppState>,
+    path: web::Path<(UserID, i32)>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<impl Responder> {
+    let (user_id, import_id) = path.into_inner();
+    let my_id = auth.user_id_unwrap();
+    if my_id != user_id {
+        return create_error_simple(StatusCode::FORBIDDEN, "not your account");
+    }
+
+    let import = get_user_import(&st.maybe_conn(), my_id, import_id).await?;
+
+    Ok(HttpResponse::Ok().json(import))
+}
+
+/// スペース区切りの文字列または配列
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ScopesParam {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl ScopesParam {
+    fn to_scopes(&self) -> ServiceResult<OAuthScopes> {
+        match self {
+            ScopesParam::One(s) => OAuthScopes::parse(s),
+            ScopesParam::Many(v) => OAuthScopes::parse(&v.join(" ")),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTokenRequest {
+    name: String,
+    scopes: ScopesParam,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateTokenResponse {
+    #[serde(flatten)]
+    token: PersonalAccessTokenModel,
+    /// 発行したトークン。後から取得することはできない。
+    access_token: String,
+}
+
+#[post(
+    "/{user_id}/tokens",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn api_user_create_token(
+    st: web::Data<AppState>,
+    user_id: web::Path<UserID>,
+    auth: web::ReqData<AuthedUser>,
+    req: web::Json<CreateTokenRequest>,
+) -> ServiceResult<impl Responder> {
+    let my_id = auth.user_id_unwrap();
+    if my_id != user_id.into_inner() {
+        return create_error_simple(StatusCode::FORBIDDEN, "not your account");
+    }
+
+    let scopes = req.scopes.to_scopes()?;
+    let (token, access_token) =
+        create_personal_access_token(st.conn(), my_id, &req.name, &scopes).await?;
+
+    Ok(HttpResponse::Created().json(CreateTokenResponse {
+        token,
+        access_token,
+    }))
+}
+
+#[get(
+    "/{user_id}/tokens",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn api_user_list_tokens(
+    st: web::Data<AppState>,
+    user_id: web::Path<UserID>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<impl Responder> {
+    let my_id = auth.user_id_unwrap();
+    if my_id != user_id.into_inner() {
+        return create_error_simple(StatusCode::FORBIDDEN, "not your account");
+    }
+
+    let tokens = get_personal_access_tokens(&st.maybe_conn(), my_id).await?;
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+#[delete(
+    "/{user_id}/tokens/{token_id}",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
+pub async fn api_user_revoke_token(
+    st: web::Data<AppState>,
+    path: web::Path<(UserID, i32)>,
+    auth: web::ReqData<AuthedUser>,
+) -> ServiceResult<impl Responder> {
+    let (user_id, token_id) = path.into_inner();
+    let my_id = auth.user_id_unwrap();
+    if my_id != user_id {
+        return create_error_simple(StatusCode::FORBIDDEN, "not your account");
+    }
+
+    revoke_personal_access_token(st.conn(), my_id, token_id).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
 #[derive(Debug, MultipartForm)]
 pub struct UserProfilePatch {
     #[multipart(limit = "10MB")]
@@ -264,7 +674,10 @@ pub struct UserProfilePatch {
     hide_follows: Option<MpText<FormBool>>,
 }
 
-#[patch("/{user_id}/edit", wrap = "from_fn(middleware_auth_jwt_required)")]
+#[patch(
+    "/{user_id}/edit",
+    wrap = "from_fn(middleware_auth_jwt_required_scoped::<SessionOnlyScope>)"
+)]
 pub async fn api_user_profile_update(
     st: web::Data<AppState>,
     auth: web::ReqData<AuthedUser>,
@@ -273,13 +686,15 @@ pub async fn api_user_profile_update(
     let user_id = auth.user_id_unwrap();
 
     let avatar_upload_id = if let Some(avatar) = req.avatar.take() {
-        let (upload_id, filename, mime_type) = save_upload_file(avatar.file)?;
+        let (upload_id, filename, mime_type, metadata) =
+            save_upload_file(st.storage(), avatar.file, &[UploadKind::Image]).await?;
         // mime_type is already verified
         save_upload_file_info(
             &st.maybe_conn(),
             &upload_id,
             &filename,
             &mime_type.to_string(),
+            &metadata,
         )
         .await?;
         Some(upload_id)
@@ -428,11 +843,23 @@ pub async fn api_get_user(
     request_type: RequestType,
     user_id: Path<UserID>,
 ) -> ServiceResult<impl Responder> {
+    let user_id = user_id.into_inner();
+
+    // 凍結または削除されたローカルユーザーは 410 Gone を返す
+    let is_local = get_user_by_id(&st.maybe_conn(), &st.rconn(), user_id)
+        .await?
+        .is_some_and(|u| u.is_local());
+    if is_local
+        && (is_user_suspended(&st.maybe_conn(), user_id).await?
+            || is_user_deleted(&st.maybe_conn(), user_id).await?)
+    {
+        return Ok(HttpResponse::Gone().finish());
+    }
+
     match request_type {
         RequestType::APUB => {
             let data = st.request_data();
-            let user =
-                get_apubuser_by_id(&st.maybe_conn(), user_id.into_inner(), st.base_url()).await?;
+            let user = get_apubuser_by_id(&st.maybe_conn(), user_id, st.base_url()).await?;
 
             if let Some(user) = user {
                 let user_json = user.into_json(&data).await?;
@@ -442,7 +869,7 @@ pub async fn api_get_user(
             }
         }
         RequestType::HTML => Ok(HttpResponse::SeeOther()
-            .insert_header(("location", format!("/client/user/{}", user_id.into_inner())))
+            .insert_header(("location", format!("/client/user/{}", user_id)))
             .finish()),
         _ => Ok(HttpResponse::NotAcceptable().finish()),
     }
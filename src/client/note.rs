@@ -93,6 +93,21 @@ pub async fn client_note_liked_list(
     render_template(st.template(), &temp)
 }
 
+#[get(
+    "/note/{note_id}/reactions",
+    wrap = "from_fn(middleware_auth_jwt_optional)"
+)]
+pub async fn client_note_reactions_list(
+    st: web::Data<AppState>,
+    note_id: web::Path<NoteID>,
+) -> ServiceResult<impl Responder> {
+    let temp = Template::UserList(UserList {
+        url: format!("/note/{}/reactions", note_id),
+        title: "リアクション一覧".to_owned(),
+    });
+    render_template(st.template(), &temp)
+}
+
 #[get(
     "/note/{note_id}/mentions",
     wrap = "from_fn(middleware_auth_jwt_optional)"
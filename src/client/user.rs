@@ -17,21 +17,27 @@ along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
 use actix_web::{get, http::StatusCode, web, Responder};
+use serde::Deserialize;
 use url::Url;
 
 use crate::{
-    api::auth::AuthedUser,
+    api::auth::{is_safe_redirect_path, AuthedUser},
     template::{
-        render_template, Profile, ProfileEdit, ProfileEditBasic, ProfileEditUser, ProfileOg,
+        render_template, Login, Profile, ProfileEdit, ProfileEditBasic, ProfileEditUser, ProfileOg,
         ProfileUser, ProfileUserBasic, Template, UserList,
     },
     AppState,
 };
 use lightpub_service::{
     services::{
+        access_token::get_personal_access_tokens,
         create_error_simple, create_error_simple_err,
+        export::get_user_exports,
         follow::FollowState,
+        import::get_user_imports,
+        two_factor::{count_remaining_recovery_codes, is_two_factor_enabled},
         user::{get_user_by_id, get_user_id_from_spec, get_user_profile, UserSpecifier},
+        webauthn::get_passkeys,
         ServiceResult,
     },
     utils::sanitize::CleanString,
@@ -48,9 +54,21 @@ pub async fn client_register_user(st: web::Data<AppState>) -> ServiceResult<impl
     render_template(st.template(), &Template::Register(()))
 }
 
+#[derive(Debug, Deserialize)]
+pub struct LoginQuery {
+    next: Option<String>,
+}
+
 #[get("/login")]
-pub async fn client_login_user(st: web::Data<AppState>) -> ServiceResult<impl Responder> {
-    render_template(st.template(), &Template::Login(()))
+pub async fn client_login_user(
+    st: web::Data<AppState>,
+    query: web::Query<LoginQuery>,
+) -> ServiceResult<impl Responder> {
+    let next = query
+        .into_inner()
+        .next
+        .filter(|next| is_safe_redirect_path(next));
+    render_template(st.template(), &Template::Login(Login { next }))
 }
 
 #[get(
@@ -160,11 +178,13 @@ pub async fn client_get_profile(
                     can_accept_follow: profile.is_followed.map(|f| f == FollowState::Pending),
                     can_refuse_follow: profile.is_followed.map(|f| f != FollowState::No),
                     is_blocked: Some(blocked),
+                    is_muting: profile.is_muting,
 
                     view_url: profile
                         .view_url
                         .map(|u| Url::parse(&u).expect("view_url should be valid")),
                     is_me: profile.is_me.into(),
+                    moved_to: profile.moved_to.map(|u| u.specifier),
                 }
             },
         }),
@@ -198,6 +218,19 @@ pub async fn client_edit_profile_get(
         None => return create_error_simple(StatusCode::NOT_FOUND, "user not found"),
     };
 
+    let (exports, imports, tokens, two_factor_enabled, remaining_recovery_codes, passkeys) =
+        match viewer_id {
+            Some(viewer_id) if viewer_id == profile.basic.id => (
+                get_user_exports(&st.maybe_conn(), viewer_id).await?,
+                get_user_imports(&st.maybe_conn(), viewer_id).await?,
+                get_personal_access_tokens(&st.maybe_conn(), viewer_id).await?,
+                is_two_factor_enabled(&st.maybe_conn(), viewer_id).await?,
+                count_remaining_recovery_codes(&st.maybe_conn(), viewer_id).await?,
+                get_passkeys(&st.maybe_conn(), viewer_id).await?,
+            ),
+            _ => (vec![], vec![], vec![], false, 0, vec![]),
+        };
+
     let edit_data = Template::ProfileEdit(ProfileEdit {
         user: ProfileEditUser {
             basic: ProfileEditBasic {
@@ -208,6 +241,12 @@ pub async fn client_edit_profile_get(
             auto_follow_accept: profile.auto_follow_accept,
             hide_follows: profile.hide_follows,
         },
+        exports,
+        imports,
+        tokens,
+        two_factor_enabled,
+        remaining_recovery_codes,
+        passkeys,
     });
 
     render_template(st.template(), &edit_data)
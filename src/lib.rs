@@ -29,8 +29,10 @@ use lightpub_service::{
         db::{Conn, MaybeTxConn},
         fulltext::FTClient,
         kv::KVObject,
+        media_cache::MediaCacheConfig,
         notification::push::WPClient,
         queue::QConn,
+        storage::StorageObject,
     },
     MyFederationData, ServiceState,
 };
@@ -119,6 +121,14 @@ impl AppState {
         self.service.wp()
     }
 
+    pub fn storage(&self) -> &StorageObject {
+        self.service.storage()
+    }
+
+    pub fn media_cache(&self) -> &MediaCacheConfig {
+        self.service.media_cache()
+    }
+
     pub fn request_data(&self) -> Data<MyFederationData> {
         self.service.fed().to_request_data()
     }
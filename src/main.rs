@@ -19,7 +19,7 @@ along with this program.  If not, see <https://www.gnu.org/licenses/>.
 use std::{fs::File, path::PathBuf, str::FromStr, sync::Arc, time::Duration};
 
 use activitypub_federation::config::FederationConfig;
-use actix_multipart::form::tempfile::TempFileConfig;
+use actix_multipart::form::{tempfile::TempFileConfig, MultipartFormConfig};
 use actix_session::{storage::RedisSessionStore, SessionMiddleware};
 use actix_web::{
     cookie::Key,
@@ -29,34 +29,79 @@ use actix_web::{
 use lightpub_rs::{
     api::{
         self,
-        admin::admin_api_rebuild_note_fulltext,
-        auth::{api_change_password, api_login_user, api_logout_user, api_register_user},
+        admin::{
+            admin_api_add_domain_policy, admin_api_add_emoji, admin_api_dismiss_report,
+            admin_api_list_domain_policies, admin_api_list_emojis, admin_api_list_reports,
+            admin_api_rebuild_note_fulltext, admin_api_remove_domain_policy,
+            admin_api_remove_emoji, admin_api_resolve_report, admin_api_set_user_state,
+        },
+        auth::{
+            api_change_password, api_delete_account, api_login_two_factor,
+            api_login_two_factor_passkey, api_login_two_factor_passkey_options, api_login_user,
+            api_logout_user, api_passkey_delete, api_passkey_login, api_passkey_login_options,
+            api_passkey_register, api_passkey_register_options, api_register_user,
+            api_totp_confirm, api_totp_disable, api_totp_setup,
+        },
+        mastodon::{
+            accounts::{
+                mastodon_block_account, mastodon_follow_account, mastodon_get_account,
+                mastodon_get_account_followers, mastodon_get_account_following,
+                mastodon_get_account_statuses, mastodon_get_relationships, mastodon_lookup_account,
+                mastodon_mute_account, mastodon_unblock_account, mastodon_unfollow_account,
+                mastodon_unmute_account, mastodon_verify_credentials,
+            },
+            apps::mastodon_create_app,
+            favourites::{mastodon_get_bookmarks, mastodon_get_favourites},
+            instance::{
+                mastodon_get_custom_emojis, mastodon_get_instance_v1, mastodon_get_instance_v2,
+            },
+            media::{
+                mastodon_get_media, mastodon_update_media, mastodon_upload_media,
+                mastodon_upload_media_v2,
+            },
+            notifications::{mastodon_get_notification, mastodon_get_notifications},
+            search::mastodon_search,
+            statuses::{
+                mastodon_bookmark_status, mastodon_create_status, mastodon_delete_status,
+                mastodon_favourite_status, mastodon_get_poll, mastodon_get_status,
+                mastodon_get_status_context, mastodon_get_status_favourited_by,
+                mastodon_get_status_reblogged_by, mastodon_reblog_status,
+                mastodon_unbookmark_status, mastodon_unfavourite_status, mastodon_unreblog_status,
+                mastodon_vote_poll,
+            },
+            timelines::{mastodon_home_timeline, mastodon_public_timeline},
+        },
         note::{
             api_create_note, api_create_renote, api_edit_note_view, api_get_note,
-            api_get_note_like_users, api_get_note_mentions_users, api_get_note_renote_users,
-            api_note_add_bookmark, api_note_add_like, api_note_delete,
-            api_note_delete_by_renote_target_id, api_note_patch, api_note_remove_bookmark,
-            api_note_remove_like,
+            api_get_note_like_users, api_get_note_mentions_users, api_get_note_reaction_users,
+            api_get_note_renote_users, api_note_add_bookmark, api_note_add_like,
+            api_note_add_reaction, api_note_delete, api_note_delete_by_renote_target_id,
+            api_note_patch, api_note_poll_vote, api_note_remove_bookmark, api_note_remove_like,
+            api_note_remove_reaction, api_note_update_upload,
         },
         notifications::{
             api_get_notifications, api_read_all_notifications, api_read_notification,
             api_unread_notification_count, api_wp_public_key, api_wp_subscribe,
         },
+        oauth::{oauth_authorize_get, oauth_authorize_post, oauth_revoke, oauth_token},
         search::api_search,
         serve_sw_js,
         timeline::api_get_timeline,
         trends::api_get_trends,
         upload::api_get_upload,
         user::{
-            api_get_user, api_get_user_avatar, api_get_user_notes, api_user_followers_list,
-            api_user_followings_list, api_user_inbox, api_user_interaction, api_user_outbox,
-            api_user_profile_update,
+            api_get_user, api_get_user_avatar, api_get_user_notes, api_user_create_token,
+            api_user_download_export, api_user_followers_list, api_user_followings_list,
+            api_user_get_import, api_user_inbox, api_user_interaction, api_user_list_exports,
+            api_user_list_imports, api_user_list_tokens, api_user_move, api_user_outbox,
+            api_user_profile_update, api_user_report, api_user_request_export,
+            api_user_request_import, api_user_revoke_token, api_user_set_aliases,
         },
     },
     client::{
         note::{
             client_get_note, client_note_liked_list, client_note_mentions_list,
-            client_note_renotes_list,
+            client_note_reactions_list, client_note_renotes_list,
         },
         notification::client_notification_get,
         search::client_get_search,
@@ -72,10 +117,15 @@ use lightpub_rs::{
 };
 use lightpub_service::{
     services::{
-        db::{Conn, RedisConn},
+        db::{Conn, MaybeTxConn, RedisConn},
         fulltext::FTClient,
+        media_cache::{evict_remote_media_cache, MediaCacheConfig},
+        note::close_expired_polls,
         notification::push::WPClient,
-        queue::{ApubWorker, QConn},
+        pending_upload::delete_expired_pending_uploads,
+        queue::{ApubWorker, JobWorker, QConn},
+        storage::{migrate_upload_storage, LocalStorage, S3Config, S3Storage, StorageObject},
+        upload::{get_uploads_dir, MAX_UPLOAD_REQUEST_SIZE},
     },
     ServiceState, ServiceStateBase,
 };
@@ -109,6 +159,64 @@ fn get_tmp_dirs() -> TmpDirConfig {
     cfg
 }
 
+/// UPLOAD_STORAGE に応じてアップロードの保存先を作成する。
+fn create_upload_storage() -> StorageObject {
+    match std::env::var("UPLOAD_STORAGE").as_deref() {
+        Ok("s3") => {
+            let config = S3Config {
+                endpoint: std::env::var("S3_ENDPOINT")
+                    .expect("S3_ENDPOINT is set")
+                    .parse()
+                    .expect("S3_ENDPOINT should be a valid URL"),
+                bucket: std::env::var("S3_BUCKET").expect("S3_BUCKET is set"),
+                region: std::env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+                access_key: std::env::var("S3_ACCESS_KEY").expect("S3_ACCESS_KEY is set"),
+                secret_key: std::env::var("S3_SECRET_KEY").expect("S3_SECRET_KEY is set"),
+                path_style: std::env::var("S3_PATH_STYLE").is_ok_and(|x| x == "true"),
+                public_url: std::env::var("S3_PUBLIC_URL")
+                    .ok()
+                    .filter(|u| !u.trim().is_empty())
+                    .map(|u| u.parse().expect("S3_PUBLIC_URL should be a valid URL")),
+                presign_expires: std::env::var("S3_PRESIGN_EXPIRES")
+                    .map(|n| n.parse::<u64>().expect("S3_PRESIGN_EXPIRES is a number"))
+                    .unwrap_or(3600),
+            };
+            info!("Uploads are stored in S3: {:?}", config);
+            Arc::new(S3Storage::new(config))
+        }
+        Ok("local") | Err(_) => {
+            let dir = get_uploads_dir();
+            info!("Uploads are stored in {}", dir.display());
+            Arc::new(LocalStorage::new(dir))
+        }
+        Ok(other) => panic!("unknown UPLOAD_STORAGE: {}", other),
+    }
+}
+
+/// MEDIA_CACHE_* からリモートのメディアのキャッシュの設定を読み込む。
+fn create_media_cache_config() -> MediaCacheConfig {
+    let mut config = MediaCacheConfig {
+        enabled: std::env::var("MEDIA_CACHE_ENABLED").is_ok_and(|x| x == "true"),
+        eager: std::env::var("MEDIA_CACHE_EAGER").is_ok_and(|x| x == "true"),
+        ..Default::default()
+    };
+    if let Ok(quota) = std::env::var("MEDIA_CACHE_QUOTA_MB") {
+        let quota = quota
+            .parse::<u64>()
+            .expect("MEDIA_CACHE_QUOTA_MB is a number");
+        config.quota = quota * 1024 * 1024;
+    }
+    if let Ok(days) = std::env::var("MEDIA_CACHE_TTL_DAYS") {
+        let days = days
+            .parse::<i64>()
+            .expect("MEDIA_CACHE_TTL_DAYS is a number");
+        // 0 の場合は期限なし
+        config.ttl = (days > 0).then(|| chrono::TimeDelta::days(days));
+    }
+    info!("Remote media cache: {:?}", config);
+    config
+}
+
 #[derive(Debug, Default)]
 struct TmpDirConfig {
     upload_tmp: Option<PathBuf>,
@@ -179,6 +287,27 @@ async fn main() {
         .await
         .expect("migration failed");
 
+    // upload storage
+    let storage = create_upload_storage();
+    let media_cache = create_media_cache_config();
+
+    // `migrate-uploads [--remove-source]`: ローカルのアップロードを設定されたストレージに移動する
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.get(1).map(|a| a.as_str()) == Some("migrate-uploads") {
+        let remove_source = args.iter().any(|a| a == "--remove-source");
+        let local: StorageObject = Arc::new(LocalStorage::new(get_uploads_dir()));
+        let moved = migrate_upload_storage(
+            &MaybeTxConn::Conn(conn.clone()),
+            &local,
+            &storage,
+            remove_source,
+        )
+        .await
+        .expect("failed to migrate uploads");
+        info!("moved {} uploads", moved);
+        return;
+    }
+
     // typesense
     let typesense_url = std::env::var("TYPESENSE_URL");
     let ft_client = match typesense_url {
@@ -279,6 +408,8 @@ async fn main() {
         client,
         ft_client,
         wp_client,
+        storage,
+        media_cache,
     );
 
     // activitypub federation config
@@ -322,6 +453,92 @@ async fn main() {
         }
     });
 
+    // Run background job worker
+    let job_worker = JobWorker::new(nats_conn.clone());
+    let job_worker_handle = tokio::spawn({
+        let worker_cancel = worker_cancel.clone();
+        let fed_data = state.request_data();
+        async move {
+            job_worker.start(&fed_data, &worker_cancel).await.unwrap();
+        }
+    });
+
+    // Close expired polls periodically
+    let poll_closer_handle = tokio::spawn({
+        let worker_cancel = worker_cancel.clone();
+        let state = state.clone();
+        async move {
+            let fed_data = state.request_data();
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+            loop {
+                tokio::select! {
+                    _ = worker_cancel.cancelled() => break,
+                    _ = interval.tick() => {
+                        if let Err(e) = close_expired_polls(
+                            state.conn(),
+                            state.qconn(),
+                            state.base_url(),
+                            &fed_data,
+                        )
+                        .await
+                        {
+                            tracing::warn!("failed to close expired polls: {:?}", e);
+                        }
+                    }
+                }
+            }
+        }
+    });
+
+    // Evict remote media cache periodically
+    let media_cache_evictor_handle = tokio::spawn({
+        let worker_cancel = worker_cancel.clone();
+        let state = state.clone();
+        async move {
+            if !state.media_cache().enabled {
+                return;
+            }
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(600));
+            loop {
+                tokio::select! {
+                    _ = worker_cancel.cancelled() => break,
+                    _ = interval.tick() => {
+                        if let Err(e) = evict_remote_media_cache(
+                            &state.maybe_conn(),
+                            state.storage(),
+                            state.media_cache(),
+                        )
+                        .await
+                        {
+                            tracing::warn!("failed to evict remote media cache: {:?}", e);
+                        }
+                    }
+                }
+            }
+        }
+    });
+
+    // Delete uploads that were never attached to a note periodically
+    let pending_upload_cleaner_handle = tokio::spawn({
+        let worker_cancel = worker_cancel.clone();
+        let state = state.clone();
+        async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(600));
+            loop {
+                tokio::select! {
+                    _ = worker_cancel.cancelled() => break,
+                    _ = interval.tick() => {
+                        if let Err(e) =
+                            delete_expired_pending_uploads(state.conn(), state.storage()).await
+                        {
+                            tracing::warn!("failed to delete expired pending uploads: {:?}", e);
+                        }
+                    }
+                }
+            }
+        }
+    });
+
     // run our app with hyper, listening globally on port 3000
     let mut server = HttpServer::new(move || {
         // tempfile config
@@ -333,12 +550,34 @@ async fn main() {
         App::new()
             .service(web::redirect("/", "/client/timeline"))
             .service(admin_api_rebuild_note_fulltext)
+            .service(admin_api_list_domain_policies)
+            .service(admin_api_add_domain_policy)
+            .service(admin_api_remove_domain_policy)
+            .service(admin_api_list_reports)
+            .service(admin_api_resolve_report)
+            .service(admin_api_dismiss_report)
+            .service(admin_api_set_user_state)
+            .service(admin_api_list_emojis)
+            .service(admin_api_add_emoji)
+            .service(admin_api_remove_emoji)
             .service(
                 web::scope("/auth")
                     .service(api_register_user)
                     .service(api_login_user)
+                    .service(api_login_two_factor)
+                    .service(api_login_two_factor_passkey_options)
+                    .service(api_login_two_factor_passkey)
+                    .service(api_passkey_login_options)
+                    .service(api_passkey_login)
                     .service(api_logout_user)
-                    .service(api_change_password),
+                    .service(api_change_password)
+                    .service(api_delete_account)
+                    .service(api_totp_setup)
+                    .service(api_totp_confirm)
+                    .service(api_totp_disable)
+                    .service(api_passkey_register_options)
+                    .service(api_passkey_register)
+                    .service(api_passkey_delete),
             )
             .service(
                 web::scope("/note")
@@ -349,6 +588,11 @@ async fn main() {
                     .service(api_note_patch)
                     .service(api_edit_note_view)
                     .service(api_note_add_like)
+                    .service(api_note_poll_vote)
+                    .service(api_note_add_reaction)
+                    .service(api_note_remove_reaction)
+                    .service(api_note_update_upload)
+                    .service(api_get_note_reaction_users)
                     .service(api_note_remove_like)
                     .service(api_note_add_bookmark)
                     .service(api_note_remove_bookmark)
@@ -363,6 +607,18 @@ async fn main() {
                     .service(api_get_user_avatar)
                     .service(api_get_user_notes)
                     .service(api_user_interaction)
+                    .service(api_user_report)
+                    .service(api_user_set_aliases)
+                    .service(api_user_move)
+                    .service(api_user_request_export)
+                    .service(api_user_list_exports)
+                    .service(api_user_download_export)
+                    .service(api_user_request_import)
+                    .service(api_user_list_imports)
+                    .service(api_user_get_import)
+                    .service(api_user_create_token)
+                    .service(api_user_list_tokens)
+                    .service(api_user_revoke_token)
                     .service(api_user_profile_update)
                     .service(api_user_followers_list)
                     .service(api_user_followings_list)
@@ -396,10 +652,67 @@ async fn main() {
                     .service(client_get_note)
                     .service(client_note_renotes_list)
                     .service(client_note_liked_list)
+                    .service(client_note_reactions_list)
                     .service(client_note_mentions_list)
                     .service(client_notification_get)
                     .service(client_get_search),
             )
+            .service(
+                web::scope("/oauth")
+                    .service(oauth_authorize_get)
+                    .service(oauth_authorize_post)
+                    .service(oauth_token)
+                    .service(oauth_revoke),
+            )
+            .service(
+                web::scope("/api/v1")
+                    // 固定のパスは /accounts/{user_id} より先に登録する
+                    .service(mastodon_verify_credentials)
+                    .service(mastodon_lookup_account)
+                    .service(mastodon_get_relationships)
+                    .service(mastodon_get_account)
+                    .service(mastodon_get_account_statuses)
+                    .service(mastodon_get_account_followers)
+                    .service(mastodon_get_account_following)
+                    .service(mastodon_follow_account)
+                    .service(mastodon_unfollow_account)
+                    .service(mastodon_block_account)
+                    .service(mastodon_unblock_account)
+                    .service(mastodon_mute_account)
+                    .service(mastodon_unmute_account)
+                    .service(mastodon_create_app)
+                    .service(mastodon_create_status)
+                    .service(mastodon_get_status)
+                    .service(mastodon_delete_status)
+                    .service(mastodon_get_status_context)
+                    .service(mastodon_favourite_status)
+                    .service(mastodon_unfavourite_status)
+                    .service(mastodon_bookmark_status)
+                    .service(mastodon_unbookmark_status)
+                    .service(mastodon_reblog_status)
+                    .service(mastodon_unreblog_status)
+                    .service(mastodon_get_status_favourited_by)
+                    .service(mastodon_get_status_reblogged_by)
+                    .service(mastodon_get_poll)
+                    .service(mastodon_vote_poll)
+                    .service(mastodon_home_timeline)
+                    .service(mastodon_public_timeline)
+                    .service(mastodon_get_notifications)
+                    .service(mastodon_get_notification)
+                    .service(mastodon_upload_media)
+                    .service(mastodon_get_media)
+                    .service(mastodon_update_media)
+                    .service(mastodon_get_favourites)
+                    .service(mastodon_get_bookmarks)
+                    .service(mastodon_get_instance_v1)
+                    .service(mastodon_get_custom_emojis),
+            )
+            .service(
+                web::scope("/api/v2")
+                    .service(mastodon_upload_media_v2)
+                    .service(mastodon_search)
+                    .service(mastodon_get_instance_v2),
+            )
             .route("/healthcheck", web::get().to(|| async { "OK" }))
             .service(api::federation::webfinger)
             .service(api::federation::api_shared_inbox)
@@ -419,6 +732,7 @@ async fn main() {
             .wrap(Logger::default())
             .app_data(web::Data::new(state.clone()))
             .app_data(web::Data::new(tempfile_config))
+            .app_data(MultipartFormConfig::default().total_limit(MAX_UPLOAD_REQUEST_SIZE))
     });
 
     if let Some(num_workers) = num_workers {
@@ -429,4 +743,8 @@ async fn main() {
 
     worker_cancel.cancel();
     worker_handle.await.unwrap();
+    job_worker_handle.await.unwrap();
+    poll_closer_handle.await.unwrap();
+    media_cache_evictor_handle.await.unwrap();
+    pending_upload_cleaner_handle.await.unwrap();
 }
@@ -3,8 +3,12 @@ use chrono::{DateTime, Utc};
 use handlebars::Handlebars;
 use lightpub_service::{
     services::{
-        id::{NoteID, NotificationID, UserID},
+        access_token::PersonalAccessTokenModel,
+        export::UserExportModel,
+        id::{NoteID, NotificationID, UploadID, UserID},
+        import::UserImportModel,
         note::{ContentType, VisibilityModel},
+        webauthn::PasskeyModel,
         MapToUnknown,
     },
     utils::sanitize::CleanString,
@@ -17,9 +21,11 @@ use url::Url;
 #[derive(Debug, Clone)]
 pub enum Template {
     Register(()),
-    Login(()),
+    Login(Login),
     NoteDetails(NoteDetails),
     Notification(()),
+    OAuthAuthorize(OAuthAuthorize),
+    OAuthCode(OAuthCode),
     PasswordChange(()),
     Profile(Profile),
     ProfileEdit(ProfileEdit),
@@ -41,6 +47,7 @@ pub enum Template {
     PartsNotifyFollowRequested(NotifyFollowRequested),
     PartsNotifyFollowed(NotifyFollowed),
     PartsNotifyMentioned(NotifyMentioned),
+    PartsNotifyQuoted(NotifyQuoted),
     PartsNotifyRenoted(NotifyRenoted),
     PartsNotifyReplied(NotifyReplied),
 }
@@ -53,6 +60,8 @@ impl Template {
             Login(_) => "login",
             NoteDetails(_) => "note_details",
             Notification(_) => "notification",
+            OAuthAuthorize(_) => "oauth_authorize",
+            OAuthCode(_) => "oauth_code",
             PasswordChange(_) => "password_change",
             Profile(_) => "profile",
             ProfileEdit(_) => "profile_edit",
@@ -74,6 +83,7 @@ impl Template {
             PartsNotifyFollowRequested(_) => "parts/notify/follow_requested",
             PartsNotifyFollowed(_) => "parts/notify/followed",
             PartsNotifyMentioned(_) => "parts/notify/mentioned",
+            PartsNotifyQuoted(_) => "parts/notify/quoted",
             PartsNotifyRenoted(_) => "parts/notify/renoted",
             PartsNotifyReplied(_) => "parts/notify/replied",
         }
@@ -85,6 +95,8 @@ impl Template {
             Template::Login(s) => Self::process(s),
             Template::NoteDetails(s) => Self::process(s),
             Template::Notification(s) => Self::process(s),
+            Template::OAuthAuthorize(s) => Self::process(s),
+            Template::OAuthCode(s) => Self::process(s),
             Template::PasswordChange(s) => Self::process(s),
             Template::Profile(s) => Self::process(s),
             Template::ProfileEdit(s) => Self::process(s),
@@ -106,6 +118,7 @@ impl Template {
             Template::PartsNotifyFollowRequested(s) => Self::process(s),
             Template::PartsNotifyFollowed(s) => Self::process(s),
             Template::PartsNotifyMentioned(s) => Self::process(s),
+            Template::PartsNotifyQuoted(s) => Self::process(s),
             Template::PartsNotifyRenoted(s) => Self::process(s),
             Template::PartsNotifyReplied(s) => Self::process(s),
         }
@@ -163,9 +176,34 @@ nest! {
                 pub content: CleanString,
             }>,
             pub visibility: VisibilityModel,
-            pub uploads: Option<Vec<String>>,
+            pub uploads: Option<Vec<pub struct PartsNoteUpload {
+                pub id: UploadID,
+                pub url: String,
+                /// タイムライン表示用の縮小画像の URL
+                pub preview_url: String,
+                /// 小さなサムネイル画像の URL
+                pub thumbnail_url: String,
+                pub mime_type: String,
+                /// "image", "video", "audio" のいずれか
+                pub kind: &'static str,
+                /// 動画のサムネイルの URL
+                pub poster_url: Option<String>,
+                /// 動画・音声の長さ (例: "1:23")
+                pub duration: Option<String>,
+                /// 説明文 (代替テキスト)
+                pub description: Option<String>,
+                pub width: Option<u32>,
+                pub height: Option<u32>,
+                pub blurhash: Option<String>,
+                pub focal_x: f32,
+                pub focal_y: f32,
+                /// フォーカルポイントに対応する CSS の object-position
+                pub object_position: Option<String>,
+            }>>,
             pub created_at: DateTime<Utc>,
             pub sensitive: bool,
+            /// コンテンツ警告 (CW) の文言
+            pub summary: Option<String>,
             pub reply_to_id: Option<NoteID>,
             pub renote_of_id: Option<NoteID>,
             pub is_my_note: bool,
@@ -180,6 +218,36 @@ nest! {
             pub bookmarked: bool,
 
             pub view_url: Option<Url>,
+            pub poll: Option<pub struct PartsNotePoll {
+                pub multiple: bool,
+                pub expires_at: Option<DateTime<Utc>>,
+                pub closed: bool,
+                pub voters_count: u64,
+                /// 投票フォームの代わりに結果を表示するか
+                pub show_results: bool,
+                pub options: Vec<pub struct PartsNotePollOption {
+                    pub index: usize,
+                    pub title: String,
+                    pub votes_count: u64,
+                    pub voted: bool,
+                }>,
+            }>,
+            pub reactions: Vec<pub struct PartsNoteReaction {
+                pub reaction: String,
+                pub count: u64,
+                /// カスタム絵文字の場合の画像
+                pub image_url: Option<Url>,
+                pub reacted: bool,
+            }>,
+            /// クイックリアクション用の Unicode 絵文字
+            pub quick_reactions: Vec<&'static str>,
+            /// 引用元ノート
+            pub quote: Option<pub struct PartsNoteQuote {
+                pub id: NoteID,
+                pub author: NoteAuthorData,
+                pub content: Option<NoteContentData>,
+                pub created_at: DateTime<Utc>,
+            }>,
         },
         #[serde(flatten)]
         pub authed: Authed,
@@ -204,7 +272,8 @@ nest! {
             pub content: pub struct EditNoteContentData {
                 pub content: String,
                 pub content_type: ContentType,
-            }
+            },
+            pub uploads: Vec<PartsNoteUpload>,
         }
     }
 }
@@ -274,6 +343,7 @@ nest! {
             pub is_following: Option<bool>,
             pub is_followed: Option<bool>,
             pub is_blocked: Option<bool>,
+            pub is_muting: Option<bool>,
             pub is_following_requested: Option<bool>,
             pub is_followed_requested: Option<bool>,
 
@@ -283,6 +353,8 @@ nest! {
             pub can_refuse_follow: Option<bool>,
 
             pub view_url: Option<Url>,
+            /// 移行先ユーザーの specifier
+            pub moved_to: Option<String>,
         }
     }
 }
@@ -299,7 +371,14 @@ nest! {
             },
             pub auto_follow_accept: bool,
             pub hide_follows: bool,
-        }
+        },
+        pub exports: Vec<UserExportModel>,
+        pub imports: Vec<UserImportModel>,
+        pub tokens: Vec<PersonalAccessTokenModel>,
+        pub two_factor_enabled: bool,
+        /// 未使用のリカバリーコードの数
+        pub remaining_recovery_codes: u64,
+        pub passkeys: Vec<PasskeyModel>,
     }
 }
 
@@ -311,6 +390,38 @@ pub struct Timeline {
     pub timeline_url: String,
 }
 
+#[derive(Debug, Clone, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Login {
+    /// ログイン後に移動するパス
+    pub next: Option<String>,
+}
+
+/// OAuth の認可画面
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OAuthAuthorize {
+    pub app_name: String,
+    pub app_website: Option<String>,
+    pub scopes: Vec<String>,
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub scope: String,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    /// CSRF 対策のためにセッションに保存した値
+    pub nonce: String,
+}
+
+/// redirect_uri を使わない場合に認可コードを表示する画面
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OAuthCode {
+    pub app_name: String,
+    pub code: String,
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct UserList {
@@ -396,6 +507,23 @@ nest! {
     }
 }
 
+nest! {
+    #[derive(Debug, Clone, Serialize)]*
+    #[serde(rename_all = "camelCase")]*
+    pub struct NotifyQuoted {
+        pub notify: pub struct NotifyQuotedBody{
+            pub data: pub struct NotifyQuotedBodyData {
+                pub author_url: String,
+                pub author_nickname: String,
+                pub quote_note_url: String,
+                pub quoted_note_url: String,
+            },
+            #[serde(flatten)]
+            pub base: NotifyNotifyBase,
+        }
+    }
+}
+
 nest! {
     #[derive(Debug, Clone, Serialize)]*
     #[serde(rename_all = "camelCase")]*
@@ -425,6 +553,7 @@ pub enum PartsNotifyListEntry {
     FollowRequested(NotifyFollowRequestedBody),
     Followed(NotifyFollowedBody),
     Mentioned(NotifyMentionedBody),
+    Quoted(NotifyQuotedBody),
     Renoted(NotifyRenotedBody),
     Replied(NotifyRepliedBody),
 }
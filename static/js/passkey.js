@@ -0,0 +1,120 @@
+// WebAuthn (パスキー) の登録とログイン
+// サーバーとはバイナリの値を Base64URL でやりとりする
+
+function passkeyBufferToBase64Url(buffer) {
+  const bytes = new Uint8Array(buffer);
+  let binary = "";
+  for (let i = 0; i < bytes.length; ++i) {
+    binary += String.fromCharCode(bytes[i]);
+  }
+  return window
+    .btoa(binary)
+    .replace(/\+/g, "-")
+    .replace(/\//g, "_")
+    .replace(/=+$/, "");
+}
+
+function passkeyDescriptors(descriptors) {
+  return descriptors.map((d) => ({
+    type: d.type,
+    id: urlBase64ToUint8Array(d.id),
+  }));
+}
+
+async function passkeyRequest(method, url, body) {
+  const res = await fetch(url, {
+    method,
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify(body ?? {}),
+  });
+  if (!res.ok) {
+    const err = await res.json().catch(() => ({}));
+    throw new Error(err.error ?? res.statusText);
+  }
+  return res;
+}
+
+function passkeyPost(url, body) {
+  return passkeyRequest("POST", url, body);
+}
+
+async function passkeyGetAssertion(options) {
+  const credential = await navigator.credentials.get({
+    publicKey: {
+      ...options,
+      challenge: urlBase64ToUint8Array(options.challenge),
+      allowCredentials: passkeyDescriptors(options.allowCredentials),
+    },
+  });
+  return {
+    rawId: passkeyBufferToBase64Url(credential.rawId),
+    response: {
+      clientDataJSON: passkeyBufferToBase64Url(
+        credential.response.clientDataJSON
+      ),
+      authenticatorData: passkeyBufferToBase64Url(
+        credential.response.authenticatorData
+      ),
+      signature: passkeyBufferToBase64Url(credential.response.signature),
+      userHandle: credential.response.userHandle
+        ? passkeyBufferToBase64Url(credential.response.userHandle)
+        : null,
+    },
+  };
+}
+
+function passkeyRedirect(res) {
+  location.href = res.headers.get("hx-redirect") ?? "/client/timeline";
+}
+
+// 登録と削除には、パスワードと (二要素認証が有効な場合は) 認証コードが必要
+async function registerPasskey(name, password, code) {
+  const options = await (
+    await passkeyPost("/auth/passkeys/options", { password, code })
+  ).json();
+  const credential = await navigator.credentials.create({
+    publicKey: {
+      ...options,
+      challenge: urlBase64ToUint8Array(options.challenge),
+      user: { ...options.user, id: urlBase64ToUint8Array(options.user.id) },
+      excludeCredentials: passkeyDescriptors(options.excludeCredentials),
+    },
+  });
+  await passkeyPost("/auth/passkeys", {
+    name,
+    password,
+    credential: {
+      rawId: passkeyBufferToBase64Url(credential.rawId),
+      response: {
+        clientDataJSON: passkeyBufferToBase64Url(
+          credential.response.clientDataJSON
+        ),
+        attestationObject: passkeyBufferToBase64Url(
+          credential.response.attestationObject
+        ),
+      },
+    },
+  });
+}
+
+async function deletePasskey(id, password, code) {
+  await passkeyRequest("DELETE", `/auth/passkeys/${id}`, { password, code });
+}
+
+// パスワードの代わりにパスキーでログインする
+async function loginWithPasskey(next) {
+  const options = await (await passkeyPost("/auth/passkey/options")).json();
+  const credential = await passkeyGetAssertion(options);
+  passkeyRedirect(await passkeyPost("/auth/passkey/login", { credential, next }));
+}
+
+// パスワードを確認した後に、認証アプリのコードの代わりにパスキーを使う
+async function verifyLoginWithPasskey(next) {
+  const options = await (
+    await passkeyPost("/auth/login/2fa/passkey/options")
+  ).json();
+  const credential = await passkeyGetAssertion(options);
+  passkeyRedirect(
+    await passkeyPost("/auth/login/2fa/passkey", { credential, next })
+  );
+}